@@ -5,21 +5,25 @@ use crate::{
     platform,
     statistics::StatisticsManager,
     AlvrEvent, VideoFrame, CONTROL_CHANNEL_SENDER, DECODER_REF, EVENT_BUFFER, IDR_PARSED,
-    STATISTICS_MANAGER, STATISTICS_SENDER, TRACKING_SENDER,
+    PAIRING_PIN, STATISTICS_MANAGER, STATISTICS_SENDER, TRACKING_SENDER,
 };
 use alvr_audio::{AudioDevice, AudioDeviceType};
 use alvr_common::{prelude::*, ALVR_NAME, ALVR_VERSION};
 use alvr_session::{AudioDeviceId, CodecType, OculusFovetionLevel, SessionDesc};
 use alvr_sockets::{
-    spawn_cancelable, ClientConfigPacket, ClientControlPacket, ClientHandshakePacket, Haptics,
-    HeadsetInfoPacket, PeerType, ProtoControlSocket, ServerControlPacket, ServerHandshakePacket,
-    StreamSocketBuilder, VideoFrameHeaderPacket, AUDIO, HAPTICS, STATISTICS, TRACKING, VIDEO,
+    spawn_cancelable, unspecified_ip, AuthRole, Capabilities, ClientAuthPacket, ClientConfigPacket,
+    ClientControlPacket, ClientHandshakePacket, ClockSync, Haptics, HeadsetInfoPacket, NackPacket,
+    NegotiatedCapabilities, PeerType, ProtoControlSocket, ResumeToken, ServerAuthPacket,
+    ServerControlPacket, ServerHandshakePacket, ShardLoss, StreamSocketBuilder,
+    VideoFrameHeaderPacket, AUDIO, CONTROL_PORT, FEATURE_CLOCK_SYNC, FEATURE_NACK, FEATURE_RESUME,
+    FEATURE_STREAM_STATISTICS, HAPTICS, STATISTICS, TRACKING, VIDEO,
 };
 use futures::future::BoxFuture;
 use glyph_brush_layout::{
     ab_glyph::{Font, FontRef, ScaleFont},
     FontId, GlyphPositioner, HorizontalAlign, Layout, SectionGeometry, SectionText, VerticalAlign,
 };
+use rand::Rng;
 use serde_json as json;
 use settings_schema::Switch;
 use std::{
@@ -43,7 +47,10 @@ use alvr_audio as audio;
 
 const INITIAL_MESSAGE: &str = "Searching for server...\n(open ALVR on your PC)";
 const NETWORK_UNREACHABLE_MESSAGE: &str = "Cannot connect to the internet";
-const CLIENT_UNTRUSTED_MESSAGE: &str = "On the PC, click \"Trust\"\nnext to the client entry";
+const CLIENT_UNTRUSTED_MESSAGE: &str =
+    "On the PC, click \"Trust\"\nnext to the client entry\nand enter the PIN:";
+const SERVER_UNAUTHENTICATED_MESSAGE: &str =
+    "The server could not be authenticated.\nRemove this client on the PC\nand pair it again.";
 const INCOMPATIBLE_VERSIONS_MESSAGE: &str = concat!(
     "Server and client have\n",
     "incompatible types.\n",
@@ -58,6 +65,8 @@ const CONTROL_CONNECT_RETRY_PAUSE: Duration = Duration::from_millis(500);
 const RETRY_CONNECT_MIN_INTERVAL: Duration = Duration::from_secs(1);
 const NETWORK_KEEPALIVE_INTERVAL: Duration = Duration::from_secs(1);
 const CLEANUP_PAUSE: Duration = Duration::from_millis(500);
+// How long to wait for retransmitted video shards before giving up the frame
+const VIDEO_RETRANSMISSION_TIMEOUT: Duration = Duration::from_millis(25);
 
 const LOADING_TEXTURE_WIDTH: usize = 1280;
 const LOADING_TEXTURE_HEIGHT: usize = 720;
@@ -74,6 +83,12 @@ impl Drop for StreamCloseGuard {
     }
 }
 
+// Kept across connections, to resume the session after a short disconnection
+struct ResumeState {
+    token: ResumeToken,
+    config_packet: ClientConfigPacket,
+}
+
 fn set_loading_message(message: &str) {
     let hostname = platform::load_config().hostname;
 
@@ -155,51 +170,139 @@ fn on_server_connected(
     .unwrap();
 }
 
-async fn connection_pipeline(headset_info: &HeadsetInfoPacket) -> StrResult {
+fn show_pairing_pin() {
+    let pin = PAIRING_PIN
+        .lock()
+        .get_or_insert_with(|| format!("{:06}", rand::thread_rng().gen_range(0..1_000_000)))
+        .clone();
+
+    set_loading_message(&format!("{CLIENT_UNTRUSTED_MESSAGE} {pin}"));
+}
+
+// Returns false if the client is not paired with the server
+async fn authenticate_server(
+    proto_socket: &mut ProtoControlSocket,
+    capabilities: &NegotiatedCapabilities,
+) -> StrResult<bool> {
+    let mut packet = proto_socket.recv::<ServerAuthPacket>().await?;
+
+    let mut maybe_pin_key = None;
+    if let ServerAuthPacket::RequestPin = packet {
+        if !capabilities.supports_pin_exchange() {
+            return fmt_e!("{INCOMPATIBLE_VERSIONS_MESSAGE}");
+        }
+
+        // Every PIN is used for one attempt only, so that it cannot be guessed. The key exchange
+        // allows a server that does not know the PIN to test a single guess
+        let pin = PAIRING_PIN.lock().take().ok_or_else(enone!())?;
+        let pin_key = proto_socket.exchange_pin(&pin, AuthRole::Client).await?;
+        let proof = proto_socket.auth_proof(&pin_key, AuthRole::Client)?;
+        proto_socket.send(&ClientAuthPacket { proof }).await?;
+
+        packet = proto_socket.recv().await?;
+        maybe_pin_key = Some(pin_key);
+    }
+
+    let maybe_pairing_secret = platform::load_config().pairing_secret;
+
+    match packet {
+        ServerAuthPacket::Authenticate { server_proof } => {
+            let pairing_secret = match maybe_pairing_secret {
+                Some(secret)
+                    if proto_socket.verify_auth_proof(
+                        &secret,
+                        AuthRole::Server,
+                        &server_proof,
+                    )? =>
+                {
+                    secret
+                }
+                _ => return fmt_e!("{SERVER_UNAUTHENTICATED_MESSAGE}"),
+            };
+
+            let proof = proto_socket.auth_proof(&pairing_secret, AuthRole::Client)?;
+            proto_socket.send(&ClientAuthPacket { proof }).await?;
+
+            Ok(true)
+        }
+        ServerAuthPacket::Paired { server_proof } => {
+            match maybe_pin_key {
+                Some(pin_key)
+                    if proto_socket.verify_auth_proof(
+                        &pin_key,
+                        AuthRole::Server,
+                        &server_proof,
+                    )? => {}
+                _ => return fmt_e!("{SERVER_UNAUTHENTICATED_MESSAGE}"),
+            }
+
+            let mut config = platform::load_config();
+            config.pairing_secret = Some(proto_socket.pairing_secret()?);
+            platform::store_config(&config);
+
+            info!("Paired with the server");
+
+            Ok(true)
+        }
+        // A paired client accepts only the server that knows its pairing secret
+        ServerAuthPacket::Trusted if maybe_pin_key.is_none() && maybe_pairing_secret.is_none() => {
+            Ok(true)
+        }
+        ServerAuthPacket::Unpaired => Ok(false),
+        _ => fmt_e!("Unexpected authentication packet"),
+    }
+}
+
+async fn connection_pipeline(
+    headset_info: &HeadsetInfoPacket,
+    resume_state: &mut Option<ResumeState>,
+) -> StrResult {
     let device_name = platform::device_name();
     let hostname = platform::load_config().hostname;
+    let identity = platform::load_identity()?;
 
     let handshake_packet = ClientHandshakePacket {
         alvr_name: ALVR_NAME.into(),
         version: ALVR_VERSION.clone(),
         device_name,
         hostname,
-        reserved1: "".into(),
-        reserved2: "".into(),
+        certificate_fingerprint: identity.fingerprint(),
+        reserved: "".into(),
     };
 
-    let (mut proto_socket, server_ip) = tokio::select! {
-        res = connection_utils::announce_client_loop(handshake_packet) => {
-            match res? {
-                ConnectionError::ServerMessage(message) => {
-                    info!("Server response: {message:?}");
-                    let message_str = match message {
-                        ServerHandshakePacket::ClientUntrusted => CLIENT_UNTRUSTED_MESSAGE,
-                        ServerHandshakePacket::IncompatibleVersions =>
-                            INCOMPATIBLE_VERSIONS_MESSAGE,
-                    };
-                    set_loading_message(message_str);
-                    return Ok(());
-                }
-                ConnectionError::NetworkUnreachable => {
+    // Without a network, the server can still connect through the port forwarded by adb (wired
+    // connection), so the listener keeps running while the announcements are retried
+    let announce_loop = async {
+        loop {
+            match connection_utils::announce_client_loop(handshake_packet.clone()).await {
+                Ok(ConnectionError::ServerMessage(message)) => break Ok(message),
+                Ok(ConnectionError::NetworkUnreachable) => {
                     info!("Network unreachable");
-                    set_loading_message(
-                        NETWORK_UNREACHABLE_MESSAGE,
-                    );
+                    set_loading_message(NETWORK_UNREACHABLE_MESSAGE);
 
                     time::sleep(RETRY_CONNECT_MIN_INTERVAL).await;
 
-                    set_loading_message(
-                        INITIAL_MESSAGE,
-                    );
-
-                    return Ok(());
+                    set_loading_message(INITIAL_MESSAGE);
                 }
+                Err(e) => break Err(e),
             }
+        }
+    };
+
+    let (mut proto_socket, server_ip) = tokio::select! {
+        res = announce_loop => {
+            let message = res?;
+            info!("Server response: {message:?}");
+            match message {
+                ServerHandshakePacket::ClientUntrusted => show_pairing_pin(),
+            }
+
+            return Ok(());
         },
         pair = async {
             loop {
-                if let Ok(pair) = ProtoControlSocket::connect_to(PeerType::Server).await {
+                let peer = PeerType::Server(identity.clone());
+                if let Ok(pair) = ProtoControlSocket::connect_to(peer, CONTROL_PORT).await {
                     break pair;
                 }
 
@@ -208,14 +311,49 @@ async fn connection_pipeline(headset_info: &HeadsetInfoPacket) -> StrResult {
         } => pair
     };
 
-    proto_socket
-        .send(&(headset_info, server_ip))
-        .await
-        .map_err(err!())?;
-    let config_packet = proto_socket
-        .recv::<ClientConfigPacket>()
-        .await
-        .map_err(err!())?;
+    let server_capabilities = proto_socket
+        .exchange_capabilities(&Capabilities::local())
+        .await?;
+    let capabilities =
+        if let Some(capabilities) = Capabilities::local().negotiate(&server_capabilities) {
+            capabilities
+        } else {
+            set_loading_message(INCOMPATIBLE_VERSIONS_MESSAGE);
+            return Ok(());
+        };
+
+    if !authenticate_server(&mut proto_socket, &capabilities).await? {
+        show_pairing_pin();
+        return Ok(());
+    }
+
+    // If the server accepts the token, the configuration of the last connection is still valid
+    let mut resumed_config_packet = None;
+    if capabilities.supports(FEATURE_RESUME) {
+        let maybe_resume_state = resume_state.take();
+        proto_socket
+            .send(&maybe_resume_state.as_ref().map(|state| state.token))
+            .await?;
+        if proto_socket.recv::<bool>().await? {
+            info!("Resuming the session");
+            resumed_config_packet = maybe_resume_state.map(|state| state.config_packet);
+        }
+    }
+
+    let config_packet = if let Some(config_packet) = resumed_config_packet {
+        config_packet
+    } else {
+        proto_socket
+            .send(&(headset_info, server_ip))
+            .await
+            .map_err(err!())?;
+        proto_socket
+            .recv::<ClientConfigPacket>()
+            .await
+            .map_err(err!())?
+    };
+
+    let stream_keys = proto_socket.stream_keys()?;
 
     let (control_sender, mut control_receiver) = proto_socket.split();
     let control_sender = Arc::new(Mutex::new(control_sender));
@@ -242,6 +380,28 @@ async fn connection_pipeline(headset_info: &HeadsetInfoPacket) -> StrResult {
         }
     }
 
+    // Spectators cannot resume
+    if capabilities.supports(FEATURE_RESUME) && !config_packet.spectator {
+        match control_receiver.recv().await {
+            Ok(ServerControlPacket::ResumeToken(token)) => {
+                *resume_state = Some(ResumeState {
+                    token,
+                    config_packet: config_packet.clone(),
+                });
+            }
+            Err(e) => {
+                info!("Server disconnected. Cause: {e}");
+                set_loading_message(SERVER_DISCONNECTED_MESSAGE);
+                return Ok(());
+            }
+            _ => {
+                info!("Unexpected packet");
+                set_loading_message("Unexpected packet");
+                return Ok(());
+            }
+        }
+    }
+
     let settings = {
         let mut session_desc = SessionDesc::default();
         session_desc
@@ -249,11 +409,19 @@ async fn connection_pipeline(headset_info: &HeadsetInfoPacket) -> StrResult {
         session_desc.to_settings()
     };
 
+    // The timestamps of the statistics are taken with the clock synchronized with the server
+    let clock_epoch = Instant::now().into_std();
+    let clock_sync = capabilities
+        .supports(FEATURE_CLOCK_SYNC)
+        .then(|| Arc::new(Mutex::new(ClockSync::new(clock_epoch))));
+
     *STATISTICS_MANAGER.lock() = Some(StatisticsManager::new(
         settings.connection.statistics_history_size as _,
+        clock_epoch,
     ));
 
     let stream_socket_builder = StreamSocketBuilder::listen_for_server(
+        unspecified_ip(server_ip),
         settings.connection.stream_port,
         settings.connection.stream_protocol,
     )
@@ -270,15 +438,20 @@ async fn connection_pipeline(headset_info: &HeadsetInfoPacket) -> StrResult {
         return Ok(());
     }
 
-    let stream_socket = tokio::select! {
+    let mut stream_socket = tokio::select! {
         res = stream_socket_builder.accept_from_server(
             server_ip,
-            settings.connection.stream_port,
+            config_packet.server_stream_port,
+            stream_keys,
         ) => res?,
         _ = time::sleep(Duration::from_secs(5)) => {
             return fmt_e!("Timeout while setting up streams");
         }
     };
+    if let Switch::Enabled(config) = &settings.extra.debug.network_impairment {
+        stream_socket.set_impairment(config.clone());
+    }
+    stream_socket.set_drop_policies(&settings.connection.stream_drop_policies);
     let stream_socket = Arc::new(stream_socket);
 
     info!("Connected to server");
@@ -392,9 +565,12 @@ async fn connection_pipeline(headset_info: &HeadsetInfoPacket) -> StrResult {
     //     }
     // };
 
-    let tracking_send_loop = {
+    // Spectators only watch the stream
+    let tracking_send_loop: BoxFuture<_> = if config_packet.spectator {
+        Box::pin(future::pending())
+    } else {
         let mut socket_sender = stream_socket.request_stream(TRACKING).await?;
-        async move {
+        Box::pin(async move {
             let (data_sender, mut data_receiver) = tmpsc::unbounded_channel();
             *TRACKING_SENDER.lock() = Some(data_sender);
             while let Some(tracking) = data_receiver.recv().await {
@@ -414,7 +590,7 @@ async fn connection_pipeline(headset_info: &HeadsetInfoPacket) -> StrResult {
             }
 
             Ok(())
-        }
+        })
     };
 
     let statistics_send_loop = {
@@ -436,15 +612,44 @@ async fn connection_pipeline(headset_info: &HeadsetInfoPacket) -> StrResult {
     let (legacy_receive_data_sender, legacy_receive_data_receiver) = smpsc::channel();
     let legacy_receive_data_sender = Arc::new(Mutex::new(legacy_receive_data_sender));
 
+    let (video_nack_sender, mut video_nack_receiver) = tmpsc::unbounded_channel();
+
+    let video_nack_loop = async move {
+        while let Some(lost_shards) = video_nack_receiver.recv().await {
+            if let Some(sender) = &*CONTROL_CHANNEL_SENDER.lock() {
+                sender
+                    .send(ClientControlPacket::Nack(NackPacket {
+                        stream_id: VIDEO,
+                        lost_shards,
+                    }))
+                    .ok();
+            }
+        }
+
+        Ok(())
+    };
+
     let video_receive_loop = {
         let mut receiver = stream_socket
             .subscribe_to_stream::<VideoFrameHeaderPacket>(VIDEO)
             .await?;
+        if capabilities.supports(FEATURE_NACK) {
+            receiver.enable_retransmission(VIDEO_RETRANSMISSION_TIMEOUT, video_nack_sender);
+        }
         let legacy_receive_data_sender = legacy_receive_data_sender.clone();
+        let spectator = config_packet.spectator;
+        let stream_start = Instant::now();
         async move {
             loop {
                 let packet = receiver.recv().await?;
 
+                // The retransmission deadline of some frames passed, the decoder needs a new IDR
+                if packet.had_packet_loss() {
+                    if let Some(sender) = &*CONTROL_CHANNEL_SENDER.lock() {
+                        sender.send(ClientControlPacket::RequestIdr).ok();
+                    }
+                }
+
                 let mut buffer = vec![0_u8; mem::size_of::<VideoFrame>() + packet.buffer.len()];
                 let header = VideoFrame {
                     packetCounter: packet.header.packet_counter,
@@ -461,10 +666,46 @@ async fn connection_pipeline(headset_info: &HeadsetInfoPacket) -> StrResult {
                 });
                 buffer[mem::size_of::<VideoFrame>()..].copy_from_slice(&packet.buffer);
 
+                // Shards rebuilt with FEC count as lost, to measure the real network loss
+                let mut shards_count = packet.shards_count;
+                let mut shards_lost = packet.recovered_shards;
+                let mut packets_lost = 0;
+                for loss in &packet.lost_shards {
+                    match loss {
+                        ShardLoss::Packets { count, .. } => {
+                            // The shards count of lost packets is unknown, assume it is the same
+                            // as the current one
+                            let lost = (*count as usize).saturating_mul(packet.shards_count);
+                            shards_count = shards_count.saturating_add(lost);
+                            shards_lost = shards_lost.saturating_add(lost);
+                            packets_lost += *count as usize;
+                        }
+                        ShardLoss::Shards { shard_indices, .. } => {
+                            shards_count += shard_indices.len();
+                            shards_lost += shard_indices.len();
+                            packets_lost += 1;
+                        }
+                    }
+                }
+
                 if let Some(stats) = &mut *STATISTICS_MANAGER.lock() {
-                    stats.report_video_packet_received(Duration::from_nanos(
-                        packet.header.tracking_frame_index,
-                    ));
+                    let target_timestamp = Duration::from_nanos(packet.header.tracking_frame_index);
+                    // Spectators do not send tracking, the frames are tracked from their arrival
+                    if spectator {
+                        stats.report_input_acquired(target_timestamp);
+                    }
+                    stats.report_video_packet_received(target_timestamp);
+                    stats.report_video_shard_loss(
+                        target_timestamp,
+                        shards_count,
+                        shards_lost,
+                        packets_lost,
+                    );
+                    stats.report_video_packet_arrival(
+                        target_timestamp,
+                        packet.packet_index,
+                        packet.arrival.saturating_duration_since(stream_start),
+                    );
                 }
 
                 legacy_receive_data_sender.lock().await.send(buffer).ok();
@@ -495,7 +736,6 @@ async fn connection_pipeline(headset_info: &HeadsetInfoPacket) -> StrResult {
     // When the parent function exits or gets canceled, this loop will run to finish.
     let legacy_stream_socket_loop = task::spawn_blocking({
         let codec = settings.video.codec;
-        let enable_fec = settings.connection.enable_fec;
         move || -> StrResult {
             unsafe {
                 // Note: legacyReceive() requires the java context to be attached to the current thread
@@ -503,7 +743,8 @@ async fn connection_pipeline(headset_info: &HeadsetInfoPacket) -> StrResult {
                 let vm = platform::vm();
                 let env = vm.attach_current_thread().unwrap();
 
-                crate::initializeSocket(matches!(codec, CodecType::HEVC) as _, enable_fec);
+                // FEC is handled by alvr_sockets
+                crate::initializeSocket(matches!(codec, CodecType::HEVC) as _, false);
 
                 let mut idr_request_deadline = None;
 
@@ -554,30 +795,56 @@ async fn connection_pipeline(headset_info: &HeadsetInfoPacket) -> StrResult {
         Box::pin(future::pending())
     };
 
-    let microphone_loop: BoxFuture<_> = if matches!(settings.audio.microphone, Switch::Enabled(_)) {
-        let device = AudioDevice::new(None, AudioDeviceId::Default, AudioDeviceType::Input)
-            .map_err(err!())?;
-
-        let microphone_sender = stream_socket.request_stream(AUDIO).await?;
-        Box::pin(audio::record_audio_loop(
-            device,
-            1,
-            false,
-            microphone_sender,
-        ))
-    } else {
-        Box::pin(future::pending())
-    };
+    let microphone_loop: BoxFuture<_> =
+        if matches!(settings.audio.microphone, Switch::Enabled(_)) && !config_packet.spectator {
+            let device = AudioDevice::new(None, AudioDeviceId::Default, AudioDeviceType::Input)
+                .map_err(err!())?;
+
+            let microphone_sender = stream_socket.request_stream(AUDIO).await?;
+            Box::pin(audio::record_audio_loop(
+                device,
+                1,
+                false,
+                microphone_sender,
+            ))
+        } else {
+            Box::pin(future::pending())
+        };
 
     let keepalive_sender_loop = {
         let control_sender = Arc::clone(&control_sender);
+        let clock_sync = clock_sync.clone();
+        let maybe_stream_socket = capabilities
+            .supports(FEATURE_STREAM_STATISTICS)
+            .then(|| Arc::clone(&stream_socket));
         async move {
             loop {
-                let res = control_sender
-                    .lock()
-                    .await
-                    .send(&ClientControlPacket::KeepAlive)
-                    .await;
+                let res = {
+                    let mut control_sender = control_sender.lock().await;
+                    let res = control_sender.send(&ClientControlPacket::KeepAlive).await;
+
+                    // The clocks are synchronized at the keepalive rate
+                    let res = match &clock_sync {
+                        Some(clock_sync) if res.is_ok() => {
+                            let request = clock_sync.lock().await.request();
+                            control_sender
+                                .send(&ClientControlPacket::ClockSync(request))
+                                .await
+                        }
+                        _ => res,
+                    };
+
+                    match &maybe_stream_socket {
+                        Some(stream_socket) if res.is_ok() => {
+                            control_sender
+                                .send(&ClientControlPacket::StreamStatistics(
+                                    stream_socket.stream_statistics(),
+                                ))
+                                .await
+                        }
+                        _ => res,
+                    }
+                };
                 if let Err(e) = res {
                     info!("Server disconnected. Cause: {e}");
                     set_loading_message(SERVER_DISCONNECTED_MESSAGE);
@@ -603,8 +870,20 @@ async fn connection_pipeline(headset_info: &HeadsetInfoPacket) -> StrResult {
                 Ok(ServerControlPacket::Restarting) => {
                     info!("{SERVER_RESTART_MESSAGE}");
                     set_loading_message(SERVER_RESTART_MESSAGE);
+                    // The session ends with the server
+                    *resume_state = None;
                     break Ok(());
                 }
+                Ok(ServerControlPacket::ClockSync(packet)) => {
+                    if let Some(clock_sync) = &clock_sync {
+                        let response = clock_sync.lock().await.handle(packet);
+                        if let (Some(response), Some(sender)) =
+                            (response, &*CONTROL_CHANNEL_SENDER.lock())
+                        {
+                            sender.send(ClientControlPacket::ClockSync(response)).ok();
+                        }
+                    }
+                }
                 Ok(_) => (),
                 Err(e) => {
                     info!("{SERVER_DISCONNECTED_MESSAGE} Cause: {e}");
@@ -634,6 +913,7 @@ async fn connection_pipeline(headset_info: &HeadsetInfoPacket) -> StrResult {
         res = spawn_cancelable(tracking_send_loop) => res,
         res = spawn_cancelable(statistics_send_loop) => res,
         res = spawn_cancelable(video_receive_loop) => res,
+        res = spawn_cancelable(video_nack_loop) => res,
         res = spawn_cancelable(haptics_receive_loop) => res,
         res = spawn_cancelable(control_send_loop) => res,
         res = legacy_stream_socket_loop => res.map_err(err!())?,
@@ -648,10 +928,11 @@ async fn connection_pipeline(headset_info: &HeadsetInfoPacket) -> StrResult {
 pub async fn connection_lifecycle_loop(headset_info: HeadsetInfoPacket) {
     set_loading_message(INITIAL_MESSAGE);
 
+    let mut resume_state = None;
     loop {
         tokio::join!(
             async {
-                let maybe_error = connection_pipeline(&headset_info).await;
+                let maybe_error = connection_pipeline(&headset_info, &mut resume_state).await;
 
                 if let Err(e) = maybe_error {
                     let message = format!("Connection error:\n{e}\nCheck the PC for more details");
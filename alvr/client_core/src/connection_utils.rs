@@ -1,10 +1,10 @@
 use alvr_common::prelude::*;
 use alvr_sockets::{
-    ClientHandshakePacket, HandshakePacket, ServerHandshakePacket, CONTROL_PORT, LOCAL_IP,
-    MAX_HANDSHAKE_PACKET_SIZE_BYTES,
+    codec, AnnounceSocket, ClientHandshakePacket, HandshakePacket, MdnsBrowser,
+    ServerHandshakePacket, CONTROL_PORT, MAX_HANDSHAKE_PACKET_SIZE_BYTES,
 };
-use std::{net::Ipv4Addr, time::Duration};
-use tokio::{net::UdpSocket, time};
+use std::time::Duration;
+use tokio::time;
 
 const CLIENT_HANDSHAKE_RESEND_INTERVAL: Duration = Duration::from_secs(1);
 
@@ -16,27 +16,34 @@ pub enum ConnectionError {
 pub async fn announce_client_loop(
     handshake_packet: ClientHandshakePacket,
 ) -> StrResult<ConnectionError> {
-    let mut handshake_socket = UdpSocket::bind((LOCAL_IP, CONTROL_PORT))
-        .await
-        .map_err(err!())?;
-    handshake_socket.set_broadcast(true).map_err(err!())?;
+    // Broadcast on IPv4 networks, multicast on IPv6 networks
+    let handshake_socket = AnnounceSocket::bind(CONTROL_PORT, CONTROL_PORT)?;
 
-    let client_handshake_packet =
-        bincode::serialize(&HandshakePacket::Client(handshake_packet)).map_err(err!())?;
+    // Broadcasts and multicasts can be blocked by the access point. The servers found with mDNS
+    // receive the announcement directly
+    let mut mdns_browser = MdnsBrowser::new()
+        .map_err(|e| warn!("mDNS discovery is not available: {e}"))
+        .ok();
+
+    let client_handshake_packet = codec::encode(&HandshakePacket::Client(handshake_packet))?;
 
     loop {
-        let broadcast_result = handshake_socket
-            .send_to(
-                &client_handshake_packet,
-                (Ipv4Addr::BROADCAST, CONTROL_PORT),
-            )
-            .await;
-        if broadcast_result.is_err() {
+        let mut announced = handshake_socket.announce(&client_handshake_packet).await;
+        let mdns_servers = mdns_browser
+            .as_mut()
+            .map(MdnsBrowser::servers)
+            .unwrap_or_default();
+        for server_address in mdns_servers {
+            announced |= handshake_socket
+                .announce_to(&client_handshake_packet, server_address)
+                .await;
+        }
+        if !announced {
             break Ok(ConnectionError::NetworkUnreachable);
         }
 
         let receive_response_loop = {
-            let handshake_socket = &mut handshake_socket;
+            let handshake_socket = &handshake_socket;
             async move {
                 let mut server_response_buffer = [0; MAX_HANDSHAKE_PACKET_SIZE_BYTES];
                 loop {
@@ -47,7 +54,7 @@ pub async fn announce_client_loop(
                         .map_err(err!())?;
 
                     if let Ok(HandshakePacket::Server(handshake_packet)) =
-                        bincode::deserialize(&server_response_buffer[..packet_size])
+                        codec::decode(&server_response_buffer[..packet_size])
                     {
                         warn!("received packet {handshake_packet:?}");
                         break Ok(ConnectionError::ServerMessage(handshake_packet));
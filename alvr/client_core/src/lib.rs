@@ -17,7 +17,6 @@ use alvr_common::{
     once_cell::sync::{Lazy, OnceCell},
     parking_lot::Mutex,
     prelude::*,
-    ALVR_VERSION,
 };
 use alvr_events::ButtonValue;
 use alvr_session::{AudioDeviceId, Fov};
@@ -51,6 +50,8 @@ static STATISTICS_SENDER: Lazy<Mutex<Option<mpsc::UnboundedSender<ClientStatisti
 static CONTROL_CHANNEL_SENDER: Lazy<Mutex<Option<mpsc::UnboundedSender<ClientControlPacket>>>> =
     Lazy::new(|| Mutex::new(None));
 static ON_PAUSE_NOTIFIER: Lazy<Notify> = Lazy::new(Notify::new);
+// Shown on the loading screen until it is used for a pairing attempt
+static PAIRING_PIN: Lazy<Mutex<Option<String>>> = Lazy::new(|| Mutex::new(None));
 
 static DECODER_REF: Lazy<Mutex<Option<GlobalRef>>> = Lazy::new(|| Mutex::new(None));
 static IDR_PARSED: Lazy<AtomicBool> = Lazy::new(|| AtomicBool::new(false));
@@ -270,7 +271,6 @@ pub unsafe extern "C" fn alvr_resume(
         available_refresh_rates,
         preferred_refresh_rate,
         microphone_sample_rate,
-        reserved: format!("{}", *ALVR_VERSION),
     };
 
     let runtime = Runtime::new().unwrap();
@@ -5,6 +5,7 @@ mod android;
 use android::*;
 
 use alvr_common::prelude::*;
+use alvr_sockets::Identity;
 use rand::Rng;
 use serde::{Deserialize, Serialize};
 
@@ -16,6 +17,12 @@ pub struct Config {
     pub protocol_id: u64,
     pub hostname: String,
     pub dark_mode: bool,
+    // Generated on the first connection
+    #[serde(default)]
+    pub identity: Option<Identity>,
+    // Set when paired with a server
+    #[serde(default)]
+    pub pairing_secret: Option<Vec<u8>>,
 }
 
 impl Default for Config {
@@ -32,6 +39,8 @@ impl Default for Config {
                 rng.gen_range(0..10),
             ),
             dark_mode: false,
+            identity: None,
+            pairing_secret: None,
         }
     }
 }
@@ -55,3 +64,17 @@ pub fn load_config() -> Config {
 pub fn store_config(config: &Config) {
     store_config_string(serde_json::to_string(config).unwrap())
 }
+
+pub fn load_identity() -> StrResult<Identity> {
+    let mut config = load_config();
+
+    if let Some(identity) = config.identity {
+        Ok(identity)
+    } else {
+        let identity = Identity::generate()?;
+        config.identity = Some(identity.clone());
+        store_config(&config);
+
+        Ok(identity)
+    }
+}
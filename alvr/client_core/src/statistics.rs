@@ -15,15 +15,18 @@ pub struct StatisticsManager {
     max_history_size: usize,
     prev_vsync: Instant,
     last_average_total_pipeline_latency: Duration,
+    // Origin of the clock synchronized with the server
+    clock_epoch: Instant,
 }
 
 impl StatisticsManager {
-    pub fn new(history_size: usize) -> Self {
+    pub fn new(history_size: usize, clock_epoch: Instant) -> Self {
         Self {
             max_history_size: history_size,
             history_buffer: VecDeque::new(),
             prev_vsync: Instant::now(),
             last_average_total_pipeline_latency: Duration::ZERO,
+            clock_epoch,
         }
     }
 
@@ -59,6 +62,42 @@ impl StatisticsManager {
         }
     }
 
+    pub fn report_video_shard_loss(
+        &mut self,
+        target_timestamp: Duration,
+        shards_count: usize,
+        shards_lost: usize,
+        packets_lost: usize,
+    ) {
+        if let Some(frame) = self
+            .history_buffer
+            .iter_mut()
+            .find(|frame| frame.intervals.target_timestamp == target_timestamp)
+        {
+            frame.intervals.video_shards_count = shards_count;
+            frame.intervals.video_shards_lost = shards_lost;
+            frame.intervals.video_packets_lost = packets_lost;
+        }
+    }
+
+    pub fn report_video_packet_arrival(
+        &mut self,
+        target_timestamp: Duration,
+        packet_index: u32,
+        arrival: Duration,
+    ) {
+        if let Some(frame) = self
+            .history_buffer
+            .iter_mut()
+            .find(|frame| frame.intervals.target_timestamp == target_timestamp)
+        {
+            frame
+                .intervals
+                .video_packet_arrivals
+                .push((packet_index, arrival));
+        }
+    }
+
     pub fn report_frame_decoded(&mut self, target_timestamp: Duration) {
         if let Some(frame) = self
             .history_buffer
@@ -119,7 +158,15 @@ impl StatisticsManager {
         self.history_buffer
             .iter()
             .find(|frame| frame.intervals.target_timestamp == target_timestamp)
-            .map(|frame| frame.intervals.clone())
+            .map(|frame| ClientStatistics {
+                tracking_sent: frame
+                    .input_acquired
+                    .saturating_duration_since(self.clock_epoch),
+                video_packet_received: frame
+                    .video_packet_received
+                    .saturating_duration_since(self.clock_epoch),
+                ..frame.intervals.clone()
+            })
     }
 
     // latency used for prediction
@@ -54,17 +54,6 @@ pub fn protocol_id() -> u64 {
     hash_string(&protocol_string)
 }
 
-// deprecated
-pub fn is_version_compatible(other_version: &Version) -> bool {
-    let protocol_string = if other_version.pre.is_empty() {
-        other_version.major.to_string()
-    } else {
-        format!("{}-{}", other_version.major, other_version.pre)
-    };
-
-    protocol_id() == hash_string(&protocol_string)
-}
-
 pub const HEAD_PATH: &str = "/user/head";
 pub const LEFT_HAND_PATH: &str = "/user/hand/left";
 pub const RIGHT_HAND_PATH: &str = "/user/hand/right";
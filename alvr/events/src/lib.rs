@@ -1,6 +1,7 @@
 use alvr_common::prelude::*;
-use alvr_session::SessionDesc;
+use alvr_session::{SessionDesc, SettingsApplyMode};
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub enum EventSeverity {
@@ -10,6 +11,23 @@ pub enum EventSeverity {
     Debug,
 }
 
+// Counters of a stream of the stream socket, since the stream was started. Losses are counted
+// after FEC and retransmissions. Shards are the datagrams the packets are split into
+#[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct StreamStatistics {
+    pub packets_sent: u64,
+    pub bytes_sent: u64,
+    pub packets_received: u64,
+    pub bytes_received: u64,
+    pub packets_lost: u64,
+    // Shards received after a shard sent later, including the retransmitted ones
+    pub shards_reordered: u64,
+    pub shards_duplicated: u64,
+    // Smoothed variation of the interval between consecutive packets, like RFC 3550
+    pub jitter_ms: f32,
+}
+
 // todo: remove some unused statistics
 #[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(rename_all = "camelCase")] // todo: remove casing conversion
@@ -20,16 +38,24 @@ pub struct Statistics {
     pub video_mbits_per_sec: f32,
     pub total_latency_ms: f32,
     pub network_latency_ms: f32,
+    // Measured only if the clocks of the peers are synchronized, otherwise 0
+    pub uplink_latency_ms: f32,
+    pub downlink_latency_ms: f32,
     pub encode_latency_ms: f32,
     pub decode_latency_ms: f32,
     pub fec_percentage: u32,
     pub fec_errors_total: usize,
     pub fec_errors_per_sec: usize,
+    pub packets_dropped_total: usize,
+    pub packets_dropped_per_sec: usize,
     pub client_fps: u32, // the name will be fixed after the old dashboard is removed
     pub server_fps: u32,
     pub battery_hmd: u32,
     pub battery_left: u32,
     pub battery_right: u32,
+    // By stream name. The client streams are reported only by the clients that support it
+    pub server_streams: HashMap<String, StreamStatistics>,
+    pub client_streams: HashMap<String, StreamStatistics>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -47,6 +73,19 @@ pub struct GraphStatistics {
     pub server_fps: f32,
 }
 
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct SpectatorStatistics {
+    pub hostname: String,
+    pub video_packets_total: usize,
+    pub video_packets_per_sec: usize,
+    pub video_mbits_per_sec: f32,
+    pub decode_latency_ms: f32,
+    pub fec_errors_total: usize,
+    pub fec_errors_per_sec: usize,
+    pub client_fps: u32,
+}
+
 // This struct is temporary, until we switch to the new event system
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct LogEvent {
@@ -74,6 +113,8 @@ pub struct ButtonEvent {
 pub enum EventType {
     Session(Box<SessionDesc>),
     SessionUpdated, // deprecated
+    // The OpenvrConfig changed. RestartDriver is sent before SteamVR is restarted
+    SettingsApplied(SettingsApplyMode),
     ClientFoundOk,
     ClientFoundInvalid,
     ClientFoundWrongVersion(String),
@@ -83,6 +124,7 @@ pub enum EventType {
     UpdateDownloadError,
     Statistics(Statistics),
     GraphStatistics(GraphStatistics),
+    SpectatorStatistics(SpectatorStatistics),
     Button(ButtonEvent),
     ServerQuitting,
     Log(LogEvent),
@@ -0,0 +1,598 @@
+// Headless client that speaks the real protocol: discovery, control socket authentication, headset
+// info and client config, then the tracking, video, audio, haptics and statistics streams. It has
+// no FFI dependencies, so connection scenarios can run on CI against the server core with a fake
+// driver, or against a real server on another host.
+
+use alvr_common::{parking_lot::Mutex, prelude::*, ALVR_NAME, ALVR_VERSION, HEAD_ID};
+use alvr_events::StreamStatistics;
+use alvr_session::SessionDesc;
+use alvr_sockets::{
+    codec, spawn_cancelable, unspecified_ip, AuthRole, Capabilities, ClientAuthPacket,
+    ClientConfigPacket, ClientControlPacket, ClientHandshakePacket, ClientStatistics, ClockSync,
+    DeviceMotion, HandshakePacket, Haptics, HeadsetInfoPacket, Identity, NackPacket,
+    NegotiatedCapabilities, PeerType, ProtoControlSocket, ResumeToken, ServerAuthPacket,
+    ServerControlPacket, ServerHandshakePacket, ShardLoss, StreamSocketBuilder, Tracking,
+    VideoFrameHeaderPacket, AUDIO, CONTROL_PORT, FEATURE_CLOCK_SYNC, FEATURE_NACK, FEATURE_RESUME,
+    FEATURE_STREAM_STATISTICS, HAPTICS, MAX_HANDSHAKE_PACKET_SIZE_BYTES, STATISTICS, TRACKING,
+    VIDEO,
+};
+use rand::Rng;
+use serde_json as json;
+use settings_schema::Switch;
+use std::{
+    collections::HashMap,
+    future,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{
+    net::UdpSocket,
+    sync::{mpsc, Mutex as AsyncMutex},
+    time,
+};
+
+const CLIENT_HANDSHAKE_RESEND_INTERVAL: Duration = Duration::from_secs(1);
+const CONTROL_CONNECT_RETRY_PAUSE: Duration = Duration::from_millis(500);
+const NETWORK_KEEPALIVE_INTERVAL: Duration = Duration::from_secs(1);
+const STREAM_SETUP_TIMEOUT: Duration = Duration::from_secs(5);
+const VIDEO_RETRANSMISSION_TIMEOUT: Duration = Duration::from_millis(25);
+
+pub struct FakeClientConfig {
+    pub hostname: String,
+    pub device_name: String,
+    pub identity: Identity,
+    // Destination of the discovery packets. The real client broadcasts them on IPv4 and sends them
+    // to a multicast group on IPv6
+    pub server_address: SocketAddr,
+    // Port on which the client waits for the server to connect
+    pub control_port: u16,
+    // Address the sockets of the client are bound to. This allows running on the same host as the
+    // server, with another loopback address
+    pub local_ip: Option<IpAddr>,
+    pub headset_info: HeadsetInfoPacket,
+    // Advertised to the server, to emulate other client versions
+    pub capabilities: Capabilities,
+    // The client disconnects after streaming for this long
+    pub stream_duration: Duration,
+}
+
+impl FakeClientConfig {
+    pub fn new(hostname: &str) -> StrResult<Self> {
+        Ok(Self {
+            hostname: hostname.into(),
+            device_name: "Fake client".into(),
+            identity: Identity::generate()?,
+            server_address: (Ipv4Addr::BROADCAST, CONTROL_PORT).into(),
+            control_port: CONTROL_PORT,
+            local_ip: None,
+            headset_info: HeadsetInfoPacket {
+                recommended_eye_width: 1832,
+                recommended_eye_height: 1920,
+                available_refresh_rates: vec![72., 90.],
+                preferred_refresh_rate: 72.,
+                microphone_sample_rate: 48000,
+            },
+            capabilities: Capabilities::local(),
+            stream_duration: Duration::from_secs(5),
+        })
+    }
+}
+
+#[derive(Default, Clone, Debug)]
+pub struct StreamReport {
+    // The server accepted the resume token of the previous connection
+    pub resumed: bool,
+    pub tracking_packets_sent: usize,
+    pub video_frames_received: usize,
+    // Video packets that could not be reassembled or recovered
+    pub video_packets_lost: usize,
+    pub audio_packets_received: usize,
+    pub haptics_received: usize,
+    // Retransmission requests sent for lost video shards
+    pub nacks_sent: usize,
+    // Counters of the client stream socket when it disconnected, by stream ID
+    pub stream_statistics: HashMap<u16, StreamStatistics>,
+}
+
+#[derive(Debug)]
+pub enum ConnectionOutcome {
+    // The server answered the discovery packet instead of connecting
+    ServerMessage(ServerHandshakePacket),
+    // There is no protocol version supported by both peers
+    IncompatibleServer,
+    // The client must be paired with a PIN first
+    Unpaired,
+    ServerRestarting,
+    // The client disconnected after stream_duration
+    Streamed(StreamReport),
+    ServerDisconnected(StreamReport),
+}
+
+pub struct FakeClient {
+    config: FakeClientConfig,
+    pin: Option<String>,
+    pairing_secret: Option<Vec<u8>>,
+    // Token and configuration of the last stream, presented on the next connection
+    resume_state: Option<(ResumeToken, ClientConfigPacket)>,
+}
+
+impl FakeClient {
+    pub fn new(config: FakeClientConfig) -> Self {
+        Self {
+            config,
+            pin: None,
+            pairing_secret: None,
+            resume_state: None,
+        }
+    }
+
+    pub fn config_mut(&mut self) -> &mut FakeClientConfig {
+        &mut self.config
+    }
+
+    pub fn fingerprint(&self) -> String {
+        self.config.identity.fingerprint()
+    }
+
+    // The real client shows a new PIN on the headset when the server does not trust it
+    pub fn generate_pin(&mut self) -> String {
+        let pin = format!("{:06}", rand::thread_rng().gen_range(0..1_000_000));
+        self.pin = Some(pin.clone());
+
+        pin
+    }
+
+    pub fn set_pin(&mut self, pin: &str) {
+        self.pin = Some(pin.into());
+    }
+
+    // Set after a successful pairing, used to authenticate the next connections
+    pub fn pairing_secret(&self) -> Option<&[u8]> {
+        self.pairing_secret.as_deref()
+    }
+
+    fn local_ip_for(&self, server_ip: IpAddr) -> IpAddr {
+        self.config
+            .local_ip
+            .unwrap_or_else(|| unspecified_ip(server_ip))
+    }
+
+    async fn announce_loop(&self) -> StrResult<ServerHandshakePacket> {
+        // The port is not CONTROL_PORT, to be able to run on the same host as the server
+        let socket = UdpSocket::bind((self.local_ip_for(self.config.server_address.ip()), 0))
+            .await
+            .map_err(err!())?;
+        if self.config.server_address.is_ipv4() {
+            socket.set_broadcast(true).map_err(err!())?;
+        }
+
+        let handshake_packet = codec::encode(&HandshakePacket::Client(ClientHandshakePacket {
+            alvr_name: ALVR_NAME.into(),
+            version: ALVR_VERSION.clone(),
+            device_name: self.config.device_name.clone(),
+            hostname: self.config.hostname.clone(),
+            certificate_fingerprint: self.fingerprint(),
+            reserved: "".into(),
+        }))?;
+
+        let mut buffer = [0; MAX_HANDSHAKE_PACKET_SIZE_BYTES];
+        loop {
+            // Like the client, keeps listening for the server if there is no route to it. The
+            // server of a wired connection connects through the forwarded port
+            socket
+                .send_to(&handshake_packet, self.config.server_address)
+                .await
+                .ok();
+
+            let deadline = time::Instant::now() + CLIENT_HANDSHAKE_RESEND_INTERVAL;
+            while let Ok(res) = time::timeout_at(deadline, socket.recv_from(&mut buffer)).await {
+                let (size, _) = res.map_err(err!())?;
+                if let Ok(HandshakePacket::Server(packet)) = codec::decode(&buffer[..size]) {
+                    return Ok(packet);
+                }
+            }
+        }
+    }
+
+    // Returns false if the server requires pairing
+    async fn authenticate_server(
+        &mut self,
+        proto_socket: &mut ProtoControlSocket,
+        capabilities: &NegotiatedCapabilities,
+    ) -> StrResult<bool> {
+        let mut packet = proto_socket.recv::<ServerAuthPacket>().await?;
+
+        let mut maybe_pin_key = None;
+        if let ServerAuthPacket::RequestPin = packet {
+            if !capabilities.supports_pin_exchange() {
+                return fmt_e!("The server cannot pair with the PIN key exchange");
+            }
+
+            let pin = self.pin.take().ok_or_else(enone!())?;
+            let pin_key = proto_socket.exchange_pin(&pin, AuthRole::Client).await?;
+            let proof = proto_socket.auth_proof(&pin_key, AuthRole::Client)?;
+            proto_socket.send(&ClientAuthPacket { proof }).await?;
+
+            packet = proto_socket.recv().await?;
+            maybe_pin_key = Some(pin_key);
+        }
+
+        match packet {
+            ServerAuthPacket::Authenticate { server_proof } => match &self.pairing_secret {
+                Some(secret)
+                    if proto_socket.verify_auth_proof(
+                        secret,
+                        AuthRole::Server,
+                        &server_proof,
+                    )? =>
+                {
+                    let proof = proto_socket.auth_proof(secret, AuthRole::Client)?;
+                    proto_socket.send(&ClientAuthPacket { proof }).await?;
+
+                    Ok(true)
+                }
+                _ => fmt_e!("The server could not be authenticated"),
+            },
+            ServerAuthPacket::Paired { server_proof } => match maybe_pin_key {
+                Some(pin_key)
+                    if proto_socket.verify_auth_proof(
+                        &pin_key,
+                        AuthRole::Server,
+                        &server_proof,
+                    )? =>
+                {
+                    self.pairing_secret = Some(proto_socket.pairing_secret()?);
+
+                    Ok(true)
+                }
+                _ => fmt_e!("The server could not be authenticated"),
+            },
+            ServerAuthPacket::Trusted
+                if maybe_pin_key.is_none() && self.pairing_secret.is_none() =>
+            {
+                Ok(true)
+            }
+            ServerAuthPacket::Unpaired => Ok(false),
+            _ => fmt_e!("Unexpected authentication packet"),
+        }
+    }
+
+    // Connects to the server and streams for stream_duration, or until the server disconnects
+    pub async fn connect(&mut self) -> StrResult<ConnectionOutcome> {
+        let (mut proto_socket, server_ip) = tokio::select! {
+            res = self.announce_loop() => return Ok(ConnectionOutcome::ServerMessage(res?)),
+            pair = async {
+                loop {
+                    let peer = PeerType::Server(self.config.identity.clone());
+                    if let Ok(pair) = ProtoControlSocket::connect_to(peer, self.config.control_port).await {
+                        break pair;
+                    }
+
+                    time::sleep(CONTROL_CONNECT_RETRY_PAUSE).await;
+                }
+            } => pair,
+        };
+
+        let server_capabilities = proto_socket
+            .exchange_capabilities(&self.config.capabilities)
+            .await?;
+        let capabilities =
+            if let Some(capabilities) = self.config.capabilities.negotiate(&server_capabilities) {
+                capabilities
+            } else {
+                return Ok(ConnectionOutcome::IncompatibleServer);
+            };
+
+        if !self
+            .authenticate_server(&mut proto_socket, &capabilities)
+            .await?
+        {
+            return Ok(ConnectionOutcome::Unpaired);
+        }
+
+        let mut resumed_config_packet = None;
+        if capabilities.supports(FEATURE_RESUME) {
+            let resume_state = self.resume_state.take();
+            proto_socket
+                .send(&resume_state.as_ref().map(|(token, _)| *token))
+                .await?;
+            if proto_socket.recv::<bool>().await? {
+                resumed_config_packet = resume_state.map(|(_, config_packet)| config_packet);
+            }
+        }
+        let resumed = resumed_config_packet.is_some();
+
+        let config_packet = if let Some(config_packet) = resumed_config_packet {
+            config_packet
+        } else {
+            proto_socket
+                .send(&(self.config.headset_info.clone(), server_ip))
+                .await?;
+            proto_socket.recv::<ClientConfigPacket>().await?
+        };
+
+        let stream_keys = proto_socket.stream_keys()?;
+        let (control_sender, mut control_receiver) =
+            proto_socket.split::<ClientControlPacket, ServerControlPacket>();
+        let control_sender = Arc::new(AsyncMutex::new(control_sender));
+
+        match control_receiver.recv().await? {
+            ServerControlPacket::StartStream => (),
+            ServerControlPacket::Restarting => return Ok(ConnectionOutcome::ServerRestarting),
+            _ => return fmt_e!("Unexpected packet"),
+        }
+        if capabilities.supports(FEATURE_RESUME) && !config_packet.spectator {
+            match control_receiver.recv().await? {
+                ServerControlPacket::ResumeToken(token) => {
+                    self.resume_state = Some((token, config_packet.clone()));
+                }
+                _ => return fmt_e!("Unexpected packet"),
+            }
+        }
+
+        let settings = {
+            let mut session_desc = SessionDesc::default();
+            session_desc
+                .merge_from_json(&json::from_str(&config_packet.session_desc).map_err(err!())?)?;
+            session_desc.to_settings()
+        };
+
+        let stream_socket_builder = StreamSocketBuilder::listen_for_server(
+            self.local_ip_for(server_ip),
+            settings.connection.stream_port,
+            settings.connection.stream_protocol,
+        )
+        .await?;
+
+        control_sender
+            .lock()
+            .await
+            .send(&ClientControlPacket::StreamReady)
+            .await?;
+
+        let mut stream_socket = tokio::select! {
+            res = stream_socket_builder.accept_from_server(
+                server_ip,
+                config_packet.server_stream_port,
+                stream_keys,
+            ) => res?,
+            _ = time::sleep(STREAM_SETUP_TIMEOUT) => {
+                return fmt_e!("Timeout while setting up streams");
+            }
+        };
+        if let Switch::Enabled(config) = &settings.extra.debug.network_impairment {
+            stream_socket.set_impairment(config.clone());
+        }
+        stream_socket.set_drop_policies(&settings.connection.stream_drop_policies);
+        let stream_socket = Arc::new(stream_socket);
+
+        let report = Arc::new(Mutex::new(StreamReport {
+            resumed,
+            ..Default::default()
+        }));
+        let stream_start = Instant::now();
+
+        // The tracking timestamps are taken with the clock synchronized with the server
+        let clock_sync = capabilities
+            .supports(FEATURE_CLOCK_SYNC)
+            .then(|| Arc::new(Mutex::new(ClockSync::new(stream_start))));
+
+        let tracking_send_loop = {
+            let mut sender = stream_socket.request_stream(TRACKING).await?;
+            let report = Arc::clone(&report);
+            let frame_interval = Duration::from_secs_f32(1. / config_packet.fps.max(1.));
+            async move {
+                // Spectators do not send tracking
+                if config_packet.spectator {
+                    return future::pending().await;
+                }
+
+                loop {
+                    sender
+                        .send(&Tracking {
+                            target_timestamp: stream_start.elapsed(),
+                            device_motions: vec![(*HEAD_ID, DeviceMotion::default())],
+                            left_hand_skeleton: None,
+                            right_hand_skeleton: None,
+                        })
+                        .await?;
+                    report.lock().tracking_packets_sent += 1;
+
+                    time::sleep(frame_interval).await;
+                }
+            }
+        };
+
+        let (nack_sender, mut nack_receiver) = mpsc::unbounded_channel();
+
+        let video_receive_loop = {
+            let mut receiver = stream_socket
+                .subscribe_to_stream::<VideoFrameHeaderPacket>(VIDEO)
+                .await?;
+            if capabilities.supports(FEATURE_NACK) {
+                receiver.enable_retransmission(VIDEO_RETRANSMISSION_TIMEOUT, nack_sender);
+            }
+            let mut statistics_sender = stream_socket.request_stream(STATISTICS).await?;
+            let report = Arc::clone(&report);
+            async move {
+                loop {
+                    let packet = receiver.recv().await?;
+
+                    let mut shards_count = packet.shards_count;
+                    let mut shards_lost = packet.recovered_shards;
+                    let mut packets_lost = 0;
+                    for loss in &packet.lost_shards {
+                        match loss {
+                            ShardLoss::Packets { count, .. } => {
+                                let lost = *count as usize * packet.shards_count;
+                                shards_count += lost;
+                                shards_lost += lost;
+                                packets_lost += *count as usize;
+                            }
+                            ShardLoss::Shards { shard_indices, .. } => {
+                                shards_count += shard_indices.len();
+                                shards_lost += shard_indices.len();
+                                packets_lost += 1;
+                            }
+                        }
+                    }
+
+                    {
+                        let mut report = report.lock();
+                        report.video_frames_received += 1;
+                        report.video_packets_lost += packets_lost;
+                    }
+
+                    let target_timestamp = Duration::from_nanos(packet.header.tracking_frame_index);
+                    statistics_sender
+                        .send(&ClientStatistics {
+                            target_timestamp,
+                            video_shards_count: shards_count,
+                            video_shards_lost: shards_lost,
+                            video_packets_lost: packets_lost,
+                            video_packet_arrivals: vec![(
+                                packet.packet_index,
+                                packet.arrival.saturating_duration_since(stream_start),
+                            )],
+                            tracking_sent: target_timestamp,
+                            video_packet_received: packet
+                                .arrival
+                                .saturating_duration_since(stream_start),
+                            ..Default::default()
+                        })
+                        .await?;
+                }
+            }
+        };
+
+        let audio_receive_loop = {
+            let mut receiver = stream_socket.subscribe_to_stream::<()>(AUDIO).await?;
+            let report = Arc::clone(&report);
+            async move {
+                loop {
+                    receiver.recv().await?;
+                    report.lock().audio_packets_received += 1;
+                }
+            }
+        };
+
+        let haptics_receive_loop = {
+            let mut receiver = stream_socket
+                .subscribe_to_stream::<Haptics>(HAPTICS)
+                .await?;
+            let report = Arc::clone(&report);
+            async move {
+                loop {
+                    receiver.recv().await?;
+                    report.lock().haptics_received += 1;
+                }
+            }
+        };
+
+        let (clock_sync_sender, mut clock_sync_receiver) = mpsc::unbounded_channel();
+
+        let control_send_loop = {
+            let control_sender = Arc::clone(&control_sender);
+            let clock_sync = clock_sync.clone();
+            let report = Arc::clone(&report);
+            let maybe_stream_socket = capabilities
+                .supports(FEATURE_STREAM_STATISTICS)
+                .then(|| Arc::clone(&stream_socket));
+            async move {
+                let mut keepalive_deadline = time::Instant::now();
+                loop {
+                    let packet = tokio::select! {
+                        Some(lost_shards) = nack_receiver.recv() => {
+                            report.lock().nacks_sent += 1;
+
+                            ClientControlPacket::Nack(NackPacket {
+                                stream_id: VIDEO,
+                                lost_shards,
+                            })
+                        }
+                        Some(response) = clock_sync_receiver.recv() => {
+                            ClientControlPacket::ClockSync(response)
+                        }
+                        _ = time::sleep_until(keepalive_deadline) => {
+                            keepalive_deadline += NETWORK_KEEPALIVE_INTERVAL;
+                            ClientControlPacket::KeepAlive
+                        }
+                    };
+
+                    let mut control_sender = control_sender.lock().await;
+                    control_sender.send(&packet).await?;
+
+                    if let ClientControlPacket::KeepAlive = packet {
+                        if let Some(clock_sync) = &clock_sync {
+                            let request = clock_sync.lock().request();
+                            control_sender
+                                .send(&ClientControlPacket::ClockSync(request))
+                                .await?;
+                        }
+                        if let Some(stream_socket) = &maybe_stream_socket {
+                            control_sender
+                                .send(&ClientControlPacket::StreamStatistics(
+                                    stream_socket.stream_statistics(),
+                                ))
+                                .await?;
+                        }
+                    }
+                }
+            }
+        };
+
+        // Returns true if the server is restarting
+        let control_receive_loop = async move {
+            loop {
+                match control_receiver.recv().await {
+                    Ok(ServerControlPacket::Restarting) => break Ok(true),
+                    Ok(ServerControlPacket::ClockSync(packet)) => {
+                        if let Some(response) = clock_sync
+                            .as_ref()
+                            .and_then(|clock_sync| clock_sync.lock().handle(packet))
+                        {
+                            clock_sync_sender.send(response).ok();
+                        }
+                    }
+                    Ok(_) => (),
+                    Err(e) => {
+                        info!("Server disconnected. Cause: {e}");
+                        break StrResult::Ok(false);
+                    }
+                }
+            }
+        };
+
+        let receive_loop = {
+            let stream_socket = Arc::clone(&stream_socket);
+            async move { stream_socket.receive_loop().await }
+        };
+
+        let stream_result: StrResult<Option<bool>> = tokio::select! {
+            res = spawn_cancelable(receive_loop) => res.map(|_| Some(false)),
+            res = spawn_cancelable(tracking_send_loop) => res.map(|_| None),
+            res = spawn_cancelable(video_receive_loop) => res.map(|_| None),
+            res = spawn_cancelable(audio_receive_loop) => res.map(|_| None),
+            res = spawn_cancelable(haptics_receive_loop) => res.map(|_| None),
+            res = spawn_cancelable(control_send_loop) => res.map(|_| None),
+            res = control_receive_loop => res.map(Some),
+            _ = time::sleep(self.config.stream_duration) => Ok(None),
+        };
+
+        let report = StreamReport {
+            stream_statistics: stream_socket.stream_statistics(),
+            ..report.lock().clone()
+        };
+        Ok(match stream_result {
+            Ok(None) => ConnectionOutcome::Streamed(report),
+            Ok(Some(true)) => {
+                self.resume_state = None;
+
+                ConnectionOutcome::ServerRestarting
+            }
+            // Stream errors are caused by the server closing the sockets
+            Ok(Some(false)) | Err(_) => ConnectionOutcome::ServerDisconnected(report),
+        })
+    }
+}
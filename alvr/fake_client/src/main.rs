@@ -0,0 +1,49 @@
+// Connects to a server on the local network, like a headset would. Enter the PIN shown here in
+// the dashboard to pair the client. The server must run on another host, since it binds the same
+// ports as the client.
+
+use alvr_fake_client::{ConnectionOutcome, FakeClient, FakeClientConfig};
+use std::time::Duration;
+
+const RETRY_CONNECT_INTERVAL: Duration = Duration::from_secs(1);
+
+#[tokio::main]
+async fn main() {
+    let mut config = match FakeClientConfig::new("fake.client.alvr") {
+        Ok(config) => config,
+        Err(e) => {
+            println!("Failed to create the client identity: {e}");
+            return;
+        }
+    };
+    config.stream_duration = Duration::from_secs(60);
+
+    let mut client = FakeClient::new(config);
+
+    loop {
+        match client.connect().await {
+            Ok(ConnectionOutcome::ServerMessage(packet)) => {
+                println!("Server message: {packet:?}");
+                println!("Pairing PIN: {}", client.generate_pin());
+            }
+            Ok(ConnectionOutcome::Unpaired) => {
+                println!("Pairing PIN: {}", client.generate_pin());
+            }
+            Ok(ConnectionOutcome::IncompatibleServer) => {
+                println!("The server has an incompatible protocol version");
+                return;
+            }
+            Ok(ConnectionOutcome::ServerRestarting) => println!("Server restarting"),
+            Ok(ConnectionOutcome::Streamed(report)) => {
+                println!("Streamed: {report:?}");
+                return;
+            }
+            Ok(ConnectionOutcome::ServerDisconnected(report)) => {
+                println!("Server disconnected: {report:?}");
+            }
+            Err(e) => println!("Connection error: {e}"),
+        }
+
+        tokio::time::sleep(RETRY_CONNECT_INTERVAL).await;
+    }
+}
@@ -0,0 +1,38 @@
+use alvr_fake_client::FakeClientConfig;
+use std::{
+    net::{Ipv4Addr, Ipv6Addr, TcpListener, UdpSocket},
+    time::Duration,
+};
+
+// The client runs on another loopback address, so that it can bind the same stream port as the
+// server
+pub const SERVER_IP: Ipv4Addr = Ipv4Addr::LOCALHOST;
+pub const CLIENT_IP: Ipv4Addr = Ipv4Addr::new(127, 0, 0, 2);
+
+const STREAM_DURATION: Duration = Duration::from_secs(2);
+
+// Free for both UDP and TCP, since the server discovers the clients on the control port and the
+// clients listen for the control socket on the same port
+pub fn free_port() -> u16 {
+    loop {
+        let port = UdpSocket::bind((Ipv6Addr::UNSPECIFIED, 0))
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+
+        if TcpListener::bind((Ipv6Addr::UNSPECIFIED, port)).is_ok() {
+            return port;
+        }
+    }
+}
+
+pub fn client_config(control_port: u16) -> FakeClientConfig {
+    let mut config = FakeClientConfig::new("test.client.alvr").unwrap();
+    config.server_address = (SERVER_IP, control_port).into();
+    config.control_port = control_port;
+    config.local_ip = Some(CLIENT_IP.into());
+    config.stream_duration = STREAM_DURATION;
+
+    config
+}
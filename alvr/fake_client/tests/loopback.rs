@@ -0,0 +1,631 @@
+// Connection scenarios between the fake client and the server core over localhost. The OpenVR
+// driver and the encoder are replaced by FakeDriver. The server core is a singleton, so the
+// scenarios run one at a time.
+
+mod common;
+
+use alvr_common::{
+    glam::{Quat, Vec2},
+    once_cell::sync::Lazy,
+    parking_lot::Mutex,
+    prelude::*,
+    LEFT_HAND_ID,
+};
+use alvr_events::ButtonValue;
+use alvr_fake_client::{ConnectionOutcome, FakeClient, StreamReport};
+use alvr_server_core::{ServerDriver, PAIRING_PINS, SERVER_DATA_MANAGER, SESSION_PATH};
+use alvr_session::{OpenvrPropValue, OpenvrPropertyKey, SessionDesc, SocketProtocolDefaultVariant};
+use alvr_sockets::{
+    Capabilities, DeviceMotion, Haptics, ServerHandshakePacket, VideoFrameHeaderPacket,
+    ViewsConfig, FEATURE_NACK, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION, TRACKING, VIDEO,
+};
+use common::SERVER_IP;
+use std::{
+    env,
+    net::Ipv6Addr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+use tokio::{sync::Notify, task::JoinHandle, time};
+
+static SERVER_LOCK: Lazy<tokio::sync::Mutex<()>> = Lazy::new(|| tokio::sync::Mutex::new(()));
+
+const CLIENT_HOSTNAME: &str = "test.client.alvr";
+const FRAME_INTERVAL: Duration = Duration::from_millis(14);
+const VIDEO_FRAME_SIZE: usize = 10_000;
+const HAPTICS_FRAMES_INTERVAL: u64 = 10;
+// Includes the restart of the server on the first connection of the client
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(20);
+
+#[derive(Default, Clone)]
+struct DriverCounters {
+    streaming_initializations: usize,
+    settings_applications: usize,
+    idr_requests: usize,
+    tracking_updates: usize,
+    network_latency_reports: usize,
+}
+
+// Stands in for the OpenVR driver. While streaming, it sends video frames and haptics to the
+// server core, like the encoder and SteamVR
+#[derive(Default)]
+struct FakeDriver {
+    counters: Mutex<DriverCounters>,
+    encoder_running: Mutex<Option<Arc<AtomicBool>>>,
+    restart_notifier: Notify,
+}
+
+impl FakeDriver {
+    fn counters(&self) -> DriverCounters {
+        self.counters.lock().clone()
+    }
+}
+
+impl ServerDriver for FakeDriver {
+    fn initialize_streaming(&self) {
+        self.counters.lock().streaming_initializations += 1;
+
+        let running = Arc::new(AtomicBool::new(true));
+        *self.encoder_running.lock() = Some(Arc::clone(&running));
+
+        thread::spawn(move || {
+            let mut frame_index = 0;
+            while running.load(Ordering::Relaxed) {
+                alvr_server_core::send_video(
+                    VideoFrameHeaderPacket {
+                        packet_counter: frame_index as _,
+                        tracking_frame_index: 0,
+                        video_frame_index: frame_index,
+                        sent_time: 0,
+                        frame_byte_size: VIDEO_FRAME_SIZE as _,
+                        fec_index: 0,
+                        fec_percentage: 0,
+                    },
+                    vec![0; VIDEO_FRAME_SIZE],
+                );
+
+                if frame_index % HAPTICS_FRAMES_INTERVAL == 0 {
+                    alvr_server_core::send_haptics(Haptics {
+                        path: *LEFT_HAND_ID,
+                        duration: Duration::from_millis(10),
+                        frequency: 100.,
+                        amplitude: 1.,
+                    });
+                }
+
+                frame_index += 1;
+                thread::sleep(FRAME_INTERVAL);
+            }
+        });
+    }
+
+    fn deinitialize_streaming(&self) {
+        if let Some(running) = self.encoder_running.lock().take() {
+            running.store(false, Ordering::Relaxed);
+        }
+    }
+
+    fn restart_streaming(&self) {}
+
+    fn apply_settings(&self) {
+        self.counters.lock().settings_applications += 1;
+    }
+
+    fn request_idr(&self) {
+        self.counters.lock().idr_requests += 1;
+    }
+
+    fn set_tracking(
+        &self,
+        _: Duration,
+        _: f32,
+        _: &[(u64, DeviceMotion)],
+        _: Option<[Quat; 19]>,
+        _: Option<[Quat; 19]>,
+    ) {
+        self.counters.lock().tracking_updates += 1;
+    }
+
+    fn report_network_latency(&self, _: Duration) {
+        self.counters.lock().network_latency_reports += 1;
+    }
+
+    fn set_bitrate(&self, _: u64) {}
+
+    fn game_frame_interval(&self) -> Duration {
+        FRAME_INTERVAL
+    }
+
+    fn report_video_error(&self) {}
+
+    fn set_openvr_property(&self, _: u64, _: OpenvrPropertyKey, _: OpenvrPropValue) {}
+
+    fn set_chaperone(&self, _: Vec2) {}
+
+    fn set_views_config(&self, _: ViewsConfig) {}
+
+    fn set_battery(&self, _: u64, _: f32, _: bool) {}
+
+    fn set_button(&self, _: u64, _: ButtonValue) {}
+
+    fn restart(&self) {
+        self.restart_notifier.notify_one();
+    }
+}
+
+struct Server {
+    driver: Arc<FakeDriver>,
+    control_port: u16,
+    task: JoinHandle<()>,
+}
+
+impl Server {
+    // The session replaces the one of the previous scenario, with its client list
+    fn start(session: SessionDesc) -> Self {
+        SESSION_PATH.get_or_init(|| {
+            env::temp_dir()
+                .join("alvr_fake_client_loopback")
+                .join("session.json")
+        });
+        *SERVER_DATA_MANAGER.lock().session_mut() = session;
+        PAIRING_PINS.lock().clear();
+
+        let driver = Arc::new(FakeDriver::default());
+        let control_port = common::free_port();
+
+        let task = tokio::spawn({
+            let driver = Arc::clone(&driver);
+            async move {
+                loop {
+                    tokio::select! {
+                        _ = alvr_server_core::connection_lifecycle_loop(
+                            Arc::clone(&driver) as _,
+                            control_port,
+                        ) => (),
+                        // Like SteamVR, the server starts over with the stored session
+                        _ = driver.restart_notifier.notified() => (),
+                    }
+                }
+            }
+        });
+
+        Self {
+            driver,
+            control_port,
+            task,
+        }
+    }
+
+    fn client(&self) -> FakeClient {
+        FakeClient::new(common::client_config(self.control_port))
+    }
+
+    fn pairing_secret(&self) -> Option<Vec<u8>> {
+        SERVER_DATA_MANAGER
+            .lock()
+            .session()
+            .client_connections
+            .get(CLIENT_HOSTNAME)?
+            .pairing_secret
+            .clone()
+    }
+
+    async fn stop(self) {
+        self.task.abort();
+        self.task.await.ok();
+    }
+}
+
+fn session(protocol: SocketProtocolDefaultVariant, auto_trust_clients: bool) -> SessionDesc {
+    let mut session = SessionDesc::default();
+    let connection = &mut session.session_settings.connection;
+    connection.stream_protocol.variant = protocol;
+    connection.stream_port = common::free_port();
+    connection.client_discovery.content.auto_trust_clients = auto_trust_clients;
+    // The stream port of the server is bound on its loopback address only
+    connection.bind_interface.enabled = true;
+    connection.bind_interface.content.name_or_address = SERVER_IP.to_string();
+
+    // There are no audio devices on CI
+    let audio = &mut session.session_settings.audio;
+    audio.game_audio.enabled = false;
+    audio.microphone.enabled = false;
+
+    session
+}
+
+// The first connection of a client changes the OpenVR config, which restarts the server
+async fn connect(client: &mut FakeClient) -> StrResult<ConnectionOutcome> {
+    let connect_loop = async {
+        loop {
+            match client.connect().await {
+                Ok(ConnectionOutcome::ServerRestarting) => (),
+                outcome => return outcome,
+            }
+        }
+    };
+
+    time::timeout(CONNECT_TIMEOUT, connect_loop)
+        .await
+        .map_err(err!())?
+}
+
+fn assert_streamed(outcome: StrResult<ConnectionOutcome>, driver: &FakeDriver) -> StreamReport {
+    let report = match outcome {
+        Ok(ConnectionOutcome::Streamed(report)) => report,
+        other => panic!("Unexpected outcome: {other:?}"),
+    };
+
+    assert!(report.video_frames_received > 0);
+    assert!(report.haptics_received > 0);
+    assert!(report.tracking_packets_sent > 0);
+
+    let counters = driver.counters();
+    assert!(counters.tracking_updates > 0);
+    assert!(counters.network_latency_reports > 0);
+
+    report
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn untrusted_client_is_rejected() {
+    let _lock = SERVER_LOCK.lock().await;
+
+    let server = Server::start(session(SocketProtocolDefaultVariant::Udp, false));
+    let mut client = server.client();
+
+    let outcome = connect(&mut client).await;
+
+    assert!(matches!(
+        outcome,
+        Ok(ConnectionOutcome::ServerMessage(
+            ServerHandshakePacket::ClientUntrusted
+        ))
+    ));
+    // The client is listed in the dashboard, to be paired
+    assert!(!SERVER_DATA_MANAGER.lock().session().client_connections[CLIENT_HOSTNAME].trusted);
+
+    server.stop().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn wrong_pin_is_rejected() {
+    let _lock = SERVER_LOCK.lock().await;
+
+    let server = Server::start(session(SocketProtocolDefaultVariant::Udp, false));
+    let mut client = server.client();
+    client.set_pin("111111");
+    PAIRING_PINS
+        .lock()
+        .insert(CLIENT_HOSTNAME.into(), "222222".into());
+
+    let outcome = connect(&mut client).await;
+
+    assert!(outcome.is_err());
+    assert!(client.pairing_secret().is_none());
+    assert!(server.pairing_secret().is_none());
+
+    server.stop().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn pair_stream_and_reconnect() {
+    let _lock = SERVER_LOCK.lock().await;
+
+    let server = Server::start(session(SocketProtocolDefaultVariant::Udp, false));
+    let mut client = server.client();
+
+    // The user enters the PIN shown on the headset in the dashboard
+    let pin = client.generate_pin();
+    PAIRING_PINS.lock().insert(CLIENT_HOSTNAME.into(), pin);
+
+    let outcome = connect(&mut client).await;
+    assert_streamed(outcome, &server.driver);
+
+    let secret = client.pairing_secret().unwrap().to_vec();
+    assert_eq!(server.pairing_secret(), Some(secret));
+
+    // The pairing secret is enough from now on
+    let outcome = connect(&mut client).await;
+    assert_streamed(outcome, &server.driver);
+
+    server.stop().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn paired_client_rejects_auto_trust() {
+    let _lock = SERVER_LOCK.lock().await;
+
+    let server = Server::start(session(SocketProtocolDefaultVariant::Udp, false));
+    let mut client = server.client();
+
+    let pin = client.generate_pin();
+    PAIRING_PINS.lock().insert(CLIENT_HOSTNAME.into(), pin);
+    let outcome = connect(&mut client).await;
+    assert_streamed(outcome, &server.driver);
+    server.stop().await;
+
+    // This server does not know the pairing secret
+    let server = Server::start(session(SocketProtocolDefaultVariant::Udp, true));
+    client.config_mut().control_port = server.control_port;
+    client
+        .config_mut()
+        .server_address
+        .set_port(server.control_port);
+
+    let outcome = connect(&mut client).await;
+
+    assert!(outcome.is_err());
+
+    server.stop().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn resume_session() {
+    let _lock = SERVER_LOCK.lock().await;
+
+    let server = Server::start(session(SocketProtocolDefaultVariant::Udp, true));
+    let mut client = server.client();
+
+    let outcome = connect(&mut client).await;
+    assert!(!assert_streamed(outcome, &server.driver).resumed);
+    assert_eq!(server.driver.counters().idr_requests, 0);
+
+    // Reconnecting within the grace period skips the client config, the encoder keeps running
+    let outcome = connect(&mut client).await;
+    assert!(assert_streamed(outcome, &server.driver).resumed);
+    let counters = server.driver.counters();
+    assert_eq!(counters.streaming_initializations, 1);
+    // The decoder of the client starts over
+    assert_eq!(counters.idr_requests, 1);
+
+    server.stop().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn hot_settings_without_session_resume() {
+    let _lock = SERVER_LOCK.lock().await;
+
+    let mut session = session(SocketProtocolDefaultVariant::Udp, true);
+    session.session_settings.connection.session_resume.enabled = false;
+    let server = Server::start(session);
+    let mut client = server.client();
+
+    let change_bitrate = async {
+        while server.driver.counters().streaming_initializations == 0 {
+            time::sleep(Duration::from_millis(100)).await;
+        }
+
+        SERVER_DATA_MANAGER
+            .lock()
+            .session_mut()
+            .session_settings
+            .video
+            .encode_bitrate_mbs += 10;
+        alvr_server_core::apply_settings_change(&*server.driver);
+    };
+
+    let (outcome, _) = tokio::join!(connect(&mut client), change_bitrate);
+    assert_streamed(outcome, &server.driver);
+
+    // The bitrate is applied to the running stream
+    assert_eq!(server.driver.counters().settings_applications, 1);
+
+    server.stop().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn stream_over_tcp() {
+    let _lock = SERVER_LOCK.lock().await;
+
+    let server = Server::start(session(SocketProtocolDefaultVariant::Tcp, true));
+    let mut client = server.client();
+
+    let outcome = connect(&mut client).await;
+    let report = assert_streamed(outcome, &server.driver);
+
+    // TCP does not lose packets
+    assert_eq!(report.video_packets_lost, 0);
+    assert_eq!(report.nacks_sent, 0);
+
+    let received = &report.stream_statistics[&VIDEO];
+    assert!(received.packets_received > 0);
+    assert_eq!(received.packets_lost, 0);
+    assert_eq!(received.shards_reordered, 0);
+    assert_eq!(received.shards_duplicated, 0);
+    assert!(report.stream_statistics[&TRACKING].packets_sent > 0);
+
+    server.stop().await;
+}
+
+fn lossy_session() -> SessionDesc {
+    let mut session = session(SocketProtocolDefaultVariant::Udp, true);
+    // Otherwise most losses are recovered with FEC
+    session.session_settings.connection.enable_fec = false;
+    let impairment = &mut session.session_settings.extra.debug.network_impairment;
+    impairment.enabled = true;
+    impairment.content.loss_probability = 0.05;
+    impairment.content.random_seed.enabled = true;
+    impairment.content.random_seed.content = 1;
+
+    session
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn stream_with_packet_loss() {
+    let _lock = SERVER_LOCK.lock().await;
+
+    let server = Server::start(lossy_session());
+    let mut client = server.client();
+
+    let outcome = connect(&mut client).await;
+    let report = assert_streamed(outcome, &server.driver);
+
+    // Lost video shards are requested again
+    assert!(report.nacks_sent > 0);
+
+    server.stop().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn one_way_latency() {
+    let _lock = SERVER_LOCK.lock().await;
+
+    let mut session = session(SocketProtocolDefaultVariant::Udp, true);
+    let impairment = &mut session.session_settings.extra.debug.network_impairment;
+    impairment.enabled = true;
+    impairment.content.latency_ms = 30;
+    impairment.content.random_seed.enabled = true;
+
+    let server = Server::start(session);
+    let mut client = server.client();
+
+    // The packets are delayed, not lost
+    let outcome = connect(&mut client).await;
+    let report = assert_streamed(outcome, &server.driver);
+    assert_eq!(report.stream_statistics[&VIDEO].packets_lost, 0);
+
+    server.stop().await;
+}
+
+fn ipv6_session() -> SessionDesc {
+    // Both ends are on ::1. Over UDP they would bind the same stream port, over TCP only the client
+    // listens on it
+    let mut session = session(SocketProtocolDefaultVariant::Tcp, true);
+    session
+        .session_settings
+        .connection
+        .bind_interface
+        .content
+        .name_or_address = Ipv6Addr::LOCALHOST.to_string();
+
+    session
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn stream_over_ipv6_with_bound_interface() {
+    let _lock = SERVER_LOCK.lock().await;
+
+    let server = Server::start(ipv6_session());
+    let mut client = server.client();
+    client.config_mut().server_address = (Ipv6Addr::LOCALHOST, server.control_port).into();
+    client.config_mut().local_ip = Some(Ipv6Addr::LOCALHOST.into());
+
+    let outcome = connect(&mut client).await;
+    assert_streamed(outcome, &server.driver);
+
+    server.stop().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn clients_outside_of_bound_interface_are_ignored() {
+    let _lock = SERVER_LOCK.lock().await;
+
+    let server = Server::start(ipv6_session());
+    // The client announces itself over IPv4
+    let mut client = server.client();
+
+    let res = time::timeout(Duration::from_secs(3), client.connect()).await;
+    assert!(res.is_err());
+    assert!(SERVER_DATA_MANAGER
+        .lock()
+        .session()
+        .client_connections
+        .is_empty());
+
+    server.stop().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn incompatible_protocol_versions() {
+    let _lock = SERVER_LOCK.lock().await;
+
+    let server = Server::start(session(SocketProtocolDefaultVariant::Udp, true));
+    let mut client = server.client();
+    client.config_mut().capabilities = Capabilities {
+        min_protocol_version: PROTOCOL_VERSION + 1,
+        max_protocol_version: PROTOCOL_VERSION + 2,
+        features: vec![],
+    };
+
+    let outcome = connect(&mut client).await;
+
+    assert!(matches!(outcome, Ok(ConnectionOutcome::IncompatibleServer)));
+    assert_eq!(server.driver.counters().streaming_initializations, 0);
+
+    server.stop().await;
+}
+
+fn oldest_protocol_client(server: &Server) -> FakeClient {
+    let mut client = server.client();
+    let capabilities = &mut client.config_mut().capabilities;
+    capabilities.min_protocol_version = MIN_PROTOCOL_VERSION;
+    capabilities.max_protocol_version = MIN_PROTOCOL_VERSION;
+
+    client
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn oldest_protocol_version() {
+    let _lock = SERVER_LOCK.lock().await;
+
+    let server = Server::start(lossy_session());
+    let mut client = oldest_protocol_client(&server);
+
+    let outcome = connect(&mut client).await;
+    let report = assert_streamed(outcome, &server.driver);
+
+    // The lost packets cannot be requested with this version
+    assert_eq!(report.nacks_sent, 0);
+    assert!(report.video_packets_lost > 0);
+
+    server.stop().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn oldest_protocol_version_is_not_paired_with_pin() {
+    let _lock = SERVER_LOCK.lock().await;
+
+    let server = Server::start(session(SocketProtocolDefaultVariant::Udp, false));
+    let mut client = oldest_protocol_client(&server);
+    let pin = client.generate_pin();
+    PAIRING_PINS.lock().insert(CLIENT_HOSTNAME.into(), pin);
+
+    let outcome = connect(&mut client).await;
+
+    assert!(matches!(outcome, Ok(ConnectionOutcome::Unpaired)));
+    assert!(client.pairing_secret().is_none());
+    assert!(server.pairing_secret().is_none());
+
+    server.stop().await;
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn client_without_retransmission() {
+    let _lock = SERVER_LOCK.lock().await;
+
+    let server = Server::start(lossy_session());
+    let mut client = server.client();
+    client
+        .config_mut()
+        .capabilities
+        .features
+        .retain(|feature| feature != FEATURE_NACK);
+
+    let outcome = connect(&mut client).await;
+    let report = assert_streamed(outcome, &server.driver);
+
+    // The lost packets are skipped instead
+    assert_eq!(report.nacks_sent, 0);
+    assert!(report.video_packets_lost > 0);
+    assert_eq!(
+        report.stream_statistics[&VIDEO].packets_lost,
+        report.video_packets_lost as u64
+    );
+
+    server.stop().await;
+}
@@ -0,0 +1,45 @@
+// The server of a wired connection connects to the client through the port forwarded by adb. The
+// client keeps listening for it even if its announcements cannot be sent
+
+mod common;
+
+use alvr_common::prelude::*;
+use alvr_fake_client::{ConnectionOutcome, FakeClient};
+use alvr_sockets::{
+    Capabilities, InterfaceBinding, PeerType, ProtoControlSocket, ServerAuthPacket,
+};
+use std::{net::Ipv4Addr, time::Duration};
+use tokio::time;
+
+const CONTROL_CONNECT_RETRY_PAUSE: Duration = Duration::from_millis(500);
+
+// Connects like the server through a forwarded port, then asks the client to pair
+async fn forwarded_server(control_port: u16) -> StrResult {
+    let (mut proto_socket, _) = loop {
+        let peer = PeerType::AnyClient(vec![common::CLIENT_IP.into()], InterfaceBinding::any());
+        if let Ok(pair) = ProtoControlSocket::connect_to(peer, control_port).await {
+            break pair;
+        }
+
+        time::sleep(CONTROL_CONNECT_RETRY_PAUSE).await;
+    };
+    proto_socket
+        .exchange_capabilities(&Capabilities::local())
+        .await?;
+
+    proto_socket.send(&ServerAuthPacket::Unpaired).await
+}
+
+#[tokio::test]
+async fn server_connects_without_announcement() {
+    let control_port = common::free_port();
+    let mut config = common::client_config(control_port);
+    // The client is bound to a loopback address, that has no route to this one
+    config.server_address = (Ipv4Addr::new(192, 0, 2, 1), control_port).into();
+    let mut client = FakeClient::new(config);
+
+    let (outcome, server_res) = tokio::join!(client.connect(), forwarded_server(control_port));
+
+    server_res.unwrap();
+    assert!(matches!(outcome, Ok(ConnectionOutcome::Unpaired)));
+}
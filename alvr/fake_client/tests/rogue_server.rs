@@ -0,0 +1,63 @@
+// A server that does not know the PIN shown on the client must not be able to pair, nor to learn the
+// PIN from the answer of the client
+
+mod common;
+
+use alvr_common::prelude::*;
+use alvr_fake_client::FakeClient;
+use alvr_sockets::{
+    AuthRole, Capabilities, ClientAuthPacket, DiscoverySocket, InterfaceBinding, PeerType,
+    ProtoControlSocket, ServerAuthPacket, MAX_HANDSHAKE_PACKET_SIZE_BYTES,
+};
+use std::time::Duration;
+use tokio::time;
+
+const CONTROL_CONNECT_RETRY_PAUSE: Duration = Duration::from_millis(500);
+
+// Follows the pairing protocol with a guessed PIN and claims that the client is paired. Returns
+// true if the proof of the client matches the guess, which is the only guess it can test
+async fn rogue_server(control_port: u16, guessed_pin: &str) -> StrResult<bool> {
+    let socket = DiscoverySocket::bind(control_port)?;
+    let mut buffer = [0; MAX_HANDSHAKE_PACKET_SIZE_BYTES];
+    let (_, client_address) = socket.recv_from(&mut buffer).await.map_err(err!())?;
+
+    let (mut proto_socket, _) = loop {
+        let peer = PeerType::AnyClient(vec![client_address.ip()], InterfaceBinding::any());
+        if let Ok(pair) = ProtoControlSocket::connect_to(peer, control_port).await {
+            break pair;
+        }
+
+        time::sleep(CONTROL_CONNECT_RETRY_PAUSE).await;
+    };
+    proto_socket
+        .exchange_capabilities(&Capabilities::local())
+        .await?;
+
+    proto_socket.send(&ServerAuthPacket::RequestPin).await?;
+    let pin_key = proto_socket
+        .exchange_pin(guessed_pin, AuthRole::Server)
+        .await?;
+    let ClientAuthPacket { proof } = proto_socket.recv().await?;
+    let guess_is_right = proto_socket.verify_auth_proof(&pin_key, AuthRole::Client, &proof)?;
+
+    let server_proof = proto_socket.auth_proof(&pin_key, AuthRole::Server)?;
+    proto_socket
+        .send(&ServerAuthPacket::Paired { server_proof })
+        .await?;
+
+    Ok(guess_is_right)
+}
+
+#[tokio::test]
+async fn rogue_server_cannot_pair() {
+    let control_port = common::free_port();
+    let mut client = FakeClient::new(common::client_config(control_port));
+    client.set_pin("482916");
+
+    let (outcome, guess_is_right) =
+        tokio::join!(client.connect(), rogue_server(control_port, "000000"));
+
+    assert!(outcome.is_err());
+    assert!(!guess_is_right.unwrap());
+    assert!(client.pairing_secret().is_none());
+}
@@ -1,8 +1,10 @@
 use alvr_common::prelude::*;
 use alvr_filesystem as afs;
+use alvr_session::{SessionDesc, SettingsFileFormat};
 use serde_json as json;
 use std::{
     env, fs,
+    path::{Path, PathBuf},
     process::Command,
     thread,
     time::{Duration, Instant},
@@ -224,3 +226,52 @@ pub fn invoke_installer() {
     )
     .ok();
 }
+
+fn session_path() -> PathBuf {
+    afs::filesystem_layout_from_launcher_exe(&env::current_exe().unwrap()).session()
+}
+
+// Sessions stored by older versions are upgraded
+fn load_session() -> StrResult<SessionDesc> {
+    let mut session_desc = SessionDesc::default();
+    if let Ok(session_string) = fs::read_to_string(session_path()) {
+        session_desc.merge_from_json(&json::from_str(&session_string).map_err(err!())?)?;
+    }
+
+    Ok(session_desc)
+}
+
+// Writes the settings that differ from the defaults. The format is chosen with the file extension
+pub fn export_settings(path: &Path) -> StrResult {
+    let diff = load_session()?.settings_diff();
+    let text = alvr_session::settings_diff_to_string(&diff, SettingsFileFormat::from_path(path))?;
+
+    fs::write(path, text).map_err(err!())
+}
+
+// Merges a file created by export_settings into the session
+pub fn import_settings(path: &Path) -> StrResult {
+    // The server would overwrite the session
+    if is_steamvr_running() {
+        return fmt_e!("Close SteamVR before importing the settings");
+    }
+
+    let diff = alvr_session::settings_diff_from_str(&fs::read_to_string(path).map_err(err!())?)?;
+
+    let mut session_desc = load_session()?;
+    session_desc
+        .merge_settings_diff(&diff)
+        .map_err(|diagnostics| {
+            diagnostics
+                .iter()
+                .map(|diagnostic| diagnostic.to_string())
+                .collect::<Vec<_>>()
+                .join("\n")
+        })?;
+
+    fs::write(
+        session_path(),
+        json::to_string_pretty(&session_desc).map_err(err!())?,
+    )
+    .map_err(err!())
+}
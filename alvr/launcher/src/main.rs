@@ -11,7 +11,7 @@ use druid::{
     AppDelegate, AppLauncher, Color, Command, Data, DelegateCtx, Env, ExtEventSink, FontDescriptor,
     Handled, Screen, Selector, Target, Widget, WindowDesc, WindowId,
 };
-use std::{env, thread, time::Duration};
+use std::{env, path::PathBuf, thread, time::Duration};
 
 const WINDOW_WIDTH: f64 = 500.0;
 const WINDOW_HEIGHT: f64 = 300.0;
@@ -245,11 +245,27 @@ fn make_window() -> StrResult {
     Ok(())
 }
 
+fn settings_file_path(args: &[String]) -> StrResult<PathBuf> {
+    args.get(2)
+        .map(PathBuf::from)
+        .ok_or_else(|| "Missing the path of the settings file (.json or .toml)".into())
+}
+
 fn main() {
     let args = env::args().collect::<Vec<_>>();
     match args.get(1) {
         Some(flag) if flag == "--restart-steamvr" => commands::restart_steamvr(),
         Some(flag) if flag == "--update" => commands::invoke_installer(),
+        Some(flag) if flag == "--export-settings" => {
+            alvr_common::show_err_blocking(
+                settings_file_path(&args).and_then(|path| commands::export_settings(&path)),
+            );
+        }
+        Some(flag) if flag == "--import-settings" => {
+            alvr_common::show_err_blocking(
+                settings_file_path(&args).and_then(|path| commands::import_settings(&path)),
+            );
+        }
         Some(_) | None => {
             alvr_common::show_err_blocking(make_window());
         }
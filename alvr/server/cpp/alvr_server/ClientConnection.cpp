@@ -129,6 +129,10 @@ void ClientConnection::ReportNetworkLatency(uint64_t latencyUs) {
 	m_Statistics->NetworkSend(latencyUs);
 }
 
+void ClientConnection::SetBitrate(uint64_t bitrateMbps) {
+	m_Statistics->SetBitrate(bitrateMbps);
+}
+
 void ClientConnection::OnFecFailure() {
 	Debug("Listener::OnFecFailure()\n");
 	if (GetTimestampUs() - m_lastFecFailure < CONTINUOUS_FEC_FAILURE) {
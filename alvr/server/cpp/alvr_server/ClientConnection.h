@@ -20,6 +20,7 @@ public:
 	void FECSend(uint8_t *buf, int len, uint64_t targetTimestampNs, uint64_t videoFrameIndex);
 	void SendVideo(uint8_t *buf, int len, uint64_t targetTimestampNs);
  	void ReportNetworkLatency(uint64_t latencyUs);
+	void SetBitrate(uint64_t bitrateMbps);
 	void OnFecFailure();
 	std::shared_ptr<Statistics> GetStatistics();
 
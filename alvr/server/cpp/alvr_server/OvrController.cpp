@@ -10,13 +10,26 @@
 
 OvrController::OvrController(uint64_t devicePath)
     : TrackedDevice(devicePath) {
-    double rightHandSignFlip = devicePath == LEFT_HAND_PATH ? 1. : -1.;
-
     memset(&m_pose, 0, sizeof(m_pose));
     m_pose.poseIsValid = true;
     m_pose.result = vr::TrackingResult_Running_OK;
     m_pose.deviceIsConnected = true;
 
+    UpdateOffsets();
+
+    m_pose.qWorldFromDriverRotation = HmdQuaternion_Init(1, 0, 0, 0);
+
+    m_pose.qRotation = HmdQuaternion_Init(1, 0, 0, 0);
+
+    // init handles
+    for (int i = 0; i < ALVR_INPUT_COUNT; i++) {
+        m_handles[i] = vr::k_ulInvalidInputComponentHandle;
+    }
+}
+
+void OvrController::UpdateOffsets() {
+    double rightHandSignFlip = this->device_path == LEFT_HAND_PATH ? 1. : -1.;
+
     // controller is rotated and translated, prepare pose
     double rotation[3] = {
         Settings::Instance().m_leftControllerRotationOffset[1] * DEG_TO_RAD * rightHandSignFlip,
@@ -36,15 +49,6 @@ OvrController::OvrController(uint64_t devicePath)
     m_pose.vecDriverFromHeadTranslation[0] = offetRes.v[0];
     m_pose.vecDriverFromHeadTranslation[1] = offetRes.v[1];
     m_pose.vecDriverFromHeadTranslation[2] = offetRes.v[2];
-
-    m_pose.qWorldFromDriverRotation = HmdQuaternion_Init(1, 0, 0, 0);
-
-    m_pose.qRotation = HmdQuaternion_Init(1, 0, 0, 0);
-
-    // init handles
-    for (int i = 0; i < ALVR_INPUT_COUNT; i++) {
-        m_handles[i] = vr::k_ulInvalidInputComponentHandle;
-    }
 }
 
 bool OvrController::GetHand() { return this->device_path == LEFT_HAND_PATH; }
@@ -13,6 +13,9 @@ class OvrController : public TrackedDevice, public vr::ITrackedDeviceServerDrive
 
     bool GetHand();
 
+    // Reads the controller position and rotation offsets from Settings
+    void UpdateOffsets();
+
     //
     // ITrackedDeviceServerDriver
     //
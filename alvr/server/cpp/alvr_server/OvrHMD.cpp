@@ -378,6 +378,29 @@ void OvrHmd::StartStreaming() {
     m_streamComponentsInitialized = true;
 }
 
+// The encoder and the listener are created again by the next StartStreaming(), with the current
+// settings
+void OvrHmd::StopStreaming() {
+    if (!m_streamComponentsInitialized) {
+        return;
+    }
+
+#ifdef _WIN32
+    // stop submitting frames to the encoder before destroying it
+    m_directModeComponent->SetEncoder(nullptr);
+#endif
+
+    if (m_encoder) {
+        Debug("OvrHmd::StopStreaming(): Stopping encoder...\n");
+        m_encoder->Stop();
+        m_encoder.reset();
+    }
+
+    m_Listener.reset();
+
+    m_streamComponentsInitialized = false;
+}
+
 void OvrHmd::SetViewsConfig(ViewsConfigData config) {
     this->views_config = config;
 
@@ -42,6 +42,8 @@ class OvrHmd : public TrackedDevice,
 
     void StartStreaming();
 
+    void StopStreaming();
+
     void OnStreamStart();
 
     void SetViewsConfig(ViewsConfigData config);
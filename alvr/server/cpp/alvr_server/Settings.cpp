@@ -13,7 +13,9 @@ using namespace std;
 
 extern uint64_t g_DriverTestMode;
 
-Settings Settings::m_Instance;
+std::atomic<Settings *> Settings::m_Instance{new Settings()};
+std::vector<std::unique_ptr<Settings>> Settings::m_retired;
+std::mutex Settings::m_loadMutex;
 
 Settings::Settings()
 	: m_loaded(false), m_EnableOffsetPos(false)
@@ -28,6 +30,20 @@ Settings::~Settings()
 }
 
 void Settings::Load()
+{
+	std::lock_guard<std::mutex> lock(m_loadMutex);
+
+	// Starts from the current settings to keep the fields that are set at runtime
+	auto loaded = std::unique_ptr<Settings>(new Settings(*m_Instance.load()));
+	if (!loaded->Parse())
+	{
+		return;
+	}
+
+	m_retired.push_back(std::unique_ptr<Settings>(m_Instance.exchange(loaded.release())));
+}
+
+bool Settings::Parse()
 {
 	try
 	{
@@ -42,7 +58,7 @@ void Settings::Load()
 		if (!err.empty())
 		{
 			Error("Error on parsing json: %hs\n", err.c_str());
-			return;
+			return false;
 		}
 
 		auto config = v.get("openvr_config");
@@ -169,9 +185,13 @@ void Settings::Load()
 		Info("Seconds from Vsync to Photons: %f\n", m_flSecondsFromVsyncToPhotons);
 		Info("Refresh Rate: %d\n", m_refreshRate);
 		m_loaded = true;
+
+		return true;
 	}
 	catch (std::exception &e)
 	{
 		Error("Exception on parsing json: %hs\n", e.what());
+
+		return false;
 	}
 }
@@ -1,20 +1,31 @@
 #pragma once
 
+#include <atomic>
+#include <memory>
+#include <mutex>
 #include <string>
+#include <vector>
 #include "ALVR-common/packet_types.h"
 
 class Settings
 {
-	static Settings m_Instance;
+	// Other threads keep reading the snapshot they got from Instance() while Load() publishes a
+	// new one, so the replaced snapshots are kept alive
+	static std::atomic<Settings *> m_Instance;
+	static std::vector<std::unique_ptr<Settings>> m_retired;
+	static std::mutex m_loadMutex;
 	bool m_loaded;
 
 	Settings();
-	virtual ~Settings();
+	Settings(const Settings &) = default;
+	bool Parse();
 
 public:
+	virtual ~Settings();
+
 	void Load();
 	static Settings &Instance() {
-		return m_Instance;
+		return *m_Instance.load();
 	}
 
 	bool IsLoaded() {
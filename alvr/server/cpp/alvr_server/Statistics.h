@@ -55,10 +55,16 @@ public:
 		return m_bitrate;
 	}
 
+	// The bitrate is decided by a bitrate controller on the Rust side, the latency based logic is disabled
+	void SetBitrate(uint64_t bitrateMbps) {
+		m_bitrate = bitrateMbps;
+		m_bitrateControlledExternally = true;
+	}
+
 	bool CheckBitrateUpdated() {
 		if (m_enableAdaptiveBitrate) {
 			uint64_t latencyUs = m_sendLatency; // using video stream transport latency
-			if (latencyUs != 0) { // check valid latency
+			if (latencyUs != 0 && !m_bitrateControlledExternally) { // check valid latency
 				if (latencyUs > m_adaptiveBitrateTarget + m_adaptiveBitrateThreshold) {
 					if (m_bitrate <= 5 + m_adaptiveBitrateDownRate)
 						m_bitrate = 5; // minimum bitrate 5mbps
@@ -124,6 +130,7 @@ public:
 	// mbit/s
 	uint64_t m_bitrate = Settings::Instance().mEncodeBitrateMBs;
 	uint64_t m_bitrateUpdated = Settings::Instance().mEncodeBitrateMBs;
+	bool m_bitrateControlledExternally = false;
 
 	int64_t m_refreshRate = Settings::Instance().m_refreshRate;
 
@@ -205,6 +205,31 @@ void DeinitializeStreaming() {
     // nothing to do
 }
 
+void RestartStreaming() {
+    if (g_driver_provider.hmd) {
+        g_driver_provider.hmd->StopStreaming();
+    }
+}
+
+void ApplySettings() {
+    Settings::Instance().Load();
+
+    if (g_driver_provider.left_controller && g_driver_provider.right_controller) {
+        g_driver_provider.left_controller->UpdateOffsets();
+        g_driver_provider.right_controller->UpdateOffsets();
+    }
+
+    if (g_driver_provider.hmd && g_driver_provider.hmd->m_Listener) {
+        g_driver_provider.hmd->m_Listener->SetBitrate(Settings::Instance().mEncodeBitrateMBs);
+    }
+
+#ifdef _WIN32
+    if (g_driver_provider.hmd && g_driver_provider.hmd->m_encoder) {
+        g_driver_provider.hmd->m_encoder->UpdateColorCorrection();
+    }
+#endif
+}
+
 void RequestIDR() {
     if (g_driver_provider.hmd && g_driver_provider.hmd->m_encoder) {
         g_driver_provider.hmd->m_encoder->InsertIDR();
@@ -239,6 +264,12 @@ void ReportNetworkLatency(unsigned long long latencyUs) {
     }
 }
 
+void SetBitrate(unsigned long long bitrateMbps) {
+    if (g_driver_provider.hmd && g_driver_provider.hmd->m_Listener) {
+        g_driver_provider.hmd->m_Listener->SetBitrate(bitrateMbps);
+    }
+}
+
 unsigned long long GetGameFrameIntervalNs() {
     vr::Compositor_FrameTiming timings[2];
     timings[0].m_nSize = sizeof(vr::Compositor_FrameTiming);
@@ -126,6 +126,10 @@ extern "C" void (*ReportFecFailure)(int percentage);
 extern "C" void *CppEntryPoint(const char *pInterfaceName, int *pReturnCode);
 extern "C" void InitializeStreaming();
 extern "C" void DeinitializeStreaming();
+// Releases the encoder, so that InitializeStreaming() creates it again with the new settings
+extern "C" void RestartStreaming();
+// Reloads the settings and pushes the ones that can change while streaming
+extern "C" void ApplySettings();
 extern "C" void RequestIDR();
 extern "C" void SetTracking(unsigned long long targetTimestampNs,
                             float controllerPredictionS,
@@ -134,6 +138,7 @@ extern "C" void SetTracking(unsigned long long targetTimestampNs,
                             OculusHand leftHand,
                             OculusHand rightHand);
 extern "C" void ReportNetworkLatency(unsigned long long latencyUs);
+extern "C" void SetBitrate(unsigned long long bitrateMbps);
 extern "C" unsigned long long GetGameFrameIntervalNs();
 extern "C" void VideoErrorReportReceive();
 extern "C" void ShutdownSteamvr();
@@ -123,4 +123,10 @@
 
 		void CEncoder::InsertIDR() {
 			m_scheduler.InsertIDR();
+		}
+
+		void CEncoder::UpdateColorCorrection() {
+			if (m_FrameRender) {
+				m_FrameRender->UpdateColorCorrection();
+			}
 		}
\ No newline at end of file
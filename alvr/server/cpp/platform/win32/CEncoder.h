@@ -53,6 +53,8 @@
 
 		void InsertIDR();
 
+		void UpdateColorCorrection();
+
 	private:
 		CThreadEvent m_newFrameReady, m_encodeFinished;
 		std::shared_ptr<VideoEncoder> m_videoEncoder;
@@ -281,25 +281,13 @@ bool FrameRender::Startup()
 			Settings::Instance().m_renderWidth, Settings::Instance().m_renderHeight,
 			DXGI_FORMAT_R8G8B8A8_UNORM_SRGB);
 
-		struct ColorCorrection {
-			float renderWidth;
-			float renderHeight;
-			float brightness;
-			float contrast;
-			float saturation;
-			float gamma;
-			float sharpening;
-			float _align;
-		};
-		ColorCorrection colorCorrectionStruct = { (float)Settings::Instance().m_renderWidth, (float)Settings::Instance().m_renderHeight,
-												  Settings::Instance().m_brightness, Settings::Instance().m_contrast + 1.f,
-												  Settings::Instance().m_saturation + 1.f, Settings::Instance().m_gamma,
-												  Settings::Instance().m_sharpening };
-		ComPtr<ID3D11Buffer> colorCorrectionBuffer = CreateBuffer(m_pD3DRender->GetDevice(), colorCorrectionStruct);
+		ColorCorrection colorCorrectionStruct = GetColorCorrection();
+		// Not immutable, to be updated when the settings change
+		m_colorCorrectionBuffer = CreateBuffer(m_pD3DRender->GetDevice(), colorCorrectionStruct, D3D11_USAGE_DEFAULT);
 
 		m_colorCorrectionPipeline = std::make_unique<RenderPipeline>(m_pD3DRender->GetDevice());
 		m_colorCorrectionPipeline->Initialize({ m_pStagingTexture.Get() }, quadVertexShader.Get(), colorCorrectionShaderCSO,
-											  colorCorrectedTexture.Get(), colorCorrectionBuffer.Get());
+											  colorCorrectedTexture.Get(), m_colorCorrectionBuffer.Get());
 
 		m_pStagingTexture = colorCorrectedTexture;
 	}
@@ -320,6 +308,12 @@ bool FrameRender::Startup()
 
 bool FrameRender::RenderFrame(ID3D11Texture2D *pTexture[][2], vr::VRTextureBounds_t bounds[][2], int layerCount, bool recentering, const std::string &message, const std::string& debugText)
 {
+	// The immediate context can be used only from the render thread
+	if (enableColorCorrection && m_colorCorrectionUpdated.exchange(false)) {
+		ColorCorrection colorCorrectionStruct = GetColorCorrection();
+		UpdateBuffer(m_pD3DRender->GetContext(), m_colorCorrectionBuffer.Get(), &colorCorrectionStruct);
+	}
+
 	// Set render target
 	m_pD3DRender->GetContext()->OMSetRenderTargets(1, m_pRenderTargetView.GetAddressOf(), m_pDepthStencilView.Get());
 
@@ -475,6 +469,19 @@ bool FrameRender::RenderFrame(ID3D11Texture2D *pTexture[][2], vr::VRTextureBound
 	return true;
 }
 
+void FrameRender::UpdateColorCorrection()
+{
+	m_colorCorrectionUpdated = true;
+}
+
+FrameRender::ColorCorrection FrameRender::GetColorCorrection()
+{
+	return { (float)Settings::Instance().m_renderWidth, (float)Settings::Instance().m_renderHeight,
+			 Settings::Instance().m_brightness, Settings::Instance().m_contrast + 1.f,
+			 Settings::Instance().m_saturation + 1.f, Settings::Instance().m_gamma,
+			 Settings::Instance().m_sharpening };
+}
+
 ComPtr<ID3D11Texture2D> FrameRender::GetTexture()
 {
 	return m_pStagingTexture;
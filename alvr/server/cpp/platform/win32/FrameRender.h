@@ -2,6 +2,7 @@
 
 #include <string>
 #include <memory>
+#include <atomic>
 #include <stdint.h>
 
 #include <d3d11.h>
@@ -49,6 +50,8 @@ public:
 	bool Startup();
 	bool RenderFrame(ID3D11Texture2D *pTexture[][2], vr::VRTextureBounds_t bounds[][2], int layerCount, bool recentering, const std::string& message, const std::string& debugText);
 	void GetEncodingResolution(uint32_t *width, uint32_t *height);
+	// Reads the color correction values from Settings before rendering the next frame
+	void UpdateColorCorrection();
 
 	ComPtr<ID3D11Texture2D> GetTexture();
 private:
@@ -85,7 +88,21 @@ private:
 	// Parameter for Draw method. 2-triangles for both eyes.
 	static const int VERTEX_INDEX_COUNT = 12;
 
+	struct ColorCorrection {
+		float renderWidth;
+		float renderHeight;
+		float brightness;
+		float contrast;
+		float saturation;
+		float gamma;
+		float sharpening;
+		float _align;
+	};
+	static ColorCorrection GetColorCorrection();
+
 	std::unique_ptr<d3d_render_utils::RenderPipeline> m_colorCorrectionPipeline;
+	ComPtr<ID3D11Buffer> m_colorCorrectionBuffer;
+	std::atomic_bool m_colorCorrectionUpdated{false};
 	bool enableColorCorrection;
 
 	std::unique_ptr<FFR> m_ffr;
@@ -1,5 +1,6 @@
-use crate::{SERVER_DATA_MANAGER, WINDOW};
+use crate::WINDOW;
 use alvr_common::prelude::*;
+use alvr_server_core::SERVER_DATA_MANAGER;
 use std::{fs, sync::Arc};
 
 #[cfg(not(target_os = "macos"))]
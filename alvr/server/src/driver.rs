@@ -0,0 +1,157 @@
+use crate::{
+    AlvrButtonType_BUTTON_TYPE_BINARY, AlvrButtonType_BUTTON_TYPE_SCALAR, AlvrButtonValue,
+    AlvrButtonValue__bindgen_ty_1, AlvrDeviceMotion, AlvrQuat, EyeFov, OculusHand, ViewsConfigData,
+};
+use alvr_common::glam::{Quat, Vec2};
+use alvr_events::ButtonValue;
+use alvr_server_core::ServerDriver;
+use alvr_session::{OpenvrPropValue, OpenvrPropertyKey};
+use alvr_sockets::{DeviceMotion, ViewsConfig};
+use std::time::Duration;
+
+fn to_tracking_quat(quat: Quat) -> AlvrQuat {
+    AlvrQuat {
+        x: quat.x,
+        y: quat.y,
+        z: quat.z,
+        w: quat.w,
+    }
+}
+
+fn to_oculus_hand(skeleton: Option<[Quat; 19]>) -> OculusHand {
+    if let Some(arr) = skeleton {
+        let vec = arr.into_iter().map(to_tracking_quat).collect::<Vec<_>>();
+        let mut array = [AlvrQuat::default(); 19];
+        array.copy_from_slice(&vec);
+
+        OculusHand {
+            enabled: true,
+            boneRotations: array,
+        }
+    } else {
+        OculusHand {
+            enabled: false,
+            ..Default::default()
+        }
+    }
+}
+
+// The server core calls into the C++ driver
+pub struct OpenvrDriver;
+
+impl ServerDriver for OpenvrDriver {
+    fn initialize_streaming(&self) {
+        unsafe { crate::InitializeStreaming() };
+    }
+
+    fn deinitialize_streaming(&self) {
+        unsafe { crate::DeinitializeStreaming() };
+    }
+
+    fn restart_streaming(&self) {
+        unsafe { crate::RestartStreaming() };
+    }
+
+    fn apply_settings(&self) {
+        unsafe { crate::ApplySettings() };
+    }
+
+    fn request_idr(&self) {
+        unsafe { crate::RequestIDR() };
+    }
+
+    fn set_tracking(
+        &self,
+        target_timestamp: Duration,
+        controller_prediction_s: f32,
+        device_motions: &[(u64, DeviceMotion)],
+        left_hand_skeleton: Option<[Quat; 19]>,
+        right_hand_skeleton: Option<[Quat; 19]>,
+    ) {
+        let raw_motions = device_motions
+            .iter()
+            .map(|(id, motion)| AlvrDeviceMotion {
+                deviceID: *id,
+                orientation: to_tracking_quat(motion.orientation),
+                position: motion.position.to_array(),
+                linearVelocity: motion.linear_velocity.to_array(),
+                angularVelocity: motion.angular_velocity.to_array(),
+            })
+            .collect::<Vec<_>>();
+
+        unsafe {
+            crate::SetTracking(
+                target_timestamp.as_nanos() as _,
+                controller_prediction_s,
+                raw_motions.as_ptr(),
+                raw_motions.len() as _,
+                to_oculus_hand(left_hand_skeleton),
+                to_oculus_hand(right_hand_skeleton),
+            )
+        };
+    }
+
+    fn report_network_latency(&self, latency: Duration) {
+        unsafe { crate::ReportNetworkLatency(latency.as_micros() as _) };
+    }
+
+    fn set_bitrate(&self, bitrate_mbps: u64) {
+        unsafe { crate::SetBitrate(bitrate_mbps) };
+    }
+
+    fn game_frame_interval(&self) -> Duration {
+        Duration::from_nanos(unsafe { crate::GetGameFrameIntervalNs() })
+    }
+
+    fn report_video_error(&self) {
+        unsafe { crate::VideoErrorReportReceive() };
+    }
+
+    fn set_openvr_property(&self, device_id: u64, key: OpenvrPropertyKey, value: OpenvrPropValue) {
+        unsafe { crate::SetOpenvrProperty(device_id, crate::to_cpp_openvr_prop(key, value)) };
+    }
+
+    fn set_chaperone(&self, area_size: Vec2) {
+        unsafe { crate::SetChaperone(area_size.x, area_size.y) };
+    }
+
+    fn set_views_config(&self, config: ViewsConfig) {
+        let eye_fov = |index: usize| EyeFov {
+            left: config.fov[index].left,
+            right: config.fov[index].right,
+            top: config.fov[index].top,
+            bottom: config.fov[index].bottom,
+        };
+
+        unsafe {
+            crate::SetViewsConfig(ViewsConfigData {
+                fov: [eye_fov(0), eye_fov(1)],
+                ipd_m: config.ipd_m,
+            })
+        };
+    }
+
+    fn set_battery(&self, device_id: u64, gauge_value: f32, is_plugged: bool) {
+        unsafe { crate::SetBattery(device_id, gauge_value, is_plugged) };
+    }
+
+    fn set_button(&self, path_id: u64, value: ButtonValue) {
+        let value = match value {
+            ButtonValue::Binary(value) => AlvrButtonValue {
+                type_: AlvrButtonType_BUTTON_TYPE_BINARY,
+                __bindgen_anon_1: AlvrButtonValue__bindgen_ty_1 { binary: value },
+            },
+
+            ButtonValue::Scalar(value) => AlvrButtonValue {
+                type_: AlvrButtonType_BUTTON_TYPE_SCALAR,
+                __bindgen_anon_1: AlvrButtonValue__bindgen_ty_1 { scalar: value },
+            },
+        };
+
+        unsafe { crate::SetButton(path_id, value) };
+    }
+
+    fn restart(&self) {
+        crate::notify_restart_driver();
+    }
+}
@@ -1,10 +1,6 @@
-mod buttons;
-mod connection;
-mod connection_utils;
 mod dashboard;
+mod driver;
 mod logging_backend;
-mod statistics;
-mod tracking;
 mod web_server;
 
 #[allow(
@@ -26,10 +22,13 @@ use alvr_common::{
 };
 use alvr_events::EventType;
 use alvr_filesystem::{self as afs, Layout};
-use alvr_server_data::ServerDataManager;
+use alvr_server_core::{
+    CLIENTS_UPDATED_NOTIFIER, RESTART_NOTIFIER, SERVER_DATA_MANAGER, SESSION_PATH,
+    STATISTICS_MANAGER,
+};
 use alvr_session::{OpenvrPropValue, OpenvrPropertyKey};
-use alvr_sockets::{ClientListAction, GpuVendor, Haptics, VideoFrameHeaderPacket};
-use statistics::StatisticsManager;
+use alvr_sockets::{ClientListAction, GpuVendor, Haptics, VideoFrameHeaderPacket, CONTROL_PORT};
+use driver::OpenvrDriver;
 use std::{
     collections::HashMap,
     ffi::{c_void, CStr, CString},
@@ -44,25 +43,15 @@ use std::{
 };
 use tokio::{
     runtime::Runtime,
-    sync::{broadcast, mpsc, Notify},
+    sync::{broadcast, Notify},
 };
 
 static FILESYSTEM_LAYOUT: Lazy<Layout> = Lazy::new(|| {
     afs::filesystem_layout_from_openvr_driver_root_dir(&alvr_commands::get_driver_dir().unwrap())
 });
-static SERVER_DATA_MANAGER: Lazy<Mutex<ServerDataManager>> =
-    Lazy::new(|| Mutex::new(ServerDataManager::new(&FILESYSTEM_LAYOUT.session())));
 static RUNTIME: Lazy<Mutex<Option<Runtime>>> = Lazy::new(|| Mutex::new(Runtime::new().ok()));
 static WINDOW: Lazy<Mutex<Option<Arc<alcro::UI>>>> = Lazy::new(|| Mutex::new(None));
-static STATISTICS_MANAGER: Lazy<Mutex<Option<StatisticsManager>>> = Lazy::new(|| Mutex::new(None));
-
-static VIDEO_SENDER: Lazy<Mutex<Option<mpsc::UnboundedSender<(VideoFrameHeaderPacket, Vec<u8>)>>>> =
-    Lazy::new(|| Mutex::new(None));
-static HAPTICS_SENDER: Lazy<Mutex<Option<mpsc::UnboundedSender<Haptics>>>> =
-    Lazy::new(|| Mutex::new(None));
 
-static CLIENTS_UPDATED_NOTIFIER: Lazy<Notify> = Lazy::new(Notify::new);
-static RESTART_NOTIFIER: Lazy<Notify> = Lazy::new(Notify::new);
 static SHUTDOWN_NOTIFIER: Lazy<Notify> = Lazy::new(Notify::new);
 
 static FRAME_RENDER_VS_CSO: Lazy<Vec<u8>> =
@@ -162,6 +151,8 @@ pub fn notify_application_update() {
 }
 
 fn init() {
+    SESSION_PATH.set(FILESYSTEM_LAYOUT.session()).ok();
+
     let (log_sender, _) = broadcast::channel(web_server::WS_BROADCAST_CAPACITY);
     let (events_sender, _) = broadcast::channel(web_server::WS_BROADCAST_CAPACITY);
     logging_backend::init_logging(log_sender.clone(), events_sender.clone());
@@ -295,43 +286,33 @@ pub unsafe extern "C" fn HmdDriverFactory(
     }
 
     extern "C" fn video_send(header: VideoFrame, buffer_ptr: *mut u8, len: i32) {
-        if let Some(sender) = &*VIDEO_SENDER.lock() {
-            let header = VideoFrameHeaderPacket {
-                packet_counter: header.packetCounter,
-                tracking_frame_index: header.trackingFrameIndex,
-                video_frame_index: header.videoFrameIndex,
-                sent_time: header.sentTime,
-                frame_byte_size: header.frameByteSize,
-                fec_index: header.fecIndex,
-                fec_percentage: header.fecPercentage,
-            };
-
-            let mut vec_buffer = vec![0; len as _];
-
-            // use copy_nonoverlapping (aka memcpy) to avoid freeing memory allocated by C++
-            unsafe {
-                ptr::copy_nonoverlapping(buffer_ptr, vec_buffer.as_mut_ptr(), len as _);
-            }
-
-            sender.send((header, vec_buffer)).ok();
-
-            if let Some(stats) = &mut *STATISTICS_MANAGER.lock() {
-                stats.report_video_packet(len as _);
-            }
+        let header = VideoFrameHeaderPacket {
+            packet_counter: header.packetCounter,
+            tracking_frame_index: header.trackingFrameIndex,
+            video_frame_index: header.videoFrameIndex,
+            sent_time: header.sentTime,
+            frame_byte_size: header.frameByteSize,
+            fec_index: header.fecIndex,
+            fec_percentage: header.fecPercentage,
+        };
+
+        let mut vec_buffer = vec![0; len as _];
+
+        // use copy_nonoverlapping (aka memcpy) to avoid freeing memory allocated by C++
+        unsafe {
+            ptr::copy_nonoverlapping(buffer_ptr, vec_buffer.as_mut_ptr(), len as _);
         }
+
+        alvr_server_core::send_video(header, vec_buffer);
     }
 
     extern "C" fn haptics_send(path: u64, duration_s: f32, frequency: f32, amplitude: f32) {
-        if let Some(sender) = &*HAPTICS_SENDER.lock() {
-            let haptics = Haptics {
-                path,
-                duration: Duration::from_secs_f32(duration_s),
-                frequency,
-                amplitude,
-            };
-
-            sender.send(haptics).ok();
-        }
+        alvr_server_core::send_haptics(Haptics {
+            path,
+            duration: Duration::from_secs_f32(duration_s),
+            frequency,
+            amplitude,
+        });
     }
 
     pub extern "C" fn driver_ready_idle(set_default_chap: bool) {
@@ -347,7 +328,10 @@ pub unsafe extern "C" fn HmdDriverFactory(
                     unsafe { SetChaperone(2.0, 2.0) };
                 }
                 tokio::select! {
-                    _ = connection::connection_lifecycle_loop() => (),
+                    _ = alvr_server_core::connection_lifecycle_loop(
+                        Arc::new(OpenvrDriver),
+                        CONTROL_PORT,
+                    ) => (),
                     _ = SHUTDOWN_NOTIFIER.notified() => (),
                 }
             });
@@ -382,7 +366,7 @@ pub unsafe extern "C" fn HmdDriverFactory(
 
     extern "C" fn report_fec_failure(percentage: i32) {
         if let Some(stats) = &mut *STATISTICS_MANAGER.lock() {
-            stats.report_fec_failure(percentage as u32);
+            stats.report_fec_failures(percentage as u32, 1);
         }
     }
 
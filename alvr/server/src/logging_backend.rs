@@ -1,6 +1,7 @@
-use crate::{FILESYSTEM_LAYOUT, SERVER_DATA_MANAGER};
+use crate::FILESYSTEM_LAYOUT;
 use alvr_common::log::{self, LevelFilter};
 use alvr_events::{EventSeverity, EventType, LogEvent};
+use alvr_server_core::SERVER_DATA_MANAGER;
 use fern::Dispatch;
 use std::fs;
 use tokio::sync::broadcast::Sender;
@@ -1,6 +1,8 @@
-use crate::{CLIENTS_UPDATED_NOTIFIER, FILESYSTEM_LAYOUT, SERVER_DATA_MANAGER};
+use crate::{driver::OpenvrDriver, FILESYSTEM_LAYOUT};
 use alvr_common::{prelude::*, ALVR_VERSION};
 use alvr_events::EventType;
+use alvr_server_core::{CLIENTS_UPDATED_NOTIFIER, PAIRING_PINS, SERVER_DATA_MANAGER};
+use alvr_session::{SessionDesc, SettingsDiagnostic, SettingsFileFormat};
 use alvr_sockets::ClientListAction;
 use bytes::Buf;
 use futures::SinkExt;
@@ -26,7 +28,12 @@ fn reply(code: StatusCode) -> StrResult<Response<Body>> {
 }
 
 fn reply_json<T: Serialize>(obj: &T) -> StrResult<Response<Body>> {
+    reply_json_with_code(StatusCode::OK, obj)
+}
+
+fn reply_json_with_code<T: Serialize>(code: StatusCode, obj: &T) -> StrResult<Response<Body>> {
     Response::builder()
+        .status(code)
         .header(header::CONTENT_TYPE, "application/json")
         .body(json::to_string(obj).map_err(err!())?.into())
         .map_err(err!())
@@ -93,6 +100,29 @@ async fn text_websocket(
     }
 }
 
+// The session is stored only if it respects the settings schema. Otherwise the problems are
+// returned to the dashboard. The stored settings are applied to the running stream if possible
+fn store_session(
+    merge: impl FnOnce(&mut SessionDesc) -> Result<(), Vec<SettingsDiagnostic>>,
+) -> StrResult<Response<Body>> {
+    let res = merge(&mut *SERVER_DATA_MANAGER.lock().session_mut());
+
+    match res {
+        Ok(()) => {
+            alvr_server_core::apply_settings_change(&OpenvrDriver);
+
+            reply(StatusCode::OK)
+        }
+        Err(diagnostics) => {
+            for diagnostic in &diagnostics {
+                warn!("Session not stored: {diagnostic}");
+            }
+
+            reply_json_with_code(StatusCode::UNPROCESSABLE_ENTITY, &diagnostics)
+        }
+    }
+}
+
 async fn http_api(
     request: Request<Body>,
     log_sender: broadcast::Sender<String>,
@@ -105,17 +135,11 @@ async fn http_api(
         "/api/session/load" => reply_json(SERVER_DATA_MANAGER.lock().session())?,
         "/api/session/store-settings" => {
             if let Ok(session_settings) = from_request_body::<json::Value>(request).await {
-                let res = SERVER_DATA_MANAGER
-                    .lock()
-                    .session_mut()
-                    .merge_from_json(&json::json!({ "session_settings": session_settings }));
-                if let Err(e) = res {
-                    warn!("{e}");
-                    // HTTP Code: WARNING
-                    reply(StatusCode::from_u16(199).map_err(err!())?)?
-                } else {
-                    reply(StatusCode::OK)?
-                }
+                store_session(|session| {
+                    session.merge_from_json_validated(
+                        &json::json!({ "sessionSettings": session_settings }),
+                    )
+                })?
             } else {
                 reply(StatusCode::BAD_REQUEST)?
             }
@@ -123,17 +147,7 @@ async fn http_api(
         "/api/session/store" => {
             if let Ok(data) = from_request_body::<json::Value>(request).await {
                 if let Some(value) = data.get("session") {
-                    let res = SERVER_DATA_MANAGER
-                        .lock()
-                        .session_mut()
-                        .merge_from_json(value);
-                    if let Err(e) = res {
-                        warn!("{e}");
-                        // HTTP Code: WARNING
-                        reply(StatusCode::from_u16(199).map_err(err!())?)?
-                    } else {
-                        reply(StatusCode::OK)?
-                    }
+                    store_session(|session| session.merge_from_json_validated(value))?
                 } else {
                     reply(StatusCode::BAD_REQUEST)?
                 }
@@ -141,6 +155,30 @@ async fn http_api(
                 reply(StatusCode::BAD_REQUEST)?
             }
         }
+        "/api/session/settings-diff" => {
+            let diff = SERVER_DATA_MANAGER.lock().session().settings_diff();
+            if request.uri().query() == Some("format=toml") {
+                let text = alvr_session::settings_diff_to_string(&diff, SettingsFileFormat::Toml)?;
+                Response::builder()
+                    .header(header::CONTENT_TYPE, "application/toml")
+                    .body(text.into())
+                    .map_err(err!())?
+            } else {
+                reply_json(&diff)?
+            }
+        }
+        "/api/session/import-settings-diff" => {
+            let body = hyper::body::to_bytes(request.into_body())
+                .await
+                .map_err(err!())?;
+            // JSON or TOML
+            if let Ok(diff) = alvr_session::settings_diff_from_str(&String::from_utf8_lossy(&body))
+            {
+                store_session(|session| session.merge_settings_diff(&diff))?
+            } else {
+                reply(StatusCode::BAD_REQUEST)?
+            }
+        }
         "/api/log" => text_websocket(request, log_sender).await?,
         "/api/events" => text_websocket(request, events_sender).await?,
         "/api/driver/register" => {
@@ -215,6 +253,40 @@ async fn http_api(
                 reply(StatusCode::BAD_REQUEST)?
             }
         }
+        "/api/client/pair" => {
+            if let Ok((hostname, pin)) = from_request_body::<(String, String)>(request).await {
+                PAIRING_PINS.lock().insert(hostname, pin.trim().to_owned());
+                CLIENTS_UPDATED_NOTIFIER.notify_waiters();
+
+                reply(StatusCode::OK)?
+            } else {
+                reply(StatusCode::BAD_REQUEST)?
+            }
+        }
+        "/api/client/spectator" => {
+            if let Ok((hostname, spectator)) = from_request_body(request).await {
+                SERVER_DATA_MANAGER.lock().update_client_list(
+                    hostname,
+                    ClientListAction::SetSpectator(spectator),
+                    Some(&CLIENTS_UPDATED_NOTIFIER),
+                );
+                reply(StatusCode::OK)?
+            } else {
+                reply(StatusCode::BAD_REQUEST)?
+            }
+        }
+        "/api/client/settings-profile" => {
+            if let Ok((hostname, maybe_profile_name)) = from_request_body(request).await {
+                SERVER_DATA_MANAGER.lock().update_client_list(
+                    hostname,
+                    ClientListAction::SetSettingsProfile(maybe_profile_name),
+                    Some(&CLIENTS_UPDATED_NOTIFIER),
+                );
+                reply(StatusCode::OK)?
+            } else {
+                reply(StatusCode::BAD_REQUEST)?
+            }
+        }
         "/api/client/remove" => {
             if let Ok((hostname, maybe_ip)) = from_request_body(request).await {
                 SERVER_DATA_MANAGER.lock().update_client_list(
@@ -0,0 +1,119 @@
+// Port forwarding over USB for the wired connection mode. The server connects to the client, so
+// the control and stream ports of the headset are forwarded to localhost. The web server port is
+// forwarded the other way, for the dashboard opened on the headset.
+
+use alvr_common::prelude::*;
+use tokio::process::Command;
+
+// The state of a device that accepted the debugging authorization
+const DEVICE_STATE_ONLINE: &str = "device";
+
+fn adb_command(adb_path: &str) -> Command {
+    let mut command = Command::new(if adb_path.is_empty() { "adb" } else { adb_path });
+
+    // Do not flash a console window on every invocation
+    #[cfg(windows)]
+    command.creation_flags(0x0800_0000); // CREATE_NO_WINDOW
+
+    command.kill_on_drop(true);
+
+    command
+}
+
+async fn run(adb_path: &str, args: &[&str]) -> StrResult<String> {
+    let output = adb_command(adb_path)
+        .args(args)
+        .output()
+        .await
+        .map_err(|e| format!("Failed to run adb: {e}"))?;
+
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    } else {
+        fmt_e!(
+            "adb {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+    }
+}
+
+// Serial numbers of the devices ready for debugging, from the output of "adb devices"
+fn parse_devices(output: &str) -> Vec<String> {
+    output
+        .lines()
+        .skip_while(|line| !line.starts_with("List of devices attached"))
+        .skip(1)
+        .filter_map(|line| {
+            let mut columns = line.split_whitespace();
+            let serial = columns.next()?;
+
+            (columns.next() == Some(DEVICE_STATE_ONLINE)).then(|| serial.to_owned())
+        })
+        .collect()
+}
+
+pub struct Device {
+    adb_path: String,
+    pub serial: String,
+}
+
+impl Device {
+    // The connected headset, or None if no headset is ready
+    pub async fn find(adb_path: &str) -> StrResult<Option<Self>> {
+        let devices = parse_devices(&run(adb_path, &["devices"]).await?);
+
+        if devices.len() > 1 {
+            warn!(
+                "Many devices are connected with USB, using {}. Disconnect the others",
+                devices[0]
+            );
+        }
+
+        Ok(devices.into_iter().next().map(|serial| Self {
+            adb_path: adb_path.to_owned(),
+            serial,
+        }))
+    }
+
+    // The forwardings are replaced if they already exist, so this can be called on each
+    // connection attempt. They are lost when the headset is unplugged
+    pub async fn forward_ports(&self, client_ports: &[u16], web_server_port: u16) -> StrResult {
+        for port in client_ports {
+            let port = format!("tcp:{port}");
+            run(
+                &self.adb_path,
+                &["-s", &self.serial, "forward", &port, &port],
+            )
+            .await?;
+        }
+
+        let port = format!("tcp:{web_server_port}");
+        run(
+            &self.adb_path,
+            &["-s", &self.serial, "reverse", &port, &port],
+        )
+        .await?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn devices_list() {
+        let output = "* daemon not running; starting now at tcp:5037\n\
+            * daemon started successfully\n\
+            List of devices attached\n\
+            1WMHH000000000\tdevice\n\
+            1WMHH000000001\tunauthorized\n\
+            emulator-5554\toffline\n\
+            \n";
+
+        assert_eq!(parse_devices(output), vec!["1WMHH000000000".to_owned()]);
+        assert!(parse_devices("List of devices attached\n\n").is_empty());
+    }
+}
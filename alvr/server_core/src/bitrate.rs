@@ -0,0 +1,529 @@
+// The bitrate controllers are driven by timestamps instead of Instant, so that they can be tested
+// and compared offline with a simulated network.
+
+use std::{collections::VecDeque, time::Duration};
+
+// Packets kept to match the client feedback. Around 3 seconds of video at 72 fps
+const MAX_SENT_PACKETS: usize = 256;
+// The receive rate is measured over this interval
+const RECEIVE_RATE_WINDOW: Duration = Duration::from_millis(500);
+
+const TRENDLINE_WINDOW_SIZE: usize = 20;
+const TRENDLINE_SMOOTHING: f64 = 0.9;
+const TRENDLINE_GAIN: f64 = 4.;
+const MAX_TRENDLINE_DELTAS: usize = 60;
+
+const INITIAL_THRESHOLD_MS: f64 = 12.5;
+const MIN_THRESHOLD_MS: f64 = 6.;
+const MAX_THRESHOLD_MS: f64 = 600.;
+const THRESHOLD_GAIN_UP: f64 = 0.0087;
+const THRESHOLD_GAIN_DOWN: f64 = 0.039;
+const MAX_THRESHOLD_STEP_MS: f64 = 100.;
+const OVERUSE_TIME_MS: f64 = 10.;
+
+const INCREASE_FACTOR_PER_SEC: f64 = 1.08;
+const DECREASE_FACTOR: f64 = 0.85;
+const MIN_DECREASE_INTERVAL: Duration = Duration::from_millis(200);
+const LOSS_INTERVAL: Duration = Duration::from_millis(500);
+const HIGH_LOSS: f64 = 0.1;
+
+pub trait BitrateController: Send {
+    fn report_packet_sent(&mut self, packet_index: u32, size_bytes: usize, timestamp: Duration);
+
+    // arrivals contains index and arrival timestamp of the packets received by the client since
+    // the previous feedback. Arrival timestamps use the client clock
+    fn report_feedback(
+        &mut self,
+        arrivals: &[(u32, Duration)],
+        packets_lost: usize,
+        timestamp: Duration,
+    );
+
+    // bits per second
+    fn bitrate(&self) -> u64;
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum BandwidthUsage {
+    Normal,
+    Overusing,
+    Underusing,
+}
+
+struct SentPacket {
+    index: u32,
+    timestamp: Duration,
+    size_bytes: usize,
+}
+
+// Delay based congestion control, modeled after Google Congestion Control. A growing one way delay
+// between consecutive packets means that a queue is building up along the path, the bitrate is
+// then lowered below the measured receive rate. Otherwise the bitrate is increased slowly. Heavy
+// packet loss lowers the bitrate too.
+pub struct DelayBasedController {
+    bitrate: f64,
+    min_bitrate: f64,
+    max_bitrate: f64,
+
+    sent_packets: VecDeque<SentPacket>,
+    // send and arrival timestamps of the previous packet
+    prev_packet: Option<(Duration, Duration)>,
+    // arrival timestamps and sizes of the packets received during RECEIVE_RATE_WINDOW
+    received_packets: VecDeque<(Duration, usize)>,
+
+    first_arrival: Option<Duration>,
+    accumulated_delay_ms: f64,
+    smoothed_delay_ms: f64,
+    // arrival time and smoothed delay, in milliseconds
+    delay_history: VecDeque<(f64, f64)>,
+    deltas_count: usize,
+
+    threshold_ms: f64,
+    last_threshold_update_ms: Option<f64>,
+    overuse_time_ms: Option<f64>,
+    overuse_count: usize,
+    prev_trend: f64,
+    usage: BandwidthUsage,
+
+    last_update: Option<Duration>,
+    last_decrease: Option<Duration>,
+    last_loss_update: Option<Duration>,
+    packets_received: usize,
+    packets_lost: usize,
+}
+
+impl DelayBasedController {
+    // Bitrates in bits per second
+    pub fn new(initial_bitrate: u64, min_bitrate: u64, max_bitrate: u64) -> Self {
+        Self {
+            bitrate: initial_bitrate as f64,
+            min_bitrate: min_bitrate as f64,
+            max_bitrate: max_bitrate as f64,
+            sent_packets: VecDeque::new(),
+            prev_packet: None,
+            received_packets: VecDeque::new(),
+            first_arrival: None,
+            accumulated_delay_ms: 0.,
+            smoothed_delay_ms: 0.,
+            delay_history: VecDeque::new(),
+            deltas_count: 0,
+            threshold_ms: INITIAL_THRESHOLD_MS,
+            last_threshold_update_ms: None,
+            overuse_time_ms: None,
+            overuse_count: 0,
+            prev_trend: 0.,
+            usage: BandwidthUsage::Normal,
+            last_update: None,
+            last_decrease: None,
+            last_loss_update: None,
+            packets_received: 0,
+            packets_lost: 0,
+        }
+    }
+
+    fn receive_rate(&self) -> Option<f64> {
+        let (first_arrival, _) = self.received_packets.front()?;
+        let (last_arrival, _) = self.received_packets.back()?;
+        let interval = last_arrival.saturating_sub(*first_arrival);
+        if interval < RECEIVE_RATE_WINDOW / 4 {
+            return None;
+        }
+
+        // The first packet marks the start of the interval
+        let bytes = self
+            .received_packets
+            .iter()
+            .skip(1)
+            .map(|(_, size)| size)
+            .sum::<usize>();
+
+        Some(bytes as f64 * 8. / interval.as_secs_f64())
+    }
+
+    fn update_trendline(&mut self, delay_variation_ms: f64, send_delta_ms: f64, arrival: Duration) {
+        self.deltas_count += 1;
+        self.accumulated_delay_ms += delay_variation_ms;
+        self.smoothed_delay_ms = TRENDLINE_SMOOTHING * self.smoothed_delay_ms
+            + (1. - TRENDLINE_SMOOTHING) * self.accumulated_delay_ms;
+
+        let first_arrival = *self.first_arrival.get_or_insert(arrival);
+        let arrival_ms = arrival.saturating_sub(first_arrival).as_secs_f64() * 1000.;
+
+        self.delay_history
+            .push_back((arrival_ms, self.smoothed_delay_ms));
+        if self.delay_history.len() > TRENDLINE_WINDOW_SIZE {
+            self.delay_history.pop_front();
+        }
+
+        if self.delay_history.len() == TRENDLINE_WINDOW_SIZE {
+            if let Some(slope) = linear_fit_slope(&self.delay_history) {
+                let trend = usize::min(self.deltas_count, MAX_TRENDLINE_DELTAS) as f64
+                    * slope
+                    * TRENDLINE_GAIN;
+                self.detect_usage(trend, send_delta_ms, arrival_ms);
+            }
+        }
+    }
+
+    fn detect_usage(&mut self, trend: f64, send_delta_ms: f64, now_ms: f64) {
+        if trend > self.threshold_ms {
+            // Overuse is signaled only if it lasts for some time
+            let overuse_time_ms = match self.overuse_time_ms {
+                Some(time) => time + send_delta_ms,
+                None => send_delta_ms / 2.,
+            };
+            self.overuse_time_ms = Some(overuse_time_ms);
+            self.overuse_count += 1;
+
+            if overuse_time_ms > OVERUSE_TIME_MS
+                && self.overuse_count > 1
+                && trend >= self.prev_trend
+            {
+                self.overuse_time_ms = Some(0.);
+                self.overuse_count = 0;
+                self.usage = BandwidthUsage::Overusing;
+            }
+        } else {
+            self.overuse_time_ms = None;
+            self.overuse_count = 0;
+            self.usage = if trend < -self.threshold_ms {
+                BandwidthUsage::Underusing
+            } else {
+                BandwidthUsage::Normal
+            };
+        }
+        self.prev_trend = trend;
+
+        // The threshold adapts to the trend, so that the controller is not starved by concurrent
+        // TCP flows. Spikes are ignored
+        let last_update_ms = self.last_threshold_update_ms.unwrap_or(now_ms);
+        self.last_threshold_update_ms = Some(now_ms);
+        if trend.abs() > self.threshold_ms + 15. {
+            return;
+        }
+
+        let gain = if trend.abs() < self.threshold_ms {
+            THRESHOLD_GAIN_DOWN
+        } else {
+            THRESHOLD_GAIN_UP
+        };
+        let step_ms = f64::min(now_ms - last_update_ms, MAX_THRESHOLD_STEP_MS);
+        self.threshold_ms = (self.threshold_ms
+            + gain * (trend.abs() - self.threshold_ms) * step_ms)
+            .clamp(MIN_THRESHOLD_MS, MAX_THRESHOLD_MS);
+    }
+
+    fn update_bitrate(&mut self, timestamp: Duration) {
+        let elapsed = self
+            .last_update
+            .map(|last_update| timestamp.saturating_sub(last_update))
+            .unwrap_or_default();
+        self.last_update = Some(timestamp);
+
+        let receive_rate = self.receive_rate();
+        let can_decrease = !matches!(
+            self.last_decrease,
+            Some(last_decrease) if timestamp < last_decrease + MIN_DECREASE_INTERVAL
+        );
+
+        match self.usage {
+            BandwidthUsage::Overusing => {
+                if can_decrease {
+                    let decreased = DECREASE_FACTOR * receive_rate.unwrap_or(self.bitrate);
+                    self.bitrate = f64::min(self.bitrate, decreased);
+                    self.last_decrease = Some(timestamp);
+                }
+            }
+            // Hold the bitrate while the queues drain
+            BandwidthUsage::Underusing => (),
+            BandwidthUsage::Normal => {
+                let increased = self.bitrate
+                    * INCREASE_FACTOR_PER_SEC.powf(f64::min(elapsed.as_secs_f64(), 1.));
+
+                // Do not get too far from what the network can deliver
+                self.bitrate = match receive_rate {
+                    Some(rate) => f64::max(self.bitrate, f64::min(increased, 1.5 * rate)),
+                    None => increased,
+                };
+            }
+        }
+
+        let last_loss_update = *self.last_loss_update.get_or_insert(timestamp);
+        if timestamp >= last_loss_update + LOSS_INTERVAL {
+            let packets_count = self.packets_received + self.packets_lost;
+            if packets_count > 0 {
+                let loss = self.packets_lost as f64 / packets_count as f64;
+                if loss > HIGH_LOSS {
+                    self.bitrate *= 1. - 0.5 * loss;
+                    self.last_decrease = Some(timestamp);
+                }
+            }
+
+            self.last_loss_update = Some(timestamp);
+            self.packets_received = 0;
+            self.packets_lost = 0;
+        }
+
+        self.bitrate = self.bitrate.clamp(self.min_bitrate, self.max_bitrate);
+    }
+}
+
+impl BitrateController for DelayBasedController {
+    fn report_packet_sent(&mut self, packet_index: u32, size_bytes: usize, timestamp: Duration) {
+        self.sent_packets.push_back(SentPacket {
+            index: packet_index,
+            timestamp,
+            size_bytes,
+        });
+        if self.sent_packets.len() > MAX_SENT_PACKETS {
+            self.sent_packets.pop_front();
+        }
+    }
+
+    fn report_feedback(
+        &mut self,
+        arrivals: &[(u32, Duration)],
+        packets_lost: usize,
+        timestamp: Duration,
+    ) {
+        for &(index, arrival) in arrivals {
+            let (send_timestamp, size_bytes) = match self
+                .sent_packets
+                .iter()
+                .find(|packet| packet.index == index)
+            {
+                Some(packet) => (packet.timestamp, packet.size_bytes),
+                None => continue,
+            };
+
+            self.received_packets.push_back((arrival, size_bytes));
+
+            // Reordered packets are skipped
+            match self.prev_packet {
+                Some((prev_send_timestamp, prev_arrival))
+                    if send_timestamp >= prev_send_timestamp && arrival >= prev_arrival =>
+                {
+                    let send_delta_ms =
+                        (send_timestamp - prev_send_timestamp).as_secs_f64() * 1000.;
+                    let arrival_delta_ms = (arrival - prev_arrival).as_secs_f64() * 1000.;
+
+                    self.update_trendline(arrival_delta_ms - send_delta_ms, send_delta_ms, arrival);
+                    self.prev_packet = Some((send_timestamp, arrival));
+                }
+                Some(_) => (),
+                None => self.prev_packet = Some((send_timestamp, arrival)),
+            }
+        }
+
+        if let Some(&(last_arrival, _)) = self.received_packets.back() {
+            while matches!(
+                self.received_packets.front(),
+                Some(&(arrival, _)) if arrival + RECEIVE_RATE_WINDOW < last_arrival
+            ) {
+                self.received_packets.pop_front();
+            }
+        }
+
+        self.packets_received += arrivals.len();
+        self.packets_lost += packets_lost;
+
+        self.update_bitrate(timestamp);
+    }
+
+    fn bitrate(&self) -> u64 {
+        self.bitrate as u64
+    }
+}
+
+fn linear_fit_slope(points: &VecDeque<(f64, f64)>) -> Option<f64> {
+    let count = points.len() as f64;
+    let x_average = points.iter().map(|(x, _)| x).sum::<f64>() / count;
+    let y_average = points.iter().map(|(_, y)| y).sum::<f64>() / count;
+
+    let mut numerator = 0.;
+    let mut denominator = 0.;
+    for (x, y) in points {
+        numerator += (x - x_average) * (y - y_average);
+        denominator += (x - x_average) * (x - x_average);
+    }
+
+    (denominator != 0.).then(|| numerator / denominator)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ops::Range;
+
+    const FPS: u32 = 72;
+    const MBPS: f64 = 1e6;
+
+    // Single bottleneck link with a FIFO queue
+    struct Link {
+        propagation_delay: Duration,
+        max_queue_delay: Duration,
+        queue_free_at: Duration,
+    }
+
+    impl Link {
+        // Returns the arrival timestamp, or None if the packet is dropped
+        fn transmit(
+            &mut self,
+            timestamp: Duration,
+            size_bytes: usize,
+            capacity: f64,
+        ) -> Option<Duration> {
+            let start = Duration::max(timestamp, self.queue_free_at);
+            if start - timestamp > self.max_queue_delay {
+                return None;
+            }
+
+            self.queue_free_at = start + Duration::from_secs_f64(size_bytes as f64 * 8. / capacity);
+
+            Some(self.queue_free_at + self.propagation_delay)
+        }
+    }
+
+    struct Sample {
+        timestamp: Duration,
+        bitrate: f64,
+        queue_delay: Duration,
+    }
+
+    // One video packet is sent every frame, with the size given by the current bitrate. The client
+    // feedback is received one propagation delay after the packet arrival. Any BitrateController
+    // can be compared with the same network conditions.
+    fn simulate(
+        controller: &mut dyn BitrateController,
+        capacity: impl Fn(Duration) -> f64,
+        is_dropped: impl Fn(u32) -> bool,
+        duration: Duration,
+    ) -> Vec<Sample> {
+        let propagation_delay = Duration::from_millis(5);
+        let mut link = Link {
+            propagation_delay,
+            max_queue_delay: Duration::from_millis(300),
+            queue_free_at: Duration::ZERO,
+        };
+
+        let frame_interval = Duration::from_secs(1) / FPS;
+        let mut in_flight = VecDeque::new();
+        let mut samples = vec![];
+
+        for packet_index in 0..(duration.as_secs_f64() * FPS as f64) as u32 {
+            let timestamp = frame_interval * packet_index;
+
+            let mut arrivals = vec![];
+            let mut packets_lost = 0;
+            while let Some(&(index, maybe_arrival)) = in_flight.front() {
+                match maybe_arrival {
+                    Some(arrival) if arrival + propagation_delay <= timestamp => {
+                        arrivals.push((index, arrival))
+                    }
+                    Some(_) => break,
+                    None => packets_lost += 1,
+                }
+                in_flight.pop_front();
+            }
+            controller.report_feedback(&arrivals, packets_lost, timestamp);
+
+            let bitrate = controller.bitrate() as f64;
+            let size_bytes = (bitrate / 8. / FPS as f64) as usize;
+            let queue_delay = link.queue_free_at.saturating_sub(timestamp);
+
+            let maybe_arrival = if is_dropped(packet_index) {
+                None
+            } else {
+                link.transmit(timestamp, size_bytes, capacity(timestamp))
+            };
+            controller.report_packet_sent(packet_index, size_bytes, timestamp);
+            in_flight.push_back((packet_index, maybe_arrival));
+
+            samples.push(Sample {
+                timestamp,
+                bitrate,
+                queue_delay,
+            });
+        }
+
+        samples
+    }
+
+    // Average bitrate and queue delay in the given interval
+    fn averages(samples: &[Sample], interval: Range<Duration>) -> (f64, Duration) {
+        let samples = samples
+            .iter()
+            .filter(|sample| interval.contains(&sample.timestamp))
+            .collect::<Vec<_>>();
+
+        let bitrate =
+            samples.iter().map(|sample| sample.bitrate).sum::<f64>() / samples.len() as f64;
+        let queue_delay = samples
+            .iter()
+            .map(|sample| sample.queue_delay)
+            .sum::<Duration>()
+            / samples.len() as u32;
+
+        (bitrate, queue_delay)
+    }
+
+    fn new_controller() -> DelayBasedController {
+        DelayBasedController::new(
+            (30. * MBPS) as u64,
+            (5. * MBPS) as u64,
+            (200. * MBPS) as u64,
+        )
+    }
+
+    #[test]
+    fn converges_to_link_capacity() {
+        let mut controller = new_controller();
+        let samples = simulate(
+            &mut controller,
+            |_| 50. * MBPS,
+            |_| false,
+            Duration::from_secs(40),
+        );
+
+        let (bitrate, queue_delay) =
+            averages(&samples, Duration::from_secs(20)..Duration::from_secs(40));
+        assert!(bitrate > 35. * MBPS && bitrate < 52. * MBPS, "{bitrate}");
+        assert!(queue_delay < Duration::from_millis(30), "{queue_delay:?}");
+    }
+
+    #[test]
+    fn backs_off_when_capacity_drops() {
+        let mut controller = new_controller();
+        let samples = simulate(
+            &mut controller,
+            |timestamp| {
+                if timestamp < Duration::from_secs(20) {
+                    60. * MBPS
+                } else {
+                    20. * MBPS
+                }
+            },
+            |_| false,
+            Duration::from_secs(40),
+        );
+
+        let (bitrate, queue_delay) =
+            averages(&samples, Duration::from_secs(25)..Duration::from_secs(40));
+        assert!(bitrate > 12. * MBPS && bitrate < 21. * MBPS, "{bitrate}");
+        assert!(queue_delay < Duration::from_millis(30), "{queue_delay:?}");
+    }
+
+    #[test]
+    fn backs_off_on_packet_loss() {
+        let mut controller = new_controller();
+        let samples = simulate(
+            &mut controller,
+            |_| 200. * MBPS,
+            |packet_index| packet_index % 4 == 0,
+            Duration::from_secs(20),
+        );
+
+        let (bitrate, _) = averages(&samples, Duration::from_secs(10)..Duration::from_secs(20));
+        assert!(bitrate < 10. * MBPS, "{bitrate}");
+    }
+}
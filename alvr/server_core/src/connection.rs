@@ -0,0 +1,2104 @@
+use crate::{
+    adb,
+    bitrate::{BitrateController, DelayBasedController},
+    buttons::BUTTON_PATH_FROM_ID,
+    connection_utils,
+    statistics::{ClientTimestamps, StatisticsManager},
+    tracking::TrackingManager,
+    ServerDriver, CLIENTS_UPDATED_NOTIFIER, HAPTICS_SENDER, LAST_SPECTATOR_IDR, PAIRING_PINS,
+    RESTART_NOTIFIER, RESUMABLE_SESSION, SERVER_DATA_MANAGER, SPECTATOR_STREAM_PORTS,
+    SPECTATOR_VIDEO_SENDERS, STATISTICS_MANAGER, STREAMING_CLIENT, STREAM_RESTART_NOTIFIER,
+    VIDEO_SENDER,
+};
+use alvr_audio::{AudioDevice, AudioDeviceType};
+use alvr_common::{glam::Vec2, prelude::*, HEAD_ID};
+use alvr_events::{ButtonEvent, EventType};
+use alvr_session::{
+    BitrateAlgorithm, BitrateAlgorithmDefaultVariant, ClientConnectionDesc, CodecType, FrameSize,
+    OpenvrConfig, SessionDesc, SettingsApplyMode, SocketProtocolDefaultVariant,
+};
+use alvr_sockets::{
+    bounded_queue, spawn_cancelable, AuthRole, Capabilities, ClientAuthPacket, ClientConfigPacket,
+    ClientControlPacket, ClientListAction, ClientStatistics, ClockSync, ControlSocketReceiver,
+    ControlSocketSender, FecController, HeadsetInfoPacket, InterfaceBinding, MdnsAdvertiser,
+    NackPacket, NegotiatedCapabilities, PeerType, ProtoControlSocket, ResumeToken,
+    ServerAuthPacket, ServerControlPacket, StreamKeys, StreamSocket, StreamSocketBuilder, Tracking,
+    AUDIO, FEATURE_CLOCK_SYNC, FEATURE_FEC, FEATURE_PACKET_ARRIVALS, FEATURE_QUIC, FEATURE_RESUME,
+    FEATURE_SPECTATOR, HAPTICS, STATISTICS, TRACKING, VIDEO,
+};
+use futures::{
+    future::{BoxFuture, Either},
+    stream::FuturesUnordered,
+    StreamExt,
+};
+use settings_schema::Switch;
+use std::{
+    future,
+    net::{IpAddr, Ipv4Addr},
+    process::Command,
+    sync::{mpsc as smpsc, Arc},
+    thread,
+    time::{Duration, Instant},
+};
+use tokio::{
+    sync::{mpsc as tmpsc, Mutex},
+    time,
+};
+
+#[cfg(windows)]
+use alvr_session::{OpenvrPropValue, OpenvrPropertyKey};
+
+const CONTROL_CONNECT_RETRY_PAUSE: Duration = Duration::from_millis(500);
+const RETRY_CONNECT_MIN_INTERVAL: Duration = Duration::from_secs(1);
+const NETWORK_KEEPALIVE_INTERVAL: Duration = Duration::from_secs(1);
+const CLEANUP_PAUSE: Duration = Duration::from_millis(500);
+// The headset connected with USB is searched again, or its ports forwarded again, after this pause
+const WIRED_DEVICE_RETRY_PAUSE: Duration = Duration::from_secs(2);
+const MAX_VIDEO_FEC_PERCENTAGE: u16 = 50;
+const MIN_VIDEO_BITRATE_MBPS: u64 = 5;
+const BITS_IN_MBIT: u64 = 1_000_000;
+// Number of video frames kept to answer retransmission requests
+const VIDEO_RETRANSMIT_CACHE_SIZE: usize = 8;
+// Frames waiting for the network. When the client can't keep up, the oldest are discarded
+const VIDEO_SEND_QUEUE_CAPACITY: usize = 32;
+const HAPTICS_SEND_QUEUE_CAPACITY: usize = 64;
+// The spectators, all together, cause at most one IDR in this interval
+const SPECTATOR_IDR_MIN_INTERVAL: Duration = Duration::from_secs(1);
+
+fn align32(value: f32) -> u32 {
+    ((value / 32.).floor() * 32.) as u32
+}
+
+fn mbits_to_bytes(value: u64) -> u32 {
+    (value * 1024 * 1024 / 8) as u32
+}
+
+// Session with the settings profile and the override of the client applied. If they are invalid,
+// the client uses the base settings
+fn client_session(hostname: &str) -> SessionDesc {
+    let data_manager = SERVER_DATA_MANAGER.lock();
+
+    data_manager
+        .session()
+        .for_client(hostname)
+        .unwrap_or_else(|e| {
+            warn!("Ignoring the settings of client {hostname}: {e}");
+            data_manager.session().clone()
+        })
+}
+
+// Session used for the streams of a client, and sent to it. The features that the client does not
+// support are disabled
+fn negotiated_session(hostname: &str, capabilities: &NegotiatedCapabilities) -> SessionDesc {
+    let mut session = client_session(hostname);
+    let settings = &mut session.session_settings;
+
+    if matches!(
+        settings.connection.stream_protocol.variant,
+        SocketProtocolDefaultVariant::Quic
+    ) && !capabilities.supports(FEATURE_QUIC)
+    {
+        settings.connection.stream_protocol.variant = SocketProtocolDefaultVariant::Udp;
+    }
+    // Only TCP can be forwarded by adb
+    if settings.connection.wired_connection.enabled {
+        settings.connection.stream_protocol.variant = SocketProtocolDefaultVariant::Tcp;
+    }
+    if !capabilities.supports(FEATURE_FEC) {
+        settings.connection.enable_fec = false;
+    }
+    if !capabilities.supports(FEATURE_PACKET_ARRIVALS) {
+        settings.video.adaptive_bitrate.content.algorithm.variant =
+            BitrateAlgorithmDefaultVariant::Latency;
+    }
+
+    session
+}
+
+// The eye resolutions and the refresh rate depend on the headset, they are given separately
+fn openvr_config(
+    session_desc: &SessionDesc,
+    (eye_width, eye_height): (u32, u32),
+    (target_eye_width, target_eye_height): (u32, u32),
+    fps: f32,
+) -> OpenvrConfig {
+    let settings = session_desc.to_settings();
+    let session_settings = &session_desc.session_settings;
+
+    OpenvrConfig {
+        universe_id: settings.headset.universe_id,
+        headset_serial_number: settings.headset.serial_number,
+        headset_tracking_system_name: settings.headset.tracking_system_name,
+        headset_model_number: settings.headset.model_number,
+        headset_driver_version: settings.headset.driver_version,
+        headset_manufacturer_name: settings.headset.manufacturer_name,
+        headset_render_model_name: settings.headset.render_model_name,
+        headset_registered_device_type: settings.headset.registered_device_type,
+        eye_resolution_width: eye_width,
+        eye_resolution_height: eye_height,
+        target_eye_resolution_width: target_eye_width,
+        target_eye_resolution_height: target_eye_height,
+        seconds_from_vsync_to_photons: settings.video.seconds_from_vsync_to_photons,
+        force_3dof: settings.headset.force_3dof,
+        tracking_ref_only: settings.headset.tracking_ref_only,
+        enable_vive_tracker_proxy: settings.headset.enable_vive_tracker_proxy,
+        aggressive_keyframe_resend: settings.connection.aggressive_keyframe_resend,
+        adapter_index: settings.video.adapter_index,
+        codec: matches!(settings.video.codec, CodecType::HEVC) as _,
+        refresh_rate: fps as _,
+        use_10bit_encoder: settings.video.use_10bit_encoder,
+        force_sw_encoding: settings.video.force_sw_encoding,
+        sw_thread_count: settings.video.sw_thread_count,
+        encode_bitrate_mbs: settings.video.encode_bitrate_mbs,
+        enable_adaptive_bitrate: session_settings.video.adaptive_bitrate.enabled,
+        bitrate_maximum: session_settings
+            .video
+            .adaptive_bitrate
+            .content
+            .bitrate_maximum,
+        latency_target: session_settings
+            .video
+            .adaptive_bitrate
+            .content
+            .latency_target,
+        latency_use_frametime: session_settings
+            .video
+            .adaptive_bitrate
+            .content
+            .latency_use_frametime
+            .enabled,
+        latency_target_maximum: session_settings
+            .video
+            .adaptive_bitrate
+            .content
+            .latency_use_frametime
+            .content
+            .latency_target_maximum,
+        latency_target_offset: session_settings
+            .video
+            .adaptive_bitrate
+            .content
+            .latency_use_frametime
+            .content
+            .latency_target_offset,
+        latency_threshold: session_settings
+            .video
+            .adaptive_bitrate
+            .content
+            .latency_threshold,
+        bitrate_up_rate: session_settings
+            .video
+            .adaptive_bitrate
+            .content
+            .bitrate_up_rate,
+        bitrate_down_rate: session_settings
+            .video
+            .adaptive_bitrate
+            .content
+            .bitrate_down_rate,
+        bitrate_light_load_threshold: session_settings
+            .video
+            .adaptive_bitrate
+            .content
+            .bitrate_light_load_threshold,
+        controllers_tracking_system_name: session_settings
+            .headset
+            .controllers
+            .content
+            .tracking_system_name
+            .clone(),
+        controllers_manufacturer_name: session_settings
+            .headset
+            .controllers
+            .content
+            .manufacturer_name
+            .clone(),
+        controllers_model_number: session_settings
+            .headset
+            .controllers
+            .content
+            .model_number
+            .clone(),
+        render_model_name_left_controller: session_settings
+            .headset
+            .controllers
+            .content
+            .render_model_name_left
+            .clone(),
+        render_model_name_right_controller: session_settings
+            .headset
+            .controllers
+            .content
+            .render_model_name_right
+            .clone(),
+        controllers_serial_number: session_settings
+            .headset
+            .controllers
+            .content
+            .serial_number
+            .clone(),
+        controllers_type_left: session_settings
+            .headset
+            .controllers
+            .content
+            .ctrl_type_left
+            .clone(),
+        controllers_type_right: session_settings
+            .headset
+            .controllers
+            .content
+            .ctrl_type_right
+            .clone(),
+        controllers_registered_device_type: session_settings
+            .headset
+            .controllers
+            .content
+            .registered_device_type
+            .clone(),
+        controllers_input_profile_path: session_settings
+            .headset
+            .controllers
+            .content
+            .input_profile_path
+            .clone(),
+        controllers_mode_idx: session_settings.headset.controllers.content.mode_idx,
+        controllers_enabled: session_settings.headset.controllers.enabled,
+        position_offset: settings.headset.position_offset,
+        linear_velocity_cutoff: session_settings
+            .headset
+            .controllers
+            .content
+            .linear_velocity_cutoff,
+        angular_velocity_cutoff: session_settings
+            .headset
+            .controllers
+            .content
+            .angular_velocity_cutoff,
+        position_offset_left: session_settings
+            .headset
+            .controllers
+            .content
+            .position_offset_left,
+        rotation_offset_left: session_settings
+            .headset
+            .controllers
+            .content
+            .rotation_offset_left,
+        haptics_intensity: session_settings
+            .headset
+            .controllers
+            .content
+            .haptics_intensity,
+        haptics_amplitude_curve: session_settings
+            .headset
+            .controllers
+            .content
+            .haptics_amplitude_curve,
+        haptics_min_duration: session_settings
+            .headset
+            .controllers
+            .content
+            .haptics_min_duration,
+        haptics_low_duration_amplitude_multiplier: session_settings
+            .headset
+            .controllers
+            .content
+            .haptics_low_duration_amplitude_multiplier,
+        haptics_low_duration_range: session_settings
+            .headset
+            .controllers
+            .content
+            .haptics_low_duration_range,
+        use_headset_tracking_system: session_settings
+            .headset
+            .controllers
+            .content
+            .use_headset_tracking_system,
+        enable_foveated_rendering: session_settings.video.foveated_rendering.enabled,
+        foveation_center_size_x: session_settings
+            .video
+            .foveated_rendering
+            .content
+            .center_size_x,
+        foveation_center_size_y: session_settings
+            .video
+            .foveated_rendering
+            .content
+            .center_size_y,
+        foveation_center_shift_x: session_settings
+            .video
+            .foveated_rendering
+            .content
+            .center_shift_x,
+        foveation_center_shift_y: session_settings
+            .video
+            .foveated_rendering
+            .content
+            .center_shift_y,
+        foveation_edge_ratio_x: session_settings
+            .video
+            .foveated_rendering
+            .content
+            .edge_ratio_x,
+        foveation_edge_ratio_y: session_settings
+            .video
+            .foveated_rendering
+            .content
+            .edge_ratio_y,
+        enable_color_correction: session_settings.video.color_correction.enabled,
+        brightness: session_settings.video.color_correction.content.brightness,
+        contrast: session_settings.video.color_correction.content.contrast,
+        saturation: session_settings.video.color_correction.content.saturation,
+        gamma: session_settings.video.color_correction.content.gamma,
+        sharpening: session_settings.video.color_correction.content.sharpening,
+        // FEC is handled by alvr_sockets
+        enable_fec: false,
+        linux_async_reprojection: session_settings.extra.patches.linux_async_reprojection,
+    }
+}
+
+#[derive(Clone)]
+struct ClientId {
+    hostname: String,
+    ip: IpAddr,
+}
+
+// Resolved again on each connection, the interface addresses can change. The wired connection
+// goes through localhost instead
+fn interface_binding() -> StrResult<InterfaceBinding> {
+    let connection = SERVER_DATA_MANAGER
+        .lock()
+        .session()
+        .to_settings()
+        .connection;
+
+    if let (Switch::Enabled(config), Switch::Disabled) =
+        (connection.bind_interface, connection.wired_connection)
+    {
+        InterfaceBinding::resolve(&config.name_or_address)
+    } else {
+        Ok(InterfaceBinding::any())
+    }
+}
+
+async fn client_discovery(control_port: u16, auto_trust_clients: bool) -> StrResult<ClientId> {
+    let binding = interface_binding()?;
+    // Withdrawn once a client is found
+    let _mdns_advertiser = MdnsAdvertiser::new(control_port)
+        .map_err(|e| warn!("mDNS advertisement is not available: {e}"))
+        .ok();
+    let (ip, handshake_packet) = connection_utils::search_client_loop(
+        &binding,
+        control_port,
+        |handshake_packet| async move {
+            let fingerprint = handshake_packet.certificate_fingerprint;
+
+            let mut data_manager = SERVER_DATA_MANAGER.lock();
+            data_manager.update_client_list(
+                handshake_packet.hostname.clone(),
+                ClientListAction::AddIfMissing {
+                    display_name: handshake_packet.device_name,
+                },
+                Some(&CLIENTS_UPDATED_NOTIFIER),
+            );
+            if !fingerprint.is_empty() {
+                data_manager.update_client_list(
+                    handshake_packet.hostname.clone(),
+                    ClientListAction::PinCertificate(fingerprint.clone()),
+                    Some(&CLIENTS_UPDATED_NOTIFIER),
+                );
+            }
+
+            if let Some(connection_desc) = data_manager
+                .session()
+                .client_connections
+                .get(&handshake_packet.hostname)
+            {
+                if connection_desc.certificate_fingerprint.as_ref() != Some(&fingerprint) {
+                    warn!(
+                        "Client {} presented a different certificate",
+                        handshake_packet.hostname
+                    );
+
+                    false
+                } else {
+                    let is_paired =
+                        connection_desc.trusted && connection_desc.pairing_secret.is_some();
+
+                    is_paired
+                        || PAIRING_PINS.lock().contains_key(&handshake_packet.hostname)
+                        || auto_trust_clients
+                }
+            } else {
+                false
+            }
+        },
+    )
+    .await?;
+
+    Ok(ClientId {
+        hostname: handshake_packet.hostname,
+        ip,
+    })
+}
+
+// A PIN entered in the dashboard takes precedence over the pairing secret, to be able to pair
+// again a client that lost its secret
+async fn authenticate_client(
+    proto_socket: &mut ProtoControlSocket,
+    hostname: String,
+    client_connection: ClientConnectionDesc,
+    capabilities: &NegotiatedCapabilities,
+) -> StrResult {
+    let maybe_pin = PAIRING_PINS.lock().remove(&hostname);
+
+    if maybe_pin.is_some() && !capabilities.supports_pin_exchange() {
+        proto_socket.send(&ServerAuthPacket::Unpaired).await?;
+
+        fmt_e!("Client {hostname} must be updated to be paired")
+    } else if let Some(pin) = maybe_pin {
+        proto_socket.send(&ServerAuthPacket::RequestPin).await?;
+        let pin_key = proto_socket.exchange_pin(&pin, AuthRole::Server).await?;
+        let ClientAuthPacket { proof } = proto_socket.recv().await?;
+
+        if !proto_socket.verify_auth_proof(&pin_key, AuthRole::Client, &proof)? {
+            return fmt_e!("Wrong pairing PIN for client {hostname}");
+        }
+
+        // Both peers derive the same secret from this connection
+        let pairing_secret = proto_socket.pairing_secret()?;
+        let server_proof = proto_socket.auth_proof(&pin_key, AuthRole::Server)?;
+        proto_socket
+            .send(&ServerAuthPacket::Paired { server_proof })
+            .await?;
+        SERVER_DATA_MANAGER.lock().update_client_list(
+            hostname,
+            ClientListAction::Pair { pairing_secret },
+            None,
+        );
+
+        Ok(())
+    } else if let (true, Some(pairing_secret)) =
+        (client_connection.trusted, &client_connection.pairing_secret)
+    {
+        let server_proof = proto_socket.auth_proof(pairing_secret, AuthRole::Server)?;
+        proto_socket
+            .send(&ServerAuthPacket::Authenticate { server_proof })
+            .await?;
+        let ClientAuthPacket { proof } = proto_socket.recv().await?;
+
+        if proto_socket.verify_auth_proof(pairing_secret, AuthRole::Client, &proof)? {
+            Ok(())
+        } else {
+            fmt_e!("Client {hostname} failed authentication")
+        }
+    } else {
+        let auto_trust_clients = matches!(
+            SERVER_DATA_MANAGER.lock().session().to_settings().connection.client_discovery,
+            Switch::Enabled(config) if config.auto_trust_clients
+        );
+        if auto_trust_clients {
+            // Without a PIN the server cannot be authenticated, so the client is not paired
+            proto_socket.send(&ServerAuthPacket::Trusted).await?;
+
+            Ok(())
+        } else {
+            proto_socket.send(&ServerAuthPacket::Unpaired).await?;
+
+            fmt_e!("Client {hostname} is not paired")
+        }
+    }
+}
+
+// Releases the server stream port of a spectator
+struct SpectatorPortGuard(u16);
+
+impl Drop for SpectatorPortGuard {
+    fn drop(&mut self) {
+        SPECTATOR_STREAM_PORTS.lock().remove(&self.0);
+    }
+}
+
+struct ConnectionInfo {
+    hostname: String,
+    client_ip: IpAddr,
+    capabilities: NegotiatedCapabilities,
+    control_sender: ControlSocketSender<ServerControlPacket>,
+    control_receiver: ControlSocketReceiver<ClientControlPacket>,
+    stream_keys: StreamKeys,
+    microphone_sample_rate: u32,
+    spectator: bool,
+    server_stream_port: u16,
+    spectator_port_guard: Option<SpectatorPortGuard>,
+    // The client presented a valid resume token. The connection replaces the one of the running
+    // session
+    resumed: bool,
+}
+
+// The primary client can reconnect with the token issued by the last StartStream. The new
+// connection is handed to the running pipeline, which restarts the streams only
+pub struct ResumableSession {
+    hostname: String,
+    token: ResumeToken,
+    connection_sender: tmpsc::UnboundedSender<ConnectionInfo>,
+}
+
+// Stops accepting resume requests when the session ends
+struct ResumableSessionGuard;
+
+impl Drop for ResumableSessionGuard {
+    fn drop(&mut self) {
+        *RESUMABLE_SESSION.lock() = None;
+    }
+}
+
+struct StreamingClientGuard;
+
+impl Drop for StreamingClientGuard {
+    fn drop(&mut self) {
+        *STREAMING_CLIENT.lock() = None;
+    }
+}
+
+fn is_resumable(hostname: &str) -> bool {
+    matches!(&*RESUMABLE_SESSION.lock(), Some(session) if session.hostname == hostname)
+}
+
+// Called when the settings are stored from the dashboard. While streaming, the hot settings are
+// pushed to the driver and the client is made to reconnect for the stream settings. The settings
+// that need a SteamVR restart are applied by the next connection handshake
+pub fn apply_settings_change(driver: &dyn ServerDriver) {
+    let hostname = match &*STREAMING_CLIENT.lock() {
+        Some(hostname) => hostname.clone(),
+        None => return,
+    };
+
+    let current_config = SERVER_DATA_MANAGER.lock().session().openvr_config.clone();
+    // The resolution and the refresh rate chosen for the headset are kept
+    let new_openvr_config = openvr_config(
+        &client_session(&hostname),
+        (
+            current_config.eye_resolution_width,
+            current_config.eye_resolution_height,
+        ),
+        (
+            current_config.target_eye_resolution_width,
+            current_config.target_eye_resolution_height,
+        ),
+        current_config.refresh_rate as _,
+    );
+
+    match current_config.apply_mode(&new_openvr_config) {
+        Some(SettingsApplyMode::Hot) => {
+            SERVER_DATA_MANAGER.lock().session_mut().openvr_config = new_openvr_config;
+            alvr_events::send_event(EventType::SettingsApplied(SettingsApplyMode::Hot));
+
+            driver.apply_settings();
+        }
+        // The handshake of the new connection applies the settings
+        Some(SettingsApplyMode::RestartStream) => {
+            info!("Restarting the stream of client {hostname} to apply the settings");
+            STREAM_RESTART_NOTIFIER.notify_waiters();
+        }
+        Some(SettingsApplyMode::RestartDriver) => {
+            info!("Some settings will be applied after SteamVR is restarted");
+        }
+        None => (),
+    }
+}
+
+// The ports of the headset connected with USB are forwarded to localhost
+async fn forward_wired_ports(device: &adb::Device, control_port: u16) -> StrResult {
+    let settings = SERVER_DATA_MANAGER.lock().session().to_settings();
+
+    device
+        .forward_ports(
+            &[control_port, settings.connection.stream_port],
+            settings.connection.web_server_port,
+        )
+        .await
+}
+
+// While the primary client is streaming, only spectators are accepted. wired_device is set when
+// the client is reached through the ports forwarded with adb
+async fn client_handshake(
+    driver: &dyn ServerDriver,
+    control_port: u16,
+    trusted_discovered_client_id: Option<ClientId>,
+    wired_device: Option<adb::Device>,
+    primary_connected: bool,
+) -> StrResult<ConnectionInfo> {
+    let client_ips = if wired_device.is_some() {
+        vec![Ipv4Addr::LOCALHOST.into()]
+    } else if let Some(id) = trusted_discovered_client_id {
+        vec![id.ip]
+    } else {
+        SERVER_DATA_MANAGER
+            .lock()
+            .session()
+            .client_connections
+            .iter()
+            .filter(|(hostname, client)| {
+                !primary_connected || client.spectator || is_resumable(hostname)
+            })
+            .fold(Vec::new(), |mut clients_info, (_, client)| {
+                clients_info.extend(client.manual_ips.clone());
+                clients_info
+            })
+    };
+
+    let binding = interface_binding()?;
+    let mut last_forward_time = Instant::now();
+    let (mut proto_socket, client_ip) = loop {
+        let peer = PeerType::AnyClient(client_ips.clone(), binding.clone());
+        if let Ok(pair) = ProtoControlSocket::connect_to(peer, control_port).await {
+            break pair;
+        }
+
+        debug!("Timeout while searching for client. Retrying");
+        time::sleep(CONTROL_CONNECT_RETRY_PAUSE).await;
+
+        // Fails if the headset has been unplugged
+        if let Some(device) = &wired_device {
+            if last_forward_time.elapsed() > WIRED_DEVICE_RETRY_PAUSE {
+                forward_wired_ports(device, control_port).await?;
+                last_forward_time = Instant::now();
+            }
+        }
+    };
+
+    let client_capabilities = proto_socket
+        .exchange_capabilities(&Capabilities::local())
+        .await?;
+    let capabilities =
+        if let Some(capabilities) = Capabilities::local().negotiate(&client_capabilities) {
+            capabilities
+        } else {
+            alvr_events::send_event(EventType::ClientFoundWrongVersion(format!(
+                "protocol {}-{}",
+                client_capabilities.min_protocol_version, client_capabilities.max_protocol_version
+            )));
+            return fmt_e!("Client at {client_ip} has an incompatible protocol version");
+        };
+
+    // The client is identified by its certificate, the hostname and the IP can be spoofed. Clients
+    // added manually are identified by IP only the first time they connect
+    let fingerprint = proto_socket.peer_fingerprint().ok_or_else(enone!())?;
+    let maybe_client = {
+        let mut data_manager = SERVER_DATA_MANAGER.lock();
+
+        // A new headset connected with USB is added to the list, to be paired with the PIN. The
+        // waiters are not notified, that would cancel this handshake
+        if let Some(device) = &wired_device {
+            let is_known = data_manager
+                .session()
+                .client_connections
+                .values()
+                .any(|client| client.certificate_fingerprint.as_ref() == Some(&fingerprint));
+            if !is_known {
+                let hostname = format!("{}.usb.alvr", device.serial);
+                data_manager.update_client_list(
+                    hostname.clone(),
+                    ClientListAction::AddIfMissing {
+                        display_name: format!("USB headset {}", device.serial),
+                    },
+                    None,
+                );
+                data_manager.update_client_list(
+                    hostname,
+                    ClientListAction::PinCertificate(fingerprint.clone()),
+                    None,
+                );
+            }
+        }
+
+        let client_connections = &data_manager.session().client_connections;
+
+        client_connections
+            .iter()
+            .find(|(_, client)| client.certificate_fingerprint.as_ref() == Some(&fingerprint))
+            .or_else(|| {
+                client_connections.iter().find(|(_, client)| {
+                    client.certificate_fingerprint.is_none()
+                        && client.manual_ips.contains(&client_ip)
+                })
+            })
+            .map(|(hostname, client)| (hostname.clone(), client.clone()))
+    };
+    let (hostname, client_connection) = if let Some(client) = maybe_client {
+        client
+    } else {
+        return fmt_e!("Client at {client_ip} presented an unknown certificate");
+    };
+    let spectator = client_connection.spectator;
+    if primary_connected && !spectator && !is_resumable(&hostname) {
+        return fmt_e!("Client {hostname} cannot connect while another client is streaming");
+    }
+    if spectator && !capabilities.supports(FEATURE_SPECTATOR) {
+        return fmt_e!("Client {hostname} does not support spectating");
+    }
+
+    SERVER_DATA_MANAGER.lock().update_client_list(
+        hostname.clone(),
+        ClientListAction::PinCertificate(fingerprint),
+        None,
+    );
+
+    authenticate_client(
+        &mut proto_socket,
+        hostname.clone(),
+        client_connection,
+        &capabilities,
+    )
+    .await?;
+
+    let stream_keys = proto_socket.stream_keys()?;
+
+    // The configuration sent to the client on the first connection is still valid
+    if capabilities.supports(FEATURE_RESUME) {
+        let maybe_token = proto_socket.recv::<Option<ResumeToken>>().await?;
+        let resumed = matches!(
+            (&*RESUMABLE_SESSION.lock(), maybe_token),
+            (Some(session), Some(token))
+                if session.hostname == hostname && session.token.matches(&token)
+        );
+        proto_socket.send(&resumed).await?;
+
+        if resumed {
+            let (control_sender, control_receiver) = proto_socket.split();
+
+            return Ok(ConnectionInfo {
+                hostname,
+                client_ip,
+                capabilities,
+                control_sender,
+                control_receiver,
+                stream_keys,
+                // Kept from the first connection
+                microphone_sample_rate: 0,
+                spectator,
+                server_stream_port: 0,
+                spectator_port_guard: None,
+                resumed: true,
+            });
+        }
+    }
+    if primary_connected && !spectator {
+        return fmt_e!("Client {hostname} cannot connect while another client is streaming");
+    }
+
+    let (headset_info, server_ip) = proto_socket
+        .recv::<(HeadsetInfoPacket, IpAddr)>()
+        .await
+        .map_err(err!())?;
+
+    let session_desc = client_session(&hostname);
+    let settings = session_desc.to_settings();
+
+    let (eye_width, eye_height) = match settings.video.render_resolution {
+        FrameSize::Scale(scale) => (
+            headset_info.recommended_eye_width as f32 * scale,
+            headset_info.recommended_eye_height as f32 * scale,
+        ),
+        FrameSize::Absolute { width, height } => (width as f32 / 2_f32, height as f32),
+    };
+    let video_eye_width = align32(eye_width);
+    let video_eye_height = align32(eye_height);
+
+    let (eye_width, eye_height) = match settings.video.recommended_target_resolution {
+        FrameSize::Scale(scale) => (
+            headset_info.recommended_eye_width as f32 * scale,
+            headset_info.recommended_eye_height as f32 * scale,
+        ),
+        FrameSize::Absolute { width, height } => (width as f32 / 2_f32, height as f32),
+    };
+    let target_eye_width = align32(eye_width);
+    let target_eye_height = align32(eye_height);
+
+    let fps = {
+        let mut best_match = 0_f32;
+        let mut min_diff = f32::MAX;
+        for rr in &headset_info.available_refresh_rates {
+            let diff = (*rr - settings.video.preferred_fps).abs();
+            if diff < min_diff {
+                best_match = *rr;
+                min_diff = diff;
+            }
+        }
+        best_match
+    };
+
+    if !headset_info
+        .available_refresh_rates
+        .contains(&settings.video.preferred_fps)
+    {
+        warn!("Chosen refresh rate not supported. Using {fps}Hz");
+    }
+
+    let dashboard_url = format!(
+        "http://{server_ip}:{}/",
+        settings.connection.web_server_port
+    );
+
+    let game_audio_sample_rate = if let Switch::Enabled(game_audio_desc) = settings.audio.game_audio
+    {
+        let game_audio_device = AudioDevice::new(
+            Some(settings.audio.linux_backend),
+            game_audio_desc.device_id,
+            AudioDeviceType::Output,
+        )?;
+
+        if let Switch::Enabled(microphone_desc) = settings.audio.microphone {
+            let microphone_device = AudioDevice::new(
+                Some(settings.audio.linux_backend),
+                microphone_desc.input_device_id,
+                AudioDeviceType::VirtualMicrophoneInput,
+            )?;
+            #[cfg(not(target_os = "linux"))]
+            if alvr_audio::is_same_device(&game_audio_device, &microphone_device) {
+                return fmt_e!("Game audio and microphone cannot point to the same device!");
+            }
+        }
+
+        game_audio_device.input_sample_rate()?
+    } else {
+        0
+    };
+
+    // Spectators receive the video encoded for the primary client
+    let (eye_resolution_width, eye_resolution_height, fps) = if spectator {
+        let data_manager = SERVER_DATA_MANAGER.lock();
+        let openvr_config = &data_manager.session().openvr_config;
+
+        (
+            openvr_config.eye_resolution_width,
+            openvr_config.eye_resolution_height,
+            openvr_config.refresh_rate as f32,
+        )
+    } else {
+        (video_eye_width, video_eye_height, fps)
+    };
+
+    let spectator_port_guard = if spectator {
+        let mut ports = SPECTATOR_STREAM_PORTS.lock();
+        let port = (1..)
+            .map_while(|offset| settings.connection.stream_port.checked_add(offset))
+            .find(|port| !ports.contains(port))
+            .ok_or_else(enone!())?;
+        ports.insert(port);
+
+        Some(SpectatorPortGuard(port))
+    } else {
+        None
+    };
+    let server_stream_port = spectator_port_guard
+        .as_ref()
+        .map(|guard| guard.0)
+        .unwrap_or(settings.connection.stream_port);
+
+    let client_config = ClientConfigPacket {
+        session_desc: {
+            let mut session = negotiated_session(&hostname, &capabilities);
+            if cfg!(target_os = "linux") {
+                session.session_settings.video.foveated_rendering.enabled = false;
+            }
+
+            serde_json::to_string(&session).map_err(err!())?
+        },
+        dashboard_url,
+        eye_resolution_width,
+        eye_resolution_height,
+        fps,
+        game_audio_sample_rate,
+        spectator,
+        server_stream_port,
+    };
+    proto_socket.send(&client_config).await?;
+
+    let (mut control_sender, control_receiver) = proto_socket.split();
+
+    if spectator {
+        return Ok(ConnectionInfo {
+            hostname,
+            client_ip,
+            capabilities,
+            control_sender,
+            control_receiver,
+            stream_keys,
+            microphone_sample_rate: headset_info.microphone_sample_rate,
+            spectator,
+            server_stream_port,
+            spectator_port_guard,
+            resumed: false,
+        });
+    }
+
+    let new_openvr_config = openvr_config(
+        &session_desc,
+        (video_eye_width, video_eye_height),
+        (target_eye_width, target_eye_height),
+        fps,
+    );
+
+    let maybe_apply_mode = SERVER_DATA_MANAGER
+        .lock()
+        .session()
+        .openvr_config
+        .apply_mode(&new_openvr_config);
+    if let Some(apply_mode) = maybe_apply_mode {
+        SERVER_DATA_MANAGER.lock().session_mut().openvr_config = new_openvr_config;
+        alvr_events::send_event(EventType::SettingsApplied(apply_mode));
+
+        match apply_mode {
+            SettingsApplyMode::Hot => driver.apply_settings(),
+            // No stream is running, the encoder is created again by initialize_streaming()
+            SettingsApplyMode::RestartStream => {
+                driver.restart_streaming();
+                driver.apply_settings();
+            }
+            SettingsApplyMode::RestartDriver => {
+                control_sender
+                    .send(&ServerControlPacket::Restarting)
+                    .await
+                    .ok();
+
+                driver.restart();
+
+                // waiting for execution canceling
+                future::pending::<()>().await;
+            }
+        }
+    }
+
+    Ok(ConnectionInfo {
+        hostname,
+        client_ip,
+        capabilities,
+        control_sender,
+        control_receiver,
+        stream_keys,
+        microphone_sample_rate: headset_info.microphone_sample_rate,
+        spectator,
+        server_stream_port,
+        spectator_port_guard,
+        resumed: false,
+    })
+}
+
+// close stream on Drop (manual disconnection or execution canceling)
+struct StreamCloseGuard(Arc<dyn ServerDriver>);
+
+impl Drop for StreamCloseGuard {
+    fn drop(&mut self) {
+        self.0.deinitialize_streaming();
+
+        let settings = SERVER_DATA_MANAGER.lock().session().to_settings();
+
+        let on_disconnect_script = settings.connection.on_disconnect_script;
+        if !on_disconnect_script.is_empty() {
+            info!("Running on disconnect script (disconnect): {on_disconnect_script}");
+            if let Err(e) = Command::new(&on_disconnect_script)
+                .env("ACTION", "disconnect")
+                .spawn()
+            {
+                warn!("Failed to run disconnect script: {e}");
+            }
+        }
+    }
+}
+
+// Waits for a headset connected with USB, then connects to the client through the forwarded ports
+async fn wired_client_handshake(
+    driver: &dyn ServerDriver,
+    control_port: u16,
+    adb_path: String,
+    primary_connected: bool,
+) -> StrResult<ConnectionInfo> {
+    let device = loop {
+        if let Some(device) = adb::Device::find(&adb_path).await? {
+            break device;
+        }
+
+        debug!("No headset connected with USB. Retrying");
+        time::sleep(WIRED_DEVICE_RETRY_PAUSE).await;
+    };
+
+    forward_wired_ports(&device, control_port).await?;
+
+    client_handshake(driver, control_port, None, Some(device), primary_connected).await
+}
+
+// Returns None if the connection attempt failed or the client list changed
+async fn accept_client(
+    driver: &dyn ServerDriver,
+    control_port: u16,
+    primary_connected: bool,
+) -> Option<ConnectionInfo> {
+    let mut trusted_discovered_client_id = None;
+    loop {
+        let connection_settings = SERVER_DATA_MANAGER
+            .lock()
+            .session()
+            .to_settings()
+            .connection;
+
+        // Discovery is skipped, the client is always reached through localhost
+        if let Switch::Enabled(config) = connection_settings.wired_connection {
+            let res = tokio::select! {
+                res = wired_client_handshake(
+                    driver,
+                    control_port,
+                    config.adb_path,
+                    primary_connected,
+                ) => res,
+                _ = CLIENTS_UPDATED_NOTIFIER.notified() => return None,
+            };
+
+            return match res {
+                Ok(connection_info) => Some(connection_info),
+                Err(e) => {
+                    warn!("Wired connection: {e}");
+                    None
+                }
+            };
+        }
+
+        let client_discovery_config = connection_settings.client_discovery;
+
+        let try_connection_future: BoxFuture<Either<StrResult<ClientId>, _>> =
+            if let (Switch::Enabled(config), None) =
+                (client_discovery_config, &trusted_discovered_client_id)
+            {
+                Box::pin(async move {
+                    let either = futures::future::select(
+                        Box::pin(client_discovery(control_port, config.auto_trust_clients)),
+                        Box::pin(client_handshake(
+                            driver,
+                            control_port,
+                            None,
+                            None,
+                            primary_connected,
+                        )),
+                    )
+                    .await;
+
+                    match either {
+                        Either::Left((res, _)) => Either::Left(res),
+                        Either::Right((res, _)) => Either::Right(res),
+                    }
+                })
+            } else {
+                Box::pin(async {
+                    Either::Right(
+                        client_handshake(
+                            driver,
+                            control_port,
+                            trusted_discovered_client_id.clone(),
+                            None,
+                            primary_connected,
+                        )
+                        .await,
+                    )
+                })
+            };
+
+        tokio::select! {
+            res = try_connection_future => {
+                match res {
+                    Either::Left(Ok(client_ip)) => {
+                        trusted_discovered_client_id = Some(client_ip);
+                    }
+                    Either::Left(Err(e)) => {
+                        error!("Client discovery failed: {e}");
+                        return None;
+                    }
+                    Either::Right(Ok(connection_info)) => {
+                        return Some(connection_info);
+                    }
+                    Either::Right(Err(e)) => {
+                        // do not treat handshake problems as an hard error
+                        warn!("Handshake: {e}");
+                        return None;
+                    }
+                }
+            }
+            _ = CLIENTS_UPDATED_NOTIFIER.notified() => return None,
+        };
+
+        time::sleep(CLEANUP_PAUSE).await;
+    }
+}
+
+async fn start_stream(
+    control_sender: &mut ControlSocketSender<ServerControlPacket>,
+    control_receiver: &mut ControlSocketReceiver<ClientControlPacket>,
+    hostname: &str,
+    client_ip: IpAddr,
+    server_stream_port: u16,
+    stream_keys: StreamKeys,
+    capabilities: &NegotiatedCapabilities,
+    resume_token: Option<ResumeToken>,
+) -> StrResult<StreamSocket> {
+    control_sender
+        .send(&ServerControlPacket::StartStream)
+        .await?;
+    if let Some(token) = resume_token {
+        control_sender
+            .send(&ServerControlPacket::ResumeToken(token))
+            .await?;
+    }
+
+    match control_receiver.recv().await {
+        Ok(ClientControlPacket::StreamReady) => {}
+        Ok(_) => {
+            return fmt_e!("Got unexpected packet waiting for stream ack");
+        }
+        Err(e) => {
+            return fmt_e!("Error while waiting for stream ack: {e}");
+        }
+    }
+
+    let settings = negotiated_session(hostname, capabilities).to_settings();
+    let local_ip = interface_binding()?
+        .local_ip_for(client_ip)
+        .ok_or_else(|| format!("Client at {client_ip} is outside of the bound interface"))?;
+
+    let mut stream_socket = tokio::select! {
+        res = StreamSocketBuilder::connect_to_client(
+            local_ip,
+            client_ip,
+            settings.connection.stream_port,
+            server_stream_port,
+            settings.connection.stream_protocol,
+            mbits_to_bytes(settings.video.encode_bitrate_mbs),
+            stream_keys,
+        ) => res?,
+        _ = time::sleep(Duration::from_secs(5)) => {
+            return fmt_e!("Timeout while setting up streams");
+        }
+    };
+    if let Switch::Enabled(config) = &settings.extra.debug.network_impairment {
+        stream_socket.set_impairment(config.clone());
+    }
+    stream_socket.set_drop_policies(&settings.connection.stream_drop_policies);
+
+    Ok(stream_socket)
+}
+
+// Issues a new resume token every time the stream starts
+async fn start_primary_stream(
+    connection_info: &mut ConnectionInfo,
+    resume_sender: Option<&tmpsc::UnboundedSender<ConnectionInfo>>,
+) -> StrResult<StreamSocket> {
+    let resume_token = connection_info
+        .capabilities
+        .supports(FEATURE_RESUME)
+        .then(ResumeToken::generate);
+    if let (Some(token), Some(sender)) = (resume_token, resume_sender) {
+        *RESUMABLE_SESSION.lock() = Some(ResumableSession {
+            hostname: connection_info.hostname.clone(),
+            token,
+            connection_sender: sender.clone(),
+        });
+    }
+
+    start_stream(
+        &mut connection_info.control_sender,
+        &mut connection_info.control_receiver,
+        &connection_info.hostname,
+        connection_info.client_ip,
+        connection_info.server_stream_port,
+        connection_info.stream_keys.clone(),
+        &connection_info.capabilities,
+        resume_token,
+    )
+    .await
+}
+
+enum StreamEnd {
+    // The client disconnected or the network failed
+    Disconnected,
+    // The client reconnected before the old connection was found to be broken
+    Resumed(ConnectionInfo),
+    Restarting,
+}
+
+// The encoder and the driver live as long as the session. If the connection is lost, the client
+// can resume the session within the grace period: only the sockets and the stream loops are
+// restarted
+async fn connection_pipeline(
+    driver: Arc<dyn ServerDriver>,
+    mut connection_info: ConnectionInfo,
+) -> StrResult {
+    let hostname = connection_info.hostname.clone();
+    let microphone_sample_rate = connection_info.microphone_sample_rate;
+    let server_stream_port = connection_info.server_stream_port;
+
+    *STREAMING_CLIENT.lock() = Some(hostname.clone());
+    let _streaming_client_guard = StreamingClientGuard;
+
+    let settings = negotiated_session(&hostname, &connection_info.capabilities).to_settings();
+
+    let resume_grace_period = match &settings.connection.session_resume {
+        Switch::Enabled(config) => Some(Duration::from_secs(config.grace_period_s)),
+        Switch::Disabled => None,
+    };
+    let (resume_sender, mut resume_receiver) = tmpsc::unbounded_channel();
+    let resume_sender = resume_grace_period.map(|_| resume_sender);
+    let _resume_guard = ResumableSessionGuard;
+
+    let mut stream_socket =
+        start_primary_stream(&mut connection_info, resume_sender.as_ref()).await?;
+
+    *STATISTICS_MANAGER.lock() = Some(StatisticsManager::new(
+        settings.connection.statistics_history_size as _,
+    ));
+
+    alvr_events::send_event(EventType::ClientConnected);
+
+    {
+        let on_connect_script = settings.connection.on_connect_script;
+
+        if !on_connect_script.is_empty() {
+            info!("Running on connect script (connect): {on_connect_script}");
+            if let Err(e) = Command::new(&on_connect_script)
+                .env("ACTION", "connect")
+                .spawn()
+            {
+                warn!("Failed to run connect script: {e}");
+            }
+        }
+    }
+
+    driver.initialize_streaming();
+    let _stream_guard = StreamCloseGuard(Arc::clone(&driver));
+
+    loop {
+        let stream_end = stream_pipeline(
+            Arc::clone(&driver),
+            connection_info,
+            stream_socket,
+            &mut resume_receiver,
+        )
+        .await?;
+
+        let resumed_connection_info = match stream_end {
+            StreamEnd::Disconnected => {
+                let grace_period = if let Some(grace_period) = resume_grace_period {
+                    grace_period
+                } else {
+                    return Ok(());
+                };
+
+                info!(
+                    "Waiting {}s for client {hostname} to resume the session",
+                    grace_period.as_secs()
+                );
+                tokio::select! {
+                    Some(connection_info) = resume_receiver.recv() => connection_info,
+                    _ = time::sleep(grace_period) => return Ok(()),
+                    _ = RESTART_NOTIFIER.notified() => return Ok(()),
+                    // The client cannot resume with the old settings
+                    _ = STREAM_RESTART_NOTIFIER.notified() => return Ok(()),
+                }
+            }
+            StreamEnd::Resumed(connection_info) => connection_info,
+            StreamEnd::Restarting => return Ok(()),
+        };
+
+        info!("Client {hostname} resumed the session");
+        connection_info = ConnectionInfo {
+            microphone_sample_rate,
+            server_stream_port,
+            ..resumed_connection_info
+        };
+        stream_socket = start_primary_stream(&mut connection_info, resume_sender.as_ref()).await?;
+
+        alvr_events::send_event(EventType::ClientConnected);
+
+        // The decoder of the client starts over
+        driver.request_idr();
+    }
+}
+
+async fn stream_pipeline(
+    driver: Arc<dyn ServerDriver>,
+    connection_info: ConnectionInfo,
+    stream_socket: StreamSocket,
+    resume_receiver: &mut tmpsc::UnboundedReceiver<ConnectionInfo>,
+) -> StrResult<StreamEnd> {
+    let ConnectionInfo {
+        hostname,
+        capabilities,
+        control_sender,
+        mut control_receiver,
+        microphone_sample_rate,
+        ..
+    } = connection_info;
+
+    let stream_socket = Arc::new(stream_socket);
+    let control_sender = Arc::new(Mutex::new(control_sender));
+
+    // A resumed client starts a new clock, the synchronization starts over on each connection
+    let clock_sync = capabilities
+        .supports(FEATURE_CLOCK_SYNC)
+        .then(|| Arc::new(Mutex::new(ClockSync::new(Instant::now()))));
+
+    let settings = negotiated_session(&hostname, &capabilities).to_settings();
+
+    let game_audio_loop: BoxFuture<_> = if let Switch::Enabled(desc) = settings.audio.game_audio {
+        let device = AudioDevice::new(
+            Some(settings.audio.linux_backend),
+            desc.device_id,
+            AudioDeviceType::Output,
+        )?;
+        let sender = stream_socket.request_stream(AUDIO).await?;
+        let mute_when_streaming = desc.mute_when_streaming;
+
+        #[cfg(windows)]
+        let driver = Arc::clone(&driver);
+
+        Box::pin(async move {
+            #[cfg(windows)]
+            {
+                let device_id = alvr_audio::get_windows_device_id(&device)?;
+                driver.set_openvr_property(
+                    *HEAD_ID,
+                    OpenvrPropertyKey::AudioDefaultPlaybackDeviceId,
+                    OpenvrPropValue::String(device_id),
+                );
+            }
+
+            alvr_audio::record_audio_loop(device, 2, mute_when_streaming, sender).await?;
+
+            #[cfg(windows)]
+            {
+                let default_device = AudioDevice::new(
+                    None,
+                    alvr_session::AudioDeviceId::Default,
+                    AudioDeviceType::Output,
+                )?;
+                let default_device_id = alvr_audio::get_windows_device_id(&default_device)?;
+
+                driver.set_openvr_property(
+                    *HEAD_ID,
+                    OpenvrPropertyKey::AudioDefaultPlaybackDeviceId,
+                    OpenvrPropValue::String(default_device_id),
+                );
+            }
+
+            Ok(())
+        })
+    } else {
+        Box::pin(future::pending())
+    };
+
+    let microphone_loop: BoxFuture<_> = if let Switch::Enabled(desc) = settings.audio.microphone {
+        let input_device = AudioDevice::new(
+            Some(settings.audio.linux_backend),
+            desc.input_device_id,
+            AudioDeviceType::VirtualMicrophoneInput,
+        )?;
+        let receiver = stream_socket.subscribe_to_stream(AUDIO).await?;
+
+        #[cfg(windows)]
+        {
+            let microphone_device = AudioDevice::new(
+                None,
+                desc.output_device_id,
+                AudioDeviceType::VirtualMicrophoneOutput {
+                    matching_input_device_name: input_device.name()?,
+                },
+            )?;
+            let microphone_device_id = alvr_audio::get_windows_device_id(&microphone_device)?;
+            driver.set_openvr_property(
+                *HEAD_ID,
+                OpenvrPropertyKey::AudioDefaultRecordingDeviceId,
+                OpenvrPropValue::String(microphone_device_id),
+            );
+        }
+
+        Box::pin(alvr_audio::play_audio_loop(
+            input_device,
+            1,
+            microphone_sample_rate,
+            desc.buffering_config,
+            receiver,
+        ))
+    } else {
+        Box::pin(future::pending())
+    };
+
+    let fec_controller = Arc::new(Mutex::new(FecController::new(MAX_VIDEO_FEC_PERCENTAGE)));
+
+    // When unset, the driver adapts the bitrate from the network latency
+    let bitrate_controller = match &settings.video.adaptive_bitrate {
+        Switch::Enabled(config) if config.algorithm == BitrateAlgorithm::DelayBased => {
+            let controller: Box<dyn BitrateController> = Box::new(DelayBasedController::new(
+                settings.video.encode_bitrate_mbs * BITS_IN_MBIT,
+                MIN_VIDEO_BITRATE_MBPS * BITS_IN_MBIT,
+                config.bitrate_maximum * BITS_IN_MBIT,
+            ));
+            Some(Arc::new(Mutex::new(controller)))
+        }
+        _ => None,
+    };
+    // Origin of the send timestamps given to the bitrate controller
+    let stream_start = Instant::now();
+
+    let (video_nack_sender, mut video_nack_receiver) = tmpsc::unbounded_channel();
+
+    let video_send_loop = {
+        let mut socket_sender = stream_socket.request_stream(VIDEO).await?;
+        let enable_fec = settings.connection.enable_fec;
+        let fec_controller = Arc::clone(&fec_controller);
+        let bitrate_controller = bitrate_controller.clone();
+        let drop_policy = settings.connection.stream_drop_policies.video;
+        async move {
+            let (data_sender, mut data_receiver) =
+                bounded_queue(VIDEO_SEND_QUEUE_CAPACITY, drop_policy);
+            *VIDEO_SENDER.lock() = Some(data_sender);
+
+            socket_sender.set_retransmit_cache_size(VIDEO_RETRANSMIT_CACHE_SIZE);
+
+            loop {
+                tokio::select! {
+                    maybe_data = data_receiver.recv() => {
+                        let (header, data) = match maybe_data {
+                            Some(data) => data,
+                            None => break,
+                        };
+
+                        if let Some(stats) = &mut *STATISTICS_MANAGER.lock() {
+                            stats.report_dropped_packets(data_receiver.take_dropped_count());
+                        }
+
+                        if enable_fec {
+                            socket_sender
+                                .set_fec_percentage(fec_controller.lock().await.percentage());
+                        }
+
+                        if let Some(controller) = &bitrate_controller {
+                            controller.lock().await.report_packet_sent(
+                                socket_sender.next_packet_index(),
+                                data.len(),
+                                stream_start.elapsed(),
+                            );
+                        }
+
+                        let mut buffer = socket_sender.new_buffer(&header, data.len())?;
+                        buffer.get_mut().extend(data);
+                        socket_sender.send_buffer(buffer).await.ok();
+
+                        if let Some(stats) = &mut *STATISTICS_MANAGER.lock() {
+                            stats.report_video_sent(Duration::from_nanos(
+                                header.tracking_frame_index,
+                            ));
+                        }
+                    }
+                    Some(lost_shards) = video_nack_receiver.recv() => {
+                        socket_sender.resend(&lost_shards).await.ok();
+                    }
+                }
+            }
+
+            Ok(())
+        }
+    };
+
+    let haptics_send_loop = {
+        let mut socket_sender = stream_socket.request_stream(HAPTICS).await?;
+        let drop_policy = settings.connection.stream_drop_policies.haptics;
+        async move {
+            let (data_sender, mut data_receiver) =
+                bounded_queue(HAPTICS_SEND_QUEUE_CAPACITY, drop_policy);
+            *HAPTICS_SENDER.lock() = Some(data_sender);
+
+            while let Some(haptics) = data_receiver.recv().await {
+                if let Some(stats) = &mut *STATISTICS_MANAGER.lock() {
+                    stats.report_dropped_packets(data_receiver.take_dropped_count());
+                }
+
+                socket_sender
+                    .send_buffer(socket_sender.new_buffer(&haptics, 0)?)
+                    .await
+                    .ok();
+            }
+
+            Ok(())
+        }
+    };
+
+    let (playspace_sync_sender, playspace_sync_receiver) = smpsc::channel::<Vec2>();
+
+    let is_tracking_ref_only = settings.headset.tracking_ref_only;
+    if !is_tracking_ref_only {
+        let driver = Arc::clone(&driver);
+        // use a separate thread because set_chaperone() is blocking
+        thread::spawn(move || {
+            while let Ok(packet) = playspace_sync_receiver.recv() {
+                driver.set_chaperone(packet.max(Vec2::new(2.0, 2.0)));
+            }
+        });
+    }
+
+    let tracking_receive_loop = {
+        let mut receiver = stream_socket
+            .subscribe_to_stream::<Tracking>(TRACKING)
+            .await?;
+        let driver = Arc::clone(&driver);
+        async move {
+            let controller_prediction_multiplier = settings
+                .headset
+                .controllers
+                .clone()
+                .into_option()
+                .map(|c| c.prediction_multiplier)
+                .unwrap_or_default();
+            let tracking_manager = TrackingManager::new(settings.headset);
+            loop {
+                let tracking = receiver.recv().await?.header;
+
+                let mut device_motions = vec![];
+                for (id, motion) in tracking.device_motions {
+                    let motion = if id == *HEAD_ID {
+                        tracking_manager.map_head(motion)
+                    } else if let Some(motion) = tracking_manager.map_controller(motion) {
+                        motion
+                    } else {
+                        continue;
+                    };
+                    device_motions.push((id, motion));
+                }
+
+                if let Some(stats) = &mut *STATISTICS_MANAGER.lock() {
+                    stats.report_dropped_packets(receiver.take_dropped_count());
+                    stats.report_tracking_received(tracking.target_timestamp);
+
+                    let prediction_s = stats.average_total_latency().as_secs_f32()
+                        * controller_prediction_multiplier;
+
+                    driver.set_tracking(
+                        tracking.target_timestamp,
+                        prediction_s,
+                        &device_motions,
+                        tracking.left_hand_skeleton,
+                        tracking.right_hand_skeleton,
+                    );
+                }
+            }
+        }
+    };
+
+    let statistics_receive_loop = {
+        let mut receiver = stream_socket
+            .subscribe_to_stream::<ClientStatistics>(STATISTICS)
+            .await?;
+        let stream_socket = Arc::clone(&stream_socket);
+        let enable_fec = settings.connection.enable_fec;
+        let mut last_bitrate_mbps = settings.video.encode_bitrate_mbs;
+        let clock_sync = clock_sync.clone();
+        let driver = Arc::clone(&driver);
+        async move {
+            loop {
+                let client_stats = receiver.recv().await?.header;
+
+                let client_timestamps = if let Some(clock_sync) = &clock_sync {
+                    let clock_sync = clock_sync.lock().await;
+                    clock_sync
+                        .to_local(client_stats.tracking_sent)
+                        .zip(clock_sync.to_local(client_stats.video_packet_received))
+                        .map(|(tracking_sent, video_packet_received)| ClientTimestamps {
+                            tracking_sent,
+                            video_packet_received,
+                        })
+                } else {
+                    None
+                };
+
+                let fec_percentage = if enable_fec {
+                    let mut fec_controller = fec_controller.lock().await;
+                    fec_controller.report_loss(
+                        client_stats.video_shards_count,
+                        client_stats.video_shards_lost,
+                    );
+
+                    fec_controller.percentage()
+                } else {
+                    0
+                };
+
+                if let Some(controller) = &bitrate_controller {
+                    let mut controller = controller.lock().await;
+                    controller.report_feedback(
+                        &client_stats.video_packet_arrivals,
+                        client_stats.video_packets_lost,
+                        stream_start.elapsed(),
+                    );
+
+                    let bitrate_mbps = controller.bitrate() / BITS_IN_MBIT;
+                    if bitrate_mbps != last_bitrate_mbps {
+                        driver.set_bitrate(bitrate_mbps);
+                        last_bitrate_mbps = bitrate_mbps;
+                    }
+                }
+
+                if let Some(stats) = &mut *STATISTICS_MANAGER.lock() {
+                    stats.report_dropped_packets(receiver.take_dropped_count());
+                    stats.report_fec_failures(fec_percentage as _, client_stats.video_packets_lost);
+                    stats.report_stream_statistics(stream_socket.stream_statistics());
+
+                    let network_latency = stats.report_statistics(
+                        client_stats,
+                        client_timestamps,
+                        driver.game_frame_interval(),
+                    );
+                    driver.report_network_latency(network_latency);
+                }
+            }
+        }
+    };
+
+    let keepalive_loop = {
+        let control_sender = Arc::clone(&control_sender);
+        let clock_sync = clock_sync.clone();
+        async move {
+            loop {
+                let res = {
+                    let mut control_sender = control_sender.lock().await;
+                    let res = control_sender.send(&ServerControlPacket::KeepAlive).await;
+
+                    // The clocks are synchronized at the keepalive rate
+                    match &clock_sync {
+                        Some(clock_sync) if res.is_ok() => {
+                            let request = clock_sync.lock().await.request();
+                            control_sender
+                                .send(&ServerControlPacket::ClockSync(request))
+                                .await
+                        }
+                        _ => res,
+                    }
+                };
+                if let Err(e) = res {
+                    alvr_events::send_event(EventType::ClientDisconnected);
+                    info!("Client disconnected. Cause: {e}");
+                    break Ok(());
+                }
+                time::sleep(NETWORK_KEEPALIVE_INTERVAL).await;
+            }
+        }
+    };
+
+    let control_loop = {
+        let control_sender = Arc::clone(&control_sender);
+        async move {
+            loop {
+                match control_receiver.recv().await {
+                    Ok(ClientControlPacket::ClockSync(packet)) => {
+                        if let Some(clock_sync) = &clock_sync {
+                            let response = clock_sync.lock().await.handle(packet);
+                            if let Some(response) = response {
+                                control_sender
+                                    .lock()
+                                    .await
+                                    .send(&ServerControlPacket::ClockSync(response))
+                                    .await
+                                    .ok();
+                            }
+                        }
+                    }
+                    Ok(ClientControlPacket::StreamStatistics(streams)) => {
+                        if let Some(stats) = &mut *STATISTICS_MANAGER.lock() {
+                            stats.report_client_stream_statistics(streams);
+                        }
+                    }
+                    Ok(ClientControlPacket::PlayspaceSync(packet)) => {
+                        if !is_tracking_ref_only {
+                            playspace_sync_sender.send(packet).ok();
+                        }
+                    }
+                    Ok(ClientControlPacket::RequestIdr) => driver.request_idr(),
+                    Ok(ClientControlPacket::Nack(NackPacket {
+                        stream_id: VIDEO,
+                        lost_shards,
+                    })) => {
+                        video_nack_sender.send(lost_shards).ok();
+                    }
+                    Ok(ClientControlPacket::VideoErrorReport) => driver.report_video_error(),
+                    Ok(ClientControlPacket::ViewsConfig(config)) => driver.set_views_config(config),
+                    Ok(ClientControlPacket::Battery(packet)) => {
+                        driver.set_battery(packet.device_id, packet.gauge_value, packet.is_plugged);
+
+                        if let Some(stats) = &mut *STATISTICS_MANAGER.lock() {
+                            stats.report_battery(packet.device_id, packet.gauge_value);
+                        }
+                    }
+                    Ok(ClientControlPacket::Button { path_id, value }) => {
+                        if settings.extra.log_button_presses {
+                            alvr_events::send_event(EventType::Button(ButtonEvent {
+                                path: BUTTON_PATH_FROM_ID
+                                    .get(&path_id)
+                                    .cloned()
+                                    .unwrap_or_else(|| format!("Unknown (ID: {:#16x})", path_id)),
+                                value: value.clone(),
+                            }));
+                        }
+
+                        driver.set_button(path_id, value);
+                    }
+                    Ok(_) => (),
+                    Err(e) => {
+                        alvr_events::send_event(EventType::ClientDisconnected);
+                        info!("Client disconnected. Cause: {e}");
+                        break;
+                    }
+                }
+            }
+
+            Ok(())
+        }
+    };
+
+    let receive_loop = async move { stream_socket.receive_loop().await };
+
+    let res = tokio::select! {
+        // Spawn new tasks and let the runtime manage threading
+        res = spawn_cancelable(receive_loop) => {
+            alvr_events::send_event(EventType::ClientDisconnected);
+            if let Err(e) = res {
+                info!("Client disconnected. Cause: {e}" );
+            }
+
+            Ok(())
+        },
+        res = spawn_cancelable(game_audio_loop) => res,
+        res = spawn_cancelable(microphone_loop) => res,
+        res = spawn_cancelable(video_send_loop) => res,
+        res = spawn_cancelable(statistics_receive_loop) => res,
+        res = spawn_cancelable(haptics_send_loop) => res,
+        res = spawn_cancelable(tracking_receive_loop) => res,
+
+        // Leave these loops on the current task
+        res = keepalive_loop => res,
+        res = control_loop => res,
+
+        Some(connection_info) = resume_receiver.recv() => {
+            return Ok(StreamEnd::Resumed(connection_info));
+        }
+
+        _ = RESTART_NOTIFIER.notified() => {
+            control_sender
+                .lock()
+                .await
+                .send(&ServerControlPacket::Restarting)
+                .await
+                .ok();
+
+            return Ok(StreamEnd::Restarting);
+        }
+        // The client reconnects and the settings are applied by the new handshake
+        _ = STREAM_RESTART_NOTIFIER.notified() => {
+            control_sender
+                .lock()
+                .await
+                .send(&ServerControlPacket::Restarting)
+                .await
+                .ok();
+
+            return Ok(StreamEnd::Restarting);
+        }
+    };
+
+    res.map(|_| StreamEnd::Disconnected)
+}
+
+// The IDRs are shared with the primary client, so the requests of the spectators are coalesced.
+// A request received too early is answered by an IDR scheduled at the end of the interval
+fn request_spectator_idr(driver: Arc<dyn ServerDriver>) {
+    let now = Instant::now();
+    let mut last_idr = LAST_SPECTATOR_IDR.lock();
+
+    match *last_idr {
+        Some(idr_time) if idr_time >= now => (),
+        Some(idr_time) if now < idr_time + SPECTATOR_IDR_MIN_INTERVAL => {
+            let next_idr_time = idr_time + SPECTATOR_IDR_MIN_INTERVAL;
+            *last_idr = Some(next_idr_time);
+
+            tokio::spawn(async move {
+                time::sleep_until(time::Instant::from_std(next_idr_time)).await;
+                driver.request_idr();
+            });
+        }
+        _ => {
+            *last_idr = Some(now);
+            driver.request_idr();
+        }
+    }
+}
+
+// Spectators receive the video and game audio of the primary client. Tracking, input and microphone
+// are ignored
+async fn spectator_pipeline(
+    driver: Arc<dyn ServerDriver>,
+    connection_info: ConnectionInfo,
+) -> StrResult {
+    let ConnectionInfo {
+        hostname,
+        client_ip,
+        capabilities,
+        mut control_sender,
+        mut control_receiver,
+        stream_keys,
+        microphone_sample_rate: _,
+        spectator: _,
+        server_stream_port,
+        spectator_port_guard: _spectator_port_guard,
+        resumed: _,
+    } = connection_info;
+
+    let stream_socket = start_stream(
+        &mut control_sender,
+        &mut control_receiver,
+        &hostname,
+        client_ip,
+        server_stream_port,
+        stream_keys,
+        &capabilities,
+        None,
+    )
+    .await?;
+    let stream_socket = Arc::new(stream_socket);
+    let control_sender = Arc::new(Mutex::new(control_sender));
+
+    let settings = negotiated_session(&hostname, &capabilities).to_settings();
+
+    let statistics_manager = Arc::new(Mutex::new(StatisticsManager::new(
+        settings.connection.statistics_history_size as _,
+    )));
+
+    info!("Spectator {hostname} connected");
+
+    let game_audio_loop: BoxFuture<_> = if let Switch::Enabled(desc) = settings.audio.game_audio {
+        let device = AudioDevice::new(
+            Some(settings.audio.linux_backend),
+            desc.device_id,
+            AudioDeviceType::Output,
+        )?;
+        let sender = stream_socket.request_stream(AUDIO).await?;
+
+        Box::pin(alvr_audio::record_audio_loop(device, 2, false, sender))
+    } else {
+        Box::pin(future::pending())
+    };
+
+    let fec_controller = Arc::new(Mutex::new(FecController::new(MAX_VIDEO_FEC_PERCENTAGE)));
+
+    let (video_nack_sender, mut video_nack_receiver) = tmpsc::unbounded_channel();
+
+    let video_send_loop = {
+        let mut socket_sender = stream_socket.request_stream(VIDEO).await?;
+        let enable_fec = settings.connection.enable_fec;
+        let fec_controller = Arc::clone(&fec_controller);
+        let statistics_manager = Arc::clone(&statistics_manager);
+        let drop_policy = settings.connection.stream_drop_policies.video;
+        async move {
+            let (data_sender, mut data_receiver) =
+                bounded_queue(VIDEO_SEND_QUEUE_CAPACITY, drop_policy);
+            SPECTATOR_VIDEO_SENDERS.lock().push(data_sender);
+
+            socket_sender.set_retransmit_cache_size(VIDEO_RETRANSMIT_CACHE_SIZE);
+
+            loop {
+                tokio::select! {
+                    maybe_data = data_receiver.recv() => {
+                        let (header, data) = match maybe_data {
+                            Some(data) => data,
+                            None => break,
+                        };
+
+                        if enable_fec {
+                            socket_sender
+                                .set_fec_percentage(fec_controller.lock().await.percentage());
+                        }
+
+                        {
+                            let mut stats = statistics_manager.lock().await;
+                            stats.report_dropped_packets(data_receiver.take_dropped_count());
+                            stats.report_video_packet(data.len());
+                        }
+
+                        let mut buffer = socket_sender.new_buffer(&header, data.len())?;
+                        buffer.get_mut().extend(data);
+                        socket_sender.send_buffer(buffer).await.ok();
+                    }
+                    Some(lost_shards) = video_nack_receiver.recv() => {
+                        socket_sender.resend(&lost_shards).await.ok();
+                    }
+                }
+            }
+
+            Ok(())
+        }
+    };
+
+    let statistics_receive_loop = {
+        let mut receiver = stream_socket
+            .subscribe_to_stream::<ClientStatistics>(STATISTICS)
+            .await?;
+        let enable_fec = settings.connection.enable_fec;
+        let hostname = hostname.clone();
+        async move {
+            loop {
+                let client_stats = receiver.recv().await?.header;
+
+                let fec_percentage = if enable_fec {
+                    let mut fec_controller = fec_controller.lock().await;
+                    fec_controller.report_loss(
+                        client_stats.video_shards_count,
+                        client_stats.video_shards_lost,
+                    );
+
+                    fec_controller.percentage()
+                } else {
+                    0
+                };
+
+                let mut stats = statistics_manager.lock().await;
+                stats.report_fec_failures(fec_percentage as _, client_stats.video_packets_lost);
+                stats.report_spectator_statistics(&hostname, client_stats);
+            }
+        }
+    };
+
+    let keepalive_loop = {
+        let control_sender = Arc::clone(&control_sender);
+        let hostname = hostname.clone();
+        async move {
+            loop {
+                let res = control_sender
+                    .lock()
+                    .await
+                    .send(&ServerControlPacket::KeepAlive)
+                    .await;
+                if let Err(e) = res {
+                    info!("Spectator {hostname} disconnected. Cause: {e}");
+                    break Ok(());
+                }
+                time::sleep(NETWORK_KEEPALIVE_INTERVAL).await;
+            }
+        }
+    };
+
+    let control_loop = async move {
+        loop {
+            match control_receiver.recv().await {
+                // The IDR is needed by the spectator to start decoding
+                Ok(ClientControlPacket::RequestIdr) => request_spectator_idr(Arc::clone(&driver)),
+                Ok(ClientControlPacket::Nack(NackPacket {
+                    stream_id: VIDEO,
+                    lost_shards,
+                })) => {
+                    video_nack_sender.send(lost_shards).ok();
+                }
+                Ok(_) => (),
+                Err(e) => {
+                    info!("Spectator {hostname} disconnected. Cause: {e}");
+                    break;
+                }
+            }
+        }
+
+        Ok(())
+    };
+
+    let receive_loop = async move { stream_socket.receive_loop().await };
+
+    tokio::select! {
+        res = spawn_cancelable(receive_loop) => res,
+        res = spawn_cancelable(game_audio_loop) => res,
+        res = spawn_cancelable(video_send_loop) => res,
+        res = spawn_cancelable(statistics_receive_loop) => res,
+
+        res = keepalive_loop => res,
+        res = control_loop => res,
+
+        _ = RESTART_NOTIFIER.notified() => {
+            control_sender
+                .lock()
+                .await
+                .send(&ServerControlPacket::Restarting)
+                .await
+                .ok();
+
+            Ok(())
+        }
+        // The video of the primary client is encoded again with the new settings
+        _ = STREAM_RESTART_NOTIFIER.notified() => {
+            control_sender
+                .lock()
+                .await
+                .send(&ServerControlPacket::Restarting)
+                .await
+                .ok();
+
+            Ok(())
+        }
+    }
+}
+
+// The primary client and the spectators are accepted by the same loop, so clients are discovered
+// also while streaming
+pub async fn connection_lifecycle_loop(driver: Arc<dyn ServerDriver>, control_port: u16) {
+    let mut primary_pipeline: Option<BoxFuture<StrResult>> = None;
+    let mut spectator_pipelines = FuturesUnordered::<BoxFuture<StrResult>>::new();
+
+    loop {
+        let primary_connected = primary_pipeline.is_some();
+
+        tokio::select! {
+            maybe_connection_info = async {
+                let maybe_connection_info =
+                    accept_client(&*driver, control_port, primary_connected).await;
+                if maybe_connection_info.is_none() {
+                    time::sleep(RETRY_CONNECT_MIN_INTERVAL).await;
+                }
+
+                maybe_connection_info
+            } => {
+                match maybe_connection_info {
+                    Some(connection_info) if connection_info.resumed => {
+                        if let Some(session) = &*RESUMABLE_SESSION.lock() {
+                            session.connection_sender.send(connection_info).ok();
+                        }
+                    }
+                    Some(connection_info) if connection_info.spectator => {
+                        spectator_pipelines.push(Box::pin(spectator_pipeline(
+                            Arc::clone(&driver),
+                            connection_info,
+                        )));
+                    }
+                    Some(connection_info) => {
+                        primary_pipeline = Some(Box::pin(connection_pipeline(
+                            Arc::clone(&driver),
+                            connection_info,
+                        )));
+                    }
+                    None => (),
+                }
+            }
+            res = async {
+                match &mut primary_pipeline {
+                    Some(pipeline) => pipeline.await,
+                    None => future::pending().await,
+                }
+            } => {
+                primary_pipeline = None;
+                alvr_common::show_err(res);
+
+                // let any running task or socket shutdown
+                time::sleep(CLEANUP_PAUSE).await;
+            }
+            Some(res) = spectator_pipelines.next() => alvr_common::show_err(res),
+        }
+    }
+}
@@ -1,20 +1,19 @@
 use alvr_common::{prelude::*, ALVR_NAME};
 use alvr_events::EventType;
 use alvr_sockets::{
-    ClientHandshakePacket, HandshakePacket, ServerHandshakePacket, CONTROL_PORT, LOCAL_IP,
-    MAX_HANDSHAKE_PACKET_SIZE_BYTES,
+    codec, ClientHandshakePacket, DiscoverySocket, HandshakePacket, InterfaceBinding,
+    ServerHandshakePacket, MAX_HANDSHAKE_PACKET_SIZE_BYTES,
 };
 use std::{future::Future, net::IpAddr};
-use tokio::net::UdpSocket;
 
 // client_found_cb: returns true if client is trusted, false otherwise
 pub async fn search_client_loop<F: Future<Output = bool>>(
+    binding: &InterfaceBinding,
+    port: u16,
     client_found_cb: impl Fn(ClientHandshakePacket) -> F,
 ) -> StrResult<(IpAddr, ClientHandshakePacket)> {
-    // use naked UdpSocket + [u8] packet buffer to have more control over datagram data
-    let handshake_socket = UdpSocket::bind((LOCAL_IP, CONTROL_PORT))
-        .await
-        .map_err(err!())?;
+    // use naked sockets + [u8] packet buffer to have more control over datagram data
+    let handshake_socket = DiscoverySocket::bind(port)?;
 
     let mut packet_buffer = [0u8; MAX_HANDSHAKE_PACKET_SIZE_BYTES];
 
@@ -27,14 +26,20 @@ pub async fn search_client_loop<F: Future<Output = bool>>(
                 }
             };
 
+        if binding.local_ip_for(client_address.ip()).is_none() {
+            debug!("Ignored client at {client_address}, outside of the bound interface");
+            continue;
+        }
+
+        let packet_bytes = &packet_buffer[..handshake_packet_size];
         let handshake_packet = if let Ok(HandshakePacket::Client(packet)) =
-            bincode::deserialize(&packet_buffer[..handshake_packet_size])
+            codec::decode(packet_bytes)
         {
             packet
-        } else if &packet_buffer[..5] == b"\x01ALVR" {
+        } else if packet_bytes.starts_with(b"\x01ALVR") {
             alvr_events::send_event(EventType::ClientFoundWrongVersion("v11 or previous".into()));
             return fmt_e!("ALVR client version is too old!");
-        } else if &packet_buffer[..4] == b"ALVR" {
+        } else if packet_bytes.starts_with(b"ALVR") {
             alvr_events::send_event(EventType::ClientFoundWrongVersion(
                 "v12.x.x - v13.x.x".into(),
             ));
@@ -49,27 +54,11 @@ pub async fn search_client_loop<F: Future<Output = bool>>(
             return fmt_e!("Error while identifying client");
         }
 
-        if !alvr_common::is_version_compatible(&handshake_packet.version) {
-            let response_bytes = bincode::serialize(&HandshakePacket::Server(
-                ServerHandshakePacket::IncompatibleVersions,
-            ))
-            .map_err(err!())?;
-            handshake_socket
-                .send_to(&response_bytes, client_address)
-                .await
-                .ok();
-
-            alvr_events::send_event(EventType::ClientFoundWrongVersion(
-                handshake_packet.version.to_string(),
-            ));
-            return fmt_e!("Found ALVR client with incompatible version");
-        }
-
+        // The protocol version is checked later, when negotiating the capabilities
         if !client_found_cb(handshake_packet.clone()).await {
-            let response_bytes = bincode::serialize(&HandshakePacket::Server(
+            let response_bytes = codec::encode(&HandshakePacket::Server(
                 ServerHandshakePacket::ClientUntrusted,
-            ))
-            .map_err(err!())?;
+            ))?;
 
             handshake_socket
                 .send_to(&response_bytes, client_address)
@@ -0,0 +1,38 @@
+use alvr_common::glam::{Quat, Vec2};
+use alvr_events::ButtonValue;
+use alvr_session::{OpenvrPropValue, OpenvrPropertyKey};
+use alvr_sockets::{DeviceMotion, ViewsConfig};
+use std::time::Duration;
+
+// Calls from the connection code into the OpenVR driver and the encoder. alvr_server implements
+// them with the C++ driver, the tests with a fake one. The video and the haptics go the other way,
+// through send_video() and send_haptics()
+pub trait ServerDriver: Send + Sync {
+    fn initialize_streaming(&self);
+    fn deinitialize_streaming(&self);
+    // Releases the encoder, so that initialize_streaming() creates it again with the new settings
+    fn restart_streaming(&self);
+    // Reloads the settings and pushes the ones that can change while streaming
+    fn apply_settings(&self);
+    fn request_idr(&self);
+    fn set_tracking(
+        &self,
+        target_timestamp: Duration,
+        controller_prediction_s: f32,
+        device_motions: &[(u64, DeviceMotion)],
+        left_hand_skeleton: Option<[Quat; 19]>,
+        right_hand_skeleton: Option<[Quat; 19]>,
+    );
+    fn report_network_latency(&self, latency: Duration);
+    fn set_bitrate(&self, bitrate_mbps: u64);
+    fn game_frame_interval(&self) -> Duration;
+    fn report_video_error(&self);
+    fn set_openvr_property(&self, device_id: u64, key: OpenvrPropertyKey, value: OpenvrPropValue);
+    // Blocking, it is called from a separate thread
+    fn set_chaperone(&self, area_size: Vec2);
+    fn set_views_config(&self, config: ViewsConfig);
+    fn set_battery(&self, device_id: u64, gauge_value: f32, is_plugged: bool);
+    fn set_button(&self, path_id: u64, value: ButtonValue);
+    // Restarts SteamVR, and the server core with it
+    fn restart(&self);
+}
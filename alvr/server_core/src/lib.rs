@@ -0,0 +1,85 @@
+// Connection side of the server: client discovery, handshake, pairing and streams. It reaches the
+// OpenVR driver and the encoder through ServerDriver only, so it can run without SteamVR.
+
+mod adb;
+mod bitrate;
+mod buttons;
+mod connection;
+mod connection_utils;
+mod driver;
+mod statistics;
+mod tracking;
+
+pub use connection::{apply_settings_change, connection_lifecycle_loop};
+pub use driver::ServerDriver;
+pub use statistics::StatisticsManager;
+
+use alvr_common::{
+    once_cell::sync::{Lazy, OnceCell},
+    parking_lot::Mutex,
+};
+use alvr_server_data::ServerDataManager;
+use alvr_sockets::{Haptics, QueueSender, VideoFrameHeaderPacket};
+use connection::ResumableSession;
+use std::{
+    collections::{HashMap, HashSet},
+    path::PathBuf,
+    time::Instant,
+};
+use tokio::sync::Notify;
+
+// Set by the host before the first use of SERVER_DATA_MANAGER
+pub static SESSION_PATH: OnceCell<PathBuf> = OnceCell::new();
+pub static SERVER_DATA_MANAGER: Lazy<Mutex<ServerDataManager>> =
+    Lazy::new(|| Mutex::new(ServerDataManager::new(SESSION_PATH.get().unwrap())));
+pub static STATISTICS_MANAGER: Lazy<Mutex<Option<StatisticsManager>>> =
+    Lazy::new(|| Mutex::new(None));
+// PINs entered in the dashboard, by client hostname. They are consumed by the next pairing attempt
+pub static PAIRING_PINS: Lazy<Mutex<HashMap<String, String>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+// Server stream ports in use by spectators
+static SPECTATOR_STREAM_PORTS: Lazy<Mutex<HashSet<u16>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+// Hostname of the primary client, set while its session runs. Session resume can be disabled
+static STREAMING_CLIENT: Lazy<Mutex<Option<String>>> = Lazy::new(|| Mutex::new(None));
+// Set while the primary client is streaming or waiting to resume the session
+static RESUMABLE_SESSION: Lazy<Mutex<Option<ResumableSession>>> = Lazy::new(|| Mutex::new(None));
+
+// Bounded, with the drop policies of the session. The encoder and the driver never wait
+static VIDEO_SENDER: Lazy<Mutex<Option<QueueSender<(VideoFrameHeaderPacket, Vec<u8>)>>>> =
+    Lazy::new(|| Mutex::new(None));
+static HAPTICS_SENDER: Lazy<Mutex<Option<QueueSender<Haptics>>>> = Lazy::new(|| Mutex::new(None));
+// Every spectator receives a copy of the video packets sent to the primary client
+static SPECTATOR_VIDEO_SENDERS: Lazy<Mutex<Vec<QueueSender<(VideoFrameHeaderPacket, Vec<u8>)>>>> =
+    Lazy::new(|| Mutex::new(vec![]));
+// Time of the last IDR requested by a spectator, or of the next one if it is scheduled
+static LAST_SPECTATOR_IDR: Lazy<Mutex<Option<Instant>>> = Lazy::new(|| Mutex::new(None));
+
+pub static CLIENTS_UPDATED_NOTIFIER: Lazy<Notify> = Lazy::new(Notify::new);
+pub static RESTART_NOTIFIER: Lazy<Notify> = Lazy::new(Notify::new);
+// Ends the stream of the primary client, that reconnects. SteamVR keeps running
+static STREAM_RESTART_NOTIFIER: Lazy<Notify> = Lazy::new(Notify::new);
+
+// Called by the encoder for each video packet. Dropped if no client is streaming
+pub fn send_video(header: VideoFrameHeaderPacket, data: Vec<u8>) {
+    if let Some(sender) = &*VIDEO_SENDER.lock() {
+        let len = data.len();
+
+        // Senders of disconnected spectators are removed
+        SPECTATOR_VIDEO_SENDERS
+            .lock()
+            .retain(|sender| sender.try_send((header.clone(), data.clone())).is_ok());
+
+        sender.try_send((header, data)).ok();
+
+        if let Some(stats) = &mut *STATISTICS_MANAGER.lock() {
+            stats.report_video_packet(len);
+        }
+    }
+}
+
+// Called by the driver. Dropped if no client is streaming
+pub fn send_haptics(haptics: Haptics) {
+    if let Some(sender) = &*HAPTICS_SENDER.lock() {
+        sender.try_send(haptics).ok();
+    }
+}
@@ -1,6 +1,6 @@
 use alvr_common::{HEAD_ID, LEFT_HAND_ID, RIGHT_HAND_ID};
-use alvr_events::{EventType, GraphStatistics, Statistics};
-use alvr_sockets::ClientStatistics;
+use alvr_events::{EventType, GraphStatistics, SpectatorStatistics, Statistics, StreamStatistics};
+use alvr_sockets::{stream_name, ClientStatistics};
 use std::{
     cmp,
     collections::{HashMap, VecDeque},
@@ -9,15 +9,29 @@ use std::{
 
 const FULL_REPORT_INTERVAL: Duration = Duration::from_millis(500);
 
+fn by_stream_name(streams: &HashMap<u16, StreamStatistics>) -> HashMap<String, StreamStatistics> {
+    streams
+        .iter()
+        .map(|(&stream_id, stats)| (stream_name(stream_id), stats.clone()))
+        .collect()
+}
+
 pub struct HistoryFrame {
     target_timestamp: Duration,
     tracking_received: Instant,
     frame_present: Instant,
     frame_composed: Instant,
     frame_encoded: Instant,
+    video_sent: Instant,
     total_pipeline_latency: Duration,
 }
 
+// Timestamps of the client, converted to the server clock with ClockSync
+pub struct ClientTimestamps {
+    pub tracking_sent: Instant,
+    pub video_packet_received: Instant,
+}
+
 impl Default for HistoryFrame {
     fn default() -> Self {
         let now = Instant::now();
@@ -27,6 +41,7 @@ impl Default for HistoryFrame {
             frame_present: now,
             frame_composed: now,
             frame_encoded: now,
+            video_sent: now,
             total_pipeline_latency: Duration::ZERO,
         }
     }
@@ -43,8 +58,12 @@ pub struct StatisticsManager {
     fec_errors_total: usize,
     fec_failures_partial_sum: usize,
     fec_percentage: u32,
+    packets_dropped_total: usize,
+    packets_dropped_partial_sum: usize,
     battery_gauges: HashMap<u64, f32>,
     last_average_total_latency: Duration,
+    server_streams: HashMap<u16, StreamStatistics>,
+    client_streams: HashMap<u16, StreamStatistics>,
 }
 
 impl StatisticsManager {
@@ -61,8 +80,12 @@ impl StatisticsManager {
             fec_errors_total: 0,
             fec_failures_partial_sum: 0,
             fec_percentage: 0,
+            packets_dropped_total: 0,
+            packets_dropped_partial_sum: 0,
             battery_gauges: HashMap::new(),
             last_average_total_latency: Duration::ZERO,
+            server_streams: HashMap::new(),
+            client_streams: HashMap::new(),
         }
     }
 
@@ -114,6 +137,16 @@ impl StatisticsManager {
         }
     }
 
+    pub fn report_video_sent(&mut self, target_timestamp: Duration) {
+        if let Some(frame) = self
+            .history_buffer
+            .iter_mut()
+            .find(|frame| frame.target_timestamp == target_timestamp)
+        {
+            frame.video_sent = Instant::now();
+        }
+    }
+
     pub fn report_video_packet(&mut self, bytes_count: usize) {
         self.video_packets_total += 1;
         self.video_packets_partial_sum += 1;
@@ -121,10 +154,26 @@ impl StatisticsManager {
         self.video_bytes_partial_sum += bytes_count;
     }
 
-    pub fn report_fec_failure(&mut self, fec_percentage: u32) {
+    pub fn report_fec_failures(&mut self, fec_percentage: u32, failures_count: usize) {
         self.fec_percentage = fec_percentage;
-        self.fec_errors_total += 1;
-        self.fec_failures_partial_sum += 1;
+        self.fec_errors_total += failures_count;
+        self.fec_failures_partial_sum += failures_count;
+    }
+
+    // Packets discarded by the bounded stream queues
+    pub fn report_dropped_packets(&mut self, count: usize) {
+        self.packets_dropped_total += count;
+        self.packets_dropped_partial_sum += count;
+    }
+
+    // Counters of the stream socket of the server, by stream ID
+    pub fn report_stream_statistics(&mut self, streams: HashMap<u16, StreamStatistics>) {
+        self.server_streams = streams;
+    }
+
+    // Sent by the clients that support FEATURE_STREAM_STATISTICS
+    pub fn report_client_stream_statistics(&mut self, streams: HashMap<u16, StreamStatistics>) {
+        self.client_streams = streams;
     }
 
     pub fn report_battery(&mut self, device_id: u64, gauge_value: f32) {
@@ -136,6 +185,7 @@ impl StatisticsManager {
     pub fn report_statistics(
         &mut self,
         client_stats: ClientStatistics,
+        client_timestamps: Option<ClientTimestamps>,
         game_frame_interval: Duration,
     ) -> Duration {
         self.last_average_total_latency = client_stats.average_total_pipeline_latency;
@@ -159,20 +209,38 @@ impl StatisticsManager {
                 .frame_encoded
                 .saturating_duration_since(frame.frame_composed);
 
-            // The network latency cannot be estiamed directly. It is what's left of the total
-            // latency after subtracting all other latency intervals. In particular it contains the
-            // transport latency of the tracking packet and the interval between the first video
-            // packet is sent and the last video packet is received for a specific frame.
+            // With synchronized clocks, the latency of each direction is measured directly: the
+            // transport of the tracking packet and the interval between the video packet is sent
+            // and it is received for a specific frame
+            let one_way_latencies = client_timestamps.map(|timestamps| {
+                (
+                    frame
+                        .tracking_received
+                        .saturating_duration_since(timestamps.tracking_sent),
+                    timestamps
+                        .video_packet_received
+                        .saturating_duration_since(frame.video_sent),
+                )
+            });
+
+            // Otherwise the network latency cannot be estiamed directly. It is what's left of the
+            // total latency after subtracting all other latency intervals.
             // For safety, use saturating_sub to avoid a crash if for some reason the network
             // latency is miscalculated as negative.
-            let network_latency = frame.total_pipeline_latency.saturating_sub(
-                game_time_latency
-                    + server_compositor_latency
-                    + encoder_latency
-                    + client_stats.video_decode
-                    + client_stats.rendering
-                    + client_stats.vsync_queue,
-            );
+            let network_latency =
+                if let Some((uplink_latency, downlink_latency)) = one_way_latencies {
+                    uplink_latency + downlink_latency
+                } else {
+                    frame.total_pipeline_latency.saturating_sub(
+                        game_time_latency
+                            + server_compositor_latency
+                            + encoder_latency
+                            + client_stats.video_decode
+                            + client_stats.rendering
+                            + client_stats.vsync_queue,
+                    )
+                };
+            let (uplink_latency, downlink_latency) = one_way_latencies.unwrap_or_default();
 
             if self.last_full_report_instant + FULL_REPORT_INTERVAL < Instant::now() {
                 self.last_full_report_instant += FULL_REPORT_INTERVAL;
@@ -188,11 +256,16 @@ impl StatisticsManager {
                         / 1e6,
                     total_latency_ms: client_stats.total_pipeline_latency.as_secs_f32() * 1000.,
                     network_latency_ms: network_latency.as_secs_f32() * 1000.,
+                    uplink_latency_ms: uplink_latency.as_secs_f32() * 1000.,
+                    downlink_latency_ms: downlink_latency.as_secs_f32() * 1000.,
                     encode_latency_ms: encoder_latency.as_secs_f32() * 1000.,
                     decode_latency_ms: client_stats.video_decode.as_secs_f32() * 1000.,
                     fec_percentage: self.fec_percentage,
                     fec_errors_total: self.fec_errors_total,
                     fec_errors_per_sec: (self.fec_failures_partial_sum as f32 / interval_secs) as _,
+                    packets_dropped_total: self.packets_dropped_total,
+                    packets_dropped_per_sec: (self.packets_dropped_partial_sum as f32
+                        / interval_secs) as _,
                     client_fps: (1. / client_stats.frame_interval.as_secs_f32()) as _,
                     server_fps: (1. / game_frame_interval.as_secs_f32()) as _,
                     battery_hmd: (self
@@ -213,11 +286,14 @@ impl StatisticsManager {
                         .cloned()
                         .unwrap_or_default()
                         * 100.) as _,
+                    server_streams: by_stream_name(&self.server_streams),
+                    client_streams: by_stream_name(&self.client_streams),
                 }));
 
                 self.video_packets_partial_sum = 0;
                 self.video_bytes_partial_sum = 0;
                 self.fec_failures_partial_sum = 0;
+                self.packets_dropped_partial_sum = 0;
             }
 
             // todo: use target timestamp in nanoseconds. the dashboard needs to use the first
@@ -241,6 +317,30 @@ impl StatisticsManager {
         }
     }
 
+    // Spectators do not send tracking, so the latency of each frame cannot be measured
+    pub fn report_spectator_statistics(&mut self, hostname: &str, client_stats: ClientStatistics) {
+        if self.last_full_report_instant + FULL_REPORT_INTERVAL < Instant::now() {
+            self.last_full_report_instant += FULL_REPORT_INTERVAL;
+
+            let interval_secs = FULL_REPORT_INTERVAL.as_secs_f32();
+
+            alvr_events::send_event(EventType::SpectatorStatistics(SpectatorStatistics {
+                hostname: hostname.to_owned(),
+                video_packets_total: self.video_packets_total,
+                video_packets_per_sec: (self.video_packets_partial_sum as f32 / interval_secs) as _,
+                video_mbits_per_sec: self.video_bytes_partial_sum as f32 / interval_secs * 8. / 1e6,
+                decode_latency_ms: client_stats.video_decode.as_secs_f32() * 1000.,
+                fec_errors_total: self.fec_errors_total,
+                fec_errors_per_sec: (self.fec_failures_partial_sum as f32 / interval_secs) as _,
+                client_fps: (1. / client_stats.frame_interval.as_secs_f32()) as _,
+            }));
+
+            self.video_packets_partial_sum = 0;
+            self.video_bytes_partial_sum = 0;
+            self.fec_failures_partial_sum = 0;
+        }
+    }
+
     pub fn average_total_latency(&self) -> Duration {
         self.last_average_total_latency
     }
@@ -63,7 +63,11 @@ impl ServerDataManager {
 
         let session_desc = match fs::read_to_string(&session_path) {
             Ok(session_string) => {
-                let json_value = json::from_str::<json::Value>(&session_string).unwrap();
+                let mut json_value = json::from_str::<json::Value>(&session_string).unwrap();
+                // The session can deserialize even if the meaning of some settings changed
+                if let Err(e) = alvr_session::migrate_session_json(&mut json_value) {
+                    error!("Error while migrating session: {e}");
+                }
                 match json::from_value(json_value.clone()) {
                     Ok(session_desc) => session_desc,
                     Err(_) => {
@@ -220,12 +224,51 @@ impl ServerDataManager {
                         trusted: false,
                         manual_ips: HashSet::new(),
                         display_name,
+                        certificate_fingerprint: None,
+                        pairing_secret: None,
+                        spectator: false,
+                        settings_profile: None,
+                        settings_override: None,
                     };
                     new_entry.insert(client_connection_desc);
 
                     updated = true;
                 }
             }
+            ClientListAction::PinCertificate(fingerprint) => {
+                if let Entry::Occupied(mut entry) = maybe_client_entry {
+                    let client_connection_ref = entry.get_mut();
+                    // Once pinned, the certificate can be changed only by removing the entry
+                    if client_connection_ref.certificate_fingerprint.is_none() {
+                        client_connection_ref.certificate_fingerprint = Some(fingerprint);
+
+                        updated = true;
+                    }
+                }
+            }
+            ClientListAction::Pair { pairing_secret } => {
+                if let Entry::Occupied(mut entry) = maybe_client_entry {
+                    let client_connection_ref = entry.get_mut();
+                    client_connection_ref.trusted = true;
+                    client_connection_ref.pairing_secret = Some(pairing_secret);
+
+                    updated = true;
+                }
+            }
+            ClientListAction::SetSpectator(spectator) => {
+                if let Entry::Occupied(mut entry) = maybe_client_entry {
+                    entry.get_mut().spectator = spectator;
+
+                    updated = true;
+                }
+            }
+            ClientListAction::SetSettingsProfile(maybe_profile_name) => {
+                if let Entry::Occupied(mut entry) = maybe_client_entry {
+                    entry.get_mut().settings_profile = maybe_profile_name;
+
+                    updated = true;
+                }
+            }
             ClientListAction::TrustAndMaybeAddIp(maybe_ip) => {
                 if let Entry::Occupied(mut entry) = maybe_client_entry {
                     let client_connection_ref = entry.get_mut();
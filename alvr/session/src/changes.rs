@@ -0,0 +1,141 @@
+// Classification of the OpenvrConfig fields by what is needed to apply a change. Hot fields are read
+// by the driver while streaming, stream fields are read when the encoder is created and when the
+// client connects, any other field is read by SteamVR only at startup.
+
+use crate::OpenvrConfig;
+use serde::{Deserialize, Serialize};
+use serde_json as json;
+
+// Ordered from the least to the most disruptive
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum SettingsApplyMode {
+    Hot,
+    RestartStream,
+    RestartDriver,
+}
+
+const HOT_FIELDS: &[&str] = &[
+    "encode_bitrate_mbs",
+    "position_offset",
+    "linear_velocity_cutoff",
+    "angular_velocity_cutoff",
+    "position_offset_left",
+    "rotation_offset_left",
+    "haptics_intensity",
+    "haptics_amplitude_curve",
+    "haptics_min_duration",
+    "haptics_low_duration_amplitude_multiplier",
+    "haptics_low_duration_range",
+    "brightness",
+    "contrast",
+    "saturation",
+    "gamma",
+    "sharpening",
+];
+
+const STREAM_FIELDS: &[&str] = &[
+    "aggressive_keyframe_resend",
+    "codec",
+    "use_10bit_encoder",
+    "force_sw_encoding",
+    "sw_thread_count",
+    "enable_adaptive_bitrate",
+    "bitrate_maximum",
+    "latency_target",
+    "latency_use_frametime",
+    "latency_target_maximum",
+    "latency_target_offset",
+    "latency_threshold",
+    "bitrate_up_rate",
+    "bitrate_down_rate",
+    "bitrate_light_load_threshold",
+    "enable_foveated_rendering",
+    "foveation_center_size_x",
+    "foveation_center_size_y",
+    "foveation_center_shift_x",
+    "foveation_center_shift_y",
+    "foveation_edge_ratio_x",
+    "foveation_edge_ratio_y",
+    "enable_color_correction",
+    "enable_fec",
+];
+
+pub fn field_apply_mode(name: &str) -> SettingsApplyMode {
+    if HOT_FIELDS.contains(&name) {
+        SettingsApplyMode::Hot
+    } else if STREAM_FIELDS.contains(&name) {
+        SettingsApplyMode::RestartStream
+    } else {
+        SettingsApplyMode::RestartDriver
+    }
+}
+
+impl OpenvrConfig {
+    // Names of the fields that differ from `other`
+    pub fn changed_fields(&self, other: &OpenvrConfig) -> Vec<String> {
+        match (json::to_value(self), json::to_value(other)) {
+            (Ok(json::Value::Object(fields)), Ok(json::Value::Object(other_fields))) => fields
+                .into_iter()
+                .filter(|(name, value)| other_fields.get(name) != Some(value))
+                .map(|(name, _)| name)
+                .collect(),
+            _ => vec![],
+        }
+    }
+
+    // None if the configurations are equivalent
+    pub fn apply_mode(&self, new_config: &OpenvrConfig) -> Option<SettingsApplyMode> {
+        self.changed_fields(new_config)
+            .iter()
+            .map(|name| field_apply_mode(name))
+            .max()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn apply_mode() {
+        let config = OpenvrConfig::default();
+
+        assert_eq!(config.apply_mode(&config.clone()), None);
+
+        let hot_config = OpenvrConfig {
+            encode_bitrate_mbs: 50,
+            haptics_amplitude_curve: 0.5,
+            brightness: 0.1,
+            ..config.clone()
+        };
+        assert_eq!(config.apply_mode(&hot_config), Some(SettingsApplyMode::Hot));
+
+        let stream_config = OpenvrConfig {
+            codec: 1,
+            ..hot_config.clone()
+        };
+        assert_eq!(
+            config.apply_mode(&stream_config),
+            Some(SettingsApplyMode::RestartStream)
+        );
+
+        let driver_config = OpenvrConfig {
+            refresh_rate: 120,
+            ..stream_config
+        };
+        assert_eq!(
+            config.apply_mode(&driver_config),
+            Some(SettingsApplyMode::RestartDriver)
+        );
+    }
+
+    // A renamed field would silently fall back to RestartDriver
+    #[test]
+    fn classified_fields_exist() {
+        let config = json::to_value(OpenvrConfig::default()).unwrap();
+
+        for name in HOT_FIELDS.iter().chain(STREAM_FIELDS) {
+            assert!(config.get(name).is_some(), "{name}");
+        }
+    }
+}
@@ -0,0 +1,129 @@
+// Compact representation of the settings, to share them: only the values that differ from the
+// defaults are kept. The result is a partial session settings tree, that can be merged into any
+// session with extrapolation.
+
+use alvr_common::prelude::*;
+use serde_json as json;
+use std::path::Path;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SettingsFileFormat {
+    Json,
+    Toml,
+}
+
+impl SettingsFileFormat {
+    pub fn from_path(path: &Path) -> Self {
+        if path
+            .extension()
+            .map_or(false, |extension| extension == "toml")
+        {
+            Self::Toml
+        } else {
+            Self::Json
+        }
+    }
+}
+
+// Fields missing from the defaults are kept. Arrays are compared as a whole
+pub fn json_diff(value: &json::Value, default: &json::Value) -> Option<json::Value> {
+    match (value, default) {
+        (json::Value::Object(fields), json::Value::Object(default_fields)) => {
+            let diff_fields = fields
+                .iter()
+                .filter_map(|(name, field)| {
+                    let maybe_diff = if let Some(default_field) = default_fields.get(name) {
+                        json_diff(field, default_field)
+                    } else {
+                        Some(field.clone())
+                    };
+
+                    maybe_diff.map(|diff| (name.clone(), diff))
+                })
+                .collect::<json::Map<_, _>>();
+
+            (!diff_fields.is_empty()).then(|| json::Value::Object(diff_fields))
+        }
+        _ => (value != default).then(|| value.clone()),
+    }
+}
+
+pub fn settings_diff_to_string(
+    diff: &json::Value,
+    format: SettingsFileFormat,
+) -> StrResult<String> {
+    match format {
+        SettingsFileFormat::Json => json::to_string_pretty(diff).map_err(err!()),
+        SettingsFileFormat::Toml => {
+            // toml::Value writes the plain values of each table before the nested tables
+            let toml_value = toml::Value::try_from(diff).map_err(err!())?;
+            toml::to_string_pretty(&toml_value).map_err(err!())
+        }
+    }
+}
+
+// The format is detected from the content
+pub fn settings_diff_from_str(text: &str) -> StrResult<json::Value> {
+    json::from_str(text).or_else(|json_error| {
+        toml::from_str(text).map_err(|toml_error| {
+            format!("Invalid settings file. As JSON: {json_error}. As TOML: {toml_error}")
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn diff() {
+        let default = json::json!({
+            "video": {
+                "preferredFps": 72.0,
+                "codec": { "variant": "H264" },
+                "offset": [0.0, 0.0]
+            },
+            "audio": { "enabled": true }
+        });
+        let value = json::json!({
+            "video": {
+                "preferredFps": 90.0,
+                "codec": { "variant": "H264" },
+                "offset": [0.0, 1.0],
+                "newField": 1
+            },
+            "audio": { "enabled": true }
+        });
+
+        assert_eq!(
+            json_diff(&value, &default),
+            Some(json::json!({
+                "video": { "preferredFps": 90.0, "offset": [0.0, 1.0], "newField": 1 }
+            }))
+        );
+        assert_eq!(json_diff(&default, &default), None);
+    }
+
+    #[test]
+    fn file_formats() {
+        let diff = json::json!({
+            "video": {
+                "preferredFps": 90.0,
+                "codec": { "variant": "HEVC" },
+                "encodeBitrateMbs": 100
+            },
+            "connection": { "streamPort": 9950 }
+        });
+
+        for format in [SettingsFileFormat::Json, SettingsFileFormat::Toml] {
+            let text = settings_diff_to_string(&diff, format).unwrap();
+            assert_eq!(settings_diff_from_str(&text).unwrap(), diff);
+        }
+
+        assert_eq!(
+            SettingsFileFormat::from_path(Path::new("quest2.toml")),
+            SettingsFileFormat::Toml
+        );
+        assert!(settings_diff_from_str("video = {").is_err());
+    }
+}
@@ -1,6 +1,14 @@
+mod changes;
+mod diff;
+mod migrations;
 mod settings;
+mod validation;
 
+pub use changes::*;
+pub use diff::*;
+pub use migrations::*;
 pub use settings::*;
+pub use validation::*;
 
 use alvr_common::{prelude::*, semver::Version, ALVR_VERSION};
 use serde::{Deserialize, Serialize};
@@ -17,12 +25,12 @@ pub type SessionSettings = settings::SettingsDefault;
 
 // This structure is used to store the minimum configuration data that ALVR driver needs to
 // initialize OpenVR before having the chance to communicate with a client. When a client is
-// connected, a new OpenvrConfig instance is generated, then the connection is accepted only if that
-// instance is equivalent to the one stored in the session, otherwise SteamVR is restarted.
+// connected, a new OpenvrConfig instance is generated, then SteamVR is restarted only if some of the
+// changed fields are read by SteamVR at startup (see SettingsApplyMode). The other fields are
+// applied to the running driver, and while streaming the hot fields are applied without
+// reconnecting the client.
 // Other components (like the encoder, audio recorder) don't need this treatment and are initialized
 // dynamically.
-// todo: properties that can be set after the OpenVR initialization should be removed and set with
-// UpdateForStream.
 #[derive(Serialize, Deserialize, PartialEq, Default, Clone, Debug)]
 pub struct OpenvrConfig {
     pub universe_id: u64,
@@ -105,6 +113,22 @@ pub struct ClientConnectionDesc {
     pub display_name: String,
     pub manual_ips: HashSet<IpAddr>,
     pub trusted: bool,
+    // Pinned the first time the client is seen. Only the client owning this certificate can
+    // connect using this entry
+    #[serde(default)]
+    pub certificate_fingerprint: Option<String>,
+    // Set when the client is paired using the PIN shown on the headset
+    #[serde(default)]
+    pub pairing_secret: Option<Vec<u8>>,
+    // Spectators watch the stream of the primary client without controlling it
+    #[serde(default)]
+    pub spectator: bool,
+    // Name of the entry of SessionDesc::settings_profiles applied when this client connects
+    #[serde(default)]
+    pub settings_profile: Option<String>,
+    // Partial sessionSettings tree applied on top of the settings profile
+    #[serde(default)]
+    pub settings_override: Option<json::Value>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -119,6 +143,10 @@ pub struct SessionDesc {
     // The hashmap key is the hostname
     pub client_connections: HashMap<String, ClientConnectionDesc>,
     pub session_settings: SessionSettings,
+    // Partial sessionSettings trees, by profile name. They are applied on top of
+    // session_settings for the clients that select them
+    #[serde(default)]
+    pub settings_profiles: HashMap<String, json::Value>,
     pub advanced: bool,
 }
 
@@ -154,6 +182,7 @@ impl Default for SessionDesc {
             },
             client_connections: HashMap::new(),
             session_settings: settings::session_settings_default(),
+            settings_profiles: HashMap::new(),
             advanced: false,
         }
     }
@@ -170,6 +199,10 @@ impl SessionDesc {
     pub fn merge_from_json(&mut self, json_value: &json::Value) -> StrResult {
         const SESSION_SETTINGS_STR: &str = "sessionSettings";
 
+        let mut json_value = json_value.clone();
+        migrate_session_json(&mut json_value)?;
+        let json_value = &json_value;
+
         if let Ok(session_desc) = json::from_value(json_value.clone()) {
             *self = session_desc;
             return Ok(());
@@ -219,31 +252,148 @@ impl SessionDesc {
         }
     }
 
+    // Like merge_from_json, but the session is left untouched if any setting does not respect the
+    // schema
+    pub fn merge_from_json_validated(
+        &mut self,
+        json_value: &json::Value,
+    ) -> Result<(), Vec<SettingsDiagnostic>> {
+        let mut json_value = json_value.clone();
+        migrate_session_json(&mut json_value).map_err(|e| {
+            vec![SettingsDiagnostic {
+                path: "serverVersion".into(),
+                problem: e,
+            }]
+        })?;
+
+        let diagnostics = validate_session(&json_value);
+        if !diagnostics.is_empty() {
+            return Err(diagnostics);
+        }
+
+        self.merge_from_json(&json_value).map_err(|e| {
+            vec![SettingsDiagnostic {
+                path: String::new(),
+                problem: e,
+            }]
+        })
+    }
+
+    // Settings that differ from the defaults, as a partial session. The version of the server is
+    // included, so that newer servers can migrate the settings when importing them
+    pub fn settings_diff(&self) -> json::Value {
+        let session_settings_diff = json_diff(
+            &json::to_value(&self.session_settings).unwrap(),
+            &json::to_value(settings::session_settings_default()).unwrap(),
+        )
+        .unwrap_or_else(|| json::Value::Object(json::Map::new()));
+
+        json::json!({
+            "serverVersion": ALVR_VERSION.to_string(),
+            "sessionSettings": session_settings_diff,
+        })
+    }
+
+    // Merges a partial session created by settings_diff(), possibly by an older server. Only the
+    // settings are merged, the version of the session is kept
+    pub fn merge_settings_diff(
+        &mut self,
+        diff: &json::Value,
+    ) -> Result<(), Vec<SettingsDiagnostic>> {
+        let mut diff = diff.clone();
+        migrate_session_json(&mut diff).map_err(|e| {
+            vec![SettingsDiagnostic {
+                path: "serverVersion".into(),
+                problem: e,
+            }]
+        })?;
+
+        let session_settings_diff = diff.get("sessionSettings").cloned().ok_or_else(|| {
+            vec![SettingsDiagnostic {
+                path: "sessionSettings".into(),
+                problem: "Missing".into(),
+            }]
+        })?;
+
+        self.merge_from_json_validated(&json::json!({ "sessionSettings": session_settings_diff }))
+    }
+
+    // Session used for a client, with its settings profile and its override applied. Clients
+    // without an entry use the base session
+    pub fn for_client(&self, hostname: &str) -> StrResult<SessionDesc> {
+        let client_connection = if let Some(connection) = self.client_connections.get(hostname) {
+            connection
+        } else {
+            return Ok(self.clone());
+        };
+
+        let schema = settings::settings_schema(settings::session_settings_default());
+        let mut session_settings_json = json::to_value(&self.session_settings).map_err(err!())?;
+
+        if let Some(profile_name) = &client_connection.settings_profile {
+            let profile_json = self
+                .settings_profiles
+                .get(profile_name)
+                .ok_or_else(|| format!("Settings profile \"{profile_name}\" does not exist"))?;
+            check_settings_tree(profile_json, &schema)?;
+
+            session_settings_json = extrapolate_session_settings_from_session_settings(
+                &session_settings_json,
+                profile_json,
+                &schema,
+            );
+        }
+        if let Some(override_json) = &client_connection.settings_override {
+            check_settings_tree(override_json, &schema)?;
+
+            session_settings_json = extrapolate_session_settings_from_session_settings(
+                &session_settings_json,
+                override_json,
+                &schema,
+            );
+        }
+
+        let mut session_desc = self.clone();
+        session_desc.session_settings = json::from_value(session_settings_json)
+            .map_err(|e| format!("Invalid settings for client {hostname}: {e}"))?;
+
+        Ok(session_desc)
+    }
+
     // This function requires that settings enums with data have tag = "type" and content = "content", and
     // enums without data do not have tag and content set.
+    // The conversion cannot fail, SessionSettings contains every branch of Settings
     pub fn to_settings(&self) -> Settings {
         let session_settings_json = json::to_value(&self.session_settings).unwrap();
         let schema = settings::settings_schema(settings::session_settings_default());
 
-        if let Err(e) = json::from_value::<Settings>(json_session_settings_to_settings(
-            &session_settings_json,
-            &schema,
-        )) {
-            dbg!(e);
-        }
         json::from_value(json_session_settings_to_settings(
             &session_settings_json,
             &schema,
         ))
-        .unwrap()
+        .unwrap_or_else(|e| panic!("Failed to convert the session settings: {e}"))
+    }
+}
+
+fn check_settings_tree(session_settings: &json::Value, schema: &SchemaNode) -> StrResult {
+    let diagnostics = validate_session_settings(session_settings, schema);
+    if diagnostics.is_empty() {
+        Ok(())
+    } else {
+        Err(diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.to_string())
+            .collect::<Vec<_>>()
+            .join("; "))
     }
 }
 
 // Current data extrapolation strategy: match both field name and value type exactly.
 // Integer bounds are not validated, if they do not match the schema, deserialization will fail and
 // all data is lost.
-// Future strategies: check if value respects schema constraints, fuzzy field name matching, accept
-// integer to float and float to integer, tree traversal.
+// Renamed, moved and converted settings are handled by the migrations, applied before extrapolation.
+// Future strategies: check if value respects schema constraints, accept integer to float and float
+// to integer.
 fn extrapolate_session_settings_from_session_settings(
     old_session_settings: &json::Value,
     new_session_settings: &json::Value,
@@ -590,4 +740,162 @@ mod tests {
             .merge_from_json(&json::from_str(input_json_string).unwrap())
             .unwrap();
     }
+
+    fn client_with_profile(
+        settings_profile: Option<&str>,
+        settings_override: Option<json::Value>,
+    ) -> ClientConnectionDesc {
+        ClientConnectionDesc {
+            display_name: "Quest 2".into(),
+            manual_ips: HashSet::new(),
+            trusted: true,
+            certificate_fingerprint: None,
+            pairing_secret: None,
+            spectator: false,
+            settings_profile: settings_profile.map(|name| name.to_owned()),
+            settings_override,
+        }
+    }
+
+    #[test]
+    fn test_session_for_client() {
+        let mut session_desc = SessionDesc::default();
+        session_desc.settings_profiles.insert(
+            "Quest2-90Hz".into(),
+            json::json!({
+                "video": {
+                    "preferredFps": 90.0,
+                    "adaptiveBitrate": { "enabled": false }
+                }
+            }),
+        );
+        session_desc.client_connections.insert(
+            "profile.client.alvr".into(),
+            client_with_profile(Some("Quest2-90Hz"), None),
+        );
+        session_desc.client_connections.insert(
+            "override.client.alvr".into(),
+            client_with_profile(
+                Some("Quest2-90Hz"),
+                Some(json::json!({ "video": { "preferredFps": 120.0 } })),
+            ),
+        );
+        session_desc.client_connections.insert(
+            "missing.client.alvr".into(),
+            client_with_profile(Some("Quest3"), None),
+        );
+        session_desc.client_connections.insert(
+            "invalid.client.alvr".into(),
+            client_with_profile(
+                None,
+                Some(json::json!({ "video": { "encodeBitrateMbs": 1000 } })),
+            ),
+        );
+
+        let base_video = &session_desc.session_settings.video;
+
+        let profile_video = session_desc
+            .for_client("profile.client.alvr")
+            .unwrap()
+            .session_settings
+            .video;
+        assert_eq!(profile_video.preferred_fps, 90.0);
+        assert!(!profile_video.adaptive_bitrate.enabled);
+        assert_eq!(
+            profile_video.encode_bitrate_mbs,
+            base_video.encode_bitrate_mbs
+        );
+
+        let override_video = session_desc
+            .for_client("override.client.alvr")
+            .unwrap()
+            .session_settings
+            .video;
+        assert_eq!(override_video.preferred_fps, 120.0);
+        assert!(!override_video.adaptive_bitrate.enabled);
+
+        let unknown_video = session_desc
+            .for_client("unknown.client.alvr")
+            .unwrap()
+            .session_settings
+            .video;
+        assert_eq!(unknown_video.preferred_fps, base_video.preferred_fps);
+
+        assert!(session_desc.for_client("missing.client.alvr").is_err());
+        assert!(session_desc.for_client("invalid.client.alvr").is_err());
+    }
+
+    #[test]
+    fn test_settings_diff() {
+        assert_eq!(
+            SessionDesc::default().settings_diff(),
+            json::json!({ "serverVersion": ALVR_VERSION.to_string(), "sessionSettings": {} })
+        );
+
+        let mut session_desc = SessionDesc::default();
+        session_desc.session_settings.video.preferred_fps = 90.;
+        session_desc.session_settings.connection.stream_port = 9950;
+        let diff = session_desc.settings_diff();
+        assert_eq!(
+            diff["sessionSettings"],
+            json::json!({
+                "video": { "preferredFps": 90.0 },
+                "connection": { "streamPort": 9950 }
+            })
+        );
+
+        let mut imported_session_desc = SessionDesc::default();
+        imported_session_desc.merge_settings_diff(&diff).unwrap();
+        assert_eq!(
+            imported_session_desc.session_settings.video.preferred_fps,
+            90.
+        );
+        assert_eq!(
+            imported_session_desc
+                .session_settings
+                .connection
+                .stream_port,
+            9950
+        );
+
+        let invalid_diff =
+            json::json!({ "sessionSettings": { "video": { "encodeBitrateMbs": 0 } } });
+        assert!(imported_session_desc
+            .merge_settings_diff(&invalid_diff)
+            .is_err());
+        assert_eq!(
+            imported_session_desc
+                .session_settings
+                .video
+                .encode_bitrate_mbs,
+            SessionDesc::default()
+                .session_settings
+                .video
+                .encode_bitrate_mbs
+        );
+    }
+
+    #[test]
+    fn test_settings_diff_migration() {
+        // Exported before AudioConfig became AudioBufferingConfig
+        let diff = json::json!({
+            "serverVersion": "18.2.3",
+            "sessionSettings": {
+                "audio": { "gameAudio": { "content": { "config": { "averageBufferingMs": 80 } } } }
+            }
+        });
+
+        let mut session_desc = SessionDesc::default();
+        session_desc.merge_settings_diff(&diff).unwrap();
+
+        let buffering_config = &session_desc
+            .session_settings
+            .audio
+            .game_audio
+            .content
+            .buffering_config;
+        assert_eq!(buffering_config.average_buffering_ms, 80);
+        // The session keeps its version
+        assert_eq!(session_desc.server_version, *ALVR_VERSION);
+    }
 }
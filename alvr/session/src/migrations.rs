@@ -0,0 +1,237 @@
+// Upgrades of the sessions stored by older servers. Extrapolation keeps only the settings whose name
+// and type did not change, so every rename, move or change of unit of a setting must register a
+// migration here, in the release that introduces it. The migrations are applied in order to the
+// session json, before extrapolation. Each upgrade path is covered by a session stored by the old
+// version, in tests/sessions.
+
+use alvr_common::{prelude::*, semver::Version};
+use serde_json as json;
+
+const SERVER_VERSION_STR: &str = "serverVersion";
+
+// Paths start from the root of the session, like "sessionSettings.video.preferredFps". Steps whose
+// value is missing are skipped
+pub enum MigrationStep {
+    Rename {
+        path: &'static str,
+        new_name: &'static str,
+    },
+    Move {
+        from: &'static str,
+        to: &'static str,
+    },
+    // Replaces the value, for example to convert its unit
+    Convert {
+        path: &'static str,
+        convert: fn(json::Value) -> json::Value,
+    },
+}
+
+pub struct Migration {
+    // First version that stores the session with the new layout
+    pub version: &'static str,
+    pub steps: &'static [MigrationStep],
+}
+
+// Sorted by version
+pub const MIGRATIONS: &[Migration] = &[Migration {
+    version: "19.0.0-dev00",
+    // AudioConfig became AudioBufferingConfig
+    steps: &[
+        MigrationStep::Rename {
+            path: "sessionSettings.audio.gameAudio.content.config",
+            new_name: "bufferingConfig",
+        },
+        MigrationStep::Rename {
+            path: "sessionSettings.audio.microphone.content.config",
+            new_name: "bufferingConfig",
+        },
+    ],
+}];
+
+fn json_pointer(path: &[&str]) -> String {
+    path.iter().map(|segment| format!("/{segment}")).collect()
+}
+
+fn take_value(session: &mut json::Value, path: &[&str]) -> Option<json::Value> {
+    let (name, parent_path) = path.split_last()?;
+
+    session
+        .pointer_mut(&json_pointer(parent_path))?
+        .as_object_mut()?
+        .remove(*name)
+}
+
+// Missing parent sections are created
+fn insert_value(session: &mut json::Value, path: &[&str], value: json::Value) {
+    if let Some((name, parent_path)) = path.split_last() {
+        let mut parent = session;
+        for segment in parent_path {
+            parent = match parent.as_object_mut() {
+                Some(object) => object
+                    .entry(*segment)
+                    .or_insert_with(|| json::Value::Object(json::Map::new())),
+                None => return,
+            };
+        }
+
+        if let Some(object) = parent.as_object_mut() {
+            object.insert((*name).to_owned(), value);
+        }
+    }
+}
+
+fn move_value(session: &mut json::Value, from: &[&str], to: &[&str]) {
+    if let Some(value) = take_value(session, from) {
+        insert_value(session, to, value);
+    }
+}
+
+fn apply_step(session: &mut json::Value, step: &MigrationStep) {
+    match step {
+        MigrationStep::Rename { path, new_name } => {
+            let path = path.split('.').collect::<Vec<_>>();
+            let mut new_path = path.clone();
+            new_path.pop();
+            new_path.push(*new_name);
+
+            move_value(session, &path, &new_path);
+        }
+        MigrationStep::Move { from, to } => {
+            let from = from.split('.').collect::<Vec<_>>();
+            let to = to.split('.').collect::<Vec<_>>();
+
+            move_value(session, &from, &to);
+        }
+        MigrationStep::Convert { path, convert } => {
+            let path = path.split('.').collect::<Vec<_>>();
+            if let Some(value) = session.pointer_mut(&json_pointer(&path)) {
+                *value = convert(value.take());
+            }
+        }
+    }
+}
+
+fn apply_migrations(session: &mut json::Value, migrations: &[Migration]) -> StrResult {
+    let stored_version =
+        if let Some(version_str) = session.get(SERVER_VERSION_STR).and_then(|v| v.as_str()) {
+            Version::parse(version_str)
+                .map_err(|e| format!("Invalid session version {version_str}: {e}"))?
+        } else {
+            return Ok(());
+        };
+
+    for migration in migrations {
+        let version = Version::parse(migration.version).map_err(err!())?;
+        if version > stored_version {
+            info!("Migrating session from version {stored_version} to {version}");
+
+            for step in migration.steps {
+                apply_step(session, step);
+            }
+            // The session is not migrated again if it is stored before being extrapolated
+            session[SERVER_VERSION_STR] = json::Value::String(version.to_string());
+        }
+    }
+
+    Ok(())
+}
+
+// Sessions without a version, like the partial ones sent by the dashboard, are considered up to
+// date
+pub fn migrate_session_json(session: &mut json::Value) -> StrResult {
+    apply_migrations(session, MIGRATIONS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn halve(value: json::Value) -> json::Value {
+        json::json!(value.as_f64().unwrap() / 2.)
+    }
+
+    const TEST_MIGRATIONS: &[Migration] = &[
+        Migration {
+            version: "18.0.0",
+            steps: &[MigrationStep::Rename {
+                path: "sessionSettings.video.fps",
+                new_name: "preferredFps",
+            }],
+        },
+        Migration {
+            version: "19.0.0-dev00",
+            steps: &[
+                MigrationStep::Move {
+                    from: "sessionSettings.video.streamPort",
+                    to: "sessionSettings.connection.streamPort",
+                },
+                MigrationStep::Convert {
+                    path: "sessionSettings.video.preferredFps",
+                    convert: halve,
+                },
+                MigrationStep::Convert {
+                    path: "sessionSettings.video.missing",
+                    convert: halve,
+                },
+            ],
+        },
+    ];
+
+    #[test]
+    fn registry_is_sorted() {
+        let versions = MIGRATIONS
+            .iter()
+            .map(|migration| Version::parse(migration.version).unwrap())
+            .collect::<Vec<_>>();
+
+        assert!(versions.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn migration_steps() {
+        let mut session = json::json!({
+            "serverVersion": "17.0.0",
+            "sessionSettings": { "video": { "fps": 144.0, "streamPort": 9944 } }
+        });
+        apply_migrations(&mut session, TEST_MIGRATIONS).unwrap();
+
+        assert_eq!(
+            session,
+            json::json!({
+                "serverVersion": "19.0.0-dev00",
+                "sessionSettings": {
+                    "video": { "preferredFps": 72.0 },
+                    "connection": { "streamPort": 9944 }
+                }
+            })
+        );
+
+        // Only the newer migrations are applied
+        let mut session = json::json!({
+            "serverVersion": "18.5.0",
+            "sessionSettings": { "video": { "fps": 144.0, "preferredFps": 90.0 } }
+        });
+        apply_migrations(&mut session, TEST_MIGRATIONS).unwrap();
+
+        assert_eq!(session["sessionSettings"]["video"]["fps"], 144.0);
+        assert_eq!(session["sessionSettings"]["video"]["preferredFps"], 45.0);
+
+        // Applying the migrations again has no effect
+        let migrated_session = session.clone();
+        apply_migrations(&mut session, TEST_MIGRATIONS).unwrap();
+        assert_eq!(session, migrated_session);
+    }
+
+    #[test]
+    fn sessions_without_version() {
+        let partial_session = json::json!({ "sessionSettings": { "video": { "fps": 144.0 } } });
+
+        let mut session = partial_session.clone();
+        apply_migrations(&mut session, TEST_MIGRATIONS).unwrap();
+        assert_eq!(session, partial_session);
+
+        let mut session = json::json!({ "serverVersion": "latest" });
+        assert!(apply_migrations(&mut session, TEST_MIGRATIONS).is_err());
+    }
+}
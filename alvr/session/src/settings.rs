@@ -59,9 +59,19 @@ pub struct LatencyUseFrametimeDesc {
     pub latency_target_offset: i32,
 }
 
+#[derive(SettingsSchema, Serialize, Deserialize, Copy, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", tag = "type", content = "content")]
+pub enum BitrateAlgorithm {
+    Latency,
+    DelayBased,
+}
+
 #[derive(SettingsSchema, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct AdaptiveBitrateDesc {
+    #[schema(advanced)]
+    pub algorithm: BitrateAlgorithm,
+
     #[schema(min = 10, max = 500, step = 1)]
     pub bitrate_maximum: u64,
 
@@ -433,6 +443,8 @@ pub enum SocketProtocol {
     },
 
     Tcp,
+
+    Quic,
 }
 
 #[derive(SettingsSchema, Serialize, Deserialize)]
@@ -442,11 +454,65 @@ pub struct DiscoveryConfig {
     pub auto_trust_clients: bool,
 }
 
+// A client that lost the connection can resume the stream within the grace period, without
+// restarting the encoder
+#[derive(SettingsSchema, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionResumeDesc {
+    #[schema(min = 1, max = 60, step = 1)]
+    pub grace_period_s: u64,
+}
+
+// Restricts the server to the clients reachable through one network interface. Discovery
+// announcements from other networks are ignored
+#[derive(SettingsSchema, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BindInterfaceDesc {
+    // Interface name, like "eth0" or "Wi-Fi", or one of its IP addresses
+    pub name_or_address: String,
+}
+
+// The client is reached through ports forwarded over USB with adb, instead of the network.
+// Discovery is skipped and the streams use TCP
+#[derive(SettingsSchema, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WiredConnectionDesc {
+    // Path of the adb executable. If empty, adb is searched in PATH
+    #[schema(advanced)]
+    pub adb_path: String,
+}
+
+// What happens to the packets of a stream when its queue is full
+#[derive(SettingsSchema, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", tag = "type", content = "content")]
+pub enum DropPolicy {
+    // The oldest queued packet is discarded, so that the latency stays bounded
+    DropOldest,
+    // The sender waits until there is room in the send queue. Received packets always drop the
+    // oldest ones, so that a stalled stream does not stall the others
+    Block,
+}
+
+#[derive(SettingsSchema, Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct StreamDropPoliciesDesc {
+    pub video: DropPolicy,
+    pub tracking: DropPolicy,
+    pub haptics: DropPolicy,
+    pub audio: DropPolicy,
+    pub statistics: DropPolicy,
+}
+
 #[derive(SettingsSchema, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct ConnectionDesc {
     pub client_discovery: Switch<DiscoveryConfig>,
 
+    pub wired_connection: Switch<WiredConnectionDesc>,
+
+    #[schema(advanced)]
+    pub bind_interface: Switch<BindInterfaceDesc>,
+
     #[schema(advanced, min = 1024, max = 65535)]
     pub web_server_port: u16,
 
@@ -469,6 +535,50 @@ pub struct ConnectionDesc {
 
     #[schema(advanced)]
     pub statistics_history_size: u64,
+
+    #[schema(advanced)]
+    pub stream_drop_policies: StreamDropPoliciesDesc,
+
+    #[schema(advanced)]
+    pub session_resume: Switch<SessionResumeDesc>,
+}
+
+// Applied to the stream packets sent by the server and by the client. A value of 0 disables the
+// corresponding impairment
+#[derive(SettingsSchema, Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct NetworkImpairmentDesc {
+    #[schema(min = 0., max = 1., step = 0.01)]
+    pub loss_probability: f32,
+
+    // Probability of starting a burst of lost packets
+    #[schema(min = 0., max = 1., step = 0.01)]
+    pub burst_loss_probability: f32,
+
+    #[schema(min = 1, max = 100, step = 1)]
+    pub average_burst_length: u64,
+
+    #[schema(min = 0, max = 1000, step = 1)]
+    pub latency_ms: u64,
+
+    #[schema(min = 0, max = 500, step = 1)]
+    pub jitter_ms: u64,
+
+    #[schema(min = 0., max = 1., step = 0.01)]
+    pub reorder_probability: f32,
+
+    #[schema(min = 0, max = 1000, step = 1)]
+    pub bandwidth_limit_mbps: u64,
+
+    // Makes the impaired packets the same on every run. Otherwise the random generator is seeded
+    // from the system entropy
+    pub random_seed: Switch<u64>,
+}
+
+#[derive(SettingsSchema, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DebugDesc {
+    pub network_impairment: Switch<NetworkImpairmentDesc>,
 }
 
 #[derive(SettingsSchema, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
@@ -514,6 +624,9 @@ pub struct ExtraDesc {
     pub exclude_notifications_without_id: bool,
 
     patches: Patches,
+
+    #[schema(advanced)]
+    pub debug: DebugDesc,
 }
 
 #[derive(SettingsSchema, Serialize, Deserialize)]
@@ -583,6 +696,9 @@ pub fn session_settings_default() -> SettingsDefault {
             adaptive_bitrate: SwitchDefault {
                 enabled: true,
                 content: AdaptiveBitrateDescDefault {
+                    algorithm: BitrateAlgorithmDefault {
+                        variant: BitrateAlgorithmDefaultVariant::Latency,
+                    },
                     bitrate_maximum: 200,
                     latency_target: 12000,
                     latency_use_frametime: SwitchDefault {
@@ -716,6 +832,18 @@ pub fn session_settings_default() -> SettingsDefault {
                     auto_trust_clients: cfg!(debug_assertions),
                 },
             },
+            wired_connection: SwitchDefault {
+                enabled: false,
+                content: WiredConnectionDescDefault {
+                    adb_path: "".into(),
+                },
+            },
+            bind_interface: SwitchDefault {
+                enabled: false,
+                content: BindInterfaceDescDefault {
+                    name_or_address: "".into(),
+                },
+            },
             web_server_port: 8082,
             stream_protocol: SocketProtocolDefault {
                 variant: if !cfg!(target_os = "linux") {
@@ -733,6 +861,27 @@ pub fn session_settings_default() -> SettingsDefault {
             on_disconnect_script: "".into(),
             enable_fec: true,
             statistics_history_size: 1024,
+            stream_drop_policies: StreamDropPoliciesDescDefault {
+                video: DropPolicyDefault {
+                    variant: DropPolicyDefaultVariant::DropOldest,
+                },
+                tracking: DropPolicyDefault {
+                    variant: DropPolicyDefaultVariant::DropOldest,
+                },
+                haptics: DropPolicyDefault {
+                    variant: DropPolicyDefaultVariant::DropOldest,
+                },
+                audio: DropPolicyDefault {
+                    variant: DropPolicyDefaultVariant::Block,
+                },
+                statistics: DropPolicyDefault {
+                    variant: DropPolicyDefaultVariant::DropOldest,
+                },
+            },
+            session_resume: SwitchDefault {
+                enabled: true,
+                content: SessionResumeDescDefault { grace_period_s: 10 },
+            },
         },
         extra: ExtraDescDefault {
             theme: ThemeDefault {
@@ -765,6 +914,24 @@ pub fn session_settings_default() -> SettingsDefault {
                 remove_sync_popup: false,
                 linux_async_reprojection: true,
             },
+            debug: DebugDescDefault {
+                network_impairment: SwitchDefault {
+                    enabled: false,
+                    content: NetworkImpairmentDescDefault {
+                        loss_probability: 0.,
+                        burst_loss_probability: 0.,
+                        average_burst_length: 5,
+                        latency_ms: 0,
+                        jitter_ms: 0,
+                        reorder_probability: 0.,
+                        bandwidth_limit_mbps: 0,
+                        random_seed: SwitchDefault {
+                            enabled: false,
+                            content: 0,
+                        },
+                    },
+                },
+            },
         },
     }
 }
@@ -0,0 +1,345 @@
+// Checks of a session settings tree against the constraints of the schema. Deserialization of
+// SessionSettings only checks the types, so out of range values and unknown variants would be
+// accepted otherwise. Fields missing from the tree are not reported, so partial trees (settings
+// profiles and client overrides) can be validated too. Unknown fields are ignored like during
+// extrapolation.
+
+use serde::{Deserialize, Serialize};
+use serde_json as json;
+use settings_schema::SchemaNode;
+use std::fmt::{self, Display, Formatter};
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SettingsDiagnostic {
+    // Same format used by the dashboard, like "video.adaptiveBitrate.content.bitrateMaximum"
+    pub path: String,
+    pub problem: String,
+}
+
+impl Display for SettingsDiagnostic {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "{}", self.problem)
+        } else {
+            write!(f, "{}: {}", self.path, self.problem)
+        }
+    }
+}
+
+fn field_path(path: &str, field_name: &str) -> String {
+    if path.is_empty() {
+        field_name.to_owned()
+    } else {
+        format!("{path}.{field_name}")
+    }
+}
+
+struct Validator {
+    diagnostics: Vec<SettingsDiagnostic>,
+}
+
+impl Validator {
+    fn report(&mut self, path: &str, problem: String) {
+        self.diagnostics.push(SettingsDiagnostic {
+            path: path.to_owned(),
+            problem,
+        });
+    }
+
+    fn check_bounds(&mut self, path: &str, value: f64, min: Option<f64>, max: Option<f64>) {
+        if let Some(min) = min {
+            if value < min {
+                self.report(path, format!("{value} is less than the minimum {min}"));
+            }
+        }
+        if let Some(max) = max {
+            if value > max {
+                self.report(path, format!("{value} is greater than the maximum {max}"));
+            }
+        }
+    }
+
+    fn check_boolean_field(&mut self, session_settings: &json::Value, path: &str, name: &str) {
+        if let Some(value) = session_settings.get(name) {
+            if !value.is_boolean() {
+                self.report(&field_path(path, name), "expected a boolean".into());
+            }
+        }
+    }
+
+    fn validate(&mut self, session_settings: &json::Value, schema: &SchemaNode, path: &str) {
+        match schema {
+            SchemaNode::Section { entries } => {
+                if !session_settings.is_object() {
+                    return self.report(path, "expected a section".into());
+                }
+
+                for (field_name, maybe_data) in entries {
+                    if let (Some(data_schema), Some(value)) =
+                        (maybe_data, session_settings.get(field_name))
+                    {
+                        self.validate(value, &data_schema.content, &field_path(path, field_name));
+                    }
+                }
+            }
+
+            SchemaNode::Choice { variants, .. } => {
+                if !session_settings.is_object() {
+                    return self.report(path, "expected a choice".into());
+                }
+
+                if let Some(variant) = session_settings.get("variant") {
+                    let variant_path = field_path(path, "variant");
+                    match variant.as_str() {
+                        Some(variant_str) => {
+                            if !variants.iter().any(|(name, _)| name == variant_str) {
+                                let variant_names = variants
+                                    .iter()
+                                    .map(|(name, _)| name.as_str())
+                                    .collect::<Vec<_>>()
+                                    .join(", ");
+                                self.report(
+                                    &variant_path,
+                                    format!(
+                                        "unknown variant \"{variant_str}\", expected one of \
+                                        {variant_names}"
+                                    ),
+                                );
+                            }
+                        }
+                        None => self.report(&variant_path, "expected a variant name".into()),
+                    }
+                }
+
+                for (variant_name, maybe_data) in variants {
+                    if let (Some(data_schema), Some(value)) =
+                        (maybe_data, session_settings.get(variant_name))
+                    {
+                        self.validate(value, &data_schema.content, &field_path(path, variant_name));
+                    }
+                }
+            }
+
+            SchemaNode::Optional { content, .. } => {
+                self.check_boolean_field(session_settings, path, "set");
+                if let Some(value) = session_settings.get("content") {
+                    self.validate(value, content, &field_path(path, "content"));
+                }
+            }
+
+            SchemaNode::Switch { content, .. } => {
+                self.check_boolean_field(session_settings, path, "enabled");
+                if let Some(value) = session_settings.get("content") {
+                    self.validate(value, content, &field_path(path, "content"));
+                }
+            }
+
+            SchemaNode::Boolean { .. } => {
+                if !session_settings.is_boolean() {
+                    self.report(path, "expected a boolean".into());
+                }
+            }
+
+            SchemaNode::Integer { min, max, .. } => {
+                if session_settings.is_i64() || session_settings.is_u64() {
+                    // All the integer settings fit in the f64 mantissa
+                    self.check_bounds(
+                        path,
+                        session_settings.as_f64().unwrap_or_default(),
+                        min.map(|min| min as f64),
+                        max.map(|max| max as f64),
+                    );
+                } else {
+                    self.report(path, "expected an integer".into());
+                }
+            }
+
+            SchemaNode::Float { min, max, .. } => {
+                if let Some(value) = session_settings.as_f64() {
+                    self.check_bounds(path, value, *min, *max);
+                } else {
+                    self.report(path, "expected a number".into());
+                }
+            }
+
+            SchemaNode::Text { .. } => {
+                if !session_settings.is_string() {
+                    self.report(path, "expected a string".into());
+                }
+            }
+
+            SchemaNode::Array(array_schema) => {
+                if let Some(elements) = session_settings.as_array() {
+                    if elements.len() != array_schema.len() {
+                        self.report(
+                            path,
+                            format!(
+                                "expected {} elements, found {}",
+                                array_schema.len(),
+                                elements.len()
+                            ),
+                        );
+                    }
+
+                    for (idx, (value, element_schema)) in
+                        elements.iter().zip(array_schema).enumerate()
+                    {
+                        self.validate(value, element_schema, &format!("{path}[{idx}]"));
+                    }
+                } else {
+                    self.report(path, "expected an array".into());
+                }
+            }
+
+            SchemaNode::Vector {
+                default_element, ..
+            } => {
+                if let Some(value) = session_settings.get("element") {
+                    self.validate(value, default_element, &field_path(path, "element"));
+                }
+                // The content uses the representation of Settings, it cannot be checked with the
+                // schema of the session settings
+                if let Some(value) = session_settings.get("content") {
+                    if !value.is_array() {
+                        self.report(&field_path(path, "content"), "expected an array".into());
+                    }
+                }
+            }
+
+            SchemaNode::Dictionary { default_value, .. } => {
+                if let Some(value) = session_settings.get("key") {
+                    if !value.is_string() {
+                        self.report(&field_path(path, "key"), "expected a string".into());
+                    }
+                }
+                if let Some(value) = session_settings.get("value") {
+                    self.validate(value, default_value, &field_path(path, "value"));
+                }
+                if let Some(value) = session_settings.get("content") {
+                    if !value.is_array() {
+                        self.report(&field_path(path, "content"), "expected an array".into());
+                    }
+                }
+            }
+        }
+    }
+}
+
+// The paths of the diagnostics are relative to session_settings
+pub fn validate_session_settings(
+    session_settings: &json::Value,
+    schema: &SchemaNode,
+) -> Vec<SettingsDiagnostic> {
+    let mut validator = Validator {
+        diagnostics: vec![],
+    };
+    validator.validate(session_settings, schema, "");
+
+    validator.diagnostics
+}
+
+// Validates all the settings trees of a (partial) SessionDesc json: the session settings, the
+// settings profiles and the overrides of each client. The paths start from the session root
+pub fn validate_session(session: &json::Value) -> Vec<SettingsDiagnostic> {
+    let schema = crate::settings_schema(crate::session_settings_default());
+
+    let mut trees = vec![];
+    if let Some(session_settings) = session.get("sessionSettings") {
+        trees.push(("sessionSettings".to_owned(), session_settings));
+    }
+    if let Some(profiles) = session.get("settingsProfiles").and_then(|p| p.as_object()) {
+        for (name, profile) in profiles {
+            trees.push((format!("settingsProfiles.{name}"), profile));
+        }
+    }
+    if let Some(clients) = session.get("clientConnections").and_then(|c| c.as_object()) {
+        for (hostname, client) in clients {
+            if let Some(settings_override) = client.get("settingsOverride") {
+                // null removes the override
+                if !settings_override.is_null() {
+                    trees.push((
+                        format!("clientConnections.{hostname}.settingsOverride"),
+                        settings_override,
+                    ));
+                }
+            }
+        }
+    }
+
+    let mut validator = Validator {
+        diagnostics: vec![],
+    };
+    for (path, tree) in trees {
+        validator.validate(tree, &schema, &path);
+    }
+
+    validator.diagnostics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths(diagnostics: &[SettingsDiagnostic]) -> Vec<&str> {
+        diagnostics
+            .iter()
+            .map(|diagnostic| diagnostic.path.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn default_session_is_valid() {
+        let session = json::to_value(crate::SessionDesc::default()).unwrap();
+
+        assert_eq!(validate_session(&session), vec![]);
+    }
+
+    #[test]
+    fn schema_constraints() {
+        let schema = crate::settings_schema(crate::session_settings_default());
+        let session_settings = json::json!({
+            "video": {
+                "encodeBitrateMbs": 1000,
+                "preferredFps": "90",
+                "codec": { "variant": "AV1" },
+                "adaptiveBitrate": { "enabled": 1 }
+            },
+            "unknownField": 0
+        });
+
+        let diagnostics = validate_session_settings(&session_settings, &schema);
+
+        assert_eq!(
+            paths(&diagnostics),
+            vec![
+                "video.preferredFps",
+                "video.codec.variant",
+                "video.encodeBitrateMbs",
+                "video.adaptiveBitrate.enabled",
+            ]
+        );
+        assert_eq!(
+            diagnostics[2].to_string(),
+            "video.encodeBitrateMbs: 1000 is greater than the maximum 500"
+        );
+    }
+
+    #[test]
+    fn profiles_and_overrides() {
+        let session = json::json!({
+            "settingsProfiles": {
+                "Quest2-90Hz": { "video": { "encodeBitrateMbs": 0 } }
+            },
+            "clientConnections": {
+                "client.alvr": { "settingsOverride": { "video": { "preferredFps": 90.0 } } },
+                "other.client.alvr": { "settingsOverride": null }
+            }
+        });
+
+        assert_eq!(
+            paths(&validate_session(&session)),
+            vec!["settingsProfiles.Quest2-90Hz.video.encodeBitrateMbs"]
+        );
+    }
+}
@@ -0,0 +1,52 @@
+// Sessions stored by older versions are upgraded with the migrations and extrapolation. Each
+// <version>.expected.json file contains the values that must survive the upgrade of
+// <version>.json, the other settings can take their default value.
+
+use alvr_session::SessionDesc;
+use serde_json as json;
+use std::{fs, path::Path};
+
+fn assert_preserved(upgraded: &json::Value, expected: &json::Value, path: &str) {
+    if let Some(expected_fields) = expected.as_object() {
+        for (name, expected_value) in expected_fields {
+            assert_preserved(&upgraded[name], expected_value, &format!("{path}.{name}"));
+        }
+    } else {
+        assert_eq!(upgraded, expected, "at {path}");
+    }
+}
+
+#[test]
+fn upgrade_stored_sessions() {
+    let sessions_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/sessions");
+
+    let mut sessions_count = 0;
+    for entry in fs::read_dir(&sessions_dir).unwrap() {
+        let stored_path = entry.unwrap().path();
+        let version = stored_path
+            .file_stem()
+            .unwrap()
+            .to_string_lossy()
+            .into_owned();
+        if version.ends_with(".expected") {
+            continue;
+        }
+
+        let read_json = |path: &Path| {
+            json::from_str::<json::Value>(&fs::read_to_string(path).unwrap()).unwrap()
+        };
+        let stored = read_json(&stored_path);
+        let expected = read_json(&sessions_dir.join(format!("{version}.expected.json")));
+
+        let mut session_desc = SessionDesc::default();
+        session_desc
+            .merge_from_json(&stored)
+            .unwrap_or_else(|e| panic!("Failed to upgrade session {version}: {e}"));
+
+        assert_preserved(&json::to_value(&session_desc).unwrap(), &expected, &version);
+
+        sessions_count += 1;
+    }
+
+    assert!(sessions_count > 0);
+}
@@ -0,0 +1,8 @@
+#![no_main]
+
+use alvr_sockets::{codec, ClientControlPacket};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    codec::decode::<ClientControlPacket>(data).ok();
+});
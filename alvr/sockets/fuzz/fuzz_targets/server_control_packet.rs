@@ -0,0 +1,8 @@
+#![no_main]
+
+use alvr_sockets::{codec, ServerControlPacket};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    codec::decode::<ServerControlPacket>(data).ok();
+});
@@ -0,0 +1,11 @@
+#![no_main]
+
+use alvr_sockets::{codec, Tracking};
+use libfuzzer_sys::fuzz_target;
+
+// Tracking is received as the header of a stream packet, followed by an arbitrary buffer
+fuzz_target!(|data: &[u8]| {
+    if let Ok((_, header_size)) = codec::decode_prefix::<Tracking>(data) {
+        assert!(header_size <= data.len());
+    }
+});
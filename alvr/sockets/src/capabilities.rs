@@ -0,0 +1,162 @@
+// Peers exchange their capabilities as soon as the control socket is connected, before any other
+// packet. Both sides negotiate the same protocol version and features from the two advertisements:
+// the schema of the packets exchanged later depends on the protocol version, and optional packets
+// and fields are used only if both peers support the corresponding feature.
+
+use serde::{Deserialize, Serialize};
+
+// Version of the schema of the control and stream packets. Bumped on breaking changes
+pub const PROTOCOL_VERSION: u32 = 3;
+// Oldest protocol version that this build can still speak
+pub const MIN_PROTOCOL_VERSION: u32 = 2;
+// Version 3 changed the encoding of ShardLoss::Packets in NackPacket, and replaced the proof of the
+// pairing PIN with the key exchange over the PIN (PinExchangePacket)
+const SHARD_LOSS_RANGE_PROTOCOL_VERSION: u32 = 3;
+const PIN_EXCHANGE_PROTOCOL_VERSION: u32 = 3;
+
+// Features are identified by name, so that the flags of newer peers can always be parsed
+pub const FEATURE_QUIC: &str = "quic";
+pub const FEATURE_FEC: &str = "fec";
+pub const FEATURE_NACK: &str = "nack";
+pub const FEATURE_SPECTATOR: &str = "spectator";
+// Video packet arrival times in ClientStatistics, used by the delay based bitrate controller
+pub const FEATURE_PACKET_ARRIVALS: &str = "packet_arrivals";
+// Resumption of the session after a short disconnection, see ResumeToken
+pub const FEATURE_RESUME: &str = "resume";
+// Synchronization of the clocks of the peers, see ClockSync
+pub const FEATURE_CLOCK_SYNC: &str = "clock_sync";
+// Counters of the client streams sent to the server, see StreamSocket::stream_statistics()
+pub const FEATURE_STREAM_STATISTICS: &str = "stream_statistics";
+
+// Sent as JSON, so that new fields can be added without breaking older peers
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Capabilities {
+    pub min_protocol_version: u32,
+    pub max_protocol_version: u32,
+    #[serde(default)]
+    pub features: Vec<String>,
+}
+
+impl Capabilities {
+    pub fn local() -> Self {
+        Self {
+            min_protocol_version: MIN_PROTOCOL_VERSION,
+            max_protocol_version: PROTOCOL_VERSION,
+            features: [
+                FEATURE_QUIC,
+                FEATURE_FEC,
+                FEATURE_NACK,
+                FEATURE_SPECTATOR,
+                FEATURE_PACKET_ARRIVALS,
+                FEATURE_RESUME,
+                FEATURE_CLOCK_SYNC,
+                FEATURE_STREAM_STATISTICS,
+            ]
+            .iter()
+            .map(|feature| feature.to_string())
+            .collect(),
+        }
+    }
+
+    // Highest common protocol version and common features. Returns None if the protocol versions
+    // do not overlap. The result does not depend on which peer calls it
+    pub fn negotiate(&self, peer: &Capabilities) -> Option<NegotiatedCapabilities> {
+        let protocol_version = u32::min(self.max_protocol_version, peer.max_protocol_version);
+        if protocol_version < u32::max(self.min_protocol_version, peer.min_protocol_version) {
+            return None;
+        }
+
+        let mut features = self
+            .features
+            .iter()
+            .filter(|feature| peer.features.contains(feature))
+            .cloned()
+            .collect::<Vec<_>>();
+        features.sort();
+        features.dedup();
+        // The lost packets reported by older peers cannot be parsed, they are skipped instead
+        if protocol_version < SHARD_LOSS_RANGE_PROTOCOL_VERSION {
+            features.retain(|feature| feature != FEATURE_NACK);
+        }
+
+        Some(NegotiatedCapabilities {
+            protocol_version,
+            features,
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NegotiatedCapabilities {
+    pub protocol_version: u32,
+    pub features: Vec<String>,
+}
+
+impl NegotiatedCapabilities {
+    pub fn supports(&self, feature: &str) -> bool {
+        self.features.iter().any(|f| f == feature)
+    }
+
+    // Older peers can still authenticate with a pairing secret stored before. Pairing them with
+    // the PIN is refused, their proof would let the peer find the PIN offline
+    pub fn supports_pin_exchange(&self) -> bool {
+        self.protocol_version >= PIN_EXCHANGE_PROTOCOL_VERSION
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn capabilities(min: u32, max: u32, features: &[&str]) -> Capabilities {
+        Capabilities {
+            min_protocol_version: min,
+            max_protocol_version: max,
+            features: features.iter().map(|feature| feature.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn negotiation() {
+        let older = capabilities(2, 3, &[FEATURE_FEC, FEATURE_NACK]);
+        let newer = capabilities(3, 5, &[FEATURE_NACK, "from_the_future", FEATURE_FEC]);
+
+        let negotiated = older.negotiate(&newer).unwrap();
+        assert_eq!(negotiated, newer.negotiate(&older).unwrap());
+        assert_eq!(negotiated.protocol_version, 3);
+        assert!(negotiated.supports(FEATURE_FEC));
+        assert!(negotiated.supports(FEATURE_NACK));
+        assert!(!negotiated.supports("from_the_future"));
+
+        let newest = capabilities(4, 5, &[]);
+        assert!(older.negotiate(&newest).is_none());
+        assert!(newest.negotiate(&older).is_none());
+    }
+
+    #[test]
+    fn oldest_protocol_version() {
+        let local = Capabilities::local();
+        let oldest = capabilities(MIN_PROTOCOL_VERSION, MIN_PROTOCOL_VERSION, &[FEATURE_NACK]);
+
+        let negotiated = local.negotiate(&oldest).unwrap();
+        assert_eq!(negotiated.protocol_version, MIN_PROTOCOL_VERSION);
+        assert!(!negotiated.supports(FEATURE_NACK));
+        assert!(!negotiated.supports_pin_exchange());
+
+        let negotiated = local.negotiate(&local).unwrap();
+        assert_eq!(negotiated.protocol_version, PROTOCOL_VERSION);
+        assert!(negotiated.supports(FEATURE_NACK));
+        assert!(negotiated.supports_pin_exchange());
+    }
+
+    #[test]
+    fn unknown_fields_are_ignored() {
+        let json = r#"{"minProtocolVersion":1,"maxProtocolVersion":3,"newField":[1,2]}"#;
+
+        assert_eq!(
+            serde_json::from_str::<Capabilities>(json).unwrap(),
+            capabilities(1, 3, &[])
+        );
+    }
+}
@@ -0,0 +1,251 @@
+// NTP-like synchronization of the clocks of the peers. Both peers periodically send a request over
+// the control socket and answer the requests of the other peer. Each round trip gives a sample of
+// the offset between the clocks, assuming the same latency in both directions. The offset and its
+// drift are estimated from the recent samples with the lowest round trip time, which are the least
+// affected by queuing. Timestamps are durations since the epoch of the local clock.
+
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+// At one request per keepalive, the drift is estimated over about half a minute
+const MAX_SAMPLES: usize = 32;
+// A shorter time span makes the drift estimate too noisy
+const MIN_DRIFT_TIME_SPAN: Duration = Duration::from_secs(10);
+// Drift of typical clock oscillators is below 100 ppm. Larger estimates are caused by jitter
+const MAX_DRIFT: f64 = 500e-6;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+pub enum ClockSyncPacket {
+    Request {
+        sent: Duration,
+    },
+    Response {
+        request_sent: Duration,
+        request_received: Duration,
+        response_sent: Duration,
+    },
+}
+
+struct ClockSample {
+    // Middle of the round trip, in seconds of the local clock
+    local_time: f64,
+    // Peer clock minus local clock, in seconds
+    offset: f64,
+    round_trip: Duration,
+}
+
+struct ClockEstimate {
+    reference_time: f64,
+    offset: f64,
+    drift: f64,
+    round_trip: Duration,
+}
+
+impl ClockEstimate {
+    fn offset_at(&self, local_time: f64) -> f64 {
+        self.offset + self.drift * (local_time - self.reference_time)
+    }
+}
+
+pub struct ClockSync {
+    epoch: Instant,
+    samples: VecDeque<ClockSample>,
+    estimate: Option<ClockEstimate>,
+}
+
+impl ClockSync {
+    pub fn new(epoch: Instant) -> Self {
+        Self {
+            epoch,
+            samples: VecDeque::new(),
+            estimate: None,
+        }
+    }
+
+    // Timestamp of an instant in the local clock
+    pub fn timestamp(&self, instant: Instant) -> Duration {
+        instant.saturating_duration_since(self.epoch)
+    }
+
+    pub fn request(&self) -> ClockSyncPacket {
+        ClockSyncPacket::Request {
+            sent: self.timestamp(Instant::now()),
+        }
+    }
+
+    // Answers the requests of the peer and takes a sample from the responses to the local requests
+    pub fn handle(&mut self, packet: ClockSyncPacket) -> Option<ClockSyncPacket> {
+        let now = self.timestamp(Instant::now());
+
+        match packet {
+            ClockSyncPacket::Request { sent } => Some(ClockSyncPacket::Response {
+                request_sent: sent,
+                request_received: now,
+                response_sent: self.timestamp(Instant::now()),
+            }),
+            ClockSyncPacket::Response {
+                request_sent,
+                request_received,
+                response_sent,
+            } => {
+                self.add_sample(request_sent, request_received, response_sent, now);
+
+                None
+            }
+        }
+    }
+
+    fn add_sample(
+        &mut self,
+        request_sent: Duration,
+        request_received: Duration,
+        response_sent: Duration,
+        response_received: Duration,
+    ) {
+        // Responses to requests of a previous connection or a misbehaving peer
+        if response_received < request_sent || response_sent < request_received {
+            return;
+        }
+
+        let round_trip =
+            (response_received - request_sent).saturating_sub(response_sent - request_received);
+        let offset = (request_received.as_secs_f64() - request_sent.as_secs_f64()
+            + response_sent.as_secs_f64()
+            - response_received.as_secs_f64())
+            / 2.;
+
+        self.samples.push_back(ClockSample {
+            local_time: (request_sent.as_secs_f64() + response_received.as_secs_f64()) / 2.,
+            offset,
+            round_trip,
+        });
+        if self.samples.len() > MAX_SAMPLES {
+            self.samples.pop_front();
+        }
+
+        self.update_estimate();
+    }
+
+    fn update_estimate(&mut self) {
+        // Use the best half of the samples
+        let mut samples = self.samples.iter().collect::<Vec<_>>();
+        samples.sort_by_key(|sample| sample.round_trip);
+        samples.truncate(usize::max(samples.len() / 2, 1));
+
+        let count = samples.len() as f64;
+        let reference_time = samples.iter().map(|s| s.local_time).sum::<f64>() / count;
+        let offset = samples.iter().map(|s| s.offset).sum::<f64>() / count;
+
+        // Least squares fit of the offset over time
+        let (min_time, max_time) = samples.iter().fold((f64::MAX, f64::MIN), |(min, max), s| {
+            (min.min(s.local_time), max.max(s.local_time))
+        });
+        let drift = if max_time - min_time >= MIN_DRIFT_TIME_SPAN.as_secs_f64() {
+            let covariance = samples
+                .iter()
+                .map(|s| (s.local_time - reference_time) * (s.offset - offset))
+                .sum::<f64>();
+            let variance = samples
+                .iter()
+                .map(|s| (s.local_time - reference_time).powi(2))
+                .sum::<f64>();
+
+            (covariance / variance).clamp(-MAX_DRIFT, MAX_DRIFT)
+        } else {
+            0.
+        };
+
+        self.estimate = Some(ClockEstimate {
+            reference_time,
+            offset,
+            drift,
+            round_trip: samples[0].round_trip,
+        });
+    }
+
+    // Lowest recent round trip time of the control socket. None until the first response
+    pub fn round_trip_time(&self) -> Option<Duration> {
+        self.estimate.as_ref().map(|estimate| estimate.round_trip)
+    }
+
+    // Converts a timestamp of the peer clock to a local instant. None until the first response
+    pub fn to_local(&self, peer_timestamp: Duration) -> Option<Instant> {
+        let estimate = self.estimate.as_ref()?;
+
+        let peer_time = peer_timestamp.as_secs_f64();
+        // The offset depends on the local time, refine it once
+        let local_time = peer_time - estimate.offset_at(peer_time - estimate.offset);
+        let local_time = peer_time - estimate.offset_at(local_time);
+
+        if local_time >= 0. {
+            self.epoch.checked_add(Duration::from_secs_f64(local_time))
+        } else {
+            self.epoch.checked_sub(Duration::from_secs_f64(-local_time))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Exchanges with a peer whose clock is ahead by `offset` seconds and runs faster by `drift`
+    fn synchronize(clock: &mut ClockSync, offset: f64, drift: f64, latencies: &[(f64, f64)]) {
+        let peer_time = |local_time: f64| local_time + offset + local_time * drift;
+        let secs = Duration::from_secs_f64;
+
+        for (index, (uplink, downlink)) in latencies.iter().enumerate() {
+            let request_sent = index as f64;
+            let request_received = peer_time(request_sent + uplink);
+            let response_sent = request_received + 0.001;
+            let response_received = request_sent + uplink + 0.001 + downlink;
+
+            clock.add_sample(
+                secs(request_sent),
+                secs(request_received),
+                secs(response_sent),
+                secs(response_received),
+            );
+        }
+    }
+
+    fn local_time(clock: &ClockSync, peer_timestamp: Duration) -> f64 {
+        let instant = clock.to_local(peer_timestamp).unwrap();
+        instant.duration_since(clock.epoch).as_secs_f64()
+    }
+
+    #[test]
+    fn offset_and_drift() {
+        let epoch = Instant::now();
+        let mut clock = ClockSync::new(epoch);
+        assert!(clock.to_local(Duration::ZERO).is_none());
+
+        synchronize(&mut clock, 5., 100e-6, &[(0.01, 0.01); 30]);
+
+        let round_trip = clock.round_trip_time().unwrap().as_secs_f64();
+        assert!((round_trip - 0.02).abs() < 1e-6);
+        for local in [2., 20., 40.] {
+            let peer = Duration::from_secs_f64(local + 5. + local * 100e-6);
+            assert!((local_time(&clock, peer) - local).abs() < 1e-5);
+        }
+    }
+
+    #[test]
+    fn queued_samples_are_ignored() {
+        let mut clock = ClockSync::new(Instant::now());
+
+        // Every other response is delayed by 50 ms
+        let latencies = (0..20)
+            .map(|index| (0.005, if index % 2 == 0 { 0.005 } else { 0.055 }))
+            .collect::<Vec<_>>();
+        synchronize(&mut clock, 3., 0., &latencies);
+
+        let round_trip = clock.round_trip_time().unwrap().as_secs_f64();
+        assert!((round_trip - 0.01).abs() < 1e-6);
+        let local = local_time(&clock, Duration::from_secs(13));
+        assert!((local - 10.).abs() < 1e-5);
+    }
+}
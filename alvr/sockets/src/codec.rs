@@ -0,0 +1,189 @@
+// Every packet is encoded with bincode, with the same layout as bincode::serialize(), so the wire
+// format is unchanged. Each packet type declares the maximum size of its encoding: length prefixes
+// of strings and vectors are checked against it before anything is allocated, and larger inputs
+// are rejected. Malformed packets always result in an error.
+
+use crate::{
+    Capabilities, ClientAuthPacket, ClientConfigPacket, ClientControlPacket, ClientStatistics,
+    HandshakePacket, Haptics, HeadsetInfoPacket, PinExchangePacket, ResumeToken, ServerAuthPacket,
+    ServerControlPacket, Tracking, VideoFrameHeaderPacket, MAX_HANDSHAKE_PACKET_SIZE_BYTES,
+};
+use alvr_common::prelude::*;
+use bincode::Options;
+use serde::{de::DeserializeOwned, Serialize};
+use std::net::IpAddr;
+
+// Maximum frame size accepted on the control socket. No control packet can be larger
+pub const MAX_CONTROL_PACKET_SIZE: usize = 1024 * 1024;
+
+pub trait BoundedPacket {
+    // Maximum size in bytes of the encoded packet
+    const MAX_SIZE: usize;
+}
+
+macro_rules! bounded_packets {
+    ($($packet:ty => $max_size:expr,)*) => {
+        $(impl BoundedPacket for $packet {
+            const MAX_SIZE: usize = $max_size;
+        })*
+    };
+}
+
+bounded_packets! {
+    HandshakePacket => MAX_HANDSHAKE_PACKET_SIZE_BYTES,
+    ServerAuthPacket => 1024,
+    ClientAuthPacket => 1024,
+    PinExchangePacket => 1024,
+    (HeadsetInfoPacket, IpAddr) => 4 * 1024,
+    ClientConfigPacket => MAX_CONTROL_PACKET_SIZE,
+    ServerControlPacket => 1024,
+    // NACKs may list many shards
+    ClientControlPacket => 64 * 1024,
+    VideoFrameHeaderPacket => 1024,
+    Tracking => 16 * 1024,
+    Haptics => 1024,
+    // Arrival times of all video packets of a frame
+    ClientStatistics => 64 * 1024,
+    // Sent as JSON, see exchange_capabilities()
+    Capabilities => 16 * 1024,
+    // Resume request of the client and answer of the server
+    Option<ResumeToken> => 64,
+    bool => 1,
+    () => 0,
+    u32 => 4,
+}
+
+fn options<T: BoundedPacket>() -> impl Options {
+    bincode::DefaultOptions::new()
+        .with_fixint_encoding()
+        .with_limit(T::MAX_SIZE as u64)
+        .allow_trailing_bytes()
+}
+
+pub fn encode<T: Serialize + BoundedPacket>(packet: &T) -> StrResult<Vec<u8>> {
+    options::<T>().serialize(packet).map_err(err!())
+}
+
+pub fn encoded_size<T: Serialize + BoundedPacket>(packet: &T) -> StrResult<usize> {
+    Ok(options::<T>().serialized_size(packet).map_err(err!())? as usize)
+}
+
+pub fn encode_into<T: Serialize + BoundedPacket>(
+    writer: impl std::io::Write,
+    packet: &T,
+) -> StrResult {
+    options::<T>()
+        .serialize_into(writer, packet)
+        .map_err(err!())
+}
+
+// The packet must span the whole buffer
+pub fn decode<T: DeserializeOwned + BoundedPacket>(bytes: &[u8]) -> StrResult<T> {
+    if bytes.len() > T::MAX_SIZE {
+        return fmt_e!("Packet too large ({} > {} bytes)", bytes.len(), T::MAX_SIZE);
+    }
+
+    options::<T>()
+        .reject_trailing_bytes()
+        .deserialize(bytes)
+        .map_err(err!())
+}
+
+// Decode a packet at the start of the buffer. Returns the packet and the size of its encoding
+pub fn decode_prefix<T: DeserializeOwned + BoundedPacket>(bytes: &[u8]) -> StrResult<(T, usize)> {
+    let mut reader = bytes;
+    let packet = options::<T>()
+        .deserialize_from(&mut reader)
+        .map_err(err!())?;
+
+    Ok((packet, bytes.len() - reader.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ClientHandshakePacket, NackPacket, ShardLoss};
+    use alvr_common::semver::Version;
+    use std::time::Duration;
+
+    #[test]
+    fn same_layout_as_bincode() {
+        let packet = ClientControlPacket::Nack(NackPacket {
+            stream_id: 3,
+            lost_shards: vec![ShardLoss::Shards {
+                packet_index: 7,
+                shard_indices: vec![1, 2],
+            }],
+        });
+
+        let bytes = encode(&packet).unwrap();
+        assert_eq!(bytes, bincode::serialize(&packet).unwrap());
+        assert!(matches!(
+            decode(&bytes).unwrap(),
+            ClientControlPacket::Nack(NackPacket { stream_id: 3, .. })
+        ));
+    }
+
+    #[test]
+    fn malformed_packets_are_rejected() {
+        let bytes = encode(&HandshakePacket::Client(ClientHandshakePacket {
+            alvr_name: "ALVR".into(),
+            version: Version::new(19, 0, 0),
+            device_name: "device".into(),
+            hostname: "1234.client.alvr".into(),
+            certificate_fingerprint: "00".into(),
+            reserved: "".into(),
+        }))
+        .unwrap();
+
+        // Truncated
+        for size in 0..bytes.len() {
+            assert!(decode::<HandshakePacket>(&bytes[..size]).is_err());
+        }
+
+        // Trailing garbage
+        let mut padded = bytes.clone();
+        padded.push(0);
+        assert!(decode::<HandshakePacket>(&padded).is_err());
+
+        // Too large
+        assert!(decode::<HandshakePacket>(&vec![0; MAX_HANDSHAKE_PACKET_SIZE_BYTES + 1]).is_err());
+    }
+
+    #[test]
+    fn huge_length_prefixes_are_rejected() {
+        // Tracking { target_timestamp, device_motions } with a device_motions length of u64::MAX
+        let mut bytes = vec![0; 12];
+        bytes.extend_from_slice(&u64::MAX.to_le_bytes());
+        assert!(decode::<Tracking>(&bytes).is_err());
+
+        // Nack with a shard_indices length of u32::MAX entries
+        let mut bytes = 9_u32.to_le_bytes().to_vec();
+        bytes.extend_from_slice(&0_u16.to_le_bytes());
+        bytes.extend_from_slice(&1_u64.to_le_bytes());
+        bytes.extend_from_slice(&1_u32.to_le_bytes());
+        bytes.extend_from_slice(&0_u32.to_le_bytes());
+        bytes.extend_from_slice(&(u32::MAX as u64).to_le_bytes());
+        assert!(decode::<ClientControlPacket>(&bytes).is_err());
+    }
+
+    #[test]
+    fn prefix_decoding() {
+        let statistics = ClientStatistics {
+            video_packet_arrivals: vec![(1, Duration::from_millis(2))],
+            ..Default::default()
+        };
+        let mut bytes = encode(&statistics).unwrap();
+        let header_size = bytes.len();
+        bytes.extend_from_slice(&[1, 2, 3]);
+
+        let (decoded, size) = decode_prefix::<ClientStatistics>(&bytes).unwrap();
+        assert_eq!(size, header_size);
+        assert_eq!(
+            decoded.video_packet_arrivals,
+            statistics.video_packet_arrivals
+        );
+
+        assert!(decode_prefix::<ClientStatistics>(&bytes[..header_size - 1]).is_err());
+    }
+}
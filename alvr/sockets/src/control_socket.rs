@@ -1,33 +1,46 @@
-use super::{Ldc, CONTROL_PORT, LOCAL_IP};
+use super::{
+    capabilities::Capabilities,
+    codec::{self, BoundedPacket, MAX_CONTROL_PACKET_SIZE},
+    crypto::{
+        self, AuthRole, Identity, PinExchange, StreamKeys, AUTH_LABEL, KEY_SIZE,
+        PAIRING_SECRET_LABEL, STREAM_KEYS_LABEL,
+    },
+    network::{self, InterfaceBinding},
+    Ldc, PinExchangePacket,
+};
 use alvr_common::prelude::*;
 use bytes::Bytes;
 use futures::{
     stream::{SplitSink, SplitStream},
     SinkExt, StreamExt,
 };
+use rustls::ServerName;
 use serde::{de::DeserializeOwned, Serialize};
-use std::{marker::PhantomData, net::IpAddr};
-use tokio::net::{TcpListener, TcpStream};
+use std::{convert::TryFrom, marker::PhantomData, net::IpAddr, sync::Arc};
+use tokio::net::TcpStream;
+use tokio_rustls::{TlsAcceptor, TlsConnector, TlsStream};
 use tokio_util::codec::Framed;
 
+type ControlStream = Framed<TlsStream<TcpStream>, Ldc>;
+
 pub struct ControlSocketSender<T> {
-    inner: SplitSink<Framed<TcpStream, Ldc>, Bytes>,
+    inner: SplitSink<ControlStream, Bytes>,
     _phantom: PhantomData<T>,
 }
 
-impl<S: Serialize> ControlSocketSender<S> {
+impl<S: Serialize + BoundedPacket> ControlSocketSender<S> {
     pub async fn send(&mut self, packet: &S) -> StrResult {
-        let packet_bytes = bincode::serialize(packet).map_err(err!())?;
+        let packet_bytes = codec::encode(packet)?;
         self.inner.send(packet_bytes.into()).await.map_err(err!())
     }
 }
 
 pub struct ControlSocketReceiver<T> {
-    inner: SplitStream<Framed<TcpStream, Ldc>>,
+    inner: SplitStream<ControlStream>,
     _phantom: PhantomData<T>,
 }
 
-impl<R: DeserializeOwned> ControlSocketReceiver<R> {
+impl<R: DeserializeOwned + BoundedPacket> ControlSocketReceiver<R> {
     pub async fn recv(&mut self) -> StrResult<R> {
         let packet_bytes = self
             .inner
@@ -35,65 +48,179 @@ impl<R: DeserializeOwned> ControlSocketReceiver<R> {
             .await
             .ok_or_else(enone!())?
             .map_err(err!())?;
-        bincode::deserialize(&packet_bytes).map_err(err!())
+        codec::decode(&packet_bytes)
     }
 }
 
 // Proto-control-socket that can send and receive any packet. After the split, only the packets of
 // the specified types can be exchanged
 pub struct ProtoControlSocket {
-    inner: Framed<TcpStream, Ldc>,
+    inner: ControlStream,
 }
 
 pub enum PeerType {
-    AnyClient(Vec<IpAddr>),
-    Server,
+    // The server connects from the local address that the binding chooses for each client
+    AnyClient(Vec<IpAddr>, InterfaceBinding),
+    // The client listens for the server and identifies itself with its certificate
+    Server(Identity),
 }
 
 impl ProtoControlSocket {
-    pub async fn connect_to(peer: PeerType) -> StrResult<(Self, IpAddr)> {
+    pub async fn connect_to(peer: PeerType, port: u16) -> StrResult<(Self, IpAddr)> {
         let socket = match peer {
-            PeerType::AnyClient(ips) => {
-                let client_addresses = ips
-                    .iter()
-                    .map(|&ip| (ip, CONTROL_PORT).into())
-                    .collect::<Vec<_>>();
-                TcpStream::connect(client_addresses.as_slice())
+            PeerType::AnyClient(ips, binding) => {
+                let socket = network::connect_tcp(&ips, port, &binding).await?;
+                socket.set_nodelay(true).map_err(err!())?;
+
+                let server_name = ServerName::try_from(crypto::CERTIFICATE_NAME).map_err(err!())?;
+                let socket = TlsConnector::from(Arc::new(crypto::tls_client_config()))
+                    .connect(server_name, socket)
                     .await
-                    .map_err(err!())?
+                    .map_err(err!())?;
+
+                TlsStream::Client(socket)
             }
-            PeerType::Server => {
-                let listener = TcpListener::bind((LOCAL_IP, CONTROL_PORT))
+            PeerType::Server(identity) => {
+                let listener = network::bind_dual_stack_listener(port)?;
+                let (socket, _) = listener.accept().await.map_err(err!())?;
+                socket.set_nodelay(true).map_err(err!())?;
+
+                let socket = TlsAcceptor::from(Arc::new(crypto::tls_server_config(&identity)?))
+                    .accept(socket)
                     .await
                     .map_err(err!())?;
-                let (socket, _) = listener.accept().await.map_err(err!())?;
-                socket
+
+                TlsStream::Server(socket)
             }
         };
 
-        socket.set_nodelay(true).map_err(err!())?;
-        let peer_ip = socket.peer_addr().map_err(err!())?.ip();
-        let socket = Framed::new(socket, Ldc::new());
+        let peer_ip = network::canonical_ip(socket.get_ref().0.peer_addr().map_err(err!())?.ip());
+        let socket = Framed::new(
+            socket,
+            Ldc::builder()
+                .max_frame_length(MAX_CONTROL_PACKET_SIZE)
+                .new_codec(),
+        );
 
         Ok((Self { inner: socket }, peer_ip))
     }
 
-    pub async fn send<S: Serialize>(&mut self, packet: &S) -> StrResult {
-        let packet_bytes = bincode::serialize(packet).map_err(err!())?;
+    // Fingerprint of the certificate presented by the client. Only the server can identify its peer
+    pub fn peer_fingerprint(&self) -> Option<String> {
+        let certificates = self.inner.get_ref().get_ref().1.peer_certificates()?;
+
+        Some(crypto::certificate_fingerprint(&certificates.first()?.0))
+    }
+
+    fn export_keying_material(&self, label: &[u8], output: &mut [u8]) -> StrResult {
+        match self.inner.get_ref() {
+            TlsStream::Client(socket) => socket
+                .get_ref()
+                .1
+                .export_keying_material(output, label, None),
+            TlsStream::Server(socket) => socket
+                .get_ref()
+                .1
+                .export_keying_material(output, label, None),
+        }
+        .map_err(err!())
+    }
+
+    // The keys are derived from the TLS session, so they are the same on both sides
+    pub fn stream_keys(&self) -> StrResult<StreamKeys> {
+        let mut keying_material = [0; 2 * KEY_SIZE];
+        self.export_keying_material(STREAM_KEYS_LABEL, &mut keying_material)?;
+
+        Ok(StreamKeys::new(&keying_material))
+    }
+
+    // New pairing secret, known only by the two peers of this connection
+    pub fn pairing_secret(&self) -> StrResult<Vec<u8>> {
+        let mut secret = vec![0; KEY_SIZE];
+        self.export_keying_material(PAIRING_SECRET_LABEL, &mut secret)?;
+
+        Ok(secret)
+    }
+
+    // Key exchange over the pairing PIN. The PIN is never sent, and the key is different if the
+    // peer used another PIN. The key must be confirmed with auth_proof() before anything is trusted
+    pub async fn exchange_pin(&mut self, pin: &str, role: AuthRole) -> StrResult<Vec<u8>> {
+        let (exchange, message) = PinExchange::start(pin, role);
+        let packet = PinExchangePacket { message };
+
+        let peer_packet = match role {
+            AuthRole::Server => {
+                self.send(&packet).await?;
+                self.recv::<PinExchangePacket>().await?
+            }
+            AuthRole::Client => {
+                let peer_packet = self.recv::<PinExchangePacket>().await?;
+                self.send(&packet).await?;
+
+                peer_packet
+            }
+        };
+
+        exchange.finish(&peer_packet.message)
+    }
+
+    pub fn auth_proof(&self, key: &[u8], role: AuthRole) -> StrResult<Vec<u8>> {
+        let mut session_material = [0; KEY_SIZE];
+        self.export_keying_material(AUTH_LABEL, &mut session_material)?;
+
+        Ok(crypto::auth_proof(&session_material, key, role))
+    }
+
+    pub fn verify_auth_proof(&self, key: &[u8], role: AuthRole, proof: &[u8]) -> StrResult<bool> {
+        let mut session_material = [0; KEY_SIZE];
+        self.export_keying_material(AUTH_LABEL, &mut session_material)?;
+
+        Ok(crypto::verify_auth_proof(
+            &session_material,
+            key,
+            role,
+            proof,
+        ))
+    }
+
+    // Must be the first exchange on a new connection. The capabilities are encoded as JSON, so that
+    // peers with any protocol version can read them
+    pub async fn exchange_capabilities(&mut self, local: &Capabilities) -> StrResult<Capabilities> {
+        let capabilities_bytes = serde_json::to_vec(local).map_err(err!())?;
+        self.inner
+            .send(capabilities_bytes.into())
+            .await
+            .map_err(err!())?;
+
+        let peer_bytes = self
+            .inner
+            .next()
+            .await
+            .ok_or_else(enone!())?
+            .map_err(err!())?;
+        if peer_bytes.len() > Capabilities::MAX_SIZE {
+            return fmt_e!("Capabilities too large ({} bytes)", peer_bytes.len());
+        }
+
+        serde_json::from_slice(&peer_bytes).map_err(err!())
+    }
+
+    pub async fn send<S: Serialize + BoundedPacket>(&mut self, packet: &S) -> StrResult {
+        let packet_bytes = codec::encode(packet)?;
         self.inner.send(packet_bytes.into()).await.map_err(err!())
     }
 
-    pub async fn recv<R: DeserializeOwned>(&mut self) -> StrResult<R> {
+    pub async fn recv<R: DeserializeOwned + BoundedPacket>(&mut self) -> StrResult<R> {
         let packet_bytes = self
             .inner
             .next()
             .await
             .ok_or_else(enone!())?
             .map_err(err!())?;
-        bincode::deserialize(&packet_bytes).map_err(err!())
+        codec::decode(&packet_bytes)
     }
 
-    pub fn split<S: Serialize, R: DeserializeOwned>(
+    pub fn split<S: Serialize + BoundedPacket, R: DeserializeOwned + BoundedPacket>(
         self,
     ) -> (ControlSocketSender<S>, ControlSocketReceiver<R>) {
         let (sender, receiver) = self.inner.split();
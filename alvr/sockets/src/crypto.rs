@@ -0,0 +1,450 @@
+// Authentication and encryption. The control socket uses TLS: the client acts as the TLS server and
+// presents a self-signed certificate that identifies it. The server pins the certificate
+// fingerprint in the client entry of the session, so trust is bound to the certificate and not to
+// the hostname or the IP. The stream keys are exported from the TLS session, so they are different
+// for every connection. Every shard is then sealed with ChaCha20-Poly1305.
+// Clients are paired with a PIN shown on the headset. The PIN is never sent, not even hashed: the
+// peers run a password authenticated key exchange (SPAKE2) with it, so a peer that does not know the
+// PIN can test only one guess per connection. Pairing produces a shared secret that both peers must
+// prove to know on every later connection.
+
+use alvr_common::prelude::*;
+use bytes::{BufMut, Bytes, BytesMut};
+use ring::{aead, digest, hmac};
+use serde::{Deserialize, Serialize};
+use spake2::{Ed25519Group, Password, Spake2};
+use std::{sync::Arc, time::SystemTime};
+
+// The certificates are identified by their fingerprint, the name is not verified
+pub(crate) const CERTIFICATE_NAME: &str = "alvr.client";
+
+// Labels of the keying material exported from the TLS session
+pub(crate) const STREAM_KEYS_LABEL: &[u8] = b"EXPORTER-alvr-stream-keys";
+pub(crate) const AUTH_LABEL: &[u8] = b"EXPORTER-alvr-auth";
+pub(crate) const PAIRING_SECRET_LABEL: &[u8] = b"EXPORTER-alvr-pairing-secret";
+
+pub(crate) const KEY_SIZE: usize = 32;
+
+const COUNTER_SIZE: usize = 8;
+const TAG_SIZE: usize = 16;
+pub(crate) const SEAL_OVERHEAD: usize = COUNTER_SIZE + TAG_SIZE;
+
+// Shards that are reordered by more than this count are discarded
+const REPLAY_WINDOW_SIZE: u64 = 128;
+
+// Persistent identity of a client
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Identity {
+    pub certificate_der: Vec<u8>,
+    pub private_key_der: Vec<u8>,
+}
+
+impl Identity {
+    pub fn generate() -> StrResult<Self> {
+        let certificate =
+            rcgen::generate_simple_self_signed(vec![CERTIFICATE_NAME.into()]).map_err(err!())?;
+
+        Ok(Self {
+            certificate_der: certificate.serialize_der().map_err(err!())?,
+            private_key_der: certificate.serialize_private_key_der(),
+        })
+    }
+
+    pub fn fingerprint(&self) -> String {
+        certificate_fingerprint(&self.certificate_der)
+    }
+}
+
+pub(crate) fn certificate_fingerprint(certificate_der: &[u8]) -> String {
+    digest::digest(&digest::SHA256, certificate_der)
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{byte:02X}"))
+        .collect::<Vec<_>>()
+        .join(":")
+}
+
+// Any certificate is accepted during the handshake. It must be checked by the caller through its
+// fingerprint once the connection is established
+pub(crate) struct AcceptAnyCertificate;
+
+impl rustls::client::ServerCertVerifier for AcceptAnyCertificate {
+    fn verify_server_cert(
+        &self,
+        _: &rustls::Certificate,
+        _: &[rustls::Certificate],
+        _: &rustls::ServerName,
+        _: &mut dyn Iterator<Item = &[u8]>,
+        _: &[u8],
+        _: SystemTime,
+    ) -> Result<rustls::client::ServerCertVerified, rustls::Error> {
+        Ok(rustls::client::ServerCertVerified::assertion())
+    }
+}
+
+pub(crate) fn tls_client_config() -> rustls::ClientConfig {
+    rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_custom_certificate_verifier(Arc::new(AcceptAnyCertificate))
+        .with_no_client_auth()
+}
+
+pub(crate) fn tls_server_config(identity: &Identity) -> StrResult<rustls::ServerConfig> {
+    rustls::ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(
+            vec![rustls::Certificate(identity.certificate_der.clone())],
+            rustls::PrivateKey(identity.private_key_der.clone()),
+        )
+        .map_err(err!())
+}
+
+// Keys of the stream packets, one per direction
+#[derive(Clone)]
+pub struct StreamKeys {
+    pub(crate) server_to_client: [u8; KEY_SIZE],
+    pub(crate) client_to_server: [u8; KEY_SIZE],
+}
+
+impl StreamKeys {
+    pub(crate) fn new(keying_material: &[u8; 2 * KEY_SIZE]) -> Self {
+        let mut server_to_client = [0; KEY_SIZE];
+        let mut client_to_server = [0; KEY_SIZE];
+        server_to_client.copy_from_slice(&keying_material[..KEY_SIZE]);
+        client_to_server.copy_from_slice(&keying_material[KEY_SIZE..]);
+
+        Self {
+            server_to_client,
+            client_to_server,
+        }
+    }
+}
+
+// Issued by the server when the stream starts. A client that lost the connection presents it in the
+// next handshake to resume the session, without negotiating the stream configuration again
+#[derive(Serialize, Deserialize, Clone, Copy)]
+pub struct ResumeToken([u8; KEY_SIZE]);
+
+impl ResumeToken {
+    pub fn generate() -> Self {
+        Self(rand::random())
+    }
+
+    pub fn matches(&self, other: &ResumeToken) -> bool {
+        ring::constant_time::verify_slices_are_equal(&self.0, &other.0).is_ok()
+    }
+}
+
+#[derive(Clone, Copy)]
+pub enum AuthRole {
+    Server,
+    Client,
+}
+
+// SPAKE2 identities of the two peers
+const PIN_EXCHANGE_SERVER_ID: &[u8] = b"alvr-server";
+const PIN_EXCHANGE_CLIENT_ID: &[u8] = b"alvr-client";
+
+// One run of the key exchange over the pairing PIN. The resulting key is the same on both sides only
+// if both peers used the same PIN, which is verified with the auth proofs of the key
+pub(crate) struct PinExchange(Spake2<Ed25519Group>);
+
+impl PinExchange {
+    // Returns the message for the peer
+    pub fn start(pin: &str, role: AuthRole) -> (Self, Vec<u8>) {
+        let password = Password::new(pin.as_bytes());
+        let server_id = spake2::Identity::new(PIN_EXCHANGE_SERVER_ID);
+        let client_id = spake2::Identity::new(PIN_EXCHANGE_CLIENT_ID);
+
+        let (state, message) = match role {
+            AuthRole::Server => Spake2::<Ed25519Group>::start_a(&password, &server_id, &client_id),
+            AuthRole::Client => Spake2::<Ed25519Group>::start_b(&password, &server_id, &client_id),
+        };
+
+        (Self(state), message)
+    }
+
+    pub fn finish(self, peer_message: &[u8]) -> StrResult<Vec<u8>> {
+        self.0.finish(peer_message).map_err(err_dbg!())
+    }
+}
+
+// Proof of the knowledge of a key (key of the PIN exchange or pairing secret). It is bound to the TLS session
+// and to the role of the peer, so it cannot be replayed or reflected
+pub(crate) fn auth_proof(session_material: &[u8], key: &[u8], role: AuthRole) -> Vec<u8> {
+    hmac::sign(
+        &hmac::Key::new(hmac::HMAC_SHA256, key),
+        &auth_message(session_material, role),
+    )
+    .as_ref()
+    .to_vec()
+}
+
+pub(crate) fn verify_auth_proof(
+    session_material: &[u8],
+    key: &[u8],
+    role: AuthRole,
+    proof: &[u8],
+) -> bool {
+    hmac::verify(
+        &hmac::Key::new(hmac::HMAC_SHA256, key),
+        &auth_message(session_material, role),
+        proof,
+    )
+    .is_ok()
+}
+
+fn auth_message(session_material: &[u8], role: AuthRole) -> Vec<u8> {
+    let mut message = session_material.to_vec();
+    message.push(match role {
+        AuthRole::Server => 0,
+        AuthRole::Client => 1,
+    });
+
+    message
+}
+
+fn aead_key(key: &[u8; KEY_SIZE]) -> aead::LessSafeKey {
+    aead::LessSafeKey::new(aead::UnboundKey::new(&aead::CHACHA20_POLY1305, key).unwrap())
+}
+
+// Nonces are unique as long as there is only one sender per stream and direction
+fn nonce(stream_id: u16, counter: u64) -> aead::Nonce {
+    let mut nonce = [0; aead::NONCE_LEN];
+    nonce[..2].copy_from_slice(&stream_id.to_be_bytes());
+    nonce[4..].copy_from_slice(&counter.to_be_bytes());
+
+    aead::Nonce::assume_unique_for_key(nonce)
+}
+
+pub(crate) struct PacketSealer {
+    key: aead::LessSafeKey,
+    next_counter: u64,
+}
+
+impl PacketSealer {
+    pub fn new(key: &[u8; KEY_SIZE]) -> Self {
+        Self {
+            key: aead_key(key),
+            next_counter: 0,
+        }
+    }
+
+    // The shard starts with the stream ID, which is left in clear. The result layout is:
+    // stream ID | counter | ciphertext | tag
+    pub fn seal(&mut self, shard: &[u8]) -> StrResult<Bytes> {
+        let stream_id = u16::from_be_bytes([shard[0], shard[1]]);
+        let counter = self.next_counter;
+        self.next_counter += 1;
+
+        let mut sealed = BytesMut::with_capacity(shard.len() + SEAL_OVERHEAD);
+        sealed.put_slice(&shard[..2]);
+        sealed.put_u64(counter);
+        sealed.put_slice(&shard[2..]);
+
+        let tag = self
+            .key
+            .seal_in_place_separate_tag(
+                nonce(stream_id, counter),
+                aead::Aad::from(stream_id.to_be_bytes()),
+                &mut sealed[2 + COUNTER_SIZE..],
+            )
+            .map_err(err!())?;
+        sealed.put_slice(tag.as_ref());
+
+        Ok(sealed.freeze())
+    }
+}
+
+pub(crate) struct PacketOpener {
+    key: aead::LessSafeKey,
+    stream_id: u16,
+    highest_counter: Option<u64>,
+    // Bit i is set if the shard with counter highest_counter - i has been received
+    received_window: u128,
+}
+
+impl PacketOpener {
+    pub fn new(key: &[u8; KEY_SIZE], stream_id: u16) -> Self {
+        Self {
+            key: aead_key(key),
+            stream_id,
+            highest_counter: None,
+            received_window: 0,
+        }
+    }
+
+    fn is_replayed(&self, counter: u64) -> bool {
+        match self.highest_counter {
+            Some(highest) if counter <= highest => {
+                let offset = highest - counter;
+                offset >= REPLAY_WINDOW_SIZE || self.received_window & (1 << offset) != 0
+            }
+            _ => false,
+        }
+    }
+
+    fn mark_received(&mut self, counter: u64) {
+        match self.highest_counter {
+            Some(highest) if counter <= highest => {
+                self.received_window |= 1 << (highest - counter);
+            }
+            Some(highest) => {
+                let shift = counter - highest;
+                self.received_window = if shift < REPLAY_WINDOW_SIZE {
+                    (self.received_window << shift) | 1
+                } else {
+                    1
+                };
+                self.highest_counter = Some(counter);
+            }
+            None => {
+                self.received_window = 1;
+                self.highest_counter = Some(counter);
+            }
+        }
+    }
+
+    // The shard must not include the stream ID. Forged, corrupted and replayed shards are rejected
+    pub fn open(&mut self, mut shard: BytesMut) -> Option<BytesMut> {
+        if shard.len() < SEAL_OVERHEAD {
+            return None;
+        }
+
+        let mut counter_bytes = [0; COUNTER_SIZE];
+        counter_bytes.copy_from_slice(&shard[..COUNTER_SIZE]);
+        let counter = u64::from_be_bytes(counter_bytes);
+        if self.is_replayed(counter) {
+            return None;
+        }
+
+        let plaintext_size = self
+            .key
+            .open_in_place(
+                nonce(self.stream_id, counter),
+                aead::Aad::from(self.stream_id.to_be_bytes()),
+                &mut shard[COUNTER_SIZE..],
+            )
+            .ok()?
+            .len();
+        self.mark_received(counter);
+
+        let mut plaintext = shard.split_off(COUNTER_SIZE);
+        plaintext.truncate(plaintext_size);
+
+        Some(plaintext)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STREAM_ID: u16 = 3;
+
+    fn shard(payload: &[u8]) -> Vec<u8> {
+        let mut shard = STREAM_ID.to_be_bytes().to_vec();
+        shard.extend(payload);
+
+        shard
+    }
+
+    fn received(sealed: &Bytes) -> BytesMut {
+        BytesMut::from(&sealed[2..])
+    }
+
+    #[test]
+    fn seal_and_open() {
+        let key = [7; KEY_SIZE];
+        let mut sealer = PacketSealer::new(&key);
+        let mut opener = PacketOpener::new(&key, STREAM_ID);
+
+        let sealed = sealer.seal(&shard(b"payload")).unwrap();
+        assert_eq!(sealed.len(), 2 + b"payload".len() + SEAL_OVERHEAD);
+        assert_eq!(&opener.open(received(&sealed)).unwrap()[..], b"payload");
+
+        // replayed
+        assert!(opener.open(received(&sealed)).is_none());
+
+        // wrong key
+        let mut opener = PacketOpener::new(&[8; KEY_SIZE], STREAM_ID);
+        assert!(opener
+            .open(received(&sealer.seal(&shard(b"")).unwrap()))
+            .is_none());
+
+        // tampered
+        let mut opener = PacketOpener::new(&key, STREAM_ID);
+        let mut tampered = received(&sealer.seal(&shard(b"payload")).unwrap());
+        tampered[COUNTER_SIZE] ^= 1;
+        assert!(opener.open(tampered).is_none());
+    }
+
+    #[test]
+    fn auth_proofs() {
+        let session_material = [1; KEY_SIZE];
+        let proof = auth_proof(&session_material, b"123456", AuthRole::Client);
+
+        assert!(verify_auth_proof(
+            &session_material,
+            b"123456",
+            AuthRole::Client,
+            &proof
+        ));
+        assert!(!verify_auth_proof(
+            &session_material,
+            b"123457",
+            AuthRole::Client,
+            &proof
+        ));
+        // reflected
+        assert!(!verify_auth_proof(
+            &session_material,
+            b"123456",
+            AuthRole::Server,
+            &proof
+        ));
+        // other session
+        assert!(!verify_auth_proof(
+            &[2; KEY_SIZE],
+            b"123456",
+            AuthRole::Client,
+            &proof
+        ));
+    }
+
+    #[test]
+    fn pin_exchange() {
+        let (server, server_message) = PinExchange::start("123456", AuthRole::Server);
+        let (client, client_message) = PinExchange::start("123456", AuthRole::Client);
+        assert_eq!(
+            server.finish(&client_message).unwrap(),
+            client.finish(&server_message).unwrap()
+        );
+
+        let (server, server_message) = PinExchange::start("123456", AuthRole::Server);
+        let (client, client_message) = PinExchange::start("123457", AuthRole::Client);
+        assert_ne!(
+            server.finish(&client_message).unwrap(),
+            client.finish(&server_message).unwrap()
+        );
+    }
+
+    #[test]
+    fn reordering_window() {
+        let key = [7; KEY_SIZE];
+        let mut sealer = PacketSealer::new(&key);
+        let mut opener = PacketOpener::new(&key, STREAM_ID);
+
+        let sealed = (0..200)
+            .map(|_| sealer.seal(&shard(b"payload")).unwrap())
+            .collect::<Vec<_>>();
+
+        assert!(opener.open(received(&sealed[150])).is_some());
+        assert!(opener.open(received(&sealed[100])).is_some());
+        assert!(opener.open(received(&sealed[10])).is_none());
+        assert!(opener.open(received(&sealed[199])).is_some());
+        assert!(opener.open(received(&sealed[100])).is_none());
+        assert!(opener.open(received(&sealed[151])).is_some());
+    }
+}
@@ -1,14 +1,26 @@
+pub mod codec;
+
+mod capabilities;
+mod clock_sync;
 mod control_socket;
+mod crypto;
+mod mdns;
+mod network;
 mod packets;
+mod queue;
 mod stream_socket;
 
-use std::net::{IpAddr, Ipv4Addr};
-
+pub use capabilities::*;
+pub use clock_sync::{ClockSync, ClockSyncPacket};
+pub use codec::BoundedPacket;
 pub use control_socket::*;
+pub use crypto::{AuthRole, Identity, ResumeToken, StreamKeys};
+pub use mdns::{MdnsAdvertiser, MdnsBrowser, MDNS_SERVICE_TYPE};
+pub use network::*;
 pub use packets::*;
+pub use queue::{bounded_queue, QueueReceiver, QueueSender};
 pub use stream_socket::*;
 
-pub const LOCAL_IP: IpAddr = IpAddr::V4(Ipv4Addr::UNSPECIFIED);
 pub const CONTROL_PORT: u16 = 9943;
 pub const MAX_HANDSHAKE_PACKET_SIZE_BYTES: usize = 4_000;
 
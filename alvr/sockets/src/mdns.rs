@@ -0,0 +1,164 @@
+// DNS-SD advertisement of the server over mDNS, for the networks that block the discovery
+// broadcasts, like enterprise access points and guest VLANs. Clients browse for the service and
+// send their announcement directly to each compatible server they find. The TXT records carry the
+// ALVR version and the range of supported protocol versions.
+
+use crate::{MIN_PROTOCOL_VERSION, PROTOCOL_VERSION};
+use alvr_common::{prelude::*, ALVR_VERSION};
+use mdns_sd::{ServiceDaemon, ServiceEvent, ServiceInfo};
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+};
+
+pub const MDNS_SERVICE_TYPE: &str = "_alvr._tcp.local.";
+const VERSION_KEY: &str = "version";
+const PROTOCOL_KEY: &str = "protocol";
+
+// Formatted as "<min>-<max>"
+fn parse_protocol_range(value: &str) -> Option<(u32, u32)> {
+    let (min, max) = value.split_once('-')?;
+
+    Some((min.trim().parse().ok()?, max.trim().parse().ok()?))
+}
+
+fn is_compatible(min_protocol_version: u32, max_protocol_version: u32) -> bool {
+    u32::min(PROTOCOL_VERSION, max_protocol_version)
+        >= u32::max(MIN_PROTOCOL_VERSION, min_protocol_version)
+}
+
+fn is_ipv6_link_local(ip: &IpAddr) -> bool {
+    matches!(ip, IpAddr::V6(ip) if ip.segments()[0] & 0xffc0 == 0xfe80)
+}
+
+// The service is withdrawn when dropped
+pub struct MdnsAdvertiser {
+    daemon: ServiceDaemon,
+    fullname: String,
+}
+
+impl MdnsAdvertiser {
+    // port is the destination of the client announcements
+    pub fn new(port: u16) -> StrResult<Self> {
+        let daemon = ServiceDaemon::new().map_err(err!())?;
+
+        // The instance name must be unique on the network
+        let instance_name = format!("alvr-server-{:08x}", rand::random::<u32>());
+        let properties = [
+            (VERSION_KEY.to_owned(), ALVR_VERSION.to_string()),
+            (
+                PROTOCOL_KEY.to_owned(),
+                format!("{MIN_PROTOCOL_VERSION}-{PROTOCOL_VERSION}"),
+            ),
+        ]
+        .into_iter()
+        .collect::<HashMap<_, _>>();
+
+        // The addresses follow the network interfaces of the host
+        let service = ServiceInfo::new(
+            MDNS_SERVICE_TYPE,
+            &instance_name,
+            &format!("{instance_name}.local."),
+            (),
+            port,
+            properties,
+        )
+        .map_err(err!())?
+        .enable_addr_auto();
+        let fullname = service.get_fullname().to_owned();
+
+        daemon.register(service).map_err(err!())?;
+
+        Ok(Self { daemon, fullname })
+    }
+}
+
+impl Drop for MdnsAdvertiser {
+    fn drop(&mut self) {
+        self.daemon.unregister(&self.fullname).ok();
+        self.daemon.shutdown().ok();
+    }
+}
+
+pub struct MdnsBrowser {
+    daemon: ServiceDaemon,
+    receiver: mdns_sd::Receiver<ServiceEvent>,
+    // Announcement addresses by service instance
+    servers: HashMap<String, Vec<SocketAddr>>,
+}
+
+impl MdnsBrowser {
+    pub fn new() -> StrResult<Self> {
+        let daemon = ServiceDaemon::new().map_err(err!())?;
+        let receiver = daemon.browse(MDNS_SERVICE_TYPE).map_err(err!())?;
+
+        Ok(Self {
+            daemon,
+            receiver,
+            servers: HashMap::new(),
+        })
+    }
+
+    // Announcement addresses of the compatible servers found so far
+    pub fn servers(&mut self) -> Vec<SocketAddr> {
+        while let Ok(event) = self.receiver.try_recv() {
+            match event {
+                ServiceEvent::ServiceResolved(service) => {
+                    let protocol_range = service
+                        .get_property_val_str(PROTOCOL_KEY)
+                        .and_then(parse_protocol_range);
+                    let version = service.get_property_val_str(VERSION_KEY).unwrap_or("");
+
+                    match protocol_range {
+                        Some((min, max)) if is_compatible(min, max) => {
+                            let port = service.get_port();
+                            // Link-local addresses cannot be reached without their scope
+                            let addresses = service
+                                .get_addresses()
+                                .iter()
+                                .filter(|ip| !is_ipv6_link_local(ip))
+                                .map(|&ip| SocketAddr::from((ip, port)))
+                                .collect();
+
+                            self.servers
+                                .insert(service.get_fullname().to_owned(), addresses);
+                        }
+                        _ => debug!(
+                            "Ignored incompatible server {} (version {version})",
+                            service.get_fullname()
+                        ),
+                    }
+                }
+                ServiceEvent::ServiceRemoved(_, fullname) => {
+                    self.servers.remove(&fullname);
+                }
+                _ => (),
+            }
+        }
+
+        self.servers.values().flatten().copied().collect()
+    }
+}
+
+impl Drop for MdnsBrowser {
+    fn drop(&mut self) {
+        self.daemon.shutdown().ok();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn protocol_ranges() {
+        assert_eq!(parse_protocol_range("2-3"), Some((2, 3)));
+        assert_eq!(parse_protocol_range("2"), None);
+        assert_eq!(parse_protocol_range("a-3"), None);
+
+        assert!(is_compatible(MIN_PROTOCOL_VERSION, PROTOCOL_VERSION));
+        assert!(is_compatible(0, PROTOCOL_VERSION + 5));
+        assert!(!is_compatible(PROTOCOL_VERSION + 1, PROTOCOL_VERSION + 5));
+        assert!(!is_compatible(0, MIN_PROTOCOL_VERSION - 1));
+    }
+}
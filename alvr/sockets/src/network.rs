@@ -0,0 +1,409 @@
+// Dual-stack networking. The peers talk over IPv4 or IPv6, and the server can be bound to a single
+// network interface. Clients announce themselves with an IPv4 broadcast and with an IPv6 link-local
+// multicast on each interface. The IPv6 announcements are sent from a routable address of the
+// interface: a link-local source address could not be reached back without its interface scope.
+
+use alvr_common::prelude::*;
+use futures::future;
+use if_addrs::IfAddr;
+use socket2::{Domain, Protocol, Socket, Type};
+use std::{
+    collections::HashSet,
+    io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV6},
+};
+use tokio::net::{TcpListener, TcpSocket, TcpStream, UdpSocket};
+
+// Not assigned by IANA. Unlike the all-nodes group, only the servers join it
+pub const DISCOVERY_MULTICAST_IP: Ipv6Addr = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0xa1, 0x9943);
+
+const LISTEN_BACKLOG: i32 = 1024;
+
+// Unspecified address of the same family as ip, to bind the sockets that talk to ip
+pub fn unspecified_ip(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V4(_) => Ipv4Addr::UNSPECIFIED.into(),
+        IpAddr::V6(_) => Ipv6Addr::UNSPECIFIED.into(),
+    }
+}
+
+// Dual-stack sockets report IPv4 peers as IPv4-mapped IPv6 addresses
+pub fn canonical_ip(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(ipv6) => match ipv6.segments() {
+            [0, 0, 0, 0, 0, 0xffff, high, low] => {
+                IpAddr::V4(Ipv4Addr::from((u32::from(high) << 16) | u32::from(low)))
+            }
+            _ => ip,
+        },
+        IpAddr::V4(_) => ip,
+    }
+}
+
+// IPv6 sockets accept only IPv6 peers, so that an IPv4 socket can be bound to the same port
+fn bind_udp(address: SocketAddr) -> StrResult<UdpSocket> {
+    let socket = Socket::new(
+        Domain::for_address(address),
+        Type::DGRAM,
+        Some(Protocol::UDP),
+    )
+    .map_err(err!())?;
+    if address.is_ipv6() {
+        socket.set_only_v6(true).map_err(err!())?;
+    }
+    socket.set_nonblocking(true).map_err(err!())?;
+    socket.bind(&address.into()).map_err(err!())?;
+
+    UdpSocket::from_std(socket.into()).map_err(err!())
+}
+
+// Accepts both IPv4 and IPv6 peers. Their addresses must be passed through canonical_ip
+pub fn bind_dual_stack_listener(port: u16) -> StrResult<TcpListener> {
+    let socket = match Socket::new(Domain::IPV6, Type::STREAM, Some(Protocol::TCP)) {
+        Ok(socket) => socket,
+        // IPv6 is disabled on this host
+        Err(_) => {
+            let listener =
+                std::net::TcpListener::bind((Ipv4Addr::UNSPECIFIED, port)).map_err(err!())?;
+            listener.set_nonblocking(true).map_err(err!())?;
+
+            return TcpListener::from_std(listener).map_err(err!());
+        }
+    };
+    socket.set_only_v6(false).map_err(err!())?;
+    // Like TcpListener::bind, the port can be bound again while old connections are closing
+    #[cfg(not(windows))]
+    socket.set_reuse_address(true).map_err(err!())?;
+    socket.set_nonblocking(true).map_err(err!())?;
+    socket
+        .bind(&SocketAddr::from((Ipv6Addr::UNSPECIFIED, port)).into())
+        .map_err(err!())?;
+    socket.listen(LISTEN_BACKLOG).map_err(err!())?;
+
+    TcpListener::from_std(socket.into()).map_err(err!())
+}
+
+// Connects to the first peer that answers, like TcpStream::connect. Peers that cannot be reached
+// through the binding are skipped
+pub async fn connect_tcp(
+    peer_ips: &[IpAddr],
+    port: u16,
+    binding: &InterfaceBinding,
+) -> StrResult<TcpStream> {
+    let mut last_error = None;
+    for &peer_ip in peer_ips {
+        let local_ip = if let Some(ip) = binding.local_ip_for(peer_ip) {
+            ip
+        } else {
+            continue;
+        };
+
+        let socket = if local_ip.is_ipv4() {
+            TcpSocket::new_v4()
+        } else {
+            TcpSocket::new_v6()
+        }
+        .map_err(err!())?;
+        socket.bind((local_ip, 0).into()).map_err(err!())?;
+
+        match socket.connect((canonical_ip(peer_ip), port).into()).await {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_error = Some(e),
+        }
+    }
+
+    if let Some(e) = last_error {
+        fmt_e!("{e}")
+    } else {
+        fmt_e!("No peer can be reached through the bound interface")
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct InterfaceAddress {
+    ip: IpAddr,
+    netmask: IpAddr,
+}
+
+impl InterfaceAddress {
+    fn contains(&self, peer_ip: IpAddr) -> bool {
+        match (self.ip, self.netmask, peer_ip) {
+            (IpAddr::V4(ip), IpAddr::V4(netmask), IpAddr::V4(peer_ip)) => {
+                let netmask = u32::from(netmask);
+                u32::from(ip) & netmask == u32::from(peer_ip) & netmask
+            }
+            (IpAddr::V6(ip), IpAddr::V6(netmask), IpAddr::V6(peer_ip)) => {
+                let netmask = u128::from(netmask);
+                u128::from(ip) & netmask == u128::from(peer_ip) & netmask
+            }
+            _ => false,
+        }
+    }
+}
+
+// Local addresses used by the server to reach the clients. Clients outside of the subnets of a
+// bound interface are ignored
+#[derive(Clone, Default, Debug)]
+pub struct InterfaceBinding {
+    // None if any interface can be used
+    addresses: Option<Vec<InterfaceAddress>>,
+}
+
+impl InterfaceBinding {
+    pub fn any() -> Self {
+        Self { addresses: None }
+    }
+
+    // name_or_address is the name of an interface, like "eth0" or "Wi-Fi", or one of its addresses.
+    // Link-local IPv6 addresses are not supported
+    pub fn resolve(name_or_address: &str) -> StrResult<Self> {
+        let name_or_address = name_or_address.trim();
+        let maybe_ip = name_or_address.parse::<IpAddr>().ok().map(canonical_ip);
+
+        let addresses = if_addrs::get_if_addrs()
+            .map_err(err!())?
+            .into_iter()
+            .filter(|interface| match maybe_ip {
+                Some(ip) => interface.ip() == ip,
+                None => interface.name == name_or_address,
+            })
+            .map(|interface| match interface.addr {
+                IfAddr::V4(address) => InterfaceAddress {
+                    ip: address.ip.into(),
+                    netmask: address.netmask.into(),
+                },
+                IfAddr::V6(address) => InterfaceAddress {
+                    ip: address.ip.into(),
+                    netmask: address.netmask.into(),
+                },
+            })
+            .collect::<Vec<_>>();
+
+        if addresses.is_empty() {
+            fmt_e!("No network interface has the name or address {name_or_address}")
+        } else {
+            Ok(Self {
+                addresses: Some(addresses),
+            })
+        }
+    }
+
+    // Address to bind to talk to the peer. None if the peer cannot be reached through the binding
+    pub fn local_ip_for(&self, peer_ip: IpAddr) -> Option<IpAddr> {
+        let peer_ip = canonical_ip(peer_ip);
+
+        match &self.addresses {
+            Some(addresses) => addresses
+                .iter()
+                .find(|address| address.contains(peer_ip))
+                .map(|address| address.ip),
+            None => Some(unspecified_ip(peer_ip)),
+        }
+    }
+}
+
+// Indices of the interfaces that can send and receive the IPv6 announcements
+fn multicast_interfaces() -> StrResult<Vec<(u32, Ipv6Addr)>> {
+    let mut indices = HashSet::new();
+
+    Ok(if_addrs::get_if_addrs()
+        .map_err(err!())?
+        .into_iter()
+        .filter_map(|interface| match (interface.addr, interface.index) {
+            (IfAddr::V6(address), Some(index))
+                if !address.is_loopback() && indices.insert(index) =>
+            {
+                Some((index, address.ip))
+            }
+            _ => None,
+        })
+        .collect())
+}
+
+// Receives from the first socket that has a datagram
+async fn recv_from_any(
+    sockets: &[&UdpSocket],
+    buffer: &mut [u8],
+) -> io::Result<(usize, SocketAddr)> {
+    loop {
+        let (res, index, _) =
+            future::select_all(sockets.iter().map(|socket| Box::pin(socket.readable()))).await;
+        res?;
+
+        match sockets[index].try_recv_from(buffer) {
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => continue,
+            res => return res,
+        }
+    }
+}
+
+// Server side of the discovery. Receives the IPv4 broadcasts and the IPv6 multicasts
+pub struct DiscoverySocket {
+    ipv4: UdpSocket,
+    ipv6: Option<UdpSocket>,
+}
+
+impl DiscoverySocket {
+    pub fn bind(port: u16) -> StrResult<Self> {
+        let ipv4 = bind_udp((Ipv4Addr::UNSPECIFIED, port).into())?;
+
+        let ipv6 = match bind_udp((Ipv6Addr::UNSPECIFIED, port).into()) {
+            Ok(socket) => {
+                for (index, _) in multicast_interfaces()? {
+                    if let Err(e) = socket.join_multicast_v6(&DISCOVERY_MULTICAST_IP, index) {
+                        debug!("Cannot join the discovery group on interface {index}: {e}");
+                    }
+                }
+
+                Some(socket)
+            }
+            Err(e) => {
+                warn!("IPv6 discovery is not available: {e}");
+
+                None
+            }
+        };
+
+        Ok(Self { ipv4, ipv6 })
+    }
+
+    pub async fn recv_from(&self, buffer: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        let sockets = std::iter::once(&self.ipv4)
+            .chain(self.ipv6.as_ref())
+            .collect::<Vec<_>>();
+
+        recv_from_any(&sockets, buffer).await
+    }
+
+    pub async fn send_to(&self, buffer: &[u8], address: SocketAddr) -> io::Result<usize> {
+        match (address, &self.ipv6) {
+            (SocketAddr::V4(_), _) => self.ipv4.send_to(buffer, address).await,
+            (SocketAddr::V6(_), Some(socket)) => socket.send_to(buffer, address).await,
+            (SocketAddr::V6(_), None) => Err(io::ErrorKind::AddrNotAvailable.into()),
+        }
+    }
+}
+
+// Client side of the discovery. Sends the announcements and receives the server responses
+pub struct AnnounceSocket {
+    ipv4: UdpSocket,
+    // Bound to a routable address of each interface, with the multicast group on that interface
+    ipv6: Vec<(UdpSocket, SocketAddr)>,
+    server_port: u16,
+}
+
+impl AnnounceSocket {
+    // local_port is the source port of the IPv4 announcements
+    pub fn bind(local_port: u16, server_port: u16) -> StrResult<Self> {
+        let ipv4 = bind_udp((Ipv4Addr::UNSPECIFIED, local_port).into())?;
+        ipv4.set_broadcast(true).map_err(err!())?;
+
+        let ipv6 = multicast_interfaces()?
+            .into_iter()
+            .filter_map(|(index, ip)| {
+                let socket = bind_udp((ip, 0).into()).ok()?;
+                let group = SocketAddrV6::new(DISCOVERY_MULTICAST_IP, server_port, 0, index);
+
+                Some((socket, group.into()))
+            })
+            .collect();
+
+        Ok(Self {
+            ipv4,
+            ipv6,
+            server_port,
+        })
+    }
+
+    // Returns false if the announcement could not be sent on any network
+    pub async fn announce(&self, packet: &[u8]) -> bool {
+        let mut sent = self
+            .ipv4
+            .send_to(packet, (Ipv4Addr::BROADCAST, self.server_port))
+            .await
+            .is_ok();
+        for (socket, group) in &self.ipv6 {
+            sent |= socket.send_to(packet, group).await.is_ok();
+        }
+
+        sent
+    }
+
+    // Announcement sent to a known server, like the ones found with mDNS. Returns false if it could
+    // not be sent
+    pub async fn announce_to(&self, packet: &[u8], server_address: SocketAddr) -> bool {
+        if server_address.is_ipv4() {
+            return self.ipv4.send_to(packet, server_address).await.is_ok();
+        }
+
+        // The packet follows the routing table, whichever routable address is the source
+        for (socket, _) in &self.ipv6 {
+            if socket.send_to(packet, server_address).await.is_ok() {
+                return true;
+            }
+        }
+
+        false
+    }
+
+    // Also receives the broadcasted announcements, which must be ignored
+    pub async fn recv_from(&self, buffer: &mut [u8]) -> io::Result<(usize, SocketAddr)> {
+        let sockets = std::iter::once(&self.ipv4)
+            .chain(self.ipv6.iter().map(|(socket, _)| socket))
+            .collect::<Vec<_>>();
+
+        recv_from_any(&sockets, buffer).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ipv4_mapped_addresses() {
+        let mapped = "::ffff:192.168.1.10".parse().unwrap();
+        assert_eq!(
+            canonical_ip(mapped),
+            "192.168.1.10".parse::<IpAddr>().unwrap()
+        );
+
+        // IPv4-compatible addresses are deprecated, ::1 is not 0.0.0.1
+        let loopback = IpAddr::V6(Ipv6Addr::LOCALHOST);
+        assert_eq!(canonical_ip(loopback), loopback);
+    }
+
+    #[test]
+    fn interface_subnets() {
+        let binding = InterfaceBinding {
+            addresses: Some(vec![
+                InterfaceAddress {
+                    ip: "192.168.1.2".parse().unwrap(),
+                    netmask: "255.255.255.0".parse().unwrap(),
+                },
+                InterfaceAddress {
+                    ip: "fd00::2".parse().unwrap(),
+                    netmask: "ffff:ffff:ffff:ffff::".parse().unwrap(),
+                },
+            ]),
+        };
+
+        let local_ip = |peer: &str| binding.local_ip_for(peer.parse().unwrap());
+        assert_eq!(
+            local_ip("192.168.1.40"),
+            Some("192.168.1.2".parse().unwrap())
+        );
+        assert_eq!(
+            local_ip("::ffff:192.168.1.40"),
+            Some("192.168.1.2".parse().unwrap())
+        );
+        assert_eq!(local_ip("fd00::1:40"), Some("fd00::2".parse().unwrap()));
+        assert_eq!(local_ip("10.0.0.40"), None);
+        assert_eq!(local_ip("fd01::40"), None);
+
+        assert_eq!(
+            InterfaceBinding::any().local_ip_for("fd01::40".parse().unwrap()),
+            Some(Ipv6Addr::UNSPECIFIED.into())
+        );
+    }
+}
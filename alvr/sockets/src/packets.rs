@@ -1,10 +1,11 @@
-use std::{net::IpAddr, time::Duration};
+use crate::{ClockSyncPacket, ResumeToken, ShardLoss};
+use std::{collections::HashMap, net::IpAddr, time::Duration};
 
 use alvr_common::{
     glam::{Quat, Vec2, Vec3},
     semver::Version,
 };
-use alvr_events::ButtonValue;
+use alvr_events::{ButtonValue, StreamStatistics};
 use alvr_session::Fov;
 use serde::{Deserialize, Serialize};
 
@@ -14,24 +15,36 @@ pub const AUDIO: u16 = 2;
 pub const VIDEO: u16 = 3;
 pub const STATISTICS: u16 = 4;
 
+// Key of the stream in the statistics shown in the dashboard
+pub fn stream_name(stream_id: u16) -> String {
+    match stream_id {
+        TRACKING => "tracking".into(),
+        HAPTICS => "haptics".into(),
+        AUDIO => "audio".into(),
+        VIDEO => "video".into(),
+        STATISTICS => "statistics".into(),
+        _ => stream_id.to_string(),
+    }
+}
+
+// The schema of this packet should never change anymore, so that any server can parse it. The
+// protocol is negotiated later, on the control socket (see Capabilities)
 #[derive(Serialize, Deserialize, Clone)]
 pub struct ClientHandshakePacket {
     pub alvr_name: String,
     pub version: Version,
     pub device_name: String,
     pub hostname: String,
-
-    // reserved field is used to add features between major releases: the schema of the packet
-    // should never change anymore (required only for this packet).
-    pub reserved1: String,
-    pub reserved2: String,
+    // The server pins this fingerprint to identify the client
+    pub certificate_fingerprint: String,
+    pub reserved: String,
 }
 
-// Since this packet is not essential, any change to it will not be a braking change
+// Since this packet is not essential, any change to it will not be a braking change. The protocol
+// versions are negotiated later, on the control socket (see Capabilities)
 #[derive(Serialize, Deserialize, Debug)]
 pub enum ServerHandshakePacket {
     ClientUntrusted,
-    IncompatibleVersions,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -40,6 +53,35 @@ pub enum HandshakePacket {
     Server(ServerHandshakePacket),
 }
 
+// Sent by the server as soon as the control socket is connected
+#[derive(Serialize, Deserialize)]
+pub enum ServerAuthPacket {
+    // The server proves to know the pairing secret, then the client must prove it too
+    Authenticate { server_proof: Vec<u8> },
+    // The peers run the key exchange over the PIN shown on the client screen (PinExchangePacket),
+    // then the client must prove to know the resulting key
+    RequestPin,
+    // Sent after a correct proof of the client. The server proves to know the key too, then both
+    // peers store the pairing secret derived from the current connection
+    Paired { server_proof: Vec<u8> },
+    // The server trusts every client (auto trust). It cannot be authenticated, so nothing is stored
+    // and only clients that were never paired accept it
+    Trusted,
+    // The client must show the PIN and wait for the user to enter it in the dashboard
+    Unpaired,
+}
+
+// SPAKE2 message. The server sends its message first
+#[derive(Serialize, Deserialize)]
+pub struct PinExchangePacket {
+    pub message: Vec<u8>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct ClientAuthPacket {
+    pub proof: Vec<u8>,
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct HeadsetInfoPacket {
     pub recommended_eye_width: u32,
@@ -47,13 +89,9 @@ pub struct HeadsetInfoPacket {
     pub available_refresh_rates: Vec<f32>,
     pub preferred_refresh_rate: f32,
     pub microphone_sample_rate: u32,
-
-    // reserved field is used to add features in a minor release that otherwise would break the
-    // packets schema
-    pub reserved: String,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub struct ClientConfigPacket {
     pub session_desc: String, // transfer session as string to allow for extrapolation
     pub dashboard_url: String,
@@ -61,8 +99,10 @@ pub struct ClientConfigPacket {
     pub eye_resolution_height: u32,
     pub fps: f32,
     pub game_audio_sample_rate: u32,
-    pub reserved: String,
-    pub server_version: Option<Version>,
+    // Spectators receive video and audio only and must not send tracking
+    pub spectator: bool,
+    // Each spectator is streamed from a different server port
+    pub server_stream_port: u16,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -70,8 +110,10 @@ pub enum ServerControlPacket {
     StartStream,
     Restarting,
     KeepAlive,
-    Reserved(String),
-    ReservedBuffer(Vec<u8>),
+    // Sent right after StartStream to the primary client, if both peers support FEATURE_RESUME
+    ResumeToken(ResumeToken),
+    // Sent only if both peers support FEATURE_CLOCK_SYNC
+    ClockSync(ClockSyncPacket),
 }
 
 #[derive(Serialize, Deserialize, Clone)]
@@ -88,6 +130,13 @@ pub struct BatteryPacket {
     pub is_plugged: bool,
 }
 
+// Shards to be resent by the server
+#[derive(Serialize, Deserialize)]
+pub struct NackPacket {
+    pub stream_id: u16,
+    pub lost_shards: Vec<ShardLoss>,
+}
+
 #[derive(Serialize, Deserialize)]
 pub enum ClientControlPacket {
     PlayspaceSync(Vec2),
@@ -99,8 +148,12 @@ pub enum ClientControlPacket {
     VideoErrorReport, // legacy
     Button { path_id: u64, value: ButtonValue },
     ActiveInteractionProfile { device_id: u64, profile_id: u64 },
-    Reserved(String),
-    ReservedBuffer(Vec<u8>),
+    Nack(NackPacket),
+    // Sent only if both peers support FEATURE_CLOCK_SYNC
+    ClockSync(ClockSyncPacket),
+    // By stream ID. Sent at the keepalive rate, only if both peers support
+    // FEATURE_STREAM_STATISTICS
+    StreamStatistics(HashMap<u16, StreamStatistics>),
 }
 
 // legacy video packet
@@ -159,6 +212,10 @@ pub enum PathSegment {
 
 pub enum ClientListAction {
     AddIfMissing { display_name: String },
+    PinCertificate(String),
+    Pair { pairing_secret: Vec<u8> },
+    SetSpectator(bool),
+    SetSettingsProfile(Option<String>),
     TrustAndMaybeAddIp(Option<IpAddr>),
     RemoveIpOrEntry(Option<IpAddr>),
 }
@@ -175,4 +232,17 @@ pub struct ClientStatistics {
     // Note: This is used for the controller prediction.
     // NB: This contains also the tracking packet send latency so it might lead to overprediction
     pub average_total_pipeline_latency: Duration,
+
+    // Video shards received or lost since the previous frame. Used to adjust the FEC percentage
+    pub video_shards_count: usize,
+    pub video_shards_lost: usize,
+    // Video packets that could not be recovered since the previous frame
+    pub video_packets_lost: usize,
+    // Index and arrival time of the video packets of this frame, relative to the start of the
+    // stream. Used by the delay based bitrate controller
+    pub video_packet_arrivals: Vec<(u32, Duration)>,
+    // Timestamps of the clock synchronized with FEATURE_CLOCK_SYNC, used to measure the network
+    // latency in each direction
+    pub tracking_sent: Duration,
+    pub video_packet_received: Duration,
 }
@@ -0,0 +1,216 @@
+// Bounded queue between a producer and a consumer that can fall behind. When the queue is full,
+// the drop policy decides whether the oldest packet is discarded or the producer waits. Discarded
+// packets are counted, so that they can be reported.
+
+use alvr_common::{parking_lot::Mutex, prelude::*};
+use alvr_session::DropPolicy;
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc,
+    },
+};
+use tokio::sync::Notify;
+
+struct Shared<T> {
+    items: Mutex<VecDeque<T>>,
+    capacity: usize,
+    drop_policy: DropPolicy,
+    dropped_count: AtomicUsize,
+    senders_count: AtomicUsize,
+    receiver_dropped: AtomicBool,
+    item_pushed: Notify,
+    item_popped: Notify,
+}
+
+pub fn bounded_queue<T>(
+    capacity: usize,
+    drop_policy: DropPolicy,
+) -> (QueueSender<T>, QueueReceiver<T>) {
+    let shared = Arc::new(Shared {
+        items: Mutex::new(VecDeque::new()),
+        capacity: usize::max(capacity, 1),
+        drop_policy,
+        dropped_count: AtomicUsize::new(0),
+        senders_count: AtomicUsize::new(1),
+        receiver_dropped: AtomicBool::new(false),
+        item_pushed: Notify::new(),
+        item_popped: Notify::new(),
+    });
+
+    (
+        QueueSender {
+            shared: Arc::clone(&shared),
+        },
+        QueueReceiver { shared },
+    )
+}
+
+pub struct QueueSender<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> QueueSender<T> {
+    // Never waits. With DropPolicy::Block, the new packet is discarded if the queue is full
+    pub fn try_send(&self, item: T) -> StrResult {
+        self.try_send_with_policy(item, self.shared.drop_policy)
+            .map(|_| ())
+    }
+
+    // Like try_send() but with a policy that overrides the one of the queue. Returns the discarded
+    // packet, if any
+    fn try_send_with_policy(&self, item: T, drop_policy: DropPolicy) -> StrResult<Option<T>> {
+        if self.shared.receiver_dropped.load(Ordering::Relaxed) {
+            return fmt_e!("Queue closed");
+        }
+
+        let mut items = self.shared.items.lock();
+        let mut maybe_dropped = None;
+        if items.len() >= self.shared.capacity {
+            self.shared.dropped_count.fetch_add(1, Ordering::Relaxed);
+
+            match drop_policy {
+                DropPolicy::DropOldest => maybe_dropped = items.pop_front(),
+                DropPolicy::Block => return Ok(Some(item)),
+            }
+        }
+        items.push_back(item);
+        self.shared.item_pushed.notify_one();
+
+        Ok(maybe_dropped)
+    }
+
+    // With DropPolicy::Block, waits until the consumer makes room
+    pub async fn send(&self, item: T) -> StrResult {
+        self.send_with_policy(item, self.shared.drop_policy)
+            .await
+            .map(|_| ())
+    }
+
+    // Like send() but with a policy that overrides the one of the queue. Returns the packet
+    // discarded with DropPolicy::DropOldest, if any
+    pub async fn send_with_policy(&self, item: T, drop_policy: DropPolicy) -> StrResult<Option<T>> {
+        if drop_policy == DropPolicy::DropOldest {
+            return self.try_send_with_policy(item, drop_policy);
+        }
+
+        loop {
+            if self.shared.receiver_dropped.load(Ordering::Relaxed) {
+                // Let the other waiting senders notice it too
+                self.shared.item_popped.notify_one();
+                return fmt_e!("Queue closed");
+            }
+
+            {
+                let mut items = self.shared.items.lock();
+                if items.len() < self.shared.capacity {
+                    items.push_back(item);
+                    self.shared.item_pushed.notify_one();
+
+                    return Ok(None);
+                }
+            }
+
+            self.shared.item_popped.notified().await;
+        }
+    }
+}
+
+impl<T> Clone for QueueSender<T> {
+    fn clone(&self) -> Self {
+        self.shared.senders_count.fetch_add(1, Ordering::Relaxed);
+
+        Self {
+            shared: Arc::clone(&self.shared),
+        }
+    }
+}
+
+impl<T> Drop for QueueSender<T> {
+    fn drop(&mut self) {
+        if self.shared.senders_count.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.shared.item_pushed.notify_one();
+        }
+    }
+}
+
+pub struct QueueReceiver<T> {
+    shared: Arc<Shared<T>>,
+}
+
+impl<T> QueueReceiver<T> {
+    // Returns None once all senders are dropped and the queue is empty
+    pub async fn recv(&mut self) -> Option<T> {
+        loop {
+            if let Some(item) = self.shared.items.lock().pop_front() {
+                self.shared.item_popped.notify_one();
+                return Some(item);
+            }
+
+            if self.shared.senders_count.load(Ordering::Acquire) == 0 {
+                return None;
+            }
+
+            self.shared.item_pushed.notified().await;
+        }
+    }
+
+    // Packets discarded since the last call
+    pub fn take_dropped_count(&self) -> usize {
+        self.shared.dropped_count.swap(0, Ordering::Relaxed)
+    }
+}
+
+impl<T> Drop for QueueReceiver<T> {
+    fn drop(&mut self) {
+        self.shared.receiver_dropped.store(true, Ordering::Relaxed);
+        self.shared.item_popped.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::time;
+
+    #[tokio::test]
+    async fn drop_oldest() {
+        let (sender, mut receiver) = bounded_queue(2, DropPolicy::DropOldest);
+
+        for item in 0..5 {
+            sender.send(item).await.unwrap();
+        }
+        drop(sender);
+
+        assert_eq!(receiver.take_dropped_count(), 3);
+        assert_eq!(receiver.recv().await, Some(3));
+        assert_eq!(receiver.recv().await, Some(4));
+        assert_eq!(receiver.recv().await, None);
+    }
+
+    #[tokio::test]
+    async fn block() {
+        let (sender, mut receiver) = bounded_queue(1, DropPolicy::Block);
+
+        sender.send(0).await.unwrap();
+        let blocked_send = tokio::spawn(async move { sender.send(1).await });
+
+        time::sleep(Duration::from_millis(50)).await;
+        assert!(!blocked_send.is_finished());
+
+        assert_eq!(receiver.recv().await, Some(0));
+        blocked_send.await.unwrap().unwrap();
+        assert_eq!(receiver.recv().await, Some(1));
+        assert_eq!(receiver.take_dropped_count(), 0);
+
+        // A blocked sender fails once the receiver is dropped
+        let (sender, receiver) = bounded_queue(1, DropPolicy::Block);
+        sender.send(0).await.unwrap();
+        let blocked_send = tokio::spawn(async move { sender.send(1).await });
+        time::sleep(Duration::from_millis(50)).await;
+        drop(receiver);
+        assert!(blocked_send.await.unwrap().is_err());
+    }
+}
@@ -0,0 +1,378 @@
+// Reed-Solomon forward error correction over the shards of a packet. Data shards are interleaved
+// into blocks (data shard i belongs to block i % blocks_count) so that a burst of lost shards is
+// spread over all blocks. The parity shards of each block are appended after all the data shards,
+// block by block. Every shard has the same size, except the last data shard which is zero-padded
+// for encoding and decoding.
+
+use alvr_common::prelude::*;
+use bytes::BytesMut;
+use reed_solomon_erasure::galois_8::ReedSolomon;
+use std::{
+    borrow::Cow,
+    collections::{hash_map::Entry, HashMap},
+};
+
+pub const MAX_FEC_PERCENTAGE: u16 = 100;
+
+// GF(2^8) supports at most 256 shards per block, which is respected even with MAX_FEC_PERCENTAGE
+const MAX_BLOCK_DATA_SHARDS: usize = 128;
+
+// Matrices are cached per (data, parity) shards count pair. Packet sizes vary, so the cache is reset
+// once it grows too much
+const MAX_CACHED_CODECS: usize = 64;
+
+const INITIAL_FEC_PERCENTAGE: f32 = 5.0;
+const MIN_FEC_PERCENTAGE: f32 = 1.0;
+// The redundancy is kept above the measured loss by this factor
+const LOSS_MARGIN: f32 = 2.0;
+// Weight of a new measurement when the redundancy is being reduced. It is increased immediately
+const DECAY_FACTOR: f32 = 0.05;
+
+struct BlockLayout {
+    data_shards_count: usize,
+    first_parity_index: usize,
+    parity_shards_count: usize,
+}
+
+pub struct FecLayout {
+    data_shards_count: usize,
+    blocks: Vec<BlockLayout>,
+}
+
+impl FecLayout {
+    pub fn new(data_shards_count: usize, fec_percentage: u16) -> Self {
+        let fec_percentage = u16::min(fec_percentage, MAX_FEC_PERCENTAGE) as usize;
+        let blocks_count = (data_shards_count + MAX_BLOCK_DATA_SHARDS - 1) / MAX_BLOCK_DATA_SHARDS;
+
+        let mut first_parity_index = data_shards_count;
+        let blocks = (0..blocks_count)
+            .map(|block_index| {
+                let block_data_shards_count =
+                    (data_shards_count - block_index + blocks_count - 1) / blocks_count;
+                let parity_shards_count = (block_data_shards_count * fec_percentage + 99) / 100;
+
+                let block = BlockLayout {
+                    data_shards_count: block_data_shards_count,
+                    first_parity_index,
+                    parity_shards_count,
+                };
+                first_parity_index += parity_shards_count;
+
+                block
+            })
+            .collect();
+
+        Self {
+            data_shards_count,
+            blocks,
+        }
+    }
+
+    pub fn data_shards_count(&self) -> usize {
+        self.data_shards_count
+    }
+
+    pub fn shards_count(&self) -> usize {
+        self.blocks
+            .last()
+            .map(|block| block.first_parity_index + block.parity_shards_count)
+            .unwrap_or(self.data_shards_count)
+    }
+
+    pub fn blocks_count(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn block_data_shards_count(&self, block_index: usize) -> usize {
+        self.blocks[block_index].data_shards_count
+    }
+
+    pub fn block_of(&self, shard_index: usize) -> usize {
+        if shard_index < self.data_shards_count {
+            shard_index % self.blocks.len()
+        } else {
+            self.blocks
+                .iter()
+                .position(|block| {
+                    shard_index < block.first_parity_index + block.parity_shards_count
+                })
+                .unwrap_or(self.blocks.len() - 1)
+        }
+    }
+
+    fn block_shard_indices(&self, block_index: usize) -> impl Iterator<Item = usize> + '_ {
+        let block = &self.blocks[block_index];
+
+        (block_index..self.data_shards_count)
+            .step_by(self.blocks.len())
+            .chain(block.first_parity_index..block.first_parity_index + block.parity_shards_count)
+    }
+}
+
+#[derive(Default)]
+pub struct FecCodec {
+    cache: HashMap<(usize, usize), ReedSolomon>,
+}
+
+impl FecCodec {
+    fn codec(&mut self, block: &BlockLayout) -> StrResult<&ReedSolomon> {
+        if self.cache.len() > MAX_CACHED_CODECS {
+            self.cache.clear();
+        }
+
+        let key = (block.data_shards_count, block.parity_shards_count);
+        Ok(match self.cache.entry(key) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(ReedSolomon::new(key.0, key.1).map_err(err!())?),
+        })
+    }
+
+    // Returns the parity shards, in the order defined by the layout. Only the last data shard can
+    // be shorter than shard_size.
+    pub fn encode(
+        &mut self,
+        layout: &FecLayout,
+        data_shards: &[&[u8]],
+        shard_size: usize,
+    ) -> StrResult<Vec<Vec<u8>>> {
+        let mut parity_shards =
+            vec![vec![0; shard_size]; layout.shards_count() - data_shards.len()];
+
+        for (block_index, block) in layout.blocks.iter().enumerate() {
+            if block.parity_shards_count == 0 {
+                continue;
+            }
+
+            let block_data_shards = (block_index..data_shards.len())
+                .step_by(layout.blocks.len())
+                .map(|index| pad(data_shards[index], shard_size))
+                .collect::<Vec<_>>();
+
+            let first_parity = block.first_parity_index - layout.data_shards_count;
+            let block_parity_shards =
+                &mut parity_shards[first_parity..first_parity + block.parity_shards_count];
+
+            self.codec(block)?
+                .encode_sep(&block_data_shards, block_parity_shards)
+                .map_err(err!())?;
+        }
+
+        Ok(parity_shards)
+    }
+
+    // Rebuild the missing data shards. Every block must have at least as many shards as its data
+    // shards count. Rebuilt shards have size shard_size, so the last data shard must be truncated
+    // by the caller.
+    pub fn reconstruct(
+        &mut self,
+        layout: &FecLayout,
+        shards: &mut [Option<BytesMut>],
+        shard_size: usize,
+    ) -> StrResult {
+        for (block_index, block) in layout.blocks.iter().enumerate() {
+            let block_shard_indices = layout.block_shard_indices(block_index).collect::<Vec<_>>();
+
+            if block_shard_indices[..block.data_shards_count]
+                .iter()
+                .all(|&index| shards[index].is_some())
+            {
+                continue;
+            }
+
+            let mut block_shards = block_shard_indices
+                .iter()
+                .map(|&index| {
+                    shards[index]
+                        .as_ref()
+                        .map(|shard| pad(shard, shard_size).into_owned())
+                })
+                .collect::<Vec<_>>();
+
+            self.codec(block)?
+                .reconstruct_data(&mut block_shards)
+                .map_err(err!())?;
+
+            for (&index, shard) in block_shard_indices.iter().zip(block_shards) {
+                if index < layout.data_shards_count && shards[index].is_none() {
+                    shards[index] = shard.map(|shard| BytesMut::from(&shard[..]));
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn pad(shard: &[u8], shard_size: usize) -> Cow<'_, [u8]> {
+    if shard.len() < shard_size {
+        let mut padded = shard.to_vec();
+        padded.resize(shard_size, 0);
+
+        Cow::Owned(padded)
+    } else {
+        Cow::Borrowed(shard)
+    }
+}
+
+// Chooses the redundancy percentage from the shard loss measured by the receiver
+pub struct FecController {
+    max_percentage: f32,
+    percentage: f32,
+}
+
+impl FecController {
+    pub fn new(max_percentage: u16) -> Self {
+        let max_percentage = u16::min(max_percentage, MAX_FEC_PERCENTAGE) as f32;
+
+        Self {
+            max_percentage,
+            percentage: f32::min(INITIAL_FEC_PERCENTAGE, max_percentage),
+        }
+    }
+
+    pub fn report_loss(&mut self, shards_count: usize, lost_shards_count: usize) {
+        if shards_count == 0 {
+            return;
+        }
+
+        let loss_percentage = lost_shards_count as f32 / shards_count as f32 * 100.0;
+        let target = loss_percentage * LOSS_MARGIN + MIN_FEC_PERCENTAGE;
+
+        self.percentage = if target > self.percentage {
+            target
+        } else {
+            self.percentage * (1.0 - DECAY_FACTOR) + target * DECAY_FACTOR
+        };
+        self.percentage = self.percentage.clamp(0.0, self.max_percentage);
+    }
+
+    pub fn percentage(&self) -> u16 {
+        self.percentage.ceil() as u16
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream_socket::shard::{self, ShardInfo, ShardReassembler, SHARD_PREFIX_SIZE};
+    use bytes::{BufMut, Bytes};
+    use std::time::Instant;
+
+    const MAX_SHARD_SIZE: usize = 200;
+
+    fn make_shards(payload: &[u8], fec_percentage: u16) -> Vec<Bytes> {
+        let mut packet = BytesMut::new();
+        packet.put_u16(0);
+        packet.put_u32(0);
+        packet.put_slice(&[0; SHARD_PREFIX_SIZE - 6]);
+        packet.put_slice(payload);
+
+        shard::split(
+            packet,
+            MAX_SHARD_SIZE,
+            fec_percentage,
+            &mut FecCodec::default(),
+        )
+        .unwrap()
+    }
+
+    fn reassemble(shards: impl Iterator<Item = Bytes>) -> Option<BytesMut> {
+        let mut reassembler = ShardReassembler::default();
+
+        for shard in shards {
+            let mut shard = BytesMut::from(&shard[2..]);
+            let info = ShardInfo::read(&mut shard).unwrap();
+
+            reassembler.push(info, shard, Instant::now());
+            if let Some(packet) = reassembler.pop(Instant::now()) {
+                return Some(packet.payload);
+            }
+        }
+
+        None
+    }
+
+    fn payload(size: usize) -> Vec<u8> {
+        (0..size).map(|i| (i * 7 + i / 256) as u8).collect()
+    }
+
+    #[test]
+    fn recover_single_loss() {
+        let payload = payload(5_000);
+        let shards = make_shards(&payload, 10);
+
+        for lost_index in 0..shards.len() {
+            let received = shards
+                .iter()
+                .enumerate()
+                .filter(|(index, _)| *index != lost_index)
+                .map(|(_, shard)| shard.clone());
+
+            assert_eq!(&reassemble(received).unwrap()[..], &payload[..]);
+        }
+    }
+
+    #[test]
+    fn recover_burst_loss() {
+        // More than MAX_BLOCK_DATA_SHARDS data shards, so that the burst is spread across blocks
+        let payload = payload(60_000);
+        let shards = make_shards(&payload, 5);
+        let layout = FecLayout::new((60_000 + 179) / 180, 5);
+        assert!(layout.blocks_count() > 1);
+
+        let parity_shards_count = shards.len() - layout.data_shards_count();
+        let received = shards
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| !(40..40 + parity_shards_count).contains(index))
+            .map(|(_, shard)| shard.clone());
+
+        assert_eq!(&reassemble(received).unwrap()[..], &payload[..]);
+    }
+
+    #[test]
+    fn recover_reordered_with_periodic_loss() {
+        let payload = payload(10_000);
+        let shards = make_shards(&payload, 20);
+
+        let received = shards
+            .iter()
+            .enumerate()
+            .rev()
+            .filter(|(index, _)| index % 8 != 3)
+            .map(|(_, shard)| shard.clone());
+
+        assert_eq!(&reassemble(received).unwrap()[..], &payload[..]);
+    }
+
+    #[test]
+    fn unrecoverable_loss() {
+        let payload = payload(5_000);
+        let shards = make_shards(&payload, 10);
+
+        let received = shards
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| !(0..4).contains(index))
+            .map(|(_, shard)| shard.clone());
+
+        assert!(reassemble(received).is_none());
+    }
+
+    #[test]
+    fn controller_follows_loss() {
+        let mut controller = FecController::new(50);
+        let initial_percentage = controller.percentage();
+
+        controller.report_loss(100, 10);
+        assert!(controller.percentage() >= 20);
+
+        for _ in 0..200 {
+            controller.report_loss(100, 0);
+        }
+        assert!(controller.percentage() <= initial_percentage);
+
+        controller.report_loss(10, 10);
+        assert_eq!(controller.percentage(), 50);
+    }
+}
@@ -0,0 +1,324 @@
+// Emulation of a bad network, independent from the socket protocol. The shards are scheduled by a
+// task that drops, delays and reorders them before sending them with the wrapped socket.
+
+use super::StreamSendSocket;
+use crate::queue::{bounded_queue, QueueReceiver, QueueSender};
+use alvr_common::prelude::*;
+use alvr_session::{DropPolicy, NetworkImpairmentDesc};
+use bytes::Bytes;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use settings_schema::Switch;
+use std::{
+    cmp::Ordering,
+    collections::BinaryHeap,
+    time::{Duration, Instant},
+};
+use tokio::time;
+
+// Shards are dropped when the bandwidth limited queue is longer than this
+const MAX_QUEUE_DELAY: Duration = Duration::from_millis(200);
+// Shards sent or waiting for their delivery time. The following ones are lost, like in the queue of
+// a router
+const MAX_QUEUED_SHARDS: usize = 4096;
+// Extra delay of the reordered shards
+const REORDER_DELAY: Duration = Duration::from_millis(5);
+
+#[derive(Clone)]
+pub struct ImpairedStreamSendSocket {
+    pub inner: Box<StreamSendSocket>,
+    shard_sender: QueueSender<(u16, Bytes, Instant)>,
+}
+
+impl ImpairedStreamSendSocket {
+    // Spawns the task that delivers the shards. It stops when all clones of the socket are dropped
+    pub fn new(inner: StreamSendSocket, config: NetworkImpairmentDesc) -> Self {
+        // With DropPolicy::Block, try_send() discards the new shard
+        let (shard_sender, shard_receiver) = bounded_queue(MAX_QUEUED_SHARDS, DropPolicy::Block);
+
+        let rng = match config.random_seed {
+            Switch::Enabled(seed) => StdRng::seed_from_u64(seed),
+            Switch::Disabled => StdRng::from_entropy(),
+        };
+        tokio::spawn(delivery_loop(
+            inner.clone(),
+            Impairer::new(config, rng),
+            shard_receiver,
+        ));
+
+        Self {
+            inner: Box::new(inner),
+            shard_sender,
+        }
+    }
+
+    pub fn send(&self, stream_id: u16, shard: Bytes) -> StrResult {
+        self.shard_sender
+            .try_send((stream_id, shard, Instant::now()))
+    }
+}
+
+struct ScheduledShard {
+    deliver_at: Instant,
+    // Shards with the same delivery time are sent in order
+    sequence: u64,
+    stream_id: u16,
+    shard: Bytes,
+}
+
+impl PartialEq for ScheduledShard {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for ScheduledShard {}
+
+impl PartialOrd for ScheduledShard {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// Reversed, so that BinaryHeap pops the earliest shard first
+impl Ord for ScheduledShard {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (other.deliver_at, other.sequence).cmp(&(self.deliver_at, self.sequence))
+    }
+}
+
+struct Impairer {
+    config: NetworkImpairmentDesc,
+    rng: StdRng,
+    in_burst: bool,
+    // When the bandwidth limited link finishes sending the queued shards
+    link_free_at: Option<Instant>,
+}
+
+impl Impairer {
+    fn new(config: NetworkImpairmentDesc, rng: StdRng) -> Self {
+        Self {
+            config,
+            rng,
+            in_burst: false,
+            link_free_at: None,
+        }
+    }
+
+    fn chance(&mut self, probability: f32) -> bool {
+        self.rng.gen_bool(probability.clamp(0., 1.) as f64)
+    }
+
+    // Returns the delivery time of the shard, None if it is lost. Bursts follow the Gilbert model:
+    // each shard of a burst ends it with probability 1 / average_burst_length
+    fn schedule(&mut self, size_bytes: usize, sent: Instant) -> Option<Instant> {
+        if self.in_burst {
+            self.in_burst = !self.chance(1. / self.config.average_burst_length.max(1) as f32);
+        } else {
+            self.in_burst = self.chance(self.config.burst_loss_probability);
+        }
+        if self.in_burst || self.chance(self.config.loss_probability) {
+            return None;
+        }
+
+        let mut deliver_at = if self.config.bandwidth_limit_mbps > 0 {
+            let start = Instant::max(sent, self.link_free_at.unwrap_or(sent));
+            if start - sent > MAX_QUEUE_DELAY {
+                return None;
+            }
+
+            let transmission_time = Duration::from_secs_f64(
+                size_bytes as f64 * 8. / (self.config.bandwidth_limit_mbps as f64 * 1e6),
+            );
+            self.link_free_at = Some(start + transmission_time);
+
+            start + transmission_time
+        } else {
+            sent
+        };
+
+        deliver_at += Duration::from_millis(self.config.latency_ms);
+        if self.config.jitter_ms > 0 {
+            deliver_at +=
+                Duration::from_micros(self.rng.gen_range(0..self.config.jitter_ms * 1000));
+        }
+        if self.chance(self.config.reorder_probability) {
+            deliver_at += REORDER_DELAY;
+        }
+
+        Some(deliver_at)
+    }
+}
+
+async fn delivery_loop(
+    socket: StreamSendSocket,
+    mut impairer: Impairer,
+    mut shard_receiver: QueueReceiver<(u16, Bytes, Instant)>,
+) {
+    let mut queue = BinaryHeap::new();
+    let mut sequence = 0;
+
+    loop {
+        let next_delivery = queue.peek().map(|shard: &ScheduledShard| shard.deliver_at);
+
+        tokio::select! {
+            maybe_shard = shard_receiver.recv() => {
+                let (stream_id, shard, sent) = match maybe_shard {
+                    Some(shard) => shard,
+                    None => break,
+                };
+
+                if queue.len() >= MAX_QUEUED_SHARDS {
+                    continue;
+                }
+                if let Some(deliver_at) = impairer.schedule(shard.len(), sent) {
+                    queue.push(ScheduledShard {
+                        deliver_at,
+                        sequence,
+                        stream_id,
+                        shard,
+                    });
+                    sequence += 1;
+                }
+            }
+            _ = time::sleep_until(time::Instant::from_std(
+                next_delivery.unwrap_or_else(Instant::now)
+            )), if next_delivery.is_some() => {
+                let now = Instant::now();
+                while matches!(queue.peek(), Some(shard) if shard.deliver_at <= now) {
+                    let shard = queue.pop().unwrap();
+                    socket.send(shard.stream_id, shard.shard).await.ok();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stream_socket::udp;
+    use std::net::{IpAddr, Ipv4Addr};
+
+    const LOCALHOST: IpAddr = IpAddr::V4(Ipv4Addr::LOCALHOST);
+
+    fn config() -> NetworkImpairmentDesc {
+        NetworkImpairmentDesc {
+            loss_probability: 0.,
+            burst_loss_probability: 0.,
+            average_burst_length: 1,
+            latency_ms: 0,
+            jitter_ms: 0,
+            reorder_probability: 0.,
+            bandwidth_limit_mbps: 0,
+            random_seed: Switch::Disabled,
+        }
+    }
+
+    // Delivery delays of `count` shards of `size` bytes, sent at the same time. None if lost
+    fn schedule(config: NetworkImpairmentDesc, count: usize, size: usize) -> Vec<Option<Duration>> {
+        let mut impairer = Impairer::new(config, StdRng::seed_from_u64(0));
+
+        let now = Instant::now();
+        (0..count)
+            .map(|_| {
+                impairer
+                    .schedule(size, now)
+                    .map(|deliver_at| deliver_at - now)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn loss() {
+        let config = NetworkImpairmentDesc {
+            loss_probability: 0.3,
+            ..config()
+        };
+        let received = schedule(config, 10_000, 100).iter().flatten().count();
+
+        assert!((6_500..7_500).contains(&received), "{received}");
+    }
+
+    #[test]
+    fn burst_length() {
+        let config = NetworkImpairmentDesc {
+            burst_loss_probability: 0.01,
+            average_burst_length: 5,
+            ..config()
+        };
+        let lost = schedule(config, 100_000, 100)
+            .iter()
+            .map(Option::is_none)
+            .collect::<Vec<_>>();
+        let bursts = lost.windows(2).filter(|pair| !pair[0] && pair[1]).count();
+        let average_burst_length = lost.iter().filter(|lost| **lost).count() as f32 / bursts as f32;
+
+        assert!(
+            (4. ..6.).contains(&average_burst_length),
+            "{average_burst_length}"
+        );
+    }
+
+    #[test]
+    fn latency() {
+        let config = NetworkImpairmentDesc {
+            latency_ms: 50,
+            jitter_ms: 10,
+            ..config()
+        };
+
+        for delay in schedule(config, 100, 100) {
+            let delay = delay.unwrap();
+            assert!(delay >= Duration::from_millis(50) && delay < Duration::from_millis(60));
+        }
+    }
+
+    #[test]
+    fn bandwidth_limit() {
+        // 1 KB shards at 1 MB/s
+        let config = NetworkImpairmentDesc {
+            bandwidth_limit_mbps: 8,
+            ..config()
+        };
+        let delays = schedule(config, 300, 1000);
+
+        assert_eq!(delays[0], Some(Duration::from_millis(1)));
+        assert_eq!(delays[99], Some(Duration::from_millis(100)));
+        // The shards that would wait more than MAX_QUEUE_DELAY are lost
+        assert!(delays[201..].iter().all(Option::is_none));
+    }
+
+    #[tokio::test]
+    async fn delivery_over_loopback() {
+        let receiver = udp::bind(LOCALHOST, 0).await.unwrap();
+        let receiver_port = receiver.local_addr().unwrap().port();
+
+        let (send_socket, _) = udp::connect(
+            udp::bind(LOCALHOST, 0).await.unwrap(),
+            LOCALHOST,
+            receiver_port,
+        )
+        .await
+        .unwrap();
+        let socket = ImpairedStreamSendSocket::new(
+            StreamSendSocket::Udp(send_socket),
+            NetworkImpairmentDesc {
+                latency_ms: 10,
+                reorder_probability: 0.5,
+                ..config()
+            },
+        );
+
+        for _ in 0..10 {
+            socket.send(0, Bytes::from_static(&[0; 100])).unwrap();
+        }
+
+        let mut buffer = [0; 1000];
+        for _ in 0..10 {
+            time::timeout(Duration::from_secs(5), receiver.recv_from(&mut buffer))
+                .await
+                .unwrap()
+                .unwrap();
+        }
+    }
+}
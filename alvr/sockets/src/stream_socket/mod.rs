@@ -1,42 +1,114 @@
 // Note: for StreamSocket, the client uses a server socket, the server uses a client socket.
 // This is because of certificate management. The server needs to trust a client and its certificate
+// The shards are sealed with the stream keys derived from the control socket TLS session, whatever
+// the transport.
 //
 // StreamSender and StreamReceiver endpoints allow for convenient conversion of the header to/from
 // bytes while still handling the additional byte buffer with zero copies and extra allocations.
 
+mod fec;
+mod impairment;
+mod quic;
+mod scheduler;
+mod shard;
+mod stats;
 mod tcp;
 mod throttled_udp;
 mod udp;
 
+use crate::{
+    codec::{self, BoundedPacket},
+    crypto::{PacketOpener, PacketSealer, StreamKeys, KEY_SIZE, SEAL_OVERHEAD},
+    queue::{bounded_queue, QueueReceiver, QueueSender},
+    AUDIO, HAPTICS, STATISTICS, TRACKING, VIDEO,
+};
 use alvr_common::prelude::*;
-use alvr_session::SocketProtocol;
-use bytes::{Buf, BufMut, BytesMut};
+use alvr_events::StreamStatistics;
+use alvr_session::{DropPolicy, NetworkImpairmentDesc, SocketProtocol, StreamDropPoliciesDesc};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use fec::FecCodec;
 use futures::SinkExt;
+use impairment::ImpairedStreamSendSocket;
+use quic::{QuicStreamReceiveSocket, QuicStreamSendSocket};
+use scheduler::ScheduledStreamSendSocket;
 use serde::{de::DeserializeOwned, Serialize};
+use shard::{ShardInfo, ShardReassembler, SHARD_PREFIX_SIZE};
+use stats::{lost_packets_count, ArrivalTracker, SharedStreamStatistics, StreamStatisticsRegistry};
 use std::{
-    collections::HashMap,
+    collections::{HashMap, VecDeque},
     marker::PhantomData,
     net::IpAddr,
     ops::{Deref, DerefMut},
     sync::Arc,
+    time::{Duration, Instant},
 };
 use tcp::{TcpStreamReceiveSocket, TcpStreamSendSocket};
 use throttled_udp::{ThrottledUdpStreamReceiveSocket, ThrottledUdpStreamSendSocket};
 use tokio::net;
 use tokio::sync::{mpsc, Mutex};
+use tokio::time;
 use udp::{UdpStreamReceiveSocket, UdpStreamSendSocket};
 
+pub use fec::{FecController, MAX_FEC_PERCENTAGE};
+pub use scheduler::{StreamPriority, StreamQueueStats};
+pub use shard::ShardLoss;
+
+// Maximum size of the datagrams sent by the UDP based sockets. Together with the IP and UDP headers
+// this stays below the usual Ethernet MTU of 1500 bytes
+const MAX_UDP_SHARD_SIZE: usize = 1400;
+
+// Shards (or whole packets over TCP) waiting to be reassembled by each receiver
+const VIDEO_RECEIVE_QUEUE_CAPACITY: usize = 2048;
+const RECEIVE_QUEUE_CAPACITY: usize = 256;
+
+type PacketEnqueuers = Arc<Mutex<HashMap<u16, QueueSender<BytesMut>>>>;
+
 #[derive(Clone)]
 enum StreamSendSocket {
     Udp(UdpStreamSendSocket),
     ThrottledUdp(ThrottledUdpStreamSendSocket),
     Tcp(TcpStreamSendSocket),
+    Quic(QuicStreamSendSocket),
+    Impaired(ImpairedStreamSendSocket),
+}
+
+impl StreamSendSocket {
+    // None for reliable transports, which need neither sharding nor FEC
+    fn max_shard_size(&self, stream_id: u16) -> Option<usize> {
+        match self {
+            StreamSendSocket::Udp(_) | StreamSendSocket::ThrottledUdp(_) => {
+                Some(MAX_UDP_SHARD_SIZE)
+            }
+            StreamSendSocket::Tcp(_) => None,
+            StreamSendSocket::Quic(socket) => socket.max_shard_size(stream_id),
+            StreamSendSocket::Impaired(socket) => socket.inner.max_shard_size(stream_id),
+        }
+    }
+
+    async fn send(&self, stream_id: u16, shard: Bytes) -> StrResult {
+        match self {
+            StreamSendSocket::Udp(socket) => socket
+                .inner
+                .lock()
+                .await
+                .send((shard, socket.peer_addr))
+                .await
+                .map_err(err!()),
+            StreamSendSocket::Tcp(socket) => socket.lock().await.send(shard).await.map_err(err!()),
+            StreamSendSocket::ThrottledUdp(socket) => {
+                socket.send(stream_id, shard).await.map_err(err!())
+            }
+            StreamSendSocket::Quic(socket) => socket.send(stream_id, shard).await,
+            StreamSendSocket::Impaired(socket) => socket.send(stream_id, shard),
+        }
+    }
 }
 
 enum StreamReceiveSocket {
     Udp(UdpStreamReceiveSocket),
     ThrottledUdp(ThrottledUdpStreamReceiveSocket),
     Tcp(TcpStreamReceiveSocket),
+    Quic(QuicStreamReceiveSocket),
 }
 
 pub struct SendBufferLock<'a> {
@@ -85,59 +157,128 @@ impl<T> SenderBuffer<T> {
 
 pub struct StreamSender<T> {
     stream_id: u16,
-    socket: StreamSendSocket,
-    // if the packet index overflows the worst that happens is a false positive packet loss
+    socket: ScheduledStreamSendSocket,
+    // Wraps around after u32::MAX, the receiver compares indices as serial numbers
     next_packet_index: u32,
+    fec_percentage: u16,
+    fec: FecCodec,
+    // Shards of the most recent packets, kept for retransmission
+    retransmit_cache: VecDeque<(u32, Vec<Bytes>)>,
+    retransmit_cache_size: usize,
+    sealer: PacketSealer,
+    stats: SharedStreamStatistics,
     _phantom: PhantomData<T>,
 }
 
 impl<T> StreamSender<T> {
+    // Keep the shards of the last `cache_size` packets to be able to resend them. 0 disables
+    // retransmission
+    pub fn set_retransmit_cache_size(&mut self, cache_size: usize) {
+        self.retransmit_cache_size = cache_size;
+        self.retransmit_cache.truncate(cache_size);
+    }
+
+    // Index assigned to the next packet sent. The receiver reports it in ReceivedPacket
+    pub fn next_packet_index(&self) -> u32 {
+        self.next_packet_index
+    }
+
+    // Redundancy added to packets sent over unreliable transports. 0 disables FEC
+    pub fn set_fec_percentage(&mut self, percentage: u16) {
+        self.fec_percentage = u16::min(percentage, MAX_FEC_PERCENTAGE);
+    }
+
     // The buffer is moved into the method. There is no way of reusing the same buffer twice without
     // extra copies/allocations
     pub async fn send_buffer(&mut self, mut buffer: SenderBuffer<T>) -> StrResult {
-        buffer.inner[2..6].copy_from_slice(&self.next_packet_index.to_be_bytes());
-        self.next_packet_index += 1;
+        let packet_index = self.next_packet_index;
+        buffer.inner[2..6].copy_from_slice(&packet_index.to_be_bytes());
+        self.next_packet_index = packet_index.wrapping_add(1);
 
-        match &self.socket {
-            StreamSendSocket::Udp(socket) => socket
-                .inner
-                .lock()
-                .await
-                .send((buffer.inner.freeze(), socket.peer_addr))
-                .await
-                .map_err(err!()),
-            StreamSendSocket::Tcp(socket) => socket
-                .lock()
-                .await
-                .send(buffer.inner.freeze())
-                .await
-                .map_err(err!()),
-            StreamSendSocket::ThrottledUdp(socket) => {
-                socket.send(buffer.inner.freeze()).await.map_err(err!())
+        let (max_shard_size, fec_percentage) =
+            match self.socket.inner.max_shard_size(self.stream_id) {
+                Some(size) => (size - SEAL_OVERHEAD, self.fec_percentage),
+                None => (usize::MAX, 0),
+            };
+
+        let shards = shard::split(buffer.inner, max_shard_size, fec_percentage, &mut self.fec)?;
+
+        for shard in &shards {
+            self.send_shard(shard).await?;
+        }
+        self.stats.lock().packets_sent += 1;
+
+        if self.retransmit_cache_size > 0 {
+            if self.retransmit_cache.len() == self.retransmit_cache_size {
+                self.retransmit_cache.pop_back();
+            }
+            self.retransmit_cache.push_front((packet_index, shards));
+        }
+
+        Ok(())
+    }
+
+    async fn send_shard(&mut self, shard: &Bytes) -> StrResult {
+        let shard = self.sealer.seal(shard)?;
+        self.stats.lock().bytes_sent += shard.len() as u64;
+
+        self.socket.send(self.stream_id, shard).await
+    }
+
+    // Resend the requested shards that are still in the retransmit cache
+    pub async fn resend(&mut self, lost_shards: &[ShardLoss]) -> StrResult {
+        let mut shards_to_resend = vec![];
+        for loss in lost_shards {
+            for (packet_index, shards) in &self.retransmit_cache {
+                match loss {
+                    ShardLoss::Packets { first_index, count }
+                        if packet_index.wrapping_sub(*first_index) < *count =>
+                    {
+                        shards_to_resend.extend(shards.iter().cloned());
+                    }
+                    ShardLoss::Shards {
+                        packet_index: index,
+                        shard_indices,
+                    } if index == packet_index => {
+                        shards_to_resend.extend(
+                            shard_indices
+                                .iter()
+                                .filter_map(|&index| shards.get(index as usize))
+                                .cloned(),
+                        );
+                    }
+                    _ => (),
+                }
             }
         }
+
+        for shard in &shards_to_resend {
+            self.send_shard(shard).await?;
+        }
+
+        Ok(())
     }
 }
 
-impl<T: Serialize> StreamSender<T> {
+impl<T: Serialize + BoundedPacket> StreamSender<T> {
     pub fn new_buffer(
         &self,
         header: &T,
         preferred_max_buffer_size: usize,
     ) -> StrResult<SenderBuffer<T>> {
-        let header_size = bincode::serialized_size(header).map_err(err!())?;
-        // the first two bytes are for the stream ID
-        let offset = 2 + 4 + header_size as usize;
+        let header_size = codec::encoded_size(header)?;
+        // the prefix contains the stream ID, the packet index and the shard info
+        let offset = SHARD_PREFIX_SIZE + header_size;
 
         let mut buffer = BytesMut::with_capacity(offset + preferred_max_buffer_size);
 
         buffer.put_u16(self.stream_id);
 
-        // make space for the packet index
-        buffer.put_u32(0);
+        // make space for the packet index and the shard info
+        buffer.put_bytes(0, SHARD_PREFIX_SIZE - 2);
 
         let mut buffer_writer = buffer.writer();
-        bincode::serialize_into(&mut buffer_writer, header).map_err(err!())?;
+        codec::encode_into(&mut buffer_writer, header)?;
         let buffer = buffer_writer.into_inner();
 
         Ok(SenderBuffer {
@@ -153,41 +294,121 @@ impl<T: Serialize> StreamSender<T> {
 }
 
 enum StreamReceiverType {
-    Queue(mpsc::UnboundedReceiver<BytesMut>),
-    // QuicReliable(...)
+    Queue(QueueReceiver<BytesMut>),
 }
 
 pub struct ReceivedPacket<T> {
     pub header: T,
     pub buffer: BytesMut,
-    pub had_packet_loss: bool,
+    pub packet_index: u32,
+    // When the last shard needed to reassemble the packet was received
+    pub arrival: Instant,
+    // Shards lost and not recovered since the previous packet was received
+    pub lost_shards: Vec<ShardLoss>,
+    // Data and parity shards of this packet
+    pub shards_count: usize,
+    // Shards of this packet rebuilt with FEC
+    pub recovered_shards: usize,
+}
+
+impl<T> ReceivedPacket<T> {
+    pub fn had_packet_loss(&self) -> bool {
+        !self.lost_shards.is_empty()
+    }
 }
 
 pub struct StreamReceiver<T> {
     receiver: StreamReceiverType,
-    next_packet_index: u32,
+    reassembler: ShardReassembler,
+    opener: PacketOpener,
+    retransmission_requests_sender: Option<mpsc::UnboundedSender<Vec<ShardLoss>>>,
+    stats: SharedStreamStatistics,
+    arrival_tracker: ArrivalTracker,
     _phantom: PhantomData<T>,
 }
 
-impl<T: DeserializeOwned> StreamReceiver<T> {
+impl<T> StreamReceiver<T> {
+    // When a gap is detected, the missing shards are requested through `requests_sender` and
+    // the packets are delivered in order, waiting up to `timeout` for the retransmitted shards. The
+    // requests should be forwarded to StreamSender::resend() of the peer.
+    pub fn enable_retransmission(
+        &mut self,
+        timeout: Duration,
+        requests_sender: mpsc::UnboundedSender<Vec<ShardLoss>>,
+    ) {
+        self.reassembler.set_retransmission_timeout(timeout);
+        self.retransmission_requests_sender = Some(requests_sender);
+    }
+
+    // Shards discarded by the receive queue since the last call. They are also reported as lost
+    pub fn take_dropped_count(&self) -> usize {
+        let StreamReceiverType::Queue(receiver) = &self.receiver;
+        receiver.take_dropped_count()
+    }
+}
+
+impl<T: DeserializeOwned + BoundedPacket> StreamReceiver<T> {
     pub async fn recv(&mut self) -> StrResult<ReceivedPacket<T>> {
-        let mut bytes = match &mut self.receiver {
-            StreamReceiverType::Queue(receiver) => receiver.recv().await.ok_or_else(enone!())?,
+        let packet = loop {
+            if let Some(packet) = self.reassembler.pop(Instant::now()) {
+                break packet;
+            }
+
+            let StreamReceiverType::Queue(receiver) = &mut self.receiver;
+            let maybe_shard = if let Some(deadline) = self.reassembler.next_deadline() {
+                match time::timeout_at(time::Instant::from_std(deadline), receiver.recv()).await {
+                    Ok(maybe_shard) => maybe_shard,
+                    // Let pop() give up the expired packets
+                    Err(_) => continue,
+                }
+            } else {
+                receiver.recv().await
+            };
+            let shard = maybe_shard.ok_or_else(enone!())?;
+
+            // forged and truncated shards are discarded
+            let shard_size = shard.len();
+            if let Some(mut shard) = self.opener.open(shard) {
+                if let Some(info) = ShardInfo::read(&mut shard) {
+                    self.arrival_tracker.report_shard(
+                        &mut self.stats.lock(),
+                        info.packet_index,
+                        info.shard_index,
+                        shard_size,
+                    );
+                    self.reassembler.push(info, shard, Instant::now());
+                }
+            }
+
+            let requests = self.reassembler.take_retransmission_requests();
+            if !requests.is_empty() {
+                if let Some(sender) = &self.retransmission_requests_sender {
+                    sender.send(requests).ok();
+                }
+            }
         };
+        let lost_shards = self.reassembler.take_lost_shards();
 
-        let packet_index = bytes.get_u32();
-        let had_packet_loss = packet_index != self.next_packet_index;
-        self.next_packet_index = packet_index + 1;
+        {
+            let mut stats = self.stats.lock();
+            stats.packets_lost += lost_packets_count(&lost_shards);
+            self.arrival_tracker
+                .report_packet(&mut stats, packet.arrival);
+        }
 
-        let mut bytes_reader = bytes.reader();
-        let header = bincode::deserialize_from(&mut bytes_reader).map_err(err!())?;
-        let buffer = bytes_reader.into_inner();
+        let (header, header_size) = codec::decode_prefix(&packet.payload)?;
+        let mut buffer = packet.payload;
+        buffer.advance(header_size);
 
         // At this point, "buffer" does not include the header anymore
         Ok(ReceivedPacket {
             header,
             buffer,
-            had_packet_loss,
+            packet_index: packet.packet_index,
+            arrival: packet.arrival,
+            lost_shards,
+            shards_count: packet.shards_count,
+            recovered_shards: packet.recovered_shards,
         })
     }
 }
@@ -196,26 +417,40 @@ pub enum StreamSocketBuilder {
     Tcp(net::TcpListener),
     Udp(net::UdpSocket),
     ThrottledUdp(net::UdpSocket),
+    Quic(quinn::Incoming),
 }
 
 impl StreamSocketBuilder {
+    // local_ip must have the same family as the server address
     pub async fn listen_for_server(
+        local_ip: IpAddr,
         port: u16,
         stream_socket_config: SocketProtocol,
     ) -> StrResult<Self> {
         Ok(match stream_socket_config {
-            SocketProtocol::Udp => StreamSocketBuilder::Udp(udp::bind(port).await?),
-            SocketProtocol::Tcp => StreamSocketBuilder::Tcp(tcp::listen_for_server(port).await?),
-            SocketProtocol::ThrottledUdp { .. } => {
-                StreamSocketBuilder::ThrottledUdp(throttled_udp::listen_for_server(port).await?)
+            SocketProtocol::Udp => StreamSocketBuilder::Udp(udp::bind(local_ip, port).await?),
+            SocketProtocol::Tcp => {
+                StreamSocketBuilder::Tcp(tcp::listen_for_server(local_ip, port).await?)
+            }
+            SocketProtocol::ThrottledUdp { .. } => StreamSocketBuilder::ThrottledUdp(
+                throttled_udp::listen_for_server(local_ip, port).await?,
+            ),
+            SocketProtocol::Quic => {
+                StreamSocketBuilder::Quic(quic::listen_for_server(local_ip, port).await?)
             }
         })
     }
 
-    pub async fn accept_from_server(self, server_ip: IpAddr, port: u16) -> StrResult<StreamSocket> {
+    pub async fn accept_from_server(
+        self,
+        server_ip: IpAddr,
+        server_port: u16,
+        keys: StreamKeys,
+    ) -> StrResult<StreamSocket> {
         let (send_socket, receive_socket) = match self {
             StreamSocketBuilder::Udp(socket) => {
-                let (send_socket, receive_socket) = udp::connect(socket, server_ip, port).await?;
+                let (send_socket, receive_socket) =
+                    udp::connect(socket, server_ip, server_port).await?;
                 (
                     StreamSendSocket::Udp(send_socket),
                     StreamReceiveSocket::Udp(receive_socket),
@@ -231,38 +466,58 @@ impl StreamSocketBuilder {
             }
             StreamSocketBuilder::ThrottledUdp(socket) => {
                 let (send_socket, receive_socket) =
-                    throttled_udp::accept_from_server(socket, server_ip, port).await?;
+                    throttled_udp::accept_from_server(socket, server_ip, server_port).await?;
                 (
                     StreamSendSocket::ThrottledUdp(send_socket),
                     StreamReceiveSocket::ThrottledUdp(receive_socket),
                 )
             }
+            StreamSocketBuilder::Quic(incoming) => {
+                let (send_socket, receive_socket) =
+                    quic::accept_from_server(incoming, server_ip).await?;
+                (
+                    StreamSendSocket::Quic(send_socket),
+                    StreamReceiveSocket::Quic(receive_socket),
+                )
+            }
         };
 
         Ok(StreamSocket {
+            scheduler: ScheduledStreamSendSocket::new(send_socket.clone()),
             send_socket,
             receive_socket: Arc::new(Mutex::new(Some(receive_socket))),
             packet_queues: Arc::new(Mutex::new(HashMap::new())),
+            drop_policies: HashMap::new(),
+            send_key: keys.client_to_server,
+            receive_key: keys.server_to_client,
+            stats: StreamStatisticsRegistry::default(),
         })
     }
 
+    // server_port can differ from client_port to stream to many clients at once. local_ip must have
+    // the same family as client_ip
     pub async fn connect_to_client(
+        local_ip: IpAddr,
         client_ip: IpAddr,
-        port: u16,
+        client_port: u16,
+        server_port: u16,
         protocol: SocketProtocol,
         video_byterate: u32,
+        keys: StreamKeys,
     ) -> StrResult<StreamSocket> {
         let (send_socket, receive_socket) = match protocol {
             SocketProtocol::Udp => {
-                let sock = udp::bind(port).await?;
-                let (send_socket, receive_socket) = udp::connect(sock, client_ip, port).await?;
+                let sock = udp::bind(local_ip, server_port).await?;
+                let (send_socket, receive_socket) =
+                    udp::connect(sock, client_ip, client_port).await?;
                 (
                     StreamSendSocket::Udp(send_socket),
                     StreamReceiveSocket::Udp(receive_socket),
                 )
             }
             SocketProtocol::Tcp => {
-                let (send_socket, receive_socket) = tcp::connect_to_client(client_ip, port).await?;
+                let (send_socket, receive_socket) =
+                    tcp::connect_to_client(local_ip, client_ip, client_port).await?;
                 (
                     StreamSendSocket::Tcp(send_socket),
                     StreamReceiveSocket::Tcp(receive_socket),
@@ -270,8 +525,10 @@ impl StreamSocketBuilder {
             }
             SocketProtocol::ThrottledUdp { bitrate_multiplier } => {
                 let (send_socket, receive_socket) = throttled_udp::connect_to_client(
+                    local_ip,
                     client_ip,
-                    port,
+                    client_port,
+                    server_port,
                     video_byterate,
                     bitrate_multiplier,
                 )
@@ -281,39 +538,116 @@ impl StreamSocketBuilder {
                     StreamReceiveSocket::ThrottledUdp(receive_socket),
                 )
             }
+            SocketProtocol::Quic => {
+                let (send_socket, receive_socket) =
+                    quic::connect_to_client(local_ip, client_ip, client_port, server_port).await?;
+                (
+                    StreamSendSocket::Quic(send_socket),
+                    StreamReceiveSocket::Quic(receive_socket),
+                )
+            }
         };
 
         Ok(StreamSocket {
+            scheduler: ScheduledStreamSendSocket::new(send_socket.clone()),
             send_socket,
             receive_socket: Arc::new(Mutex::new(Some(receive_socket))),
             packet_queues: Arc::new(Mutex::new(HashMap::new())),
+            drop_policies: HashMap::new(),
+            send_key: keys.server_to_client,
+            receive_key: keys.client_to_server,
+            stats: StreamStatisticsRegistry::default(),
         })
     }
 }
 
 pub struct StreamSocket {
     send_socket: StreamSendSocket,
+    // Shared by the streams, to send their shards by priority
+    scheduler: ScheduledStreamSendSocket,
     receive_socket: Arc<Mutex<Option<StreamReceiveSocket>>>,
-    packet_queues: Arc<Mutex<HashMap<u16, mpsc::UnboundedSender<BytesMut>>>>,
+    packet_queues: PacketEnqueuers,
+    drop_policies: HashMap<u16, DropPolicy>,
+    send_key: [u8; KEY_SIZE],
+    receive_key: [u8; KEY_SIZE],
+    stats: StreamStatisticsRegistry,
 }
 
 impl StreamSocket {
+    // Degrade the packets sent from now on. Streams requested before are not affected
+    pub fn set_impairment(&mut self, config: NetworkImpairmentDesc) {
+        self.send_socket = StreamSendSocket::Impaired(ImpairedStreamSendSocket::new(
+            self.send_socket.clone(),
+            config,
+        ));
+        self.scheduler = ScheduledStreamSendSocket::new(self.send_socket.clone());
+    }
+
+    // Applied to the streams requested from now on. With DropPolicy::Block, a full send queue stops
+    // the sender until there is room. Received packets always drop the oldest packet of a full
+    // queue, since the receive loop is shared by all streams and must never wait for one of them
+    pub fn set_drop_policies(&mut self, policies: &StreamDropPoliciesDesc) {
+        self.drop_policies = [
+            (VIDEO, policies.video),
+            (TRACKING, policies.tracking),
+            (HAPTICS, policies.haptics),
+            (AUDIO, policies.audio),
+            (STATISTICS, policies.statistics),
+        ]
+        .into_iter()
+        .collect();
+    }
+
+    // Streams use StreamPriority::of_stream() by default
+    pub fn set_stream_priority(&self, stream_id: u16, priority: StreamPriority) {
+        self.scheduler.set_priority(stream_id, priority);
+    }
+
+    // Shards waiting to be sent, by stream ID
+    pub fn queue_stats(&self) -> HashMap<u16, StreamQueueStats> {
+        self.scheduler.queue_stats()
+    }
+
+    // Counters of the streams requested or subscribed to, by stream ID
+    pub fn stream_statistics(&self) -> HashMap<u16, StreamStatistics> {
+        self.stats.snapshot()
+    }
+
     pub async fn request_stream<T>(&self, stream_id: u16) -> StrResult<StreamSender<T>> {
+        if let Some(&drop_policy) = self.drop_policies.get(&stream_id) {
+            self.scheduler.set_drop_policy(stream_id, drop_policy);
+        }
+
         Ok(StreamSender {
             stream_id,
-            socket: self.send_socket.clone(),
+            socket: self.scheduler.clone(),
             next_packet_index: 0,
+            fec_percentage: 0,
+            fec: FecCodec::default(),
+            retransmit_cache: VecDeque::new(),
+            retransmit_cache_size: 0,
+            sealer: PacketSealer::new(&self.send_key),
+            stats: self.stats.get(stream_id),
             _phantom: PhantomData,
         })
     }
 
     pub async fn subscribe_to_stream<T>(&self, stream_id: u16) -> StrResult<StreamReceiver<T>> {
-        let (enqueuer, dequeuer) = mpsc::unbounded_channel();
+        let capacity = if stream_id == VIDEO {
+            VIDEO_RECEIVE_QUEUE_CAPACITY
+        } else {
+            RECEIVE_QUEUE_CAPACITY
+        };
+        let (enqueuer, dequeuer) = bounded_queue(capacity, DropPolicy::DropOldest);
         self.packet_queues.lock().await.insert(stream_id, enqueuer);
 
         Ok(StreamReceiver {
             receiver: StreamReceiverType::Queue(dequeuer),
-            next_packet_index: 0,
+            reassembler: ShardReassembler::default(),
+            opener: PacketOpener::new(&self.receive_key, stream_id),
+            retransmission_requests_sender: None,
+            stats: self.stats.get(stream_id),
+            arrival_tracker: ArrivalTracker::default(),
             _phantom: PhantomData,
         })
     }
@@ -329,6 +663,9 @@ impl StreamSocket {
             StreamReceiveSocket::ThrottledUdp(socket) => {
                 throttled_udp::receive_loop(socket, Arc::clone(&self.packet_queues)).await
             }
+            StreamReceiveSocket::Quic(socket) => {
+                quic::receive_loop(socket, Arc::clone(&self.packet_queues)).await
+            }
         }
     }
 }
@@ -0,0 +1,198 @@
+// QUIC transport. Everything is multiplexed over a single connection: streams that need
+// reliability use one unidirectional QUIC stream each (so they don't block each other), the others
+// are sent as unreliable datagrams. If the peer doesn't support datagrams, everything falls back
+// to the reliable path.
+
+use super::{shard, PacketEnqueuers};
+use crate::{
+    crypto::{self, CERTIFICATE_NAME},
+    network, Ldc, HAPTICS, STATISTICS,
+};
+use alvr_common::prelude::*;
+use bytes::{Bytes, BytesMut};
+use futures::{stream::SelectAll, SinkExt, StreamExt};
+use quinn::{
+    ClientConfig, Connection, Datagrams, Endpoint, Incoming, IncomingUniStreams, NewConnection,
+    RecvStream, SendStream, ServerConfig,
+};
+use std::{
+    collections::HashMap,
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+use tokio::sync::Mutex;
+use tokio_util::codec::{FramedRead, FramedWrite};
+
+const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(1);
+
+const RELIABLE_STREAMS: &[u16] = &[HAPTICS, STATISTICS];
+
+#[derive(Clone)]
+pub struct QuicStreamSendSocket {
+    connection: Connection,
+    reliable_streams: Arc<Mutex<HashMap<u16, FramedWrite<SendStream, Ldc>>>>,
+}
+
+impl QuicStreamSendSocket {
+    // Unreliable streams are sharded to fit in datagrams
+    pub fn max_shard_size(&self, stream_id: u16) -> Option<usize> {
+        if RELIABLE_STREAMS.contains(&stream_id) {
+            None
+        } else {
+            self.connection.max_datagram_size()
+        }
+    }
+
+    pub async fn send(&self, stream_id: u16, data: Bytes) -> StrResult {
+        let fits_datagram = self
+            .connection
+            .max_datagram_size()
+            .map(|size| data.len() <= size)
+            .unwrap_or(false);
+
+        if !RELIABLE_STREAMS.contains(&stream_id) && fits_datagram {
+            self.connection.send_datagram(data).map_err(err!())
+        } else {
+            let mut streams_lock = self.reliable_streams.lock().await;
+            let stream = match streams_lock.get_mut(&stream_id) {
+                Some(stream) => stream,
+                None => {
+                    let stream = self.connection.open_uni().await.map_err(err!())?;
+                    streams_lock
+                        .entry(stream_id)
+                        .or_insert_with(|| FramedWrite::new(stream, Ldc::new()))
+                }
+            };
+
+            stream.send(data).await.map_err(err!())
+        }
+    }
+}
+
+pub struct QuicStreamReceiveSocket {
+    uni_streams: IncomingUniStreams,
+    datagrams: Datagrams,
+}
+
+fn split_connection(connection: NewConnection) -> (QuicStreamSendSocket, QuicStreamReceiveSocket) {
+    let NewConnection {
+        connection,
+        uni_streams,
+        datagrams,
+        ..
+    } = connection;
+
+    (
+        QuicStreamSendSocket {
+            connection,
+            reliable_streams: Arc::new(Mutex::new(HashMap::new())),
+        },
+        QuicStreamReceiveSocket {
+            uni_streams,
+            datagrams,
+        },
+    )
+}
+
+fn transport_config() -> quinn::TransportConfig {
+    let mut config = quinn::TransportConfig::default();
+    config.keep_alive_interval(Some(KEEP_ALIVE_INTERVAL));
+
+    config
+}
+
+pub async fn listen_for_server(ip: IpAddr, port: u16) -> StrResult<Incoming> {
+    let certificate =
+        rcgen::generate_simple_self_signed(vec![CERTIFICATE_NAME.into()]).map_err(err!())?;
+    let certificate_der = certificate.serialize_der().map_err(err!())?;
+    let private_key = certificate.serialize_private_key_der();
+
+    let mut config = ServerConfig::with_single_cert(
+        vec![rustls::Certificate(certificate_der)],
+        rustls::PrivateKey(private_key),
+    )
+    .map_err(err!())?;
+    config.transport = Arc::new(transport_config());
+
+    // The endpoint is kept alive by the incoming connections stream and by the connections
+    let (_, incoming) = Endpoint::server(config, (ip, port).into()).map_err(err!())?;
+
+    Ok(incoming)
+}
+
+pub async fn accept_from_server(
+    mut incoming: Incoming,
+    server_ip: IpAddr,
+) -> StrResult<(QuicStreamSendSocket, QuicStreamReceiveSocket)> {
+    let connecting = incoming.next().await.ok_or_else(enone!())?;
+
+    let server_address = connecting.remote_address();
+    if network::canonical_ip(server_address.ip()) != server_ip {
+        return fmt_e!("Connected to wrong client: {server_address} != {server_ip}");
+    }
+
+    let connection = connecting.await.map_err(err!())?;
+
+    Ok(split_connection(connection))
+}
+
+pub async fn connect_to_client(
+    local_ip: IpAddr,
+    client_ip: IpAddr,
+    client_port: u16,
+    server_port: u16,
+) -> StrResult<(QuicStreamSendSocket, QuicStreamReceiveSocket)> {
+    // The peer certificate is not checked. The packets are authenticated with the stream keys
+    let mut config = ClientConfig::new(Arc::new(crypto::tls_client_config()));
+    config.transport = Arc::new(transport_config());
+
+    let mut endpoint = Endpoint::client((local_ip, server_port).into()).map_err(err!())?;
+    endpoint.set_default_client_config(config);
+
+    let client_addr = SocketAddr::new(client_ip, client_port);
+    let connection = endpoint
+        .connect(client_addr, CERTIFICATE_NAME)
+        .map_err(err!())?
+        .await
+        .map_err(err!())?;
+
+    Ok(split_connection(connection))
+}
+
+pub async fn receive_loop(
+    socket: QuicStreamReceiveSocket,
+    packet_enqueuers: PacketEnqueuers,
+) -> StrResult {
+    let QuicStreamReceiveSocket {
+        mut uni_streams,
+        mut datagrams,
+    } = socket;
+
+    let mut reliable_streams = SelectAll::<FramedRead<RecvStream, Ldc>>::new();
+
+    loop {
+        let mut packet = tokio::select! {
+            maybe_stream = uni_streams.next() => {
+                let stream = maybe_stream.ok_or_else(enone!())?.map_err(err!())?;
+                reliable_streams.push(FramedRead::new(stream, Ldc::new()));
+
+                continue;
+            }
+            maybe_datagram = datagrams.next() => {
+                BytesMut::from(&maybe_datagram.ok_or_else(enone!())?.map_err(err!())?[..])
+            }
+            Some(maybe_packet) = reliable_streams.next() => maybe_packet.map_err(err!())?,
+        };
+
+        let stream_id = match shard::read_stream_id(&mut packet) {
+            Some(id) => id,
+            None => continue,
+        };
+        // Cloned, so that a blocked queue does not keep the map locked
+        let maybe_enqueuer = packet_enqueuers.lock().await.get(&stream_id).cloned();
+        if let Some(enqueuer) = maybe_enqueuer {
+            enqueuer.try_send(packet)?;
+        }
+    }
+}
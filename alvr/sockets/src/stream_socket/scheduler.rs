@@ -0,0 +1,258 @@
+// Shards of all streams are queued by priority class and sent by a single task, so that small and
+// latency critical packets (tracking, haptics) preempt bulk video. Preemption happens between
+// shards: over TCP packets are never split, so a video packet is always sent as a whole. When the
+// queue of a class is full, the drop policy of the stream decides whether the oldest shard of the
+// class is discarded or the stream waits.
+
+use super::StreamSendSocket;
+use crate::{
+    queue::{bounded_queue, QueueReceiver, QueueSender},
+    AUDIO, HAPTICS, STATISTICS, TRACKING, VIDEO,
+};
+use alvr_common::{parking_lot::Mutex, prelude::*};
+use alvr_session::DropPolicy;
+use bytes::Bytes;
+use std::{collections::HashMap, sync::Arc};
+
+// Shards waiting in each priority class. Enough for a few video frames at high bitrate
+const CLASS_QUEUE_CAPACITY: usize = 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StreamPriority {
+    Realtime,
+    Normal,
+    Bulk,
+}
+
+impl StreamPriority {
+    const COUNT: usize = 3;
+
+    // Default class of the streams. Unknown streams are sent with normal priority
+    pub fn of_stream(stream_id: u16) -> Self {
+        match stream_id {
+            TRACKING | HAPTICS | STATISTICS => StreamPriority::Realtime,
+            AUDIO => StreamPriority::Normal,
+            VIDEO => StreamPriority::Bulk,
+            _ => StreamPriority::Normal,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Default, Debug)]
+pub struct StreamQueueStats {
+    pub queued_shards: usize,
+    pub queued_bytes: usize,
+    // Highest value of queued_bytes since the stream was first used
+    pub peak_queued_bytes: usize,
+    // Discarded because the queue of the class was full
+    pub dropped_shards: usize,
+}
+
+#[derive(Default)]
+struct SchedulerState {
+    priorities: HashMap<u16, StreamPriority>,
+    drop_policies: HashMap<u16, DropPolicy>,
+    queues: HashMap<u16, StreamQueueStats>,
+    // Set if the wrapped socket fails. The following sends return it
+    error: Option<String>,
+}
+
+// One queue per priority class, in the order of StreamPriority
+type ShardSenders = [QueueSender<(u16, Bytes)>; StreamPriority::COUNT];
+type ShardReceivers = [QueueReceiver<(u16, Bytes)>; StreamPriority::COUNT];
+
+// The drop policy is chosen by the stream of each shard
+fn shard_queues(capacity: usize) -> (ShardSenders, ShardReceivers) {
+    let (realtime_sender, realtime_receiver) = bounded_queue(capacity, DropPolicy::DropOldest);
+    let (normal_sender, normal_receiver) = bounded_queue(capacity, DropPolicy::DropOldest);
+    let (bulk_sender, bulk_receiver) = bounded_queue(capacity, DropPolicy::DropOldest);
+
+    (
+        [realtime_sender, normal_sender, bulk_sender],
+        [realtime_receiver, normal_receiver, bulk_receiver],
+    )
+}
+
+#[derive(Clone)]
+pub struct ScheduledStreamSendSocket {
+    pub inner: Box<StreamSendSocket>,
+    shard_senders: ShardSenders,
+    state: Arc<Mutex<SchedulerState>>,
+}
+
+impl ScheduledStreamSendSocket {
+    // Spawns the task that sends the shards. It stops when all clones of the socket are dropped
+    pub fn new(inner: StreamSendSocket) -> Self {
+        let (shard_senders, shard_receivers) = shard_queues(CLASS_QUEUE_CAPACITY);
+        let state = Arc::new(Mutex::new(SchedulerState::default()));
+
+        tokio::spawn(send_loop(
+            inner.clone(),
+            shard_receivers,
+            Arc::clone(&state),
+        ));
+
+        Self {
+            inner: Box::new(inner),
+            shard_senders,
+            state,
+        }
+    }
+
+    pub fn set_priority(&self, stream_id: u16, priority: StreamPriority) {
+        self.state.lock().priorities.insert(stream_id, priority);
+    }
+
+    // Streams drop the oldest shards by default
+    pub fn set_drop_policy(&self, stream_id: u16, drop_policy: DropPolicy) {
+        self.state
+            .lock()
+            .drop_policies
+            .insert(stream_id, drop_policy);
+    }
+
+    pub fn queue_stats(&self) -> HashMap<u16, StreamQueueStats> {
+        self.state.lock().queues.clone()
+    }
+
+    // With DropPolicy::Block, waits until there is room in the queue of the class
+    pub async fn send(&self, stream_id: u16, shard: Bytes) -> StrResult {
+        let (priority, drop_policy) = {
+            let mut state = self.state.lock();
+            if let Some(e) = &state.error {
+                return fmt_e!("{e}");
+            }
+
+            let priority = state
+                .priorities
+                .get(&stream_id)
+                .copied()
+                .unwrap_or_else(|| StreamPriority::of_stream(stream_id));
+            let drop_policy = state
+                .drop_policies
+                .get(&stream_id)
+                .copied()
+                .unwrap_or(DropPolicy::DropOldest);
+
+            let stats = state.queues.entry(stream_id).or_default();
+            stats.queued_shards += 1;
+            stats.queued_bytes += shard.len();
+            stats.peak_queued_bytes = usize::max(stats.peak_queued_bytes, stats.queued_bytes);
+
+            (priority, drop_policy)
+        };
+
+        let maybe_dropped = self.shard_senders[priority as usize]
+            .send_with_policy((stream_id, shard), drop_policy)
+            .await?;
+
+        // The discarded shard can belong to another stream of the same class
+        if let Some((dropped_stream_id, dropped_shard)) = maybe_dropped {
+            if let Some(stats) = self.state.lock().queues.get_mut(&dropped_stream_id) {
+                stats.queued_shards -= 1;
+                stats.queued_bytes -= dropped_shard.len();
+                stats.dropped_shards += 1;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Pop the shard from the highest priority queue that is not empty
+async fn next_shard(shard_receivers: &mut ShardReceivers) -> Option<(u16, Bytes)> {
+    let [realtime, normal, bulk] = shard_receivers;
+
+    tokio::select! {
+        biased;
+        Some(shard) = realtime.recv() => Some(shard),
+        Some(shard) = normal.recv() => Some(shard),
+        Some(shard) = bulk.recv() => Some(shard),
+        else => None,
+    }
+}
+
+async fn send_loop(
+    socket: StreamSendSocket,
+    mut shard_receivers: ShardReceivers,
+    state: Arc<Mutex<SchedulerState>>,
+) {
+    while let Some((stream_id, shard)) = next_shard(&mut shard_receivers).await {
+        if let Some(stats) = state.lock().queues.get_mut(&stream_id) {
+            stats.queued_shards -= 1;
+            stats.queued_bytes -= shard.len();
+        }
+
+        if let Err(e) = socket.send(stream_id, shard).await {
+            state.lock().error = Some(e);
+            break;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn realtime_preempts_bulk() {
+        let (shard_senders, mut shard_receivers) = shard_queues(CLASS_QUEUE_CAPACITY);
+        let enqueue = |stream_id| {
+            shard_senders[StreamPriority::of_stream(stream_id) as usize]
+                .try_send((stream_id, Bytes::new()))
+                .unwrap()
+        };
+
+        enqueue(VIDEO);
+        enqueue(AUDIO);
+        enqueue(VIDEO);
+        enqueue(TRACKING);
+        enqueue(HAPTICS);
+
+        for stream_id in [TRACKING, HAPTICS, AUDIO, VIDEO, VIDEO] {
+            assert_eq!(next_shard(&mut shard_receivers).await.unwrap().0, stream_id);
+        }
+
+        drop(shard_senders);
+        assert!(next_shard(&mut shard_receivers).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn full_queue() {
+        let (shard_senders, mut shard_receivers) = shard_queues(2);
+        let [_, _, bulk] = &shard_senders;
+
+        let mut dropped_shards = vec![];
+        for index in 0..3 {
+            dropped_shards.push(
+                bulk.send_with_policy((VIDEO, Bytes::from(vec![index])), DropPolicy::DropOldest)
+                    .await
+                    .unwrap(),
+            );
+        }
+        // The oldest shard makes room for the new one
+        assert!(matches!(
+            &dropped_shards[..],
+            [None, None, Some((VIDEO, shard))] if shard[0] == 0
+        ));
+
+        // With DropPolicy::Block, the stream waits for the send loop
+        let blocked_sender = bulk.clone();
+        let blocked_send = tokio::spawn(async move {
+            blocked_sender
+                .send_with_policy((VIDEO, Bytes::from(vec![3])), DropPolicy::Block)
+                .await
+        });
+        tokio::task::yield_now().await;
+        assert!(!blocked_send.is_finished());
+
+        for index in 1..4 {
+            let (_, shard) = next_shard(&mut shard_receivers).await.unwrap();
+            assert_eq!(shard[0], index);
+
+            if index == 1 {
+                assert!(blocked_send.await.unwrap().unwrap().is_none());
+            }
+        }
+    }
+}
@@ -0,0 +1,633 @@
+// Packets are split into shards so that datagram based transports never rely on IP fragmentation.
+// Every shard starts with the same prefix: stream ID (u16), packet index (u32), shard index (u32),
+// data shards count (u32), FEC percentage (u16) and payload size (u32). The stream ID is consumed
+// by the receive loops, the rest is used here to reassemble the packet.
+
+use super::fec::{FecCodec, FecLayout, MAX_FEC_PERCENTAGE};
+use alvr_common::prelude::*;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    mem,
+    ops::Range,
+    time::{Duration, Instant},
+};
+
+pub const SHARD_PREFIX_SIZE: usize = 2 + 4 + 4 + 4 + 2 + 4;
+
+// Bounds the memory reserved for a packet when its first shard is received. With UDP, packets can
+// be up to about 11 MB
+const MAX_DATA_SHARDS_COUNT: u32 = 8192;
+
+// Maximum number of packets waiting for missing shards
+const MAX_PENDING_PACKETS: u32 = 64;
+
+// Offset of the shard info inside the prefix, after stream ID and packet index
+const SHARD_INFO_OFFSET: usize = 2 + 4;
+
+// Packet indices wrap around after u32::MAX. Like serial numbers (RFC 1982), an index is newer than
+// another if it is ahead by less than half of the index space
+pub fn is_newer_packet_index(index: u32, other: u32) -> bool {
+    index != other && index.wrapping_sub(other) < 1 << 31
+}
+
+pub struct ShardInfo {
+    pub packet_index: u32,
+    pub shard_index: u32,
+    pub data_shards_count: u32,
+    pub fec_percentage: u16,
+    pub payload_size: u32,
+}
+
+// Consumes the stream ID. Returns None if the packet is truncated
+pub fn read_stream_id(packet: &mut BytesMut) -> Option<u16> {
+    (packet.len() >= 2).then(|| packet.get_u16())
+}
+
+impl ShardInfo {
+    // Consumes the prefix (without stream ID). Returns None if the shard is truncated or invalid
+    pub fn read(shard: &mut BytesMut) -> Option<Self> {
+        if shard.len() < SHARD_PREFIX_SIZE - 2 {
+            return None;
+        }
+
+        let info = Self {
+            packet_index: shard.get_u32(),
+            shard_index: shard.get_u32(),
+            data_shards_count: shard.get_u32(),
+            fec_percentage: shard.get_u16(),
+            payload_size: shard.get_u32(),
+        };
+
+        (info.data_shards_count <= MAX_DATA_SHARDS_COUNT
+            && info.fec_percentage <= MAX_FEC_PERCENTAGE)
+            .then(|| info)
+    }
+
+    fn write(&self, mut buffer: &mut [u8]) {
+        buffer.put_u32(self.shard_index);
+        buffer.put_u32(self.data_shards_count);
+        buffer.put_u16(self.fec_percentage);
+        buffer.put_u32(self.payload_size);
+    }
+}
+
+// Split a packet (with a prefix already reserved) into shards of at most max_shard_size bytes,
+// prefix included, and append the parity shards. If the result is a single shard, no copies are
+// made.
+pub fn split(
+    mut packet: BytesMut,
+    max_shard_size: usize,
+    fec_percentage: u16,
+    fec: &mut FecCodec,
+) -> StrResult<Vec<Bytes>> {
+    let payload_size = packet.len() - SHARD_PREFIX_SIZE;
+    let max_payload_size = max_shard_size - SHARD_PREFIX_SIZE;
+    // Without overflows when the packet is not split (max_shard_size is usize::MAX)
+    let data_shards_count = payload_size.saturating_sub(1) / max_payload_size + 1;
+    if data_shards_count > MAX_DATA_SHARDS_COUNT as usize {
+        return fmt_e!("Packet too large ({payload_size} bytes)");
+    }
+
+    // An empty payload cannot be protected
+    let fec_percentage = if payload_size > 0 { fec_percentage } else { 0 };
+    let layout = FecLayout::new(data_shards_count, fec_percentage);
+
+    let mut info = ShardInfo {
+        packet_index: 0,
+        shard_index: 0,
+        data_shards_count: data_shards_count as u32,
+        fec_percentage,
+        payload_size: payload_size as u32,
+    };
+
+    if layout.shards_count() == 1 {
+        info.write(&mut packet[SHARD_INFO_OFFSET..SHARD_PREFIX_SIZE]);
+
+        return Ok(vec![packet.freeze()]);
+    }
+
+    let prefix = &packet[..SHARD_INFO_OFFSET];
+    let data_shards = packet[SHARD_PREFIX_SIZE..]
+        .chunks(max_payload_size)
+        .collect::<Vec<_>>();
+    let parity_shards = fec.encode(&layout, &data_shards, data_shards[0].len())?;
+
+    let shards = data_shards
+        .iter()
+        .copied()
+        .chain(parity_shards.iter().map(|shard| &shard[..]))
+        .enumerate()
+        .map(|(index, payload)| {
+            let mut shard = BytesMut::with_capacity(SHARD_PREFIX_SIZE + payload.len());
+            shard.put_slice(prefix);
+            shard.put_bytes(0, SHARD_PREFIX_SIZE - SHARD_INFO_OFFSET);
+            info.shard_index = index as u32;
+            info.write(&mut shard[SHARD_INFO_OFFSET..]);
+            shard.put_slice(payload);
+
+            shard.freeze()
+        })
+        .collect();
+
+    Ok(shards)
+}
+
+// Describes lost shards, either to report them or to request their retransmission
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum ShardLoss {
+    // No shard was received for these packets, so their shards count is unknown. The indices wrap
+    // around after u32::MAX
+    Packets {
+        first_index: u32,
+        count: u32,
+    },
+    // The packet was partially received. Parity shards are included
+    Shards {
+        packet_index: u32,
+        shard_indices: Vec<u32>,
+    },
+}
+
+pub struct ReassembledPacket {
+    pub packet_index: u32,
+    // When the last shard needed to reassemble the packet was received
+    pub arrival: Instant,
+    pub payload: BytesMut,
+    // Data and parity shards
+    pub shards_count: usize,
+    // Data shards rebuilt with FEC
+    pub recovered_shards: usize,
+}
+
+struct PartialPacket {
+    data_shards_count: u32,
+    fec_percentage: u16,
+    payload_size: u32,
+    layout: FecLayout,
+    shards: Vec<Option<BytesMut>>,
+    received_data_count: usize,
+    block_received_counts: Vec<usize>,
+}
+
+impl PartialPacket {
+    fn new(info: &ShardInfo) -> Self {
+        let layout = FecLayout::new(info.data_shards_count as usize, info.fec_percentage);
+
+        Self {
+            data_shards_count: info.data_shards_count,
+            fec_percentage: info.fec_percentage,
+            payload_size: info.payload_size,
+            shards: vec![None; layout.shards_count()],
+            received_data_count: 0,
+            block_received_counts: vec![0; layout.blocks_count()],
+            layout,
+        }
+    }
+
+    fn is_ready(&self) -> bool {
+        self.received_data_count == self.layout.data_shards_count()
+            || self
+                .block_received_counts
+                .iter()
+                .enumerate()
+                .all(|(block_index, &count)| {
+                    count >= self.layout.block_data_shards_count(block_index)
+                })
+    }
+
+    fn missing_shards(&self) -> Vec<u32> {
+        self.shards
+            .iter()
+            .enumerate()
+            .filter(|(_, shard)| shard.is_none())
+            .map(|(index, _)| index as u32)
+            .collect()
+    }
+}
+
+enum PacketState {
+    Missing,
+    Partial(PartialPacket),
+    Complete(ReassembledPacket),
+}
+
+struct PendingPacket {
+    state: PacketState,
+    // Set once a shard of a later packet is received
+    deadline: Option<Instant>,
+}
+
+// Packets are delivered in order. An incomplete packet is given up once the retransmission timeout
+// has passed since a shard of a later packet was received. With a zero timeout (the default), this
+// happens immediately. Shards of packets already delivered or given up are discarded.
+#[derive(Default)]
+pub struct ShardReassembler {
+    // Packet indices are extended to 64 bits, so that they stay ordered when the index sent by the
+    // peer wraps around
+    next_packet_index: u64,
+    // Contains all packets from next_packet_index to the latest packet seen
+    pending_packets: BTreeMap<u64, PendingPacket>,
+    lost_shards: Vec<ShardLoss>,
+    retransmission_timeout: Duration,
+    retransmission_requests: Vec<ShardLoss>,
+    fec: FecCodec,
+}
+
+impl ShardReassembler {
+    pub fn set_retransmission_timeout(&mut self, timeout: Duration) {
+        self.retransmission_timeout = timeout;
+    }
+
+    pub fn push(&mut self, info: ShardInfo, shard: BytesMut, now: Instant) {
+        let packet_index = match self.extend_packet_index(info.packet_index) {
+            Some(index) => index,
+            None => return,
+        };
+        if info.data_shards_count == 0 || info.data_shards_count > MAX_DATA_SHARDS_COUNT {
+            return;
+        }
+
+        // Give up the oldest packets if too many are pending
+        if packet_index - self.next_packet_index >= MAX_PENDING_PACKETS as u64 {
+            let first_kept_index = packet_index - MAX_PENDING_PACKETS as u64 + 1;
+
+            while !self.pending_packets.is_empty() && self.next_packet_index < first_kept_index {
+                self.give_up_next_packet();
+            }
+            if self.next_packet_index < first_kept_index {
+                push_packets_loss(
+                    &mut self.lost_shards,
+                    self.next_packet_index..first_kept_index,
+                );
+                self.next_packet_index = first_kept_index;
+            }
+        }
+
+        let first_new_index = self
+            .pending_packets
+            .keys()
+            .next_back()
+            .map(|index| index + 1)
+            .unwrap_or(self.next_packet_index);
+        for index in first_new_index..=packet_index {
+            self.pending_packets.insert(
+                index,
+                PendingPacket {
+                    state: PacketState::Missing,
+                    deadline: None,
+                },
+            );
+        }
+
+        // A gap is detected for every incomplete packet before this one
+        if first_new_index <= packet_index {
+            let deadline = now + self.retransmission_timeout;
+            for (&index, packet) in self.pending_packets.range_mut(..packet_index) {
+                if packet.deadline.is_some() {
+                    continue;
+                }
+                packet.deadline = Some(deadline);
+
+                if self.retransmission_timeout > Duration::ZERO {
+                    match &packet.state {
+                        PacketState::Missing => {
+                            push_packets_loss(&mut self.retransmission_requests, index..index + 1)
+                        }
+                        PacketState::Partial(partial) => {
+                            self.retransmission_requests.push(ShardLoss::Shards {
+                                packet_index: index as u32,
+                                shard_indices: partial.missing_shards(),
+                            })
+                        }
+                        PacketState::Complete(_) => (),
+                    }
+                }
+            }
+        }
+
+        let packet = match self.pending_packets.get_mut(&packet_index) {
+            Some(packet) => packet,
+            None => return,
+        };
+
+        if let PacketState::Missing = packet.state {
+            packet.state = PacketState::Partial(PartialPacket::new(&info));
+        }
+        let partial = match &mut packet.state {
+            PacketState::Partial(partial) => partial,
+            _ => return,
+        };
+
+        let shard_index = info.shard_index as usize;
+        if info.data_shards_count != partial.data_shards_count
+            || info.fec_percentage != partial.fec_percentage
+            || info.payload_size != partial.payload_size
+            || shard_index >= partial.shards.len()
+            || partial.shards[shard_index].is_some()
+        {
+            return;
+        }
+
+        partial.shards[shard_index] = Some(shard);
+        partial.block_received_counts[partial.layout.block_of(shard_index)] += 1;
+        if shard_index < partial.layout.data_shards_count() {
+            partial.received_data_count += 1;
+        }
+
+        if partial.is_ready() {
+            packet.state = match assemble(&mut self.fec, partial, packet_index as u32, now) {
+                Ok(packet) => PacketState::Complete(packet),
+                Err(e) => {
+                    debug!("Dropping packet {packet_index}: {e}");
+                    PacketState::Missing
+                }
+            };
+        }
+    }
+
+    // Returns the next packet in order, if it is complete. Packets past their deadline are given
+    // up
+    pub fn pop(&mut self, now: Instant) -> Option<ReassembledPacket> {
+        loop {
+            let packet = self.pending_packets.get(&self.next_packet_index)?;
+
+            if let PacketState::Complete(_) = packet.state {
+                let packet = self.pending_packets.remove(&self.next_packet_index)?;
+                self.next_packet_index += 1;
+
+                if let PacketState::Complete(packet) = packet.state {
+                    return Some(packet);
+                }
+            } else if matches!(packet.deadline, Some(deadline) if deadline <= now) {
+                self.give_up_next_packet();
+            } else {
+                return None;
+            }
+        }
+    }
+
+    // The earliest instant at which pop() could give up a packet
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.pending_packets
+            .values()
+            .filter_map(|packet| packet.deadline)
+            .min()
+    }
+
+    // Losses detected since the last call
+    pub fn take_lost_shards(&mut self) -> Vec<ShardLoss> {
+        mem::take(&mut self.lost_shards)
+    }
+
+    pub fn take_retransmission_requests(&mut self) -> Vec<ShardLoss> {
+        mem::take(&mut self.retransmission_requests)
+    }
+
+    // Returns None for the shards of packets older than next_packet_index
+    fn extend_packet_index(&self, index: u32) -> Option<u64> {
+        let offset = index.wrapping_sub(self.next_packet_index as u32);
+
+        (offset < 1 << 31).then(|| self.next_packet_index + offset as u64)
+    }
+
+    fn give_up_next_packet(&mut self) {
+        let index = self.next_packet_index;
+        self.next_packet_index += 1;
+
+        match self
+            .pending_packets
+            .remove(&index)
+            .map(|packet| packet.state)
+        {
+            Some(PacketState::Partial(partial)) => self.lost_shards.push(ShardLoss::Shards {
+                packet_index: index as u32,
+                shard_indices: partial.missing_shards(),
+            }),
+            // A complete packet cannot be given up. It is dropped only if too many packets are
+            // pending
+            Some(PacketState::Complete(_)) | Some(PacketState::Missing) | None => {
+                push_packets_loss(&mut self.lost_shards, index..index + 1)
+            }
+        }
+    }
+}
+
+// Consecutive lost packets are merged
+fn push_packets_loss(losses: &mut Vec<ShardLoss>, packet_indices: Range<u64>) {
+    let first_index = packet_indices.start as u32;
+    let count = (packet_indices.end - packet_indices.start) as u32;
+
+    if let Some(ShardLoss::Packets {
+        first_index: last_first_index,
+        count: last_count,
+    }) = losses.last_mut()
+    {
+        if last_first_index.wrapping_add(*last_count) == first_index {
+            if let Some(merged_count) = last_count.checked_add(count) {
+                *last_count = merged_count;
+                return;
+            }
+        }
+    }
+
+    losses.push(ShardLoss::Packets { first_index, count });
+}
+
+fn assemble(
+    fec: &mut FecCodec,
+    partial: &mut PartialPacket,
+    packet_index: u32,
+    arrival: Instant,
+) -> StrResult<ReassembledPacket> {
+    let data_shards_count = partial.layout.data_shards_count();
+    let recovered_shards = data_shards_count - partial.received_data_count;
+
+    if recovered_shards > 0 {
+        // Parity shards are never padded
+        let shard_size = partial.shards[data_shards_count..]
+            .iter()
+            .flatten()
+            .map(|shard| shard.len())
+            .next()
+            .ok_or_else(enone!())?;
+
+        fec.reconstruct(&partial.layout, &mut partial.shards, shard_size)?;
+
+        let last_shard_size = partial.payload_size as usize
+            - usize::min(
+                partial.payload_size as usize,
+                (data_shards_count - 1) * shard_size,
+            );
+        if let Some(Some(last_shard)) = partial.shards.get_mut(data_shards_count - 1) {
+            last_shard.truncate(last_shard_size);
+        }
+    }
+
+    let mut shards = partial.shards.drain(..data_shards_count).flatten();
+    let mut payload = shards.next().ok_or_else(enone!())?;
+    for shard in shards {
+        payload.unsplit(shard);
+    }
+
+    if payload.len() != partial.payload_size as usize {
+        return fmt_e!("Wrong payload size");
+    }
+
+    Ok(ReassembledPacket {
+        packet_index,
+        arrival,
+        payload,
+        shards_count: partial.layout.shards_count(),
+        recovered_shards,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_shards(packet_index: u32, payload_size: usize) -> Vec<(ShardInfo, BytesMut)> {
+        let mut packet = BytesMut::new();
+        packet.put_u16(0);
+        packet.put_u32(packet_index);
+        packet.put_bytes(0, SHARD_PREFIX_SIZE - SHARD_INFO_OFFSET);
+        packet.put_bytes(packet_index as u8, payload_size);
+
+        split(packet, 100, 0, &mut FecCodec::default())
+            .unwrap()
+            .into_iter()
+            .map(|shard| {
+                let mut shard = BytesMut::from(&shard[2..]);
+                (ShardInfo::read(&mut shard).unwrap(), shard)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn unsplit_packet() {
+        let mut packet = BytesMut::new();
+        packet.put_bytes(0, SHARD_PREFIX_SIZE);
+        packet.put_bytes(1, 10_000);
+
+        // Reliable sockets do not limit the shard size
+        let shards = split(packet, usize::MAX, 0, &mut FecCodec::default()).unwrap();
+
+        assert_eq!(shards.len(), 1);
+        assert_eq!(shards[0].len(), SHARD_PREFIX_SIZE + 10_000);
+    }
+
+    #[test]
+    fn retransmission() {
+        let timeout = Duration::from_millis(10);
+        let start = Instant::now();
+
+        let mut reassembler = ShardReassembler::default();
+        reassembler.set_retransmission_timeout(timeout);
+
+        let mut packet0 = make_shards(0, 300);
+        let (lost_info, lost_shard) = packet0.remove(1);
+        for (info, shard) in packet0.into_iter().chain(make_shards(1, 300)) {
+            reassembler.push(info, shard, start);
+        }
+
+        // Packet 1 is held until packet 0 is complete
+        assert!(reassembler.pop(start).is_none());
+        assert_eq!(
+            reassembler.take_retransmission_requests(),
+            vec![ShardLoss::Shards {
+                packet_index: 0,
+                shard_indices: vec![1]
+            }]
+        );
+
+        reassembler.push(lost_info, lost_shard, start);
+        assert_eq!(reassembler.pop(start).unwrap().payload[0], 0);
+        assert_eq!(reassembler.pop(start).unwrap().payload[0], 1);
+
+        // Packet 2 is never received
+        for (info, shard) in make_shards(3, 300) {
+            reassembler.push(info, shard, start);
+        }
+        assert_eq!(
+            reassembler.take_retransmission_requests(),
+            vec![ShardLoss::Packets {
+                first_index: 2,
+                count: 1
+            }]
+        );
+        assert!(reassembler.pop(start).is_none());
+
+        let packet = reassembler.pop(start + timeout).unwrap();
+        assert_eq!(packet.payload[0], 3);
+        assert_eq!(
+            reassembler.take_lost_shards(),
+            vec![ShardLoss::Packets {
+                first_index: 2,
+                count: 1
+            }]
+        );
+    }
+
+    #[test]
+    fn packet_index_wrap_around() {
+        let now = Instant::now();
+
+        let mut reassembler = ShardReassembler {
+            next_packet_index: u32::MAX as u64 - 1,
+            ..Default::default()
+        };
+
+        // Packet u32::MAX is lost
+        for packet_index in [u32::MAX - 1, 0, 1] {
+            for (info, shard) in make_shards(packet_index, 300) {
+                reassembler.push(info, shard, now);
+            }
+            assert_eq!(reassembler.pop(now).unwrap().packet_index, packet_index);
+        }
+        assert_eq!(
+            reassembler.take_lost_shards(),
+            vec![ShardLoss::Packets {
+                first_index: u32::MAX,
+                count: 1
+            }]
+        );
+
+        // Packets from before the wrap around are old
+        for (info, shard) in make_shards(u32::MAX, 300) {
+            reassembler.push(info, shard, now);
+        }
+        assert!(reassembler.pending_packets.is_empty());
+    }
+
+    #[test]
+    fn too_many_shards() {
+        let mut reassembler = ShardReassembler::default();
+
+        let info = ShardInfo {
+            packet_index: 0,
+            shard_index: 0,
+            data_shards_count: u32::MAX,
+            fec_percentage: 0,
+            payload_size: 1,
+        };
+        reassembler.push(info, BytesMut::from(&[0][..]), Instant::now());
+
+        assert!(reassembler.pending_packets.is_empty());
+    }
+
+    #[test]
+    fn malformed_prefix() {
+        assert!(read_stream_id(&mut BytesMut::from(&[0][..])).is_none());
+        assert!(ShardInfo::read(&mut BytesMut::from(&[0; SHARD_PREFIX_SIZE - 3][..])).is_none());
+
+        let mut shard = BytesMut::new();
+        shard.put_u32(0);
+        shard.put_u32(0);
+        shard.put_u32(u32::MAX);
+        shard.put_u16(0);
+        shard.put_u32(0);
+        assert!(ShardInfo::read(&mut shard).is_none());
+    }
+}
@@ -0,0 +1,186 @@
+// Per-stream counters, updated by the StreamSenders and the StreamReceiver of each stream. The
+// shards are classified as they arrive, before reassembly: a shard that comes after a shard sent
+// later is reordered, a shard seen twice is duplicated. Only the shards of the most recent packets
+// are remembered, older duplicates are counted as reordered.
+
+use super::{shard::is_newer_packet_index, ShardLoss};
+use alvr_common::parking_lot::Mutex;
+use alvr_events::StreamStatistics;
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+// Packets whose shards are remembered to detect duplicates
+const TRACKED_PACKETS_COUNT: u32 = 64;
+// Weight of the new sample, like RFC 3550
+const JITTER_GAIN: f32 = 1. / 16.;
+
+pub type SharedStreamStatistics = Arc<Mutex<StreamStatistics>>;
+
+// Counters of all the streams of a socket
+#[derive(Default)]
+pub struct StreamStatisticsRegistry {
+    streams: Mutex<HashMap<u16, SharedStreamStatistics>>,
+}
+
+impl StreamStatisticsRegistry {
+    pub fn get(&self, stream_id: u16) -> SharedStreamStatistics {
+        Arc::clone(self.streams.lock().entry(stream_id).or_default())
+    }
+
+    pub fn snapshot(&self) -> HashMap<u16, StreamStatistics> {
+        self.streams
+            .lock()
+            .iter()
+            .map(|(&stream_id, stats)| (stream_id, stats.lock().clone()))
+            .collect()
+    }
+}
+
+pub fn lost_packets_count(lost_shards: &[ShardLoss]) -> u64 {
+    lost_shards
+        .iter()
+        .map(|loss| match loss {
+            ShardLoss::Packets { count, .. } => *count as u64,
+            // The packet was given up because some shards are missing
+            ShardLoss::Shards { .. } => 1,
+        })
+        .sum()
+}
+
+#[derive(Default)]
+pub struct ArrivalTracker {
+    // (packet index, shard index)
+    recent_shards: BTreeSet<(u32, u32)>,
+    last_arrival: Option<Instant>,
+    last_interval: Option<Duration>,
+    jitter_s: f32,
+}
+
+impl ArrivalTracker {
+    pub fn report_shard(
+        &mut self,
+        stats: &mut StreamStatistics,
+        packet_index: u32,
+        shard_index: u32,
+        size: usize,
+    ) {
+        stats.bytes_received += size as u64;
+
+        let shard = (packet_index, shard_index);
+        let mut latest_shard = self.recent_shards.iter().next_back().copied();
+
+        // Start over when the packet index wraps around
+        if matches!(latest_shard, Some((latest_index, _))
+            if packet_index < latest_index && is_newer_packet_index(packet_index, latest_index))
+        {
+            self.recent_shards.clear();
+            latest_shard = None;
+        }
+
+        if !self.recent_shards.insert(shard) {
+            stats.shards_duplicated += 1;
+        } else if matches!(latest_shard, Some(latest) if shard < latest) {
+            stats.shards_reordered += 1;
+        }
+
+        // Forget the shards of the old packets
+        let latest_packet_index = latest_shard.map_or(packet_index, |(index, _)| index);
+        let first_tracked_index =
+            u32::max(latest_packet_index, packet_index).saturating_sub(TRACKED_PACKETS_COUNT - 1);
+        while let Some(&oldest_shard) = self.recent_shards.iter().next() {
+            if oldest_shard.0 >= first_tracked_index {
+                break;
+            }
+            self.recent_shards.remove(&oldest_shard);
+        }
+    }
+
+    pub fn report_packet(&mut self, stats: &mut StreamStatistics, arrival: Instant) {
+        stats.packets_received += 1;
+
+        if let Some(last_arrival) = self.last_arrival {
+            let interval = arrival.saturating_duration_since(last_arrival);
+            if let Some(last_interval) = self.last_interval {
+                let variation = (interval.as_secs_f32() - last_interval.as_secs_f32()).abs();
+                self.jitter_s += (variation - self.jitter_s) * JITTER_GAIN;
+                stats.jitter_ms = self.jitter_s * 1000.;
+            }
+
+            self.last_interval = Some(interval);
+        }
+        self.last_arrival = Some(arrival);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shard_classification() {
+        let mut tracker = ArrivalTracker::default();
+        let mut stats = StreamStatistics::default();
+
+        for (packet_index, shard_index) in [(0, 0), (0, 1), (1, 1), (1, 0), (1, 1), (0, 0)] {
+            tracker.report_shard(&mut stats, packet_index, shard_index, 100);
+        }
+        assert_eq!(stats.bytes_received, 600);
+        assert_eq!(stats.shards_reordered, 1);
+        assert_eq!(stats.shards_duplicated, 2);
+
+        // Duplicates of packets that are not tracked anymore look reordered
+        tracker.report_shard(&mut stats, 1000, 0, 100);
+        tracker.report_shard(&mut stats, 1, 0, 100);
+        assert_eq!(stats.shards_reordered, 2);
+        assert_eq!(stats.shards_duplicated, 2);
+
+        // The packet index wraps around
+        tracker.report_shard(&mut stats, u32::MAX, 0, 100);
+        tracker.report_shard(&mut stats, 0, 0, 100);
+        tracker.report_shard(&mut stats, 0, 0, 100);
+        assert_eq!(stats.shards_reordered, 2);
+        assert_eq!(stats.shards_duplicated, 3);
+    }
+
+    #[test]
+    fn jitter() {
+        let mut tracker = ArrivalTracker::default();
+        let mut stats = StreamStatistics::default();
+
+        // Constant intervals
+        let start = Instant::now();
+        for i in 0..10 {
+            tracker.report_packet(&mut stats, start + Duration::from_millis(10 * i));
+        }
+        assert_eq!(stats.packets_received, 10);
+        assert_eq!(stats.jitter_ms, 0.);
+
+        // Alternating 5 ms and 15 ms intervals
+        let mut arrival = start + Duration::from_millis(90);
+        for i in 0..200 {
+            arrival += Duration::from_millis(if i % 2 == 0 { 5 } else { 15 });
+            tracker.report_packet(&mut stats, arrival);
+        }
+        assert!((stats.jitter_ms - 10.).abs() < 0.1);
+    }
+
+    #[test]
+    fn lost_packets() {
+        let losses = [
+            ShardLoss::Packets {
+                first_index: 3,
+                count: 3,
+            },
+            ShardLoss::Shards {
+                packet_index: 8,
+                shard_indices: vec![0, 2],
+            },
+        ];
+
+        assert_eq!(lost_packets_count(&losses), 4);
+        assert_eq!(lost_packets_count(&[]), 0);
+    }
+}
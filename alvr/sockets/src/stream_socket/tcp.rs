@@ -1,22 +1,23 @@
-use crate::{Ldc, LOCAL_IP};
+use super::{shard, PacketEnqueuers};
+use crate::{network, Ldc};
 use alvr_common::prelude::*;
-use bytes::{Buf, Bytes, BytesMut};
+use bytes::Bytes;
 use futures::{
     stream::{SplitSink, SplitStream},
     StreamExt,
 };
-use std::{collections::HashMap, net::IpAddr, sync::Arc};
+use std::{net::IpAddr, sync::Arc};
 use tokio::{
-    net::{TcpListener, TcpStream},
-    sync::{mpsc, Mutex},
+    net::{TcpListener, TcpSocket, TcpStream},
+    sync::Mutex,
 };
 use tokio_util::codec::Framed;
 
 pub type TcpStreamSendSocket = Arc<Mutex<SplitSink<Framed<TcpStream, Ldc>, Bytes>>>;
 pub type TcpStreamReceiveSocket = SplitStream<Framed<TcpStream, Ldc>>;
 
-pub async fn listen_for_server(port: u16) -> StrResult<TcpListener> {
-    TcpListener::bind((LOCAL_IP, port)).await.map_err(err!())
+pub async fn listen_for_server(ip: IpAddr, port: u16) -> StrResult<TcpListener> {
+    TcpListener::bind((ip, port)).await.map_err(err!())
 }
 
 pub async fn accept_from_server(
@@ -25,7 +26,7 @@ pub async fn accept_from_server(
 ) -> StrResult<(TcpStreamSendSocket, TcpStreamReceiveSocket)> {
     let (socket, server_address) = listener.accept().await.map_err(err!())?;
 
-    if server_address.ip() != server_ip {
+    if network::canonical_ip(server_address.ip()) != server_ip {
         return fmt_e!("Connected to wrong client: {server_address} != {server_ip}");
     }
 
@@ -37,10 +38,19 @@ pub async fn accept_from_server(
 }
 
 pub async fn connect_to_client(
+    local_ip: IpAddr,
     client_ip: IpAddr,
     port: u16,
 ) -> StrResult<(TcpStreamSendSocket, TcpStreamReceiveSocket)> {
-    let socket = TcpStream::connect((client_ip, port))
+    let socket = if local_ip.is_ipv4() {
+        TcpSocket::new_v4()
+    } else {
+        TcpSocket::new_v6()
+    }
+    .map_err(err!())?;
+    socket.bind((local_ip, 0).into()).map_err(err!())?;
+    let socket = socket
+        .connect((client_ip, port).into())
         .await
         .map_err(err!())?;
     socket.set_nodelay(true).map_err(err!())?;
@@ -52,14 +62,19 @@ pub async fn connect_to_client(
 
 pub async fn receive_loop(
     mut socket: TcpStreamReceiveSocket,
-    packet_enqueuers: Arc<Mutex<HashMap<u16, mpsc::UnboundedSender<BytesMut>>>>,
+    packet_enqueuers: PacketEnqueuers,
 ) -> StrResult {
     while let Some(maybe_packet) = socket.next().await {
         let mut packet = maybe_packet.map_err(err!())?;
 
-        let stream_id = packet.get_u16();
-        if let Some(enqueuer) = packet_enqueuers.lock().await.get_mut(&stream_id) {
-            enqueuer.send(packet).map_err(err!())?;
+        let stream_id = match shard::read_stream_id(&mut packet) {
+            Some(id) => id,
+            None => continue,
+        };
+        // Cloned, so that a blocked queue does not keep the map locked
+        let maybe_enqueuer = packet_enqueuers.lock().await.get(&stream_id).cloned();
+        if let Some(enqueuer) = maybe_enqueuer {
+            enqueuer.try_send(packet)?;
         }
     }
 
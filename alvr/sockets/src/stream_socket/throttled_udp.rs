@@ -1,6 +1,7 @@
-use crate::LOCAL_IP;
-use alvr_common::prelude::*;
-use bytes::{Buf, BufMut, Bytes, BytesMut};
+use super::{shard, PacketEnqueuers, MAX_UDP_SHARD_SIZE};
+use crate::VIDEO;
+use alvr_common::{parking_lot::Mutex as SyncMutex, prelude::*};
+use bytes::{BufMut, Bytes, BytesMut};
 use futures::{Stream, StreamExt};
 use governor::{
     clock,
@@ -17,31 +18,58 @@ use std::{
     sync::Arc,
     task::{Context, Poll},
 };
-use tokio::{
-    io::ReadBuf,
-    net::UdpSocket,
-    sync::{mpsc, Mutex},
-};
+use tokio::{io::ReadBuf, net::UdpSocket};
 
 const INITIAL_RD_CAPACITY: usize = 64 * 1024;
 
 // Don't go below this rate, limiting then is unneeded anyway.
 const MINIMUM_BYTERATE: u32 = 30 * 1024 * 1024 * 3 / 2 / 8;
-// Reserve includes audio along with other small fluctuations.
+// Budget of each of the other streams (audio, haptics, ...)
 const RESERVE_BYTERATE: u32 = 5_000_000 / 8;
 
-#[allow(clippy::type_complexity)]
+type Limiter = RateLimiter<NotKeyed, InMemoryState, clock::DefaultClock>;
+
+fn limiter(byterate: u32) -> Limiter {
+    // A burst must fit at least a whole shard
+    let burst = u32::max(byterate / 1000, MAX_UDP_SHARD_SIZE as u32);
+    let quota = Quota::per_second(NonZero::new(byterate).unwrap())
+        .allow_burst(NonZero::new(burst).unwrap());
+
+    RateLimiter::direct(quota)
+}
+
+// Each stream has its own budget, so that video cannot starve the other streams
+struct StreamLimiters {
+    video_byterate: u32,
+    limiters: SyncMutex<HashMap<u16, Arc<Limiter>>>,
+}
+
+impl StreamLimiters {
+    fn get(&self, stream_id: u16) -> Arc<Limiter> {
+        let mut limiters = self.limiters.lock();
+        let limiter = limiters.entry(stream_id).or_insert_with(|| {
+            Arc::new(limiter(if stream_id == VIDEO {
+                self.video_byterate
+            } else {
+                RESERVE_BYTERATE
+            }))
+        });
+
+        Arc::clone(limiter)
+    }
+}
+
 #[derive(Clone)]
 pub struct ThrottledUdpStreamSendSocket {
     inner: Arc<UdpSocket>,
-    limiter: Arc<Option<RateLimiter<NotKeyed, InMemoryState, clock::DefaultClock>>>,
+    limiters: Option<Arc<StreamLimiters>>,
 }
 
 impl ThrottledUdpStreamSendSocket {
-    pub async fn send(&self, data: Bytes) -> io::Result<()> {
-        if let Some(ref limiter) = *self.limiter {
+    pub async fn send(&self, stream_id: u16, data: Bytes) -> io::Result<()> {
+        if let Some(limiters) = &self.limiters {
             if let Some(len) = NonZero::new(data.len() as u32) {
-                limiter.until_n_ready(len).await.ok();
+                limiters.get(stream_id).until_n_ready(len).await.ok();
             }
         }
         match self.inner.send(&data).await {
@@ -84,36 +112,36 @@ impl Stream for ThrottledUdpStreamReceiveSocket {
 }
 
 pub async fn connect_to_client(
+    local_ip: IpAddr,
     client_ip: IpAddr,
-    port: u16,
+    client_port: u16,
+    server_port: u16,
     video_byterate: u32,
     bitrate_multiplier: f32,
 ) -> StrResult<(
     ThrottledUdpStreamSendSocket,
     ThrottledUdpStreamReceiveSocket,
 )> {
-    let client_addr: SocketAddr = (client_ip, port).into();
-    let socket = UdpSocket::bind((LOCAL_IP, port)).await.map_err(err!())?;
+    let client_addr: SocketAddr = (client_ip, client_port).into();
+    let socket = UdpSocket::bind((local_ip, server_port))
+        .await
+        .map_err(err!())?;
     socket.connect(client_addr).await.map_err(err!())?;
 
     let rx = Arc::new(socket);
     let tx = Arc::clone(&rx);
 
-    let limiter = {
-        // The byterate and burst amount computation here is based
-        // on the previous C++ implementation.
-        let byterate = (video_byterate as f32 * bitrate_multiplier) as u32 + RESERVE_BYTERATE;
-        let byterate = std::cmp::max(MINIMUM_BYTERATE, byterate);
-        let burst = byterate / 1000;
-        let quota = Quota::per_second(NonZero::new(byterate).unwrap())
-            .allow_burst(NonZero::new(burst).unwrap());
-        Some(RateLimiter::direct(quota))
+    // The byterate and burst amount computation here is based on the previous C++ implementation
+    let video_byterate = (video_byterate as f32 * bitrate_multiplier) as u32;
+    let limiters = StreamLimiters {
+        video_byterate: std::cmp::max(MINIMUM_BYTERATE, video_byterate),
+        limiters: SyncMutex::new(HashMap::new()),
     };
 
     Ok((
         ThrottledUdpStreamSendSocket {
             inner: tx,
-            limiter: Arc::new(limiter),
+            limiters: Some(Arc::new(limiters)),
         },
         ThrottledUdpStreamReceiveSocket {
             inner: rx,
@@ -122,8 +150,8 @@ pub async fn connect_to_client(
     ))
 }
 
-pub async fn listen_for_server(port: u16) -> StrResult<UdpSocket> {
-    UdpSocket::bind((LOCAL_IP, port)).await.map_err(err!())
+pub async fn listen_for_server(ip: IpAddr, port: u16) -> StrResult<UdpSocket> {
+    UdpSocket::bind((ip, port)).await.map_err(err!())
 }
 
 pub async fn accept_from_server(
@@ -143,7 +171,7 @@ pub async fn accept_from_server(
     Ok((
         ThrottledUdpStreamSendSocket {
             inner: tx,
-            limiter: Arc::new(None),
+            limiters: None,
         },
         ThrottledUdpStreamReceiveSocket {
             inner: rx,
@@ -154,14 +182,19 @@ pub async fn accept_from_server(
 
 pub async fn receive_loop(
     mut socket: ThrottledUdpStreamReceiveSocket,
-    packet_enqueuers: Arc<Mutex<HashMap<u16, mpsc::UnboundedSender<BytesMut>>>>,
+    packet_enqueuers: PacketEnqueuers,
 ) -> StrResult {
     while let Some(maybe_packet) = socket.next().await {
         let (mut packet_bytes, _) = maybe_packet.map_err(err!())?;
 
-        let stream_id = packet_bytes.get_u16();
-        if let Some(enqueuer) = packet_enqueuers.lock().await.get_mut(&stream_id) {
-            enqueuer.send(packet_bytes).map_err(err!())?;
+        let stream_id = match shard::read_stream_id(&mut packet_bytes) {
+            Some(id) => id,
+            None => continue,
+        };
+        // Cloned, so that a blocked queue does not keep the map locked
+        let maybe_enqueuer = packet_enqueuers.lock().await.get(&stream_id).cloned();
+        if let Some(enqueuer) = maybe_enqueuer {
+            enqueuer.try_send(packet_bytes)?;
         }
     }
 
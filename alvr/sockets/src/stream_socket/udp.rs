@@ -1,19 +1,16 @@
-use crate::{Ldc, LOCAL_IP};
+use super::{shard, PacketEnqueuers};
+use crate::Ldc;
 use alvr_common::prelude::*;
-use bytes::{Buf, Bytes, BytesMut};
+use bytes::Bytes;
 use futures::{
     stream::{SplitSink, SplitStream},
     StreamExt,
 };
 use std::{
-    collections::HashMap,
     net::{IpAddr, SocketAddr},
     sync::Arc,
 };
-use tokio::{
-    net::UdpSocket,
-    sync::{mpsc, Mutex},
-};
+use tokio::{net::UdpSocket, sync::Mutex};
 use tokio_util::udp::UdpFramed;
 
 #[allow(clippy::type_complexity)]
@@ -30,8 +27,8 @@ pub struct UdpStreamReceiveSocket {
     pub inner: SplitStream<UdpFramed<Ldc>>,
 }
 
-pub async fn bind(port: u16) -> StrResult<UdpSocket> {
-    UdpSocket::bind((LOCAL_IP, port)).await.map_err(err!())
+pub async fn bind(ip: IpAddr, port: u16) -> StrResult<UdpSocket> {
+    UdpSocket::bind((ip, port)).await.map_err(err!())
 }
 
 pub async fn connect(
@@ -57,7 +54,7 @@ pub async fn connect(
 
 pub async fn receive_loop(
     mut socket: UdpStreamReceiveSocket,
-    packet_enqueuers: Arc<Mutex<HashMap<u16, mpsc::UnboundedSender<BytesMut>>>>,
+    packet_enqueuers: PacketEnqueuers,
 ) -> StrResult {
     while let Some(maybe_packet) = socket.inner.next().await {
         let (mut packet_bytes, address) = maybe_packet.map_err(err!())?;
@@ -66,9 +63,14 @@ pub async fn receive_loop(
             continue;
         }
 
-        let stream_id = packet_bytes.get_u16();
-        if let Some(enqueuer) = packet_enqueuers.lock().await.get_mut(&stream_id) {
-            enqueuer.send(packet_bytes).map_err(err!())?;
+        let stream_id = match shard::read_stream_id(&mut packet_bytes) {
+            Some(id) => id,
+            None => continue,
+        };
+        // Cloned, so that a blocked queue does not keep the map locked
+        let maybe_enqueuer = packet_enqueuers.lock().await.get(&stream_id).cloned();
+        if let Some(enqueuer) = maybe_enqueuer {
+            enqueuer.try_send(packet_bytes)?;
         }
     }
 
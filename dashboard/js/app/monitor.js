@@ -85,7 +85,8 @@ define([
                 const connection = pair[1];
                 const displayName = connection.displayName;
 
-                if (connection.trusted) {
+                // Clients are trusted only once paired
+                if (connection.trusted && connection.pairingSecret) {
                     addTrustedClient(displayName, hostname);
                 } else {
                     addNewClient(displayName, hostname);
@@ -174,6 +175,7 @@ define([
                 templateConfigureClient = compiledTemplate({
                     i18n: i18n,
                     knownIps: session.clientConnections[hostname].manualIps,
+                    spectator: session.clientConnections[hostname].spectator,
                 });
 
                 $("#configureClientModal").remove();
@@ -187,6 +189,15 @@ define([
                         keyboard: false,
                     });
 
+                    $("#spectatorCheckbox").change((evt) => {
+                        $.ajax({
+                            type: "POST",
+                            url: "api/client/spectator",
+                            contentType: "application/json;charset=UTF-8",
+                            data: JSON.stringify([_hostmane, evt.target.checked]),
+                        });
+                    });
+
                     $("#addNewIpAddressButton").click(() => {
                         const ip = $("#newIpAddress").val();
 
@@ -264,11 +275,14 @@ define([
             // this call need const variable unless you want them overwriten by the next call.
             $(document).ready(() => {
                 $("#btnAddTrustedClient_" + id).click(() => {
+                    const pin = prompt(i18n["pairingPin"]);
+                    if (!pin) return;
+
                     $.ajax({
                         type: "POST",
-                        url: "api/client/trust",
+                        url: "api/client/pair",
                         contentType: "application/json;charset=UTF-8",
-                        data: JSON.stringify([_hostmane, null]),
+                        data: JSON.stringify([_hostmane, pin]),
                     });
                 });
             });
@@ -355,6 +369,9 @@ define([
                     case "GraphStatistics":
                         addToTable = false;
                         break;
+                    case "SpectatorStatistics":
+                        addToTable = false;
+                        break;
                     default:
                         line = idObject.id;
                         break;
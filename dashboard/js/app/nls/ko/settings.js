@@ -183,6 +183,7 @@ define({
     "_root_connection_streamProtocol_udp-choice-.name": "UDP",
     "_root_connection_streamProtocol_throttledUdp-choice-.name": "Throttled UDP",
     "_root_connection_streamProtocol_tcp-choice-.name": "TCP",
+    "_root_connection_streamProtocol_quic-choice-.name": "QUIC",
     "_root_connection_streamPort.name": "서버 전송 포트", // adv
     "_root_connection_streamPort.description": "서버가 패킷을 수신하는 데 사용하는 포트입니다.", // adv
     "_root_connection_aggressiveKeyframeResend.name": "적극적인 키 프레임 재전송",
@@ -6,6 +6,7 @@ define({
         clients: "Clients",
         newClients: "New Clients",
         addTrustedClient: "Trust",
+        pairingPin: "Enter the PIN shown on the headset",
         trustedClients: "Trusted Clients",
         removeTrustedClient: "Remove",
         troubleshooting:
@@ -25,6 +26,7 @@ define({
         configureClientButton: "Configure",
         configureClientAddIp: "Add new IP",
         configureClientRemoveIp: "Remove",
+        configureClientSpectator: "Spectator (receives video and audio only)",
         // Statistics container
         statistics: "Statistics",
         streamingStatistics: "Streaming Statistics",
@@ -40,10 +42,13 @@ define({
         encodeLatency: "Encoder Latency",
         encodeLatencyMax: "Encode latency max",
         transportLatency: "Transport latency",
+        uplinkLatency: "Client to server latency",
+        downlinkLatency: "Server to client latency",
         decodeLatency: "Decoder latency",
         fecPercentage: "Fec percentage",
         fecFailureTotal: "Fec failure total",
         fecFailureInSecond: "Fec failure / s",
+        packetsDropped: "Packets dropped",
         clientFPS: "Client FPS",
         serverFPS: "Server FPS",
         packets: "Packets",
@@ -90,6 +90,11 @@ define({
         "_root_video_adaptiveBitrate.name": "Adaptive bitrate",
         "_root_video_adaptiveBitrate_enabled.description":
             "Use frame time or user selected target to adjust bitrate",
+        "_root_video_adaptiveBitrate_content_algorithm-choice-.name": "Algorithm", // adv
+        "_root_video_adaptiveBitrate_content_algorithm-choice-.description":
+            "Latency: adjust the bitrate from the total latency measured by the headset. Delay based: adjust the bitrate from the growth of the network delay of each video packet and from packet loss, reacting earlier to congestion", // adv
+        "_root_video_adaptiveBitrate_content_algorithm_latency-choice-.name": "Latency", // adv
+        "_root_video_adaptiveBitrate_content_algorithm_delayBased-choice-.name": "Delay based", // adv
         "_root_video_adaptiveBitrate_content_bitrateMaximum.name": "Bitrate limit",
         "_root_video_adaptiveBitrate_content_bitrateMaximum.description":
             "Adaptive bitrate will not use a bitrate higher than this limit",
@@ -292,13 +297,26 @@ define({
         "_root_connection_clientDiscovery.name": "Client discovery",
         "_root_connection_clientDiscovery_content_autoTrustClients.name":
             "Trust clients automatically (not recommended)", // adv
+        "_root_connection_wiredConnection.name": "Wired connection (USB)",
+        "_root_connection_wiredConnection.description":
+            "Stream over a USB cable instead of the network. The headset must be connected to this PC with USB debugging enabled. Client discovery is skipped and TCP is used as streaming protocol.",
+        "_root_connection_wiredConnection_content_adbPath.name": "adb executable path", // adv
+        "_root_connection_wiredConnection_content_adbPath.description":
+            "Leave empty to use the adb found in PATH.", // adv
+        "_root_connection_bindInterface.name": "Bind to network interface", // adv
+        "_root_connection_bindInterface.description":
+            "Use only one network interface of a PC with many network adapters. Clients that are not reachable through this interface are ignored.", // adv
+        "_root_connection_bindInterface_content_nameOrAddress.name": "Interface name or IP address", // adv
+        "_root_connection_bindInterface_content_nameOrAddress.description":
+            "For example eth0 or Wi-Fi, or one of the addresses of the interface. IPv6 link-local addresses are not supported.", // adv
         "_root_connection_webServerPort.name": "Web server port",
         "_root_connection_streamProtocol-choice-.name": "Streaming protocol",
         "_root_connection_streamProtocol-choice-.description":
-            "Network protocol used to stream data between client and server. UDP works best at low bitrates (<30), Throttled UDP works best at medium bitrates (~100), TCP works at any bitrate.",
+            "Network protocol used to stream data between client and server. UDP works best at low bitrates (<30), Throttled UDP works best at medium bitrates (~100), TCP works at any bitrate. QUIC sends video and tracking as datagrams and other data on reliable streams, with congestion control and encryption.",
         "_root_connection_streamProtocol_udp-choice-.name": "UDP",
         "_root_connection_streamProtocol_throttledUdp-choice-.name": "Throttled UDP",
         "_root_connection_streamProtocol_tcp-choice-.name": "TCP",
+        "_root_connection_streamProtocol_quic-choice-.name": "QUIC",
         "_root_connection_streamPort.name": "Server streaming port", // adv
         "_root_connection_streamPort.description": "Port used by the server to receive packets.", // adv
         "_root_connection_aggressiveKeyframeResend.name": "Aggressive keyframe resend",
@@ -310,6 +328,28 @@ define({
         "_root_connection_onDisconnectScript.name": "On disconnect script",
         "_root_connection_onDisconnectScript.description":
             "This script/executable will be run asynchronously when headset disconnects and on SteamVR shutdown.\nEnvironment variable ACTION will be set to &#34;disconnect&#34; (without quotes).",
+        "_root_connection_streamDropPolicies.name": "Stream drop policies", // adv
+        "_root_connection_streamDropPolicies.description":
+            "What happens to the packets of each stream when they arrive faster than they can be processed. Dropping the oldest packets keeps the latency low, blocking never loses packets.", // adv
+        "_root_connection_streamDropPolicies_video-choice-.name": "Video", // adv
+        "_root_connection_streamDropPolicies_video_dropOldest-choice-.name": "Drop oldest", // adv
+        "_root_connection_streamDropPolicies_video_block-choice-.name": "Block", // adv
+        "_root_connection_streamDropPolicies_tracking-choice-.name": "Tracking", // adv
+        "_root_connection_streamDropPolicies_tracking_dropOldest-choice-.name": "Drop oldest", // adv
+        "_root_connection_streamDropPolicies_tracking_block-choice-.name": "Block", // adv
+        "_root_connection_streamDropPolicies_haptics-choice-.name": "Haptics", // adv
+        "_root_connection_streamDropPolicies_haptics_dropOldest-choice-.name": "Drop oldest", // adv
+        "_root_connection_streamDropPolicies_haptics_block-choice-.name": "Block", // adv
+        "_root_connection_streamDropPolicies_audio-choice-.name": "Audio", // adv
+        "_root_connection_streamDropPolicies_audio_dropOldest-choice-.name": "Drop oldest", // adv
+        "_root_connection_streamDropPolicies_audio_block-choice-.name": "Block", // adv
+        "_root_connection_streamDropPolicies_statistics-choice-.name": "Statistics", // adv
+        "_root_connection_streamDropPolicies_statistics_dropOldest-choice-.name": "Drop oldest", // adv
+        "_root_connection_streamDropPolicies_statistics_block-choice-.name": "Block", // adv
+        "_root_connection_sessionResume.name": "Session resume", // adv
+        "_root_connection_sessionResume.description":
+            "When the connection is lost, the client can reconnect within the grace period and resume the stream without restarting it.", // adv
+        "_root_connection_sessionResume_content_gracePeriodS.name": "Grace period (s)", // adv
         // Extra tab
         "_root_extra_tab.name": "Extra",
         "_root_extra_theme-choice-.name": "Theme",
@@ -344,8 +384,25 @@ define({
         "_root_extra_patches_linuxAsyncReprojection.name": "Linux async reprojection",
         "_root_extra_patches_linuxAsyncReprojection.description":
             "This is the cause of jitter on Linux. It should always be disabled on Nvidia GPUs. AMD users should keep it on.",
+        "_root_extra_debug_networkImpairment.name": "Network impairment", // adv
+        "_root_extra_debug_networkImpairment_enabled.description":
+            "Degrade the stream packets sent by the server and the client, to test the streaming on a bad network", // adv
+        "_root_extra_debug_networkImpairment_content_lossProbability.name": "Loss probability", // adv
+        "_root_extra_debug_networkImpairment_content_burstLossProbability.name": "Burst loss probability", // adv
+        "_root_extra_debug_networkImpairment_content_burstLossProbability.description":
+            "Probability of starting a burst of lost packets", // adv
+        "_root_extra_debug_networkImpairment_content_averageBurstLength.name": "Average burst length (packets)", // adv
+        "_root_extra_debug_networkImpairment_content_latencyMs.name": "Latency (ms)", // adv
+        "_root_extra_debug_networkImpairment_content_jitterMs.name": "Jitter (ms)", // adv
+        "_root_extra_debug_networkImpairment_content_reorderProbability.name": "Reorder probability", // adv
+        "_root_extra_debug_networkImpairment_content_bandwidthLimitMbps.name": "Bandwidth limit (Mbps)", // adv
+        "_root_extra_debug_networkImpairment_content_bandwidthLimitMbps.description": "0 disables the limit", // adv
+        "_root_extra_debug_networkImpairment_content_randomSeed.name": "Random seed", // adv
+        "_root_extra_debug_networkImpairment_content_randomSeed_enabled.description":
+            "Drop, delay and reorder the same packets on every run", // adv
         // Others
         steamVRRestartSuccess: "SteamVR successfully restarted",
+        "settingsStoreError.name": "The settings were not stored",
         audioDeviceError: "No audio devices found. Cannot stream audio or microphone",
     },
     it: true,
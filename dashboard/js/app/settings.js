@@ -202,11 +202,23 @@ define([
                     }
                 },
                 error: function (res) {
+                    // The server replies with the settings that do not respect the schema
+                    const diagnostics = res.responseJSON || [];
+                    Lobibox.notify("error", {
+                        size: "mini",
+                        rounded: true,
+                        delayIndicator: false,
+                        sound: false,
+                        title: getI18n("settingsStoreError").name,
+                        msg: diagnostics
+                            .map((diagnostic) => diagnostic.path + ": " + diagnostic.problem)
+                            .join("<br>"),
+                    });
+
                     console.log("FAILED");
-                    updating = true;
-                    session = res;
-                    setProperties(res.sessionSettings, "_root");
-                    updating = false;
+                    $.getJSON("api/session/load", function (storedSession) {
+                        self.updateSession(storedSession);
+                    });
                 },
             });
         };
@@ -64,13 +64,16 @@ define(["jquery", "lib/lodash", "text!app/templates/uploadPreset.html"], functio
                                 }
                             },
                             error: function (res) {
+                                const diagnostics = res.responseJSON || [];
                                 Lobibox.notify("error", {
                                     size: "mini",
                                     rounded: true,
                                     delayIndicator: false,
                                     sound: false,
                                     title: "Error while storing the settings",
-                                    msg: res,
+                                    msg: diagnostics
+                                        .map((diagnostic) => diagnostic.path + ": " + diagnostic.problem)
+                                        .join("<br>"),
                                 });
                                 console.log("FAILED", res);
                             },